@@ -5,12 +5,21 @@ use crate::interface::types::OutputValue;
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, strum::EnumIter)]
 pub enum AclCategory {
     Admin,
+    Bitmap,
+    Blocking,
     Connection,
     Dangerous,
     Fast,
+    Geo,
+    Hash,
+    HyperLogLog,
     Keyspace,
+    List,
     Read,
+    Set,
     Slow,
+    SortedSet,
+    Stream,
     String,
     Write,
     Scripting,
@@ -20,12 +29,21 @@ impl AclCategory {
     pub const fn as_bytes(&self) -> &'static [u8] {
         match self {
             AclCategory::Admin => b"admin".as_slice(),
+            AclCategory::Bitmap => b"bitmap".as_slice(),
+            AclCategory::Blocking => b"blocking".as_slice(),
             AclCategory::Connection => b"connection".as_slice(),
             AclCategory::Dangerous => b"dangerous".as_slice(),
             AclCategory::Fast => b"fast".as_slice(),
+            AclCategory::Geo => b"geo".as_slice(),
+            AclCategory::Hash => b"hash".as_slice(),
+            AclCategory::HyperLogLog => b"hyperloglog".as_slice(),
             AclCategory::Keyspace => b"keyspace".as_slice(),
+            AclCategory::List => b"list".as_slice(),
             AclCategory::Read => b"read".as_slice(),
+            AclCategory::Set => b"set".as_slice(),
             AclCategory::Slow => b"slow".as_slice(),
+            AclCategory::SortedSet => b"sortedset".as_slice(),
+            AclCategory::Stream => b"stream".as_slice(),
             AclCategory::String => b"string".as_slice(),
             AclCategory::Write => b"write".as_slice(),
             AclCategory::Scripting => b"scripting".as_slice(),
@@ -51,12 +69,21 @@ impl std::str::FromStr for AclCategory {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_ascii_lowercase().as_str() {
             "admin" => Ok(AclCategory::Admin),
+            "bitmap" => Ok(AclCategory::Bitmap),
+            "blocking" => Ok(AclCategory::Blocking),
             "connection" => Ok(AclCategory::Connection),
             "dangerous" => Ok(AclCategory::Dangerous),
             "fast" => Ok(AclCategory::Fast),
+            "geo" => Ok(AclCategory::Geo),
+            "hash" => Ok(AclCategory::Hash),
+            "hyperloglog" => Ok(AclCategory::HyperLogLog),
             "keyspace" => Ok(AclCategory::Keyspace),
+            "list" => Ok(AclCategory::List),
             "read" => Ok(AclCategory::Read),
+            "set" => Ok(AclCategory::Set),
             "slow" => Ok(AclCategory::Slow),
+            "sortedset" => Ok(AclCategory::SortedSet),
+            "stream" => Ok(AclCategory::Stream),
             "string" => Ok(AclCategory::String),
             "write" => Ok(AclCategory::Write),
             "scripting" => Ok(AclCategory::Scripting),
@@ -0,0 +1,431 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use smol::channel::Sender;
+
+use crate::bstr::BStr;
+use crate::interface::connection::ConnectionId;
+use crate::interface::types::{InputValue, OutputValue};
+
+/// Outcome of running a blocking command against the database.
+pub enum Blocking {
+    /// The command could be served (or failed) right away.
+    Ready(OutputValue),
+    /// Nothing to serve yet; the client should wait for one of `keys` to be written.
+    Wait {
+        keys: Vec<Vec<u8>>,
+        timeout: Option<Duration>,
+        timeout_reply: OutputValue,
+        /// Arguments to retry with instead of the original ones, for commands such as XREAD
+        /// whose `$` has to be resolved when they block rather than each time they are retried.
+        retry_input: Option<Vec<InputValue>>,
+    },
+}
+
+/// Parses a blocking timeout given in (possibly fractional) seconds; `0` means "forever".
+pub fn parse_timeout(input: &[u8]) -> Result<Option<Duration>, OutputValue> {
+    let Some(secs) = input.parse_into::<f64>().filter(|f| f.is_finite()) else {
+        return Err(OutputValue::Error(
+            b"ERR timeout is not a float or out of range".to_vec(),
+        ));
+    };
+    if secs < 0.0 {
+        return Err(OutputValue::Error(b"ERR timeout is negative".to_vec()));
+    }
+    if secs == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(secs)
+        .map(Some)
+        .map_err(|_| OutputValue::Error(b"ERR timeout is out of range".to_vec()))
+}
+
+/// Parses a blocking timeout given in milliseconds, as the BLOCK option of XREAD takes;
+/// `0` means "forever".
+pub fn parse_timeout_ms(input: &[u8]) -> Result<Option<Duration>, OutputValue> {
+    match input.parse_into::<i64>() {
+        None => Err(OutputValue::Error(
+            b"ERR timeout is not an integer or out of range".to_vec(),
+        )),
+        Some(ms) if ms < 0 => Err(OutputValue::Error(b"ERR timeout is negative".to_vec())),
+        Some(0) => Ok(None),
+        Some(ms) => Ok(Some(Duration::from_millis(ms as u64))),
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockedClient {
+    pub db: usize,
+    pub keys: Vec<Vec<u8>>,
+    pub name: String,
+    pub input: Vec<InputValue>,
+    pub timeout_reply: OutputValue,
+    pub sender: Sender<Vec<u8>>,
+}
+
+/// Clients parked on keys, served per key in the order they blocked.
+#[derive(Debug, Default)]
+pub struct BlockedClients {
+    clients: HashMap<ConnectionId, BlockedClient>,
+    waiting: HashMap<(usize, Vec<u8>), VecDeque<ConnectionId>>,
+}
+
+impl BlockedClients {
+    pub fn block(&mut self, con_id: ConnectionId, client: BlockedClient) {
+        for key in client.keys.iter() {
+            let queue = self.waiting.entry((client.db, key.clone())).or_default();
+            if !queue.contains(&con_id) {
+                queue.push_back(con_id.clone());
+            }
+        }
+        self.clients.insert(con_id, client);
+    }
+
+    pub fn unblock(&mut self, con_id: &ConnectionId) -> Option<BlockedClient> {
+        let client = self.clients.remove(con_id)?;
+        for key in client.keys.iter() {
+            let entry = (client.db, key.clone());
+            if let Some(queue) = self.waiting.get_mut(&entry) {
+                queue.retain(|id| id != con_id);
+                if queue.is_empty() {
+                    self.waiting.remove(&entry);
+                }
+            }
+        }
+        Some(client)
+    }
+
+    pub fn get(&self, con_id: &ConnectionId) -> Option<&BlockedClient> {
+        self.clients.get(con_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// Snapshot of the clients waiting on `key` in `db`, in the order they blocked.
+    pub fn waiting_on(&self, db: usize, key: &[u8]) -> Vec<ConnectionId> {
+        self.waiting
+            .get(&(db, key.to_vec()))
+            .map(|queue| queue.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The keys in `db` that clients are waiting on, sorted so that they are served in a
+    /// predictable order.
+    pub fn keys_in(&self, db: usize) -> Vec<Vec<u8>> {
+        let mut keys: Vec<_> = self
+            .waiting
+            .keys()
+            .filter(|(d, _)| *d == db)
+            .map(|(_, key)| key.clone())
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementation::database::Map;
+    use crate::implementation::Controller;
+    use crate::interface::types::Reply;
+    use crate::interface::IController;
+    use smol::channel::Receiver;
+
+    fn controller() -> Controller<Map> {
+        Controller::new(16)
+    }
+
+    fn connect(controller: &mut Controller<Map>) -> ConnectionId {
+        controller.connect("127.0.0.1:6379".parse().unwrap())
+    }
+
+    fn input(args: &[&str]) -> Vec<InputValue> {
+        args.iter().map(|arg| arg.as_bytes().to_vec()).collect()
+    }
+
+    /// Runs a command that is expected to reply right away.
+    fn run(controller: &mut Controller<Map>, con_id: &ConnectionId, args: &[&str]) -> Vec<u8> {
+        match controller.execute(input(args), con_id.clone()) {
+            Reply::Ready(reply) => reply,
+            Reply::Blocked { .. } => panic!("{:?} blocked", args),
+        }
+    }
+
+    /// Runs a command that is expected to block, returning where its reply will come from.
+    fn block(
+        controller: &mut Controller<Map>,
+        con_id: &ConnectionId,
+        args: &[&str],
+    ) -> Receiver<Vec<u8>> {
+        match controller.execute(input(args), con_id.clone()) {
+            Reply::Blocked { receiver, .. } => receiver,
+            Reply::Ready(reply) => {
+                panic!("{:?} replied {:?}", args, String::from_utf8_lossy(&reply))
+            }
+        }
+    }
+
+    fn bulk_array(items: &[&str]) -> Vec<u8> {
+        OutputValue::Array(
+            items
+                .iter()
+                .map(|item| OutputValue::BulkString(item.as_bytes().to_vec()))
+                .collect(),
+        )
+        .to_bytes_vec()
+    }
+
+    fn client(db: usize, keys: &[&[u8]]) -> (BlockedClient, smol::channel::Receiver<Vec<u8>>) {
+        let (sender, receiver) = smol::channel::bounded(1);
+        let client = BlockedClient {
+            db,
+            keys: keys.iter().map(|k| k.to_vec()).collect(),
+            name: "blpop".to_string(),
+            input: vec![],
+            timeout_reply: OutputValue::NullArray,
+            sender,
+        };
+        (client, receiver)
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout(b"0"), Ok(None));
+        assert_eq!(parse_timeout(b"1.5"), Ok(Some(Duration::from_millis(1500))));
+        assert!(parse_timeout(b"-1").is_err());
+        assert!(parse_timeout(b"inf").is_err());
+        assert!(parse_timeout(b"soon").is_err());
+    }
+
+    #[test]
+    fn test_parse_timeout_ms() {
+        assert_eq!(parse_timeout_ms(b"0"), Ok(None));
+        assert_eq!(
+            parse_timeout_ms(b"1500"),
+            Ok(Some(Duration::from_millis(1500)))
+        );
+        assert!(parse_timeout_ms(b"-1").is_err());
+        assert!(parse_timeout_ms(b"1.5").is_err());
+    }
+
+    #[test]
+    fn test_waiting_is_fifo_per_key() {
+        let mut blocked = BlockedClients::default();
+        let (c1, _r1) = client(0, &[b"a"]);
+        let (c2, _r2) = client(0, &[b"b", b"a"]);
+        let (c3, _r3) = client(1, &[b"a"]);
+        blocked.block(ConnectionId::from(1u32), c1);
+        blocked.block(ConnectionId::from(2u32), c2);
+        blocked.block(ConnectionId::from(3u32), c3);
+        assert_eq!(
+            blocked.waiting_on(0, b"a"),
+            [1u32, 2].map(ConnectionId::from).to_vec()
+        );
+        assert_eq!(blocked.waiting_on(0, b"b"), vec![ConnectionId::from(2u32)]);
+        assert_eq!(blocked.keys_in(0), vec![b"a".to_vec(), b"b".to_vec()]);
+
+        blocked.unblock(&ConnectionId::from(1u32));
+        assert_eq!(blocked.waiting_on(0, b"a"), vec![ConnectionId::from(2u32)]);
+        assert_eq!(blocked.waiting_on(1, b"a"), vec![ConnectionId::from(3u32)]);
+        blocked.unblock(&ConnectionId::from(2u32));
+        assert!(blocked.waiting_on(0, b"a").is_empty());
+        assert!(blocked.keys_in(0).is_empty());
+    }
+
+    #[test]
+    fn test_clients_on_a_key_are_served_in_arrival_order() {
+        let mut controller = controller();
+        let writer = connect(&mut controller);
+        let first = connect(&mut controller);
+        let second = connect(&mut controller);
+        let other = connect(&mut controller);
+        let r1 = block(&mut controller, &first, &["blpop", "a", "0"]);
+        let r2 = block(&mut controller, &second, &["blpop", "b", "a", "0"]);
+        let r3 = block(&mut controller, &other, &["blpop", "c", "0"]);
+
+        run(&mut controller, &writer, &["rpush", "a", "x", "y"]);
+        assert_eq!(r1.try_recv().unwrap(), bulk_array(&["a", "x"]));
+        assert_eq!(r2.try_recv().unwrap(), bulk_array(&["a", "y"]));
+        // a write to another key leaves the rest waiting
+        assert!(r3.try_recv().is_err());
+        run(&mut controller, &writer, &["set", "c", "v"]);
+        assert!(r3.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_keys_signalled_while_serving_are_served_too() {
+        let mut controller = controller();
+        let writer = connect(&mut controller);
+        let mover = connect(&mut controller);
+        let popper = connect(&mut controller);
+        let r1 = block(&mut controller, &popper, &["blpop", "dst", "0"]);
+        let r2 = block(
+            &mut controller,
+            &mover,
+            &["blmove", "src", "dst", "left", "right", "0"],
+        );
+
+        run(&mut controller, &writer, &["lpush", "src", "v"]);
+        assert_eq!(
+            r2.try_recv().unwrap(),
+            OutputValue::BulkString(b"v".to_vec()).to_bytes_vec()
+        );
+        assert_eq!(r1.try_recv().unwrap(), bulk_array(&["dst", "v"]));
+    }
+
+    #[test]
+    fn test_deleting_a_stream_fails_readers_of_its_groups() {
+        let mut controller = controller();
+        let writer = connect(&mut controller);
+        let reader = connect(&mut controller);
+        run(
+            &mut controller,
+            &writer,
+            &["xgroup", "create", "s", "g", "$", "mkstream"],
+        );
+        let args = [
+            "xreadgroup",
+            "group",
+            "g",
+            "c",
+            "block",
+            "0",
+            "streams",
+            "s",
+            ">",
+        ];
+        let receiver = block(&mut controller, &reader, &args);
+        run(&mut controller, &writer, &["del", "s"]);
+        let reply = receiver.try_recv().unwrap();
+        assert!(
+            reply.starts_with(b"-NOGROUP"),
+            "{:?}",
+            String::from_utf8_lossy(&reply)
+        );
+    }
+
+    fn nested(key: &str, pairs: &[[&str; 2]]) -> Vec<u8> {
+        let pairs = pairs
+            .iter()
+            .map(|pair| {
+                OutputValue::Array(
+                    pair.iter()
+                        .map(|item| OutputValue::BulkString(item.as_bytes().to_vec()))
+                        .collect(),
+                )
+            })
+            .collect();
+        OutputValue::Array(vec![
+            OutputValue::BulkString(key.as_bytes().to_vec()),
+            OutputValue::Array(pairs),
+        ])
+        .to_bytes_vec()
+    }
+
+    #[test]
+    fn test_zmpop() {
+        let mut controller = controller();
+        let con_id = connect(&mut controller);
+        let null_array = OutputValue::NullArray.to_bytes_vec();
+        assert_eq!(
+            run(&mut controller, &con_id, &["zmpop", "2", "a", "b", "min"]),
+            null_array
+        );
+        run(
+            &mut controller,
+            &con_id,
+            &["zadd", "b", "1", "x", "2", "y", "3", "z"],
+        );
+        assert_eq!(
+            run(&mut controller, &con_id, &["zmpop", "2", "a", "b", "min"]),
+            nested("b", &[["x", "1"]])
+        );
+        assert_eq!(
+            run(
+                &mut controller,
+                &con_id,
+                &["zmpop", "2", "a", "b", "max", "count", "5"]
+            ),
+            nested("b", &[["z", "3"], ["y", "2"]])
+        );
+        assert_eq!(
+            run(&mut controller, &con_id, &["exists", "b"]),
+            OutputValue::Integer(0).to_bytes_vec()
+        );
+        let reply = run(&mut controller, &con_id, &["zmpop", "1", "a", "middle"]);
+        assert!(reply.starts_with(b"-ERR"));
+    }
+
+    #[test]
+    fn test_bzpop_replies_with_key_member_and_score() {
+        let mut controller = controller();
+        let con_id = connect(&mut controller);
+        run(&mut controller, &con_id, &["zadd", "z", "1", "a", "2", "b"]);
+        assert_eq!(
+            run(&mut controller, &con_id, &["bzpopmin", "missing", "z", "0"]),
+            bulk_array(&["z", "a", "1"])
+        );
+        assert_eq!(
+            run(&mut controller, &con_id, &["bzpopmax", "z", "0"]),
+            bulk_array(&["z", "b", "2"])
+        );
+        run(&mut controller, &con_id, &["zadd", "z", "1", "a", "2", "b"]);
+        assert_eq!(
+            run(
+                &mut controller,
+                &con_id,
+                &["bzmpop", "0", "1", "z", "max", "count", "2"]
+            ),
+            nested("z", &[["b", "2"], ["a", "1"]])
+        );
+    }
+
+    #[test]
+    fn test_bzpop_times_out_with_a_null_array() {
+        let mut controller = controller();
+        let waiting = connect(&mut controller);
+        let writer = connect(&mut controller);
+        for args in [
+            ["bzpopmin", "z", "0.1"].as_slice(),
+            &["bzpopmax", "z", "0.1"],
+            &["bzmpop", "0.1", "1", "z", "min"],
+        ] {
+            let receiver = block(&mut controller, &waiting, args);
+            assert_eq!(
+                controller.unblock(waiting.clone()),
+                OutputValue::NullArray.to_bytes_vec()
+            );
+            // once timed out, the client is no longer served
+            run(&mut controller, &writer, &["zadd", "z", "1", "a"]);
+            assert!(receiver.try_recv().is_err());
+            run(&mut controller, &writer, &["del", "z"]);
+        }
+    }
+
+    #[test]
+    fn test_zadd_serves_bzpop_clients_in_arrival_order() {
+        let mut controller = controller();
+        let writer = connect(&mut controller);
+        let first = connect(&mut controller);
+        let second = connect(&mut controller);
+        let third = connect(&mut controller);
+        let r1 = block(&mut controller, &first, &["bzpopmax", "z", "0"]);
+        let r2 = block(
+            &mut controller,
+            &second,
+            &["bzmpop", "0", "2", "y", "z", "min"],
+        );
+        let r3 = block(&mut controller, &third, &["bzpopmin", "z", "0"]);
+
+        run(&mut controller, &writer, &["zadd", "z", "1", "a", "2", "b"]);
+        assert_eq!(r1.try_recv().unwrap(), bulk_array(&["z", "b", "2"]));
+        assert_eq!(r2.try_recv().unwrap(), nested("z", &[["a", "1"]]));
+        // the set is empty again, so the last client keeps waiting
+        assert!(r3.try_recv().is_err());
+        run(&mut controller, &writer, &["zadd", "z", "3", "c"]);
+        assert_eq!(r3.try_recv().unwrap(), bulk_array(&["z", "c", "3"]));
+    }
+}
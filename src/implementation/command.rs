@@ -1,13 +1,37 @@
 use std::collections::HashMap;
 
 use super::acl::AclCategory;
+use super::blocking::Blocking;
+use super::database::float;
 use super::InputValue;
 use super::Interrupt;
 use crate::bstr::BStr;
-use crate::interface::database::map::{Key, MapAllCommands, MapMiscCommands, MapStringCommands};
+use crate::interface::database::map::{
+    ExpireFlags, ExpireTime, Key, LcsOutput, MapAllCommands, MapMiscCommands, MapStringCommands,
+    ScanOptions, SetCondition, TtlFormat, TtlUpdate,
+};
 use crate::interface::types::OutputValue;
 
+mod bitmap;
+mod geo;
+mod hash;
+mod hyperloglog;
+mod list;
+mod set;
+mod sorted_set;
+mod stream;
+
+use bitmap::initialise_bitmap_commands;
+use geo::initialise_geo_commands;
+use hash::initialise_hash_commands;
+use hyperloglog::initialise_hyperloglog_commands;
+use list::{initialise_blocking_list_commands, initialise_list_commands};
+use set::initialise_set_commands;
+use sorted_set::{initialise_blocking_sorted_set_commands, initialise_sorted_set_commands};
+use stream::{initialise_blocking_stream_commands, initialise_stream_commands};
+
 type CommandHandler<D> = dyn Fn(&mut D, Vec<InputValue>) -> OutputValue + 'static;
+type BlockingCommandHandler<D> = dyn Fn(&mut D, &[InputValue]) -> Blocking + 'static;
 type ControllerCommandHandler = dyn Fn(Vec<InputValue>) -> Result<Interrupt, OutputValue>;
 
 impl Key for Vec<u8> {}
@@ -50,6 +74,24 @@ impl<D> std::fmt::Debug for SimpleCommand<D> {
     }
 }
 
+pub struct BlockingCommand<D: 'static> {
+    pub handler: &'static BlockingCommandHandler<D>,
+    pub category: &'static [AclCategory],
+    arity_min: usize,
+    arity_max: Option<usize>,
+}
+
+impl<D> std::fmt::Debug for BlockingCommand<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlockingCommand")
+            .field("handler", &"...")
+            .field("category", &self.category)
+            .field("arity_min", &self.arity_min)
+            .field("arity_max", &self.arity_max)
+            .finish()
+    }
+}
+
 pub struct ControllerCommandDefinition {
     pub handler: &'static ControllerCommandHandler,
     pub category: &'static [AclCategory],
@@ -110,6 +152,29 @@ impl<D> Command<D> for SimpleCommand<D> {
     }
 }
 
+impl<D> BlockingCommand<D> {
+    fn is_arity_correct(&self, arity: usize) -> bool {
+        if arity < self.arity_min {
+            false
+        } else if let Some(max) = self.arity_max {
+            arity <= max
+        } else {
+            true
+        }
+    }
+
+    /// Unlike [`Command::execute`], the input is borrowed so that the command can be retried
+    /// with the same arguments once the client is woken up.
+    pub fn execute(&self, name: &str, db: &mut D, input: &[InputValue]) -> Blocking {
+        if !self.is_arity_correct(input.len()) {
+            return Blocking::Ready(OutputValue::Error(
+                format!("ERR wrong number of arguments for '{}'", name).into_bytes(),
+            ));
+        }
+        (self.handler)(db, input)
+    }
+}
+
 pub trait ControllerCommand {
     fn is_arity_correct(&self, arity: usize) -> bool;
     fn execute(&self, name: &str, input: Vec<InputValue>) -> Result<Interrupt, OutputValue>;
@@ -178,6 +243,7 @@ impl ControllerCommand for ContainerCommand {
 #[derive(Debug)]
 pub struct CommandStore<D: 'static> {
     pub simple_commands: HashMap<&'static str, SimpleCommand<D>>,
+    pub blocking_commands: HashMap<&'static str, BlockingCommand<D>>,
     pub container_commands: HashMap<&'static str, ContainerCommand>,
     pub controller_commands: HashMap<&'static str, ControllerCommandDefinition>,
     pub names: OutputValue,
@@ -187,10 +253,12 @@ pub struct CommandStore<D: 'static> {
 impl<D: 'static + MapAllCommands> Default for CommandStore<D> {
     fn default() -> Self {
         let simple_commands = initialise_simple_commands();
+        let blocking_commands = initialise_blocking_commands();
         let container_commands = initialise_container_commands();
         let controller_commands = initialise_controller_commands();
         let name_to_category = generate_command_name_to_category(
             &simple_commands,
+            &blocking_commands,
             &container_commands,
             &controller_commands,
         );
@@ -199,6 +267,7 @@ impl<D: 'static + MapAllCommands> Default for CommandStore<D> {
 
         Self {
             simple_commands,
+            blocking_commands,
             container_commands,
             controller_commands,
             names,
@@ -217,6 +286,7 @@ pub enum CommandListFilter {
 impl<D> CommandStore<D> {
     pub fn count(&self) -> OutputValue {
         let simple_counts = self.simple_commands.len();
+        let blocking_counts = self.blocking_commands.len();
         let controller_counts = self.controller_commands.len();
         let container_counts: usize = self
             .container_commands
@@ -224,7 +294,9 @@ impl<D> CommandStore<D> {
             .map(|c| c.subcommands.len() + c.handler.is_some() as usize)
             .sum();
         // i64 should be enough for counting commands
-        OutputValue::Integer((simple_counts + controller_counts + container_counts) as i64)
+        OutputValue::Integer(
+            (simple_counts + blocking_counts + controller_counts + container_counts) as i64,
+        )
     }
 
     pub fn list(&self, filter: CommandListFilter) -> OutputValue {
@@ -258,6 +330,7 @@ impl<D> CommandStore<D> {
 
 fn generate_command_name_to_category<D>(
     simple_commands: &HashMap<&'static str, SimpleCommand<D>>,
+    blocking_commands: &HashMap<&'static str, BlockingCommand<D>>,
     container_commands: &HashMap<&'static str, ContainerCommand>,
     controller_commands: &HashMap<&'static str, ControllerCommandDefinition>,
 ) -> HashMap<String, &'static [AclCategory]> {
@@ -266,6 +339,10 @@ fn generate_command_name_to_category<D>(
         ret.insert(k.to_string(), v.category);
     }
 
+    for (k, v) in blocking_commands.iter() {
+        ret.insert(k.to_string(), v.category);
+    }
+
     for (k, v) in container_commands.iter() {
         ret.insert(k.to_string(), v.category);
         for (k2, v2) in v.subcommands.iter() {
@@ -330,6 +407,279 @@ fn get_first_three<T>(args: Vec<T>) -> (T, T, T) {
     )
 }
 
+/// The error for a count of HRANDFIELD or SRANDMEMBER whose magnitude does not fit an `i64`.
+fn count_out_of_range() -> OutputValue {
+    OutputValue::Error(
+        format!(
+            "ERR value is out of range, must be between {} and {}",
+            -i64::MAX,
+            i64::MAX
+        )
+        .into_bytes(),
+    )
+}
+
+/// Parses the argument of an EX, PX, EXAT or PXAT option (`option` is lowercase),
+/// which must be a positive integer.
+fn parse_expire_option(
+    option: &str,
+    value: &[u8],
+    command: &str,
+) -> Result<ExpireTime, OutputValue> {
+    let invalid = || {
+        OutputValue::Error(format!("ERR invalid expire time in '{}' command", command).into_bytes())
+    };
+    let Some(n) = value.parse_into::<i64>() else {
+        return Err(OutputValue::Error(b"ERR value is not an integer".to_vec()));
+    };
+    if n <= 0 {
+        return Err(invalid());
+    }
+    match option {
+        "ex" => n
+            .checked_mul(1000)
+            .map(ExpireTime::Relative)
+            .ok_or_else(invalid),
+        "px" => Ok(ExpireTime::Relative(n)),
+        "exat" => n
+            .checked_mul(1000)
+            .map(ExpireTime::Absolute)
+            .ok_or_else(invalid),
+        "pxat" => Ok(ExpireTime::Absolute(n)),
+        _ => unreachable!(),
+    }
+}
+
+/// Parses the options of SET, rejecting conflicting ones as Redis does.
+fn parse_set_options(
+    mut input: &[InputValue],
+) -> Result<(SetCondition, bool, TtlUpdate), OutputValue> {
+    let syntax_error = || OutputValue::Error(b"ERR syntax error".to_vec());
+    let mut condition = SetCondition::Always;
+    let mut get = false;
+    let mut ttl = TtlUpdate::Persist;
+    while let Some(option) = input.first().and_then(|o| o.to_lower_string()) {
+        let consumed = match option.as_str() {
+            "nx" | "xx" if condition == SetCondition::Always => {
+                condition = if option == "nx" {
+                    SetCondition::Nx
+                } else {
+                    SetCondition::Xx
+                };
+                1
+            }
+            "get" => {
+                get = true;
+                1
+            }
+            "ex" | "px" | "exat" | "pxat" if ttl == TtlUpdate::Persist => {
+                let value = input.get(1).ok_or_else(syntax_error)?;
+                ttl = TtlUpdate::Expire(parse_expire_option(option.as_str(), value, "set")?);
+                2
+            }
+            "keepttl" if ttl == TtlUpdate::Persist => {
+                ttl = TtlUpdate::Keep;
+                1
+            }
+            _ => return Err(syntax_error()),
+        };
+        input = &input[consumed..];
+    }
+    if !input.is_empty() {
+        return Err(syntax_error());
+    }
+    Ok((condition, get, ttl))
+}
+
+/// Parses the single EX, PX, EXAT, PXAT or PERSIST option GETEX may be given.
+fn parse_getex_options(input: &[InputValue]) -> Result<TtlUpdate, OutputValue> {
+    let option = input.first().and_then(|o| o.to_lower_string());
+    match (option.as_deref(), input.len()) {
+        (None, 0) => Ok(TtlUpdate::Keep),
+        (Some("persist"), 1) => Ok(TtlUpdate::Persist),
+        (Some(option @ ("ex" | "px" | "exat" | "pxat")), 2) => Ok(TtlUpdate::Expire(
+            parse_expire_option(option, &input[1], "getex")?,
+        )),
+        _ => Err(OutputValue::Error(b"ERR syntax error".to_vec())),
+    }
+}
+
+/// Parses the NX, XX, GT and LT flags of the EXPIRE family.
+fn parse_expire_flags(input: &[InputValue]) -> Result<ExpireFlags, OutputValue> {
+    let mut flags = ExpireFlags::default();
+    for option in input {
+        match option.to_lower_string().as_deref() {
+            Some("nx") => flags.nx = true,
+            Some("xx") => flags.xx = true,
+            Some("gt") => flags.gt = true,
+            Some("lt") => flags.lt = true,
+            _ => {
+                return Err(OutputValue::Error(
+                    [b"ERR Unsupported option ", option.as_slice()].concat(),
+                ))
+            }
+        }
+    }
+    if flags.nx && (flags.xx || flags.gt || flags.lt) {
+        return Err(OutputValue::Error(
+            b"ERR NX and XX, GT or LT options at the same time are not compatible".to_vec(),
+        ));
+    }
+    if flags.gt && flags.lt {
+        return Err(OutputValue::Error(
+            b"ERR GT and LT options at the same time are not compatible".to_vec(),
+        ));
+    }
+    Ok(flags)
+}
+
+fn expire_handler<D: MapMiscCommands>(
+    db: &mut D,
+    input: Vec<InputValue>,
+    command: &str,
+    unit_ms: i64,
+    absolute: bool,
+) -> OutputValue {
+    let flags = match parse_expire_flags(&input[2..]) {
+        Ok(flags) => flags,
+        Err(e) => return e,
+    };
+    let Some(n) = input[1].parse_into::<i64>() else {
+        return OutputValue::Error(b"ERR value is not an integer".to_vec());
+    };
+    let Some(ms) = n.checked_mul(unit_ms) else {
+        return OutputValue::Error(
+            format!("ERR invalid expire time in '{}' command", command).into_bytes(),
+        );
+    };
+    let at = if absolute {
+        ExpireTime::Absolute(ms)
+    } else {
+        ExpireTime::Relative(ms)
+    };
+    db.expire(input[0].as_slice(), at, flags, command)
+}
+
+/// Parses `cursor [MATCH pattern] [COUNT count]` of the SCAN family. Any other option is
+/// handed to `other` with the arguments after it, to be accepted or rejected.
+fn parse_scan_args<'a>(
+    input: &'a [InputValue],
+    mut other: impl FnMut(&str, &mut std::slice::Iter<'a, InputValue>) -> Result<(), OutputValue>,
+) -> Result<(u64, ScanOptions), OutputValue> {
+    let syntax_error = || OutputValue::Error(b"ERR syntax error".to_vec());
+    let (cursor, rest) = input.split_first().expect("the cursor is mandatory");
+    let Some(cursor) = cursor.parse_into::<u64>() else {
+        return Err(OutputValue::Error(b"ERR invalid cursor".to_vec()));
+    };
+    let mut options = ScanOptions::default();
+    let mut it = rest.iter();
+    while let Some(option) = it.next() {
+        match option.to_lower_string().as_deref() {
+            Some("match") => {
+                options.pattern = Some(it.next().ok_or_else(syntax_error)?.clone());
+            }
+            Some("count") => {
+                let count = it.next().ok_or_else(syntax_error)?;
+                let Some(count) = count.parse_into::<i64>() else {
+                    return Err(OutputValue::Error(b"ERR value is not an integer".to_vec()));
+                };
+                if count < 1 {
+                    return Err(syntax_error());
+                }
+                options.count = count as usize;
+            }
+            Some(name) => other(name, &mut it)?,
+            None => return Err(syntax_error()),
+        }
+    }
+    Ok((cursor, options))
+}
+
+/// Parses `[LEN] [IDX] [MINMATCHLEN len] [WITHMATCHLEN]` of LCS.
+fn parse_lcs_options(mut input: &[InputValue]) -> Result<LcsOutput, OutputValue> {
+    let (mut len, mut idx, mut with_match_len) = (false, false, false);
+    let mut min_match_len = 0;
+    while let Some(option) = input.first() {
+        let consumed = match option.to_lower_string().as_deref() {
+            Some("len") => {
+                len = true;
+                1
+            }
+            Some("idx") => {
+                idx = true;
+                1
+            }
+            Some("withmatchlen") => {
+                with_match_len = true;
+                1
+            }
+            Some("minmatchlen") => {
+                let Some(value) = input.get(1) else {
+                    return Err(OutputValue::Error(b"ERR syntax error".to_vec()));
+                };
+                let Some(value) = value.parse_into::<i64>() else {
+                    return Err(OutputValue::Error(b"ERR value is not an integer".to_vec()));
+                };
+                min_match_len = value.max(0) as usize;
+                2
+            }
+            _ => return Err(OutputValue::Error(b"ERR syntax error".to_vec())),
+        };
+        input = &input[consumed..];
+    }
+    match (len, idx) {
+        (true, true) => Err(OutputValue::Error(
+            b"ERR If you want both the length and indexes, please just use IDX.".to_vec(),
+        )),
+        (true, false) => Ok(LcsOutput::Len),
+        (false, true) => Ok(LcsOutput::Idx {
+            min_match_len,
+            with_match_len,
+        }),
+        (false, false) => Ok(LcsOutput::String),
+    }
+}
+
+/// Parses `numkeys key [key ...] <end> [COUNT count]`, shared by LMPOP, ZMPOP and their
+/// blocking variants; `parse_end` reads LEFT|RIGHT or MIN|MAX.
+fn parse_mpop_args<E>(
+    input: &[InputValue],
+    parse_end: fn(&[u8]) -> Option<E>,
+) -> Result<(&[InputValue], E, usize), OutputValue> {
+    let numkeys = match input[0].parse_into::<i64>() {
+        None => return Err(OutputValue::Error(b"ERR value is not an integer".to_vec())),
+        Some(n) if n <= 0 => {
+            return Err(OutputValue::Error(
+                b"ERR numkeys should be greater than 0".to_vec(),
+            ))
+        }
+        Some(n) => n as usize,
+    };
+    let Some((keys, rest)) = input[1..].split_at_checked(numkeys) else {
+        return Err(OutputValue::Error(b"ERR syntax error".to_vec()));
+    };
+    let (end, count) = match rest {
+        [end] => (parse_end(end), Some(1)),
+        [end, option, count] if option.to_lower_string().as_deref() == Some("count") => (
+            parse_end(end),
+            count
+                .parse_into::<i64>()
+                .filter(|c| *c > 0)
+                .map(|c| c as usize),
+        ),
+        _ => return Err(OutputValue::Error(b"ERR syntax error".to_vec())),
+    };
+    let Some(end) = end else {
+        return Err(OutputValue::Error(b"ERR syntax error".to_vec()));
+    };
+    let Some(count) = count else {
+        return Err(OutputValue::Error(
+            b"ERR count should be greater than 0".to_vec(),
+        ));
+    };
+    Ok((keys, end, count))
+}
+
 fn initialise_simple_commands<D: MapAllCommands>() -> HashMap<&'static str, SimpleCommand<D>> {
     let mut map = HashMap::<&'static str, SimpleCommand<D>>::new();
     map.insert_without_duplicate(
@@ -352,9 +702,22 @@ fn initialise_simple_commands<D: MapAllCommands>() -> HashMap<&'static str, Simp
     map.extend(initialise_ping_echo());
     map.extend(initialise_misc_commands());
     map.extend(initialise_string_commands());
+    map.extend(initialise_bitmap_commands());
     map.extend(initialise_list_commands());
     map.extend(initialise_hash_commands());
     map.extend(initialise_set_commands());
+    map.extend(initialise_sorted_set_commands());
+    map.extend(initialise_stream_commands());
+    map.extend(initialise_hyperloglog_commands());
+    map.extend(initialise_geo_commands());
+    map
+}
+
+fn initialise_blocking_commands<D: MapAllCommands>() -> HashMap<&'static str, BlockingCommand<D>> {
+    let mut map = HashMap::new();
+    map.extend(initialise_blocking_list_commands());
+    map.extend(initialise_blocking_sorted_set_commands());
+    map.extend(initialise_blocking_stream_commands());
     map
 }
 
@@ -433,6 +796,127 @@ fn initialise_misc_commands<D: MapMiscCommands>() -> HashMap<&'static str, Simpl
             },
         },
     );
+    map.insert_without_duplicate(
+        "scan",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Keyspace, AclCategory::Read, AclCategory::Slow],
+            handler: &move |db, input| {
+                let mut value_type = None;
+                let args = parse_scan_args(&input, |option, rest| match (option, rest.next()) {
+                    ("type", Some(t)) => {
+                        value_type = Some(t.clone());
+                        Ok(())
+                    }
+                    _ => Err(OutputValue::Error(b"ERR syntax error".to_vec())),
+                });
+                match args {
+                    Ok((cursor, options)) => db.scan(cursor, options, value_type),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "expire",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Keyspace, AclCategory::Write, AclCategory::Fast],
+            handler: &move |db, input| expire_handler(db, input, "expire", 1000, false),
+        },
+    );
+    map.insert_without_duplicate(
+        "pexpire",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Keyspace, AclCategory::Write, AclCategory::Fast],
+            handler: &move |db, input| expire_handler(db, input, "pexpire", 1, false),
+        },
+    );
+    map.insert_without_duplicate(
+        "expireat",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Keyspace, AclCategory::Write, AclCategory::Fast],
+            handler: &move |db, input| expire_handler(db, input, "expireat", 1000, true),
+        },
+    );
+    map.insert_without_duplicate(
+        "pexpireat",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Keyspace, AclCategory::Write, AclCategory::Fast],
+            handler: &move |db, input| expire_handler(db, input, "pexpireat", 1, true),
+        },
+    );
+    map.insert_without_duplicate(
+        "ttl",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Keyspace, AclCategory::Read, AclCategory::Fast],
+            handler: &move |db, input| db.ttl(get_first(input), TtlFormat::Seconds),
+        },
+    );
+    map.insert_without_duplicate(
+        "pttl",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Keyspace, AclCategory::Read, AclCategory::Fast],
+            handler: &move |db, input| db.ttl(get_first(input), TtlFormat::Milliseconds),
+        },
+    );
+    map.insert_without_duplicate(
+        "expiretime",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Keyspace, AclCategory::Read, AclCategory::Fast],
+            handler: &move |db, input| db.ttl(get_first(input), TtlFormat::UnixSeconds),
+        },
+    );
+    map.insert_without_duplicate(
+        "pexpiretime",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Keyspace, AclCategory::Read, AclCategory::Fast],
+            handler: &move |db, input| db.ttl(get_first(input), TtlFormat::UnixMilliseconds),
+        },
+    );
+    map.insert_without_duplicate(
+        "persist",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Keyspace, AclCategory::Write, AclCategory::Fast],
+            handler: &move |db, input| db.persist(get_first(input)),
+        },
+    );
+    map.insert_without_duplicate(
+        "object",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Keyspace, AclCategory::Read, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (subcommand, rest) = input.split_first().unwrap();
+                match (subcommand.to_lower_string().as_deref(), rest) {
+                    (Some("encoding"), [key]) => db.object_encoding(key.as_slice()),
+                    (Some("encoding"), _) => OutputValue::Error(
+                        b"ERR wrong number of arguments for 'object|encoding'".to_vec(),
+                    ),
+                    _ => OutputValue::Error(b"ERR unknown subcommand for 'object'".to_vec()),
+                }
+            },
+        },
+    );
     map
 }
 
@@ -472,6 +956,15 @@ fn initialise_controller_commands() -> HashMap<&'static str, ControllerCommandDe
             },
         },
     );
+    map.insert_without_duplicate(
+        "info",
+        ControllerCommandDefinition {
+            arity_min: 0,
+            arity_max: None,
+            category: &[AclCategory::Slow, AclCategory::Dangerous],
+            handler: &move |input| Ok(Interrupt::Info(input)),
+        },
+    );
     map.insert_without_duplicate(
         "swapdb",
         ControllerCommandDefinition {
@@ -516,12 +1009,97 @@ fn initialise_string_commands<T: MapStringCommands>() -> HashMap<&'static str, S
         "set",
         SimpleCommand {
             arity_min: 2,
-            arity_max: Some(5),
+            arity_max: None,
             category: &[AclCategory::Write, AclCategory::String, AclCategory::Slow],
+            handler: &move |db, mut input| {
+                let (condition, get, ttl) = match parse_set_options(&input[2..]) {
+                    Ok(options) => options,
+                    Err(e) => return e,
+                };
+                input.truncate(2);
+                let (key, value) = get_first_two(input);
+                db.set_with_options(key, value, condition, get, ttl)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "getset",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Write, AclCategory::String, AclCategory::Fast],
             handler: &move |db, input| {
-                // TODO: support options
                 let (key, value) = get_first_two(input);
-                db.set(key, value)
+                db.getset(key, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "setnx",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Write, AclCategory::String, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, value) = get_first_two(input);
+                db.setnx(key, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "setex",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::String, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, seconds, value) = get_first_three(input);
+                match parse_expire_option("ex", &seconds, "setex") {
+                    Ok(ttl) => db.setex(key, ttl, value),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "psetex",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::String, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, milliseconds, value) = get_first_three(input);
+                match parse_expire_option("px", &milliseconds, "psetex") {
+                    Ok(ttl) => db.psetex(key, ttl, value),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "getdel",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Write, AclCategory::String, AclCategory::Fast],
+            handler: &move |db, input| {
+                let key = get_first(input);
+                db.getdel(key)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "getex",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::String, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let ttl = match parse_getex_options(&input[1..]) {
+                    Ok(ttl) => ttl,
+                    Err(e) => return e,
+                };
+                db.getex(input.swap_remove(0), ttl)
             },
         },
     );
@@ -590,6 +1168,58 @@ fn initialise_string_commands<T: MapStringCommands>() -> HashMap<&'static str, S
             },
         },
     );
+    for name in ["getrange", "substr"] {
+        map.insert_without_duplicate(
+            name,
+            SimpleCommand {
+                arity_min: 3,
+                arity_max: Some(3),
+                category: &[AclCategory::Read, AclCategory::String, AclCategory::Slow],
+                handler: &move |db, input| {
+                    let (key, start, end) = get_first_three(input);
+                    let (Some(start), Some(end)) = (start.parse_into(), end.parse_into()) else {
+                        return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                    };
+                    db.getrange(key, start, end)
+                },
+            },
+        );
+    }
+    map.insert_without_duplicate(
+        "setrange",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::String, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, offset, value) = get_first_three(input);
+                let Some(offset) = offset.parse_into::<i64>() else {
+                    return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                };
+                let Ok(offset) = usize::try_from(offset) else {
+                    return OutputValue::Error(b"ERR offset is out of range".to_vec());
+                };
+                db.setrange(key, offset, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "lcs",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::String, AclCategory::Slow],
+            handler: &move |db, mut input| {
+                let output = match parse_lcs_options(&input[2..]) {
+                    Ok(output) => output,
+                    Err(e) => return e,
+                };
+                input.truncate(2);
+                let (key1, key2) = get_first_two(input);
+                db.lcs(key1, key2, output)
+            },
+        },
+    );
     map.insert_without_duplicate(
         "incr",
         SimpleCommand {
@@ -652,8 +1282,8 @@ fn initialise_string_commands<T: MapStringCommands>() -> HashMap<&'static str, S
             category: &[AclCategory::Write, AclCategory::String, AclCategory::Fast],
             handler: &move |db, input| {
                 let (key, value) = get_first_two(input);
-                let Some(value) = value.parse_into() else {
-                    return OutputValue::Error(b"ERR value is not an floating number".to_vec());
+                let Some(value) = float::parse(&value) else {
+                    return OutputValue::Error(b"ERR value is not a valid float".to_vec());
                 };
                 db.incrbyfloat(key, value)
             },
@@ -662,24 +1292,6 @@ fn initialise_string_commands<T: MapStringCommands>() -> HashMap<&'static str, S
     map
 }
 
-fn initialise_list_commands<T>() -> HashMap<&'static str, SimpleCommand<T>> {
-    let mut map = HashMap::new();
-    // TODO
-    map
-}
-
-fn initialise_hash_commands<T>() -> HashMap<&'static str, SimpleCommand<T>> {
-    let mut map = HashMap::new();
-    // TODO
-    map
-}
-
-fn initialise_set_commands<T>() -> HashMap<&'static str, SimpleCommand<T>> {
-    let mut map = HashMap::new();
-    // TODO
-    map
-}
-
 fn initialise_container_commands() -> HashMap<&'static str, ContainerCommand> {
     let mut map = HashMap::new();
     map.insert_without_duplicate(
@@ -874,9 +1486,26 @@ fn initialise_container_commands() -> HashMap<&'static str, ContainerCommand> {
                             AclCategory::Slow,
                             AclCategory::Dangerous,
                         ],
-                        handler: &move |_| {
-                            // TODO: just for test
-                            Err(OutputValue::NullArray)
+                        handler: &move |input| Ok(Interrupt::ConfigGet(input)),
+                    },
+                );
+                map.insert_without_duplicate(
+                    "set",
+                    ControllerCommandDefinition {
+                        arity_min: 2,
+                        arity_max: None,
+                        category: &[
+                            AclCategory::Admin,
+                            AclCategory::Slow,
+                            AclCategory::Dangerous,
+                        ],
+                        handler: &move |input| {
+                            if !input.len().is_multiple_of(2) {
+                                return Err(OutputValue::Error(
+                                    b"ERR wrong number of arguments for 'config set'".to_vec(),
+                                ));
+                            }
+                            Ok(Interrupt::ConfigSet(input))
                         },
                     },
                 );
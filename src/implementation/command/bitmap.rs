@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use super::{get_first_two, HashMapExt, SimpleCommand};
+use crate::bstr::BStr;
+use crate::implementation::acl::AclCategory;
+use crate::interface::database::map::{
+    BitOperation, BitRange, BitUnit, BitfieldOperation, BitfieldOverflow, BitfieldSubcommand,
+    BitfieldType, MapBitmapCommands,
+};
+use crate::interface::types::{InputValue, OutputValue};
+
+/// Bitmaps are limited to 512MB, like any other string.
+const MAX_BIT_OFFSET: u64 = 512 * 1024 * 1024 * 8;
+
+fn bit_offset_error() -> OutputValue {
+    OutputValue::Error(b"ERR bit offset is not an integer or out of range".to_vec())
+}
+
+pub(super) fn parse_bit_offset(input: &[u8]) -> Result<u64, OutputValue> {
+    input
+        .parse_into::<u64>()
+        .filter(|offset| *offset < MAX_BIT_OFFSET)
+        .ok_or_else(bit_offset_error)
+}
+
+/// Parses a BITFIELD offset, which counts fields rather than bits when prefixed with `#`.
+fn parse_bitfield_offset(input: &[u8], field: BitfieldType) -> Result<u64, OutputValue> {
+    match input.strip_prefix(b"#") {
+        Some(index) => index
+            .parse_into::<u64>()
+            .and_then(|index| index.checked_mul(field.bits as u64))
+            .filter(|offset| *offset < MAX_BIT_OFFSET)
+            .ok_or_else(bit_offset_error),
+        None => parse_bit_offset(input),
+    }
+}
+
+fn parse_bitfield_type(input: &[u8]) -> Result<BitfieldType, OutputValue> {
+    let field = match input.split_first() {
+        Some((b'i' | b'I', bits)) => bits
+            .parse_into()
+            .filter(|bits| (1..=64).contains(bits))
+            .map(|bits| BitfieldType { signed: true, bits }),
+        Some((b'u' | b'U', bits)) => bits
+            .parse_into()
+            .filter(|bits| (1..=63).contains(bits))
+            .map(|bits| BitfieldType {
+                signed: false,
+                bits,
+            }),
+        _ => None,
+    };
+    field.ok_or_else(|| {
+        OutputValue::Error(
+            b"ERR Invalid bitfield type. Use something like i16 u8. \
+              Note that u64 is not supported but i64 is."
+                .to_vec(),
+        )
+    })
+}
+
+/// Parses the subcommands of BITFIELD, each OVERFLOW applying to those that follow it.
+fn parse_bitfield(input: &[InputValue]) -> Result<Vec<BitfieldSubcommand>, OutputValue> {
+    let syntax_error = || OutputValue::Error(b"ERR syntax error".to_vec());
+    let mut input = input.iter();
+    let mut overflow = BitfieldOverflow::Wrap;
+    let mut subcommands = vec![];
+    while let Some(name) = input.next() {
+        let name = name.to_lower_string();
+        if name.as_deref() == Some("overflow") {
+            overflow = match input
+                .next()
+                .ok_or_else(syntax_error)?
+                .to_lower_string()
+                .as_deref()
+            {
+                Some("wrap") => BitfieldOverflow::Wrap,
+                Some("sat") => BitfieldOverflow::Sat,
+                Some("fail") => BitfieldOverflow::Fail,
+                _ => {
+                    return Err(OutputValue::Error(
+                        b"ERR Invalid OVERFLOW type specified".to_vec(),
+                    ))
+                }
+            };
+            continue;
+        }
+        let takes_value = match name.as_deref() {
+            Some("get") => false,
+            Some("set" | "incrby") => true,
+            _ => return Err(syntax_error()),
+        };
+        let (Some(field), Some(offset)) = (input.next(), input.next()) else {
+            return Err(syntax_error());
+        };
+        let field = parse_bitfield_type(field)?;
+        let offset = parse_bitfield_offset(offset, field)?;
+        let operation = if takes_value {
+            let value = input
+                .next()
+                .ok_or_else(syntax_error)?
+                .parse_into()
+                .ok_or_else(|| OutputValue::Error(b"ERR value is not an integer".to_vec()))?;
+            if name.as_deref() == Some("set") {
+                BitfieldOperation::Set(value)
+            } else {
+                BitfieldOperation::IncrBy(value)
+            }
+        } else {
+            BitfieldOperation::Get
+        };
+        subcommands.push(BitfieldSubcommand {
+            operation,
+            field,
+            offset,
+            overflow,
+        });
+    }
+    Ok(subcommands)
+}
+
+fn parse_index(input: &[u8]) -> Result<i64, OutputValue> {
+    input
+        .parse_into()
+        .ok_or_else(|| OutputValue::Error(b"ERR value is not an integer".to_vec()))
+}
+
+/// Parses `[start [end [BYTE|BIT]]]`, shared by BITCOUNT and BITPOS.
+fn parse_bit_range(input: &[InputValue]) -> Result<Option<BitRange>, OutputValue> {
+    let Some(start) = input.first() else {
+        return Ok(None);
+    };
+    let start = parse_index(start)?;
+    let end = input.get(1).map(|end| parse_index(end)).transpose()?;
+    let unit = match input.get(2).map(|unit| unit.to_lower_string()) {
+        None => BitUnit::Byte,
+        Some(unit) => match unit.as_deref() {
+            Some("byte") => BitUnit::Byte,
+            Some("bit") => BitUnit::Bit,
+            _ => return Err(OutputValue::Error(b"ERR syntax error".to_vec())),
+        },
+    };
+    Ok(Some(BitRange { start, end, unit }))
+}
+
+fn parse_bit_operation(input: &[u8]) -> Option<BitOperation> {
+    match input.to_lower_string()?.as_str() {
+        "and" => Some(BitOperation::And),
+        "or" => Some(BitOperation::Or),
+        "xor" => Some(BitOperation::Xor),
+        "not" => Some(BitOperation::Not),
+        "diff" => Some(BitOperation::Diff),
+        "andor" => Some(BitOperation::AndOr),
+        "one" => Some(BitOperation::One),
+        _ => None,
+    }
+}
+
+pub(super) fn initialise_bitmap_commands<T: MapBitmapCommands>(
+) -> HashMap<&'static str, SimpleCommand<T>> {
+    let mut map = HashMap::<_, SimpleCommand<T>>::new();
+    map.insert_without_duplicate(
+        "setbit",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::Bitmap, AclCategory::Slow],
+            handler: &move |db, input| {
+                let offset = match parse_bit_offset(&input[1]) {
+                    Ok(offset) => offset,
+                    Err(e) => return e,
+                };
+                let value = match input[2].as_slice() {
+                    b"0" => false,
+                    b"1" => true,
+                    _ => {
+                        return OutputValue::Error(
+                            b"ERR bit is not an integer or out of range".to_vec(),
+                        )
+                    }
+                };
+                db.setbit(input[0].as_slice(), offset, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "getbit",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Read, AclCategory::Bitmap, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, offset) = get_first_two(input);
+                match parse_bit_offset(&offset) {
+                    Ok(offset) => db.getbit(key, offset),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "bitcount",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(4),
+            category: &[AclCategory::Read, AclCategory::Bitmap, AclCategory::Slow],
+            handler: &move |db, input| {
+                // a start needs an end
+                if input.len() == 2 {
+                    return OutputValue::Error(b"ERR syntax error".to_vec());
+                }
+                match parse_bit_range(&input[1..]) {
+                    Ok(range) => db.bitcount(input[0].as_slice(), range),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "bitpos",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(5),
+            category: &[AclCategory::Read, AclCategory::Bitmap, AclCategory::Slow],
+            handler: &move |db, input| {
+                let bit = match input[1].parse_into::<i64>() {
+                    Some(0) => false,
+                    Some(1) => true,
+                    Some(_) => {
+                        return OutputValue::Error(b"ERR The bit argument must be 1 or 0.".to_vec())
+                    }
+                    None => return OutputValue::Error(b"ERR value is not an integer".to_vec()),
+                };
+                match parse_bit_range(&input[2..]) {
+                    Ok(range) => db.bitpos(input[0].as_slice(), bit, range),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "bitop",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Bitmap, AclCategory::Slow],
+            handler: &move |db, input| {
+                let mut input = input.into_iter();
+                let name = input.next().unwrap();
+                let Some(operation) = parse_bit_operation(&name) else {
+                    return OutputValue::Error(b"ERR syntax error".to_vec());
+                };
+                let destination = input.next().unwrap();
+                let sources: Vec<_> = input.collect();
+                match operation {
+                    BitOperation::Not if sources.len() != 1 => {
+                        return OutputValue::Error(
+                            b"ERR BITOP NOT must be called with a single source key.".to_vec(),
+                        )
+                    }
+                    BitOperation::Diff | BitOperation::AndOr if sources.len() < 2 => {
+                        return OutputValue::Error(
+                            format!(
+                                "ERR BITOP {} must be called with at least two source keys.",
+                                name.to_str().unwrap_or_default().to_ascii_uppercase()
+                            )
+                            .into_bytes(),
+                        )
+                    }
+                    _ => {}
+                }
+                db.bitop(operation, destination, sources)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "bitfield",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Bitmap, AclCategory::Slow],
+            handler: &move |db, input| match parse_bitfield(&input[1..]) {
+                Ok(subcommands) => db.bitfield(input[0].as_slice(), subcommands),
+                Err(e) => e,
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "bitfield_ro",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Bitmap, AclCategory::Fast],
+            handler: &move |db, input| {
+                let subcommands = match parse_bitfield(&input[1..]) {
+                    Ok(subcommands) => subcommands,
+                    Err(e) => return e,
+                };
+                if subcommands
+                    .iter()
+                    .any(|s| s.operation != BitfieldOperation::Get)
+                {
+                    return OutputValue::Error(
+                        b"ERR BITFIELD_RO only supports the GET subcommand".to_vec(),
+                    );
+                }
+                db.bitfield_ro(input[0].as_slice(), subcommands)
+            },
+        },
+    );
+    map
+}
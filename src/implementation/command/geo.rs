@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use super::sorted_set::parse_score;
+use super::{HashMapExt, SimpleCommand};
+use crate::bstr::BStr;
+use crate::implementation::acl::AclCategory;
+use crate::interface::database::map::{
+    GeoOrigin, GeoSearch, GeoShape, GeoSort, GeoUnit, MapGeoCommands, SetCondition, ZaddOptions,
+};
+use crate::interface::types::{InputValue, OutputValue};
+
+fn parse_geo_unit(input: &[u8]) -> Result<GeoUnit, OutputValue> {
+    match input.to_lower_string().as_deref() {
+        Some("m") => Ok(GeoUnit::Meters),
+        Some("km") => Ok(GeoUnit::Kilometers),
+        Some("ft") => Ok(GeoUnit::Feet),
+        Some("mi") => Ok(GeoUnit::Miles),
+        _ => Err(OutputValue::Error(
+            b"ERR unsupported unit provided. please use M, KM, FT, MI".to_vec(),
+        )),
+    }
+}
+
+fn parse_coordinate(input: &[u8]) -> Result<f64, OutputValue> {
+    parse_score(input).ok_or_else(|| OutputValue::Error(b"ERR value is not a valid float".to_vec()))
+}
+
+fn parse_distance(input: &[u8], error: &[u8]) -> Result<f64, OutputValue> {
+    parse_score(input).ok_or_else(|| OutputValue::Error(error.to_vec()))
+}
+
+/// Parses the options of GEOSEARCH, or of GEOSEARCHSTORE when `storing`, which also accepts
+/// STOREDIST. Returns the search and whether STOREDIST was given.
+fn parse_geosearch(
+    input: &[InputValue],
+    command: &str,
+    storing: bool,
+) -> Result<(GeoSearch, bool), OutputValue> {
+    let syntax_error = || OutputValue::Error(b"ERR syntax error".to_vec());
+    // each of these may be given once, and only one of each pair
+    let (mut from_member, mut from_lonlat) = (None, None);
+    let (mut by_radius, mut by_box, mut unit) = (None, None, GeoUnit::Meters);
+    let (mut sort, mut count, mut any) = (None, None, false);
+    let (mut with_coord, mut with_dist, mut with_hash) = (false, false, false);
+    let mut store_dist = false;
+    let mut i = 0;
+    while i < input.len() {
+        let remaining = input.len() - i - 1;
+        match input[i].to_lower_string().as_deref() {
+            Some("withcoord") => with_coord = true,
+            Some("withdist") => with_dist = true,
+            Some("withhash") => with_hash = true,
+            Some("storedist") if storing => store_dist = true,
+            Some("any") => any = true,
+            Some("asc") => sort = Some(GeoSort::Asc),
+            Some("desc") => sort = Some(GeoSort::Desc),
+            Some("count") if remaining >= 1 => {
+                count = match input[i + 1].parse_into::<i64>() {
+                    Some(count) if count > 0 => Some(count as usize),
+                    Some(_) => return Err(OutputValue::Error(b"ERR COUNT must be > 0".to_vec())),
+                    None => {
+                        return Err(OutputValue::Error(b"ERR value is not an integer".to_vec()))
+                    }
+                };
+                i += 1;
+            }
+            Some("frommember") if remaining >= 1 && from_member.is_none() => {
+                from_member = Some(GeoOrigin::Member(input[i + 1].clone()));
+                i += 1;
+            }
+            Some("fromlonlat") if remaining >= 2 && from_lonlat.is_none() => {
+                let longitude = parse_coordinate(&input[i + 1])?;
+                let latitude = parse_coordinate(&input[i + 2])?;
+                from_lonlat = Some(GeoOrigin::LonLat(longitude, latitude));
+                i += 2;
+            }
+            Some("byradius") if remaining >= 2 && by_radius.is_none() => {
+                let radius = parse_distance(&input[i + 1], b"ERR need numeric radius")?;
+                if radius < 0.0 {
+                    return Err(OutputValue::Error(
+                        b"ERR radius cannot be negative".to_vec(),
+                    ));
+                }
+                unit = parse_geo_unit(&input[i + 2])?;
+                by_radius = Some(GeoShape::Radius(radius));
+                i += 2;
+            }
+            Some("bybox") if remaining >= 3 && by_box.is_none() => {
+                let width = parse_distance(&input[i + 1], b"ERR need numeric width")?;
+                let height = parse_distance(&input[i + 2], b"ERR need numeric height")?;
+                if width < 0.0 || height < 0.0 {
+                    return Err(OutputValue::Error(
+                        b"ERR height or width cannot be negative".to_vec(),
+                    ));
+                }
+                unit = parse_geo_unit(&input[i + 3])?;
+                by_box = Some(GeoShape::Box { width, height });
+                i += 3;
+            }
+            _ => return Err(syntax_error()),
+        }
+        i += 1;
+    }
+    let ((Some(origin), None) | (None, Some(origin))) = (from_member, from_lonlat) else {
+        return Err(OutputValue::Error(
+            format!(
+                "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for {}",
+                command
+            )
+            .into_bytes(),
+        ));
+    };
+    let ((Some(shape), None) | (None, Some(shape))) = (by_radius, by_box) else {
+        return Err(OutputValue::Error(
+            format!(
+                "ERR exactly one of BYRADIUS and BYBOX can be specified for {}",
+                command
+            )
+            .into_bytes(),
+        ));
+    };
+    if storing && (with_coord || with_dist || with_hash) {
+        return Err(OutputValue::Error(
+            b"ERR GEOSEARCHSTORE is not compatible with WITHDIST, WITHHASH and WITHCOORD options"
+                .to_vec(),
+        ));
+    }
+    if any && count.is_none() {
+        return Err(OutputValue::Error(
+            b"ERR the ANY argument requires COUNT argument".to_vec(),
+        ));
+    }
+    let search = GeoSearch {
+        origin,
+        shape,
+        unit,
+        sort,
+        count,
+        any,
+        with_coord,
+        with_dist,
+        with_hash,
+    };
+    Ok((search, store_dist))
+}
+
+fn geoadd_handler<T: MapGeoCommands>(db: &mut T, input: Vec<InputValue>) -> OutputValue {
+    let mut input = input.into_iter().peekable();
+    let key = input.next().unwrap();
+    let mut options = ZaddOptions::default();
+    let (mut nx, mut xx) = (false, false);
+    while let Some(option) = input.peek().and_then(|o| o.to_lower_string()) {
+        match option.as_str() {
+            "nx" => nx = true,
+            "xx" => xx = true,
+            "ch" => options.changed = true,
+            _ => break,
+        }
+        input.next();
+    }
+    let rest: Vec<_> = input.collect();
+    if nx && xx {
+        return OutputValue::Error(
+            b"ERR XX and NX options at the same time are not compatible".to_vec(),
+        );
+    }
+    if rest.is_empty() || !rest.len().is_multiple_of(3) {
+        return OutputValue::Error(b"ERR syntax error".to_vec());
+    }
+    options.condition = match (nx, xx) {
+        (true, _) => SetCondition::Nx,
+        (_, true) => SetCondition::Xx,
+        _ => SetCondition::Always,
+    };
+    let mut points = Vec::with_capacity(rest.len() / 3);
+    let mut rest = rest.into_iter();
+    while let (Some(longitude), Some(latitude), Some(member)) =
+        (rest.next(), rest.next(), rest.next())
+    {
+        let (longitude, latitude) =
+            match (parse_coordinate(&longitude), parse_coordinate(&latitude)) {
+                (Ok(longitude), Ok(latitude)) => (longitude, latitude),
+                (Err(e), _) | (_, Err(e)) => return e,
+            };
+        points.push((longitude, latitude, member));
+    }
+    db.geoadd(key, options, points)
+}
+
+pub(super) fn initialise_geo_commands<T: MapGeoCommands>() -> HashMap<&'static str, SimpleCommand<T>>
+{
+    let mut map = HashMap::<_, SimpleCommand<T>>::new();
+    map.insert_without_duplicate(
+        "geoadd",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Geo, AclCategory::Slow],
+            handler: &move |db, input| geoadd_handler(db, input),
+        },
+    );
+    map.insert_without_duplicate(
+        "geopos",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Geo, AclCategory::Slow],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.geopos(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "geodist",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(4),
+            category: &[AclCategory::Read, AclCategory::Geo, AclCategory::Slow],
+            handler: &move |db, input| {
+                let mut input = input.into_iter();
+                let (key, member1, member2) = (
+                    input.next().unwrap(),
+                    input.next().unwrap(),
+                    input.next().unwrap(),
+                );
+                let unit = match input.next().map(|unit| parse_geo_unit(&unit)) {
+                    None => GeoUnit::Meters,
+                    Some(Ok(unit)) => unit,
+                    Some(Err(e)) => return e,
+                };
+                db.geodist(key, member1, member2, unit)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "geohash",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Geo, AclCategory::Slow],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.geohash(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "geosearch",
+        SimpleCommand {
+            arity_min: 6,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Geo, AclCategory::Slow],
+            handler: &move |db, input| match parse_geosearch(&input[1..], "GEOSEARCH", false) {
+                Ok((search, _)) => db.geosearch(input[0].as_slice(), search),
+                Err(e) => e,
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "geosearchstore",
+        SimpleCommand {
+            arity_min: 7,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Geo, AclCategory::Slow],
+            handler: &move |db, input| match parse_geosearch(&input[2..], "GEOSEARCHSTORE", true) {
+                Ok((search, store_dist)) => {
+                    db.geosearchstore(input[0].as_slice(), input[1].as_slice(), search, store_dist)
+                }
+                Err(e) => e,
+            },
+        },
+    );
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementation::database::Map;
+
+    fn run(map: &mut Map, command: &str, items: &[&str]) -> OutputValue {
+        let commands = initialise_geo_commands::<Map>();
+        let input = items.iter().map(|item| item.as_bytes().to_vec()).collect();
+        (commands[command].handler)(map, input)
+    }
+
+    #[test]
+    fn test_geoadd_rejects_nx_with_xx() {
+        let mut map = Map::default();
+        assert_eq!(
+            run(
+                &mut map,
+                "geoadd",
+                &["k", "nx", "xx", "13.361389", "38.115556", "a"]
+            ),
+            OutputValue::Error(
+                b"ERR XX and NX options at the same time are not compatible".to_vec()
+            )
+        );
+        assert_eq!(
+            run(&mut map, "geoadd", &["k", "nx", "13.361389", "38.115556"]),
+            OutputValue::Error(b"ERR syntax error".to_vec())
+        );
+        assert_eq!(
+            run(
+                &mut map,
+                "geoadd",
+                &["k", "nx", "13.361389", "38.115556", "a"]
+            ),
+            OutputValue::Integer(1)
+        );
+    }
+
+    #[test]
+    fn test_geosearch_needs_exactly_one_shape_and_origin() {
+        let mut map = Map::default();
+        let one_shape = OutputValue::Error(
+            b"ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH".to_vec(),
+        );
+        assert_eq!(
+            run(
+                &mut map,
+                "geosearch",
+                &["k", "fromlonlat", "15", "37", "asc"]
+            ),
+            one_shape
+        );
+        assert_eq!(
+            run(
+                &mut map,
+                "geosearch",
+                &[
+                    "k",
+                    "frommember",
+                    "a",
+                    "byradius",
+                    "1",
+                    "km",
+                    "bybox",
+                    "1",
+                    "1",
+                    "km"
+                ]
+            ),
+            one_shape
+        );
+        assert_eq!(
+            run(
+                &mut map,
+                "geosearch",
+                &[
+                    "k",
+                    "frommember",
+                    "a",
+                    "fromlonlat",
+                    "15",
+                    "37",
+                    "byradius",
+                    "1",
+                    "km"
+                ]
+            ),
+            OutputValue::Error(
+                b"ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH"
+                    .to_vec()
+            )
+        );
+        assert_eq!(
+            run(
+                &mut map,
+                "geosearch",
+                &["k", "fromlonlat", "15", "37", "byradius", "1", "km"]
+            ),
+            OutputValue::Array(vec![])
+        );
+    }
+}
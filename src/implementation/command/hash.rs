@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+
+use super::{
+    count_out_of_range, get_first, get_first_three, get_first_two, parse_expire_option,
+    parse_scan_args, HashMapExt, SimpleCommand,
+};
+use crate::bstr::BStr;
+use crate::implementation::acl::AclCategory;
+use crate::implementation::database::float;
+use crate::interface::database::map::{
+    ExpireCondition, ExpireTime, MapHashCommands, SetCondition, TtlFormat, TtlUpdate,
+};
+use crate::interface::types::{InputValue, OutputValue};
+
+/// Parses `FIELDS numfields field [field ...]`, or `field value` pairs if `with_values`.
+fn parse_fields(input: &[InputValue], with_values: bool) -> Result<Vec<Vec<u8>>, OutputValue> {
+    let Some((fields_token, rest)) = input.split_first() else {
+        return Err(OutputValue::Error(
+            b"ERR Mandatory argument FIELDS is missing or not at the right position".to_vec(),
+        ));
+    };
+    if fields_token.to_lower_string().as_deref() != Some("fields") {
+        return Err(OutputValue::Error(
+            b"ERR Mandatory argument FIELDS is missing or not at the right position".to_vec(),
+        ));
+    }
+    let Some(numfields) = rest
+        .first()
+        .and_then(|n| n.parse_into::<i64>())
+        .filter(|n| *n > 0)
+    else {
+        return Err(OutputValue::Error(
+            b"ERR Number of fields must be a positive integer".to_vec(),
+        ));
+    };
+    let per_field = if with_values { 2 } else { 1 };
+    if (rest.len() - 1) as i64 != numfields.saturating_mul(per_field) {
+        return Err(OutputValue::Error(
+            b"ERR The `numfields` parameter must match the number of arguments".to_vec(),
+        ));
+    }
+    Ok(rest[1..].to_vec())
+}
+
+fn hexpire_handler<T: MapHashCommands>(
+    db: &mut T,
+    input: Vec<InputValue>,
+    command: &str,
+    unit_ms: i64,
+    absolute: bool,
+) -> OutputValue {
+    let Some(n) = input[1].parse_into::<i64>() else {
+        return OutputValue::Error(b"ERR value is not an integer".to_vec());
+    };
+    let Some(ms) = n.checked_mul(unit_ms).filter(|ms| *ms >= 0) else {
+        return OutputValue::Error(
+            format!("ERR invalid expire time in '{}' command", command).into_bytes(),
+        );
+    };
+    let (condition, rest) = match input[2].to_lower_string().as_deref() {
+        Some("nx") => (ExpireCondition::Nx, &input[3..]),
+        Some("xx") => (ExpireCondition::Xx, &input[3..]),
+        Some("gt") => (ExpireCondition::Gt, &input[3..]),
+        Some("lt") => (ExpireCondition::Lt, &input[3..]),
+        _ => (ExpireCondition::Always, &input[2..]),
+    };
+    let fields = match parse_fields(rest, false) {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+    let at = if absolute {
+        ExpireTime::Absolute(ms)
+    } else {
+        ExpireTime::Relative(ms)
+    };
+    db.hexpire(input[0].as_slice(), at, condition, fields, command)
+}
+
+fn httl_handler<T: MapHashCommands>(
+    db: &mut T,
+    input: Vec<InputValue>,
+    format: TtlFormat,
+) -> OutputValue {
+    match parse_fields(&input[1..], false) {
+        Ok(fields) => db.httl(input[0].as_slice(), format, fields),
+        Err(e) => e,
+    }
+}
+
+/// Parses the options of HGETEX or HSETEX that precede the FIELDS token.
+fn parse_field_ttl_options<'a>(
+    mut input: &'a [InputValue],
+    command: &str,
+) -> Result<(SetCondition, Option<TtlUpdate>, &'a [InputValue]), OutputValue> {
+    let mut condition = SetCondition::Always;
+    let mut ttl = None;
+    while let Some(option) = input.first().and_then(|o| o.to_lower_string()) {
+        let consumed = match option.as_str() {
+            "fields" => break,
+            "fnx" | "fxx" if command == "hsetex" && condition == SetCondition::Always => {
+                condition = if option == "fnx" {
+                    SetCondition::Nx
+                } else {
+                    SetCondition::Xx
+                };
+                1
+            }
+            "ex" | "px" | "exat" | "pxat" if ttl.is_none() => {
+                let Some(value) = input.get(1) else {
+                    return Err(OutputValue::Error(b"ERR syntax error".to_vec()));
+                };
+                ttl = Some(TtlUpdate::Expire(parse_expire_option(
+                    option.as_str(),
+                    value,
+                    command,
+                )?));
+                2
+            }
+            "persist" if command == "hgetex" && ttl.is_none() => {
+                ttl = Some(TtlUpdate::Persist);
+                1
+            }
+            "keepttl" if command == "hsetex" && ttl.is_none() => {
+                ttl = Some(TtlUpdate::Keep);
+                1
+            }
+            _ => return Err(OutputValue::Error(b"ERR syntax error".to_vec())),
+        };
+        input = &input[consumed..];
+    }
+    Ok((condition, ttl, input))
+}
+
+pub(super) fn initialise_hash_commands<T: MapHashCommands>(
+) -> HashMap<&'static str, SimpleCommand<T>> {
+    let mut map = HashMap::<_, SimpleCommand<T>>::new();
+    map.insert_without_duplicate(
+        "hset",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                if !input.len().is_multiple_of(2) {
+                    return OutputValue::Error(
+                        b"ERR wrong number of arguments for 'hset'".to_vec(),
+                    );
+                }
+                db.hset(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hmset",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                if !input.len().is_multiple_of(2) {
+                    return OutputValue::Error(
+                        b"ERR wrong number of arguments for 'hmset'".to_vec(),
+                    );
+                }
+                match db.hset(key, input) {
+                    OutputValue::Integer(_) => OutputValue::Ok,
+                    e => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hsetnx",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, field, value) = get_first_three(input);
+                db.hsetnx(key, field, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hget",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, field) = get_first_two(input);
+                db.hget(key, field)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hmget",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.hmget(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hdel",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.hdel(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hlen",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| {
+                let key = get_first(input);
+                db.hlen(key)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hexists",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, field) = get_first_two(input);
+                db.hexists(key, field)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hkeys",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Slow],
+            handler: &move |db, input| {
+                let key = get_first(input);
+                db.hkeys(key)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hvals",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Slow],
+            handler: &move |db, input| {
+                let key = get_first(input);
+                db.hvals(key)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hgetall",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Slow],
+            handler: &move |db, input| {
+                let key = get_first(input);
+                db.hgetall(key)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hstrlen",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, field) = get_first_two(input);
+                db.hstrlen(key, field)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hincrby",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, field, value) = get_first_three(input);
+                let Some(value) = value.parse_into() else {
+                    return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                };
+                db.hincrby(key, field, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hincrbyfloat",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, field, value) = get_first_three(input);
+                let Some(value) = float::parse(&value) else {
+                    return OutputValue::Error(b"ERR value is not a valid float".to_vec());
+                };
+                db.hincrbyfloat(key, field, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hexpire",
+        SimpleCommand {
+            arity_min: 5,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| hexpire_handler(db, input, "hexpire", 1000, false),
+        },
+    );
+    map.insert_without_duplicate(
+        "hpexpire",
+        SimpleCommand {
+            arity_min: 5,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| hexpire_handler(db, input, "hpexpire", 1, false),
+        },
+    );
+    map.insert_without_duplicate(
+        "hexpireat",
+        SimpleCommand {
+            arity_min: 5,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| hexpire_handler(db, input, "hexpireat", 1000, true),
+        },
+    );
+    map.insert_without_duplicate(
+        "hpexpireat",
+        SimpleCommand {
+            arity_min: 5,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| hexpire_handler(db, input, "hpexpireat", 1, true),
+        },
+    );
+    map.insert_without_duplicate(
+        "httl",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| httl_handler(db, input, TtlFormat::Seconds),
+        },
+    );
+    map.insert_without_duplicate(
+        "hpttl",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| httl_handler(db, input, TtlFormat::Milliseconds),
+        },
+    );
+    map.insert_without_duplicate(
+        "hexpiretime",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| httl_handler(db, input, TtlFormat::UnixSeconds),
+        },
+    );
+    map.insert_without_duplicate(
+        "hpexpiretime",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| httl_handler(db, input, TtlFormat::UnixMilliseconds),
+        },
+    );
+    map.insert_without_duplicate(
+        "hpersist",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| match parse_fields(&input[1..], false) {
+                Ok(fields) => db.hpersist(input[0].as_slice(), fields),
+                Err(e) => e,
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hgetdel",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| match parse_fields(&input[1..], false) {
+                Ok(fields) => db.hgetdel(input[0].as_slice(), fields),
+                Err(e) => e,
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hgetex",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (_, ttl, rest) = match parse_field_ttl_options(&input[1..], "hgetex") {
+                    Ok(options) => options,
+                    Err(e) => return e,
+                };
+                match parse_fields(rest, false) {
+                    Ok(fields) => {
+                        db.hgetex(input[0].as_slice(), ttl.unwrap_or(TtlUpdate::Keep), fields)
+                    }
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hsetex",
+        SimpleCommand {
+            arity_min: 5,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Hash, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (condition, ttl, rest) = match parse_field_ttl_options(&input[1..], "hsetex") {
+                    Ok(options) => options,
+                    Err(e) => return e,
+                };
+                match parse_fields(rest, true) {
+                    Ok(field_values) => db.hsetex(
+                        input[0].as_slice(),
+                        condition,
+                        ttl.unwrap_or(TtlUpdate::Persist),
+                        field_values,
+                    ),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hrandfield",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(3),
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Slow],
+            handler: &move |db, input| {
+                let mut input = input.into_iter();
+                let key = input.next().unwrap();
+                let count = match input.next().map(|c| c.parse_into::<i64>()) {
+                    None => None,
+                    Some(Some(c)) => Some(c),
+                    Some(None) => {
+                        return OutputValue::Error(b"ERR value is not an integer".to_vec())
+                    }
+                };
+                if count == Some(i64::MIN) {
+                    return count_out_of_range();
+                }
+                let with_values = match input.next() {
+                    None => false,
+                    Some(v) if v.to_lower_string().as_deref() == Some("withvalues") => true,
+                    Some(_) => return OutputValue::Error(b"ERR syntax error".to_vec()),
+                };
+                // as in Redis, so that the number of items in the reply cannot overflow
+                if with_values && count.is_some_and(|c| c.unsigned_abs() > i64::MAX as u64 / 2) {
+                    return OutputValue::Error(b"ERR value is out of range".to_vec());
+                }
+                db.hrandfield(key, count, with_values)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "hscan",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Hash, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, rest) = input.split_first().unwrap();
+                let mut with_values = true;
+                let args = parse_scan_args(rest, |option, _| match option {
+                    "novalues" => {
+                        with_values = false;
+                        Ok(())
+                    }
+                    _ => Err(OutputValue::Error(b"ERR syntax error".to_vec())),
+                });
+                match args {
+                    Ok((cursor, options)) => db.hscan(key.as_slice(), cursor, options, with_values),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use super::{HashMapExt, SimpleCommand};
+use crate::implementation::acl::AclCategory;
+use crate::interface::database::map::MapHyperLogLogCommands;
+
+pub(super) fn initialise_hyperloglog_commands<T: MapHyperLogLogCommands>(
+) -> HashMap<&'static str, SimpleCommand<T>> {
+    let mut map = HashMap::<_, SimpleCommand<T>>::new();
+    map.insert_without_duplicate(
+        "pfadd",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::HyperLogLog,
+                AclCategory::Fast,
+            ],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.pfadd(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "pfcount",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[
+                AclCategory::Read,
+                AclCategory::HyperLogLog,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, input| db.pfcount(input),
+        },
+    );
+    map.insert_without_duplicate(
+        "pfmerge",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::HyperLogLog,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, mut input| {
+                let destination = input.remove(0);
+                db.pfmerge(destination, input)
+            },
+        },
+    );
+    map
+}
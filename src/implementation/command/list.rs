@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+
+use super::{
+    get_first, get_first_three, get_first_two, parse_mpop_args, BlockingCommand, HashMapExt,
+    SimpleCommand,
+};
+use crate::bstr::BStr;
+use crate::implementation::acl::AclCategory;
+use crate::implementation::blocking::{parse_timeout, Blocking};
+use crate::interface::database::map::{ListEnd, MapListCommands};
+use crate::interface::types::{InputValue, OutputValue};
+
+fn parse_list_end(input: &[u8]) -> Option<ListEnd> {
+    match input.to_lower_string()?.as_str() {
+        "left" => Some(ListEnd::Left),
+        "right" => Some(ListEnd::Right),
+        _ => None,
+    }
+}
+
+fn parse_pop_count(input: &[u8]) -> Result<usize, OutputValue> {
+    match input.parse_into::<i64>() {
+        None => Err(OutputValue::Error(b"ERR value is not an integer".to_vec())),
+        Some(i) if i < 0 => Err(OutputValue::Error(
+            b"ERR value is out of range, must be positive".to_vec(),
+        )),
+        Some(i) => Ok(i as usize),
+    }
+}
+
+fn pop_handler<T: MapListCommands>(
+    db: &mut T,
+    input: Vec<Vec<u8>>,
+    pop: fn(&mut T, Vec<u8>, Option<usize>) -> OutputValue,
+) -> OutputValue {
+    let mut input = input.into_iter();
+    let key = input.next().unwrap();
+    let count = match input.next().map(|c| parse_pop_count(&c)) {
+        None => None,
+        Some(Ok(c)) => Some(c),
+        Some(Err(e)) => return e,
+    };
+    pop(db, key, count)
+}
+
+fn lpos_handler<T: MapListCommands>(db: &mut T, input: Vec<Vec<u8>>) -> OutputValue {
+    let mut input = input.into_iter();
+    let key = input.next().unwrap();
+    let element = input.next().unwrap();
+    let mut rank = 1;
+    let mut count = None;
+    let mut maxlen = 0;
+    while let Some(option) = input.next() {
+        let Some(value) = input.next() else {
+            return OutputValue::Error(b"ERR syntax error".to_vec());
+        };
+        let Some(value) = value.parse_into::<i64>() else {
+            return OutputValue::Error(b"ERR value is not an integer".to_vec());
+        };
+        match option.to_lower_string().as_deref() {
+            Some("rank") => {
+                if value == 0 {
+                    return OutputValue::Error(
+                        b"ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list".to_vec(),
+                    );
+                }
+                rank = value;
+            }
+            Some("count") => {
+                if value < 0 {
+                    return OutputValue::Error(b"ERR COUNT can't be negative".to_vec());
+                }
+                count = Some(value as usize);
+            }
+            Some("maxlen") => {
+                if value < 0 {
+                    return OutputValue::Error(b"ERR MAXLEN can't be negative".to_vec());
+                }
+                maxlen = value as usize;
+            }
+            _ => return OutputValue::Error(b"ERR syntax error".to_vec()),
+        }
+    }
+    db.lpos(key, element, rank, count, maxlen)
+}
+
+/// Pops from the first non-empty list in `keys`, or returns `None` if all of them are empty.
+fn mpop<T: MapListCommands>(
+    db: &mut T,
+    keys: &[InputValue],
+    end: ListEnd,
+    count: usize,
+) -> Option<OutputValue> {
+    for key in keys {
+        match db.pop(key.as_slice(), end, Some(count)) {
+            OutputValue::NullArray => continue,
+            OutputValue::Array(v) => {
+                return Some(OutputValue::Array(vec![
+                    OutputValue::BulkString(key.clone()),
+                    OutputValue::Array(v),
+                ]))
+            }
+            e => return Some(e),
+        }
+    }
+    None
+}
+
+fn blocking_pop<T: MapListCommands>(db: &mut T, input: &[InputValue], end: ListEnd) -> Blocking {
+    let (timeout, keys) = input.split_last().unwrap();
+    let timeout = match parse_timeout(timeout) {
+        Ok(t) => t,
+        Err(e) => return Blocking::Ready(e),
+    };
+    for key in keys {
+        match db.pop(key.as_slice(), end, None) {
+            OutputValue::NullBulkString => continue,
+            OutputValue::BulkString(v) => {
+                return Blocking::Ready(OutputValue::Array(vec![
+                    OutputValue::BulkString(key.clone()),
+                    OutputValue::BulkString(v),
+                ]))
+            }
+            e => return Blocking::Ready(e),
+        }
+    }
+    Blocking::Wait {
+        keys: keys.to_vec(),
+        timeout,
+        timeout_reply: OutputValue::NullArray,
+        retry_input: None,
+    }
+}
+
+fn blocking_move<T: MapListCommands>(
+    db: &mut T,
+    source: &[u8],
+    destination: &[u8],
+    from: ListEnd,
+    to: ListEnd,
+    timeout: &[u8],
+) -> Blocking {
+    let timeout = match parse_timeout(timeout) {
+        Ok(t) => t,
+        Err(e) => return Blocking::Ready(e),
+    };
+    match db.lmove(source, destination, from, to) {
+        OutputValue::NullBulkString => Blocking::Wait {
+            keys: vec![source.to_vec()],
+            timeout,
+            timeout_reply: OutputValue::NullBulkString,
+            retry_input: None,
+        },
+        v => Blocking::Ready(v),
+    }
+}
+
+pub(super) fn initialise_blocking_list_commands<T: MapListCommands>(
+) -> HashMap<&'static str, BlockingCommand<T>> {
+    let mut map = HashMap::<_, BlockingCommand<T>>::new();
+    map.insert_without_duplicate(
+        "blpop",
+        BlockingCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::List,
+                AclCategory::Slow,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| blocking_pop(db, input, ListEnd::Left),
+        },
+    );
+    map.insert_without_duplicate(
+        "brpop",
+        BlockingCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::List,
+                AclCategory::Slow,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| blocking_pop(db, input, ListEnd::Right),
+        },
+    );
+    map.insert_without_duplicate(
+        "blmove",
+        BlockingCommand {
+            arity_min: 5,
+            arity_max: Some(5),
+            category: &[
+                AclCategory::Write,
+                AclCategory::List,
+                AclCategory::Slow,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| {
+                let (Some(from), Some(to)) = (parse_list_end(&input[2]), parse_list_end(&input[3]))
+                else {
+                    return Blocking::Ready(OutputValue::Error(b"ERR syntax error".to_vec()));
+                };
+                blocking_move(db, &input[0], &input[1], from, to, &input[4])
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "brpoplpush",
+        BlockingCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[
+                AclCategory::Write,
+                AclCategory::List,
+                AclCategory::Slow,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| {
+                blocking_move(
+                    db,
+                    &input[0],
+                    &input[1],
+                    ListEnd::Right,
+                    ListEnd::Left,
+                    &input[2],
+                )
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "blmpop",
+        BlockingCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::List,
+                AclCategory::Slow,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| {
+                let timeout = match parse_timeout(&input[0]) {
+                    Ok(t) => t,
+                    Err(e) => return Blocking::Ready(e),
+                };
+                let (keys, end, count) = match parse_mpop_args(&input[1..], parse_list_end) {
+                    Ok(args) => args,
+                    Err(e) => return Blocking::Ready(e),
+                };
+                match mpop(db, keys, end, count) {
+                    Some(v) => Blocking::Ready(v),
+                    None => Blocking::Wait {
+                        keys: keys.to_vec(),
+                        timeout,
+                        timeout_reply: OutputValue::NullArray,
+                        retry_input: None,
+                    },
+                }
+            },
+        },
+    );
+    map
+}
+
+pub(super) fn initialise_list_commands<T: MapListCommands>(
+) -> HashMap<&'static str, SimpleCommand<T>> {
+    let mut map = HashMap::<_, SimpleCommand<T>>::new();
+    map.insert_without_duplicate(
+        "lpush",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.lpush(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "rpush",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.rpush(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "lpushx",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.lpushx(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "rpushx",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.rpushx(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "lpop",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(2),
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Fast],
+            handler: &move |db, input| pop_handler(db, input, T::lpop),
+        },
+    );
+    map.insert_without_duplicate(
+        "rpop",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(2),
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Fast],
+            handler: &move |db, input| pop_handler(db, input, T::rpop),
+        },
+    );
+    map.insert_without_duplicate(
+        "lrange",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Read, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, start, stop) = get_first_three(input);
+                let (Some(start), Some(stop)) = (start.parse_into(), stop.parse_into()) else {
+                    return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                };
+                db.lrange(key, start, stop)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "llen",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Read, AclCategory::List, AclCategory::Fast],
+            handler: &move |db, input| {
+                let key = get_first(input);
+                db.llen(key)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "lindex",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Read, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, index) = get_first_two(input);
+                let Some(index) = index.parse_into() else {
+                    return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                };
+                db.lindex(key, index)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "lset",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, index, value) = get_first_three(input);
+                let Some(index) = index.parse_into() else {
+                    return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                };
+                db.lset(key, index, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "lrem",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, count, value) = get_first_three(input);
+                let Some(count) = count.parse_into() else {
+                    return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                };
+                db.lrem(key, count, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "ltrim",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, start, stop) = get_first_three(input);
+                let (Some(start), Some(stop)) = (start.parse_into(), stop.parse_into()) else {
+                    return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                };
+                db.ltrim(key, start, stop)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "linsert",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: Some(4),
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| {
+                let mut input = input.into_iter();
+                let key = input.next().unwrap();
+                let before = match input.next().unwrap().to_lower_string().as_deref() {
+                    Some("before") => true,
+                    Some("after") => false,
+                    _ => return OutputValue::Error(b"ERR syntax error".to_vec()),
+                };
+                let pivot = input.next().unwrap();
+                let value = input.next().unwrap();
+                db.linsert(key, before, pivot, value)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "lpos",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| lpos_handler(db, input),
+        },
+    );
+    map.insert_without_duplicate(
+        "lmove",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: Some(4),
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| {
+                let mut input = input.into_iter();
+                let source = input.next().unwrap();
+                let destination = input.next().unwrap();
+                let (Some(from), Some(to)) = (
+                    parse_list_end(&input.next().unwrap()),
+                    parse_list_end(&input.next().unwrap()),
+                ) else {
+                    return OutputValue::Error(b"ERR syntax error".to_vec());
+                };
+                db.lmove(source, destination, from, to)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "lmpop",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| match parse_mpop_args(&input, parse_list_end) {
+                Ok((keys, end, count)) => {
+                    mpop(db, keys, end, count).unwrap_or(OutputValue::NullArray)
+                }
+                Err(e) => e,
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "rpoplpush",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Write, AclCategory::List, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (source, destination) = get_first_two(input);
+                db.rpoplpush(source, destination)
+            },
+        },
+    );
+    map
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use super::{
+    count_out_of_range, get_first, get_first_three, get_first_two, parse_scan_args, HashMapExt,
+    SimpleCommand,
+};
+use crate::bstr::BStr;
+use crate::implementation::acl::AclCategory;
+use crate::interface::database::map::{MapSetCommands, SetOperation};
+use crate::interface::types::OutputValue;
+
+/// Parses `numkeys key [key ...] [LIMIT limit]`, shared by SINTERCARD and ZINTERCARD.
+pub(super) fn parse_intercard(input: Vec<Vec<u8>>) -> Result<(Vec<Vec<u8>>, usize), OutputValue> {
+    let mut input = input.into_iter();
+    let Some(numkeys) = input.next().unwrap().parse_into::<i64>().filter(|n| *n > 0) else {
+        return Err(OutputValue::Error(
+            b"ERR numkeys should be greater than 0".to_vec(),
+        ));
+    };
+    if numkeys as usize > input.len() {
+        return Err(OutputValue::Error(
+            b"ERR Number of keys can't be greater than number of args".to_vec(),
+        ));
+    }
+    let keys: Vec<_> = input.by_ref().take(numkeys as usize).collect();
+    let mut limit = 0;
+    while let Some(option) = input.next() {
+        if option.to_lower_string().as_deref() != Some("limit") {
+            return Err(OutputValue::Error(b"ERR syntax error".to_vec()));
+        }
+        let Some(value) = input.next() else {
+            return Err(OutputValue::Error(b"ERR syntax error".to_vec()));
+        };
+        limit = match value.parse_into::<i64>() {
+            None => return Err(OutputValue::Error(b"ERR value is not an integer".to_vec())),
+            Some(l) if l < 0 => {
+                return Err(OutputValue::Error(b"ERR LIMIT can't be negative".to_vec()))
+            }
+            Some(l) => l as usize,
+        };
+    }
+    Ok((keys, limit))
+}
+
+pub(super) fn initialise_set_commands<T: MapSetCommands>() -> HashMap<&'static str, SimpleCommand<T>>
+{
+    let mut map = HashMap::<_, SimpleCommand<T>>::new();
+    map.insert_without_duplicate(
+        "sadd",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Set, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.sadd(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "srem",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Set, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.srem(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "sismember",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, member) = get_first_two(input);
+                db.sismember(key, member)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "smismember",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.smismember(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "smembers",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, input| db.smembers(get_first(input)),
+        },
+    );
+    map.insert_without_duplicate(
+        "scard",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Fast],
+            handler: &move |db, input| db.scard(get_first(input)),
+        },
+    );
+    map.insert_without_duplicate(
+        "smove",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Write, AclCategory::Set, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (source, destination, member) = get_first_three(input);
+                db.smove(source, destination, member)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "srandmember",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(2),
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, input| {
+                let mut input = input.into_iter();
+                let key = input.next().unwrap();
+                let count = match input.next().map(|c| c.parse_into::<i64>()) {
+                    None => None,
+                    Some(Some(c)) => Some(c),
+                    Some(None) => {
+                        return OutputValue::Error(b"ERR value is not an integer".to_vec())
+                    }
+                };
+                if count == Some(i64::MIN) {
+                    return count_out_of_range();
+                }
+                db.srandmember(key, count)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "spop",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(2),
+            category: &[AclCategory::Write, AclCategory::Set, AclCategory::Fast],
+            handler: &move |db, input| {
+                let mut input = input.into_iter();
+                let key = input.next().unwrap();
+                let count = match input.next().map(|c| c.parse_into::<i64>()) {
+                    None => None,
+                    Some(Some(c)) if c >= 0 => Some(c as usize),
+                    Some(Some(_)) => {
+                        return OutputValue::Error(
+                            b"ERR value is out of range, must be positive".to_vec(),
+                        )
+                    }
+                    Some(None) => {
+                        return OutputValue::Error(b"ERR value is not an integer".to_vec())
+                    }
+                };
+                db.spop(key, count)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "sinter",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, input| db.set_operation(SetOperation::Inter, input),
+        },
+    );
+    map.insert_without_duplicate(
+        "sinterstore",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, mut input| {
+                let destination = input.remove(0);
+                db.set_operation_store(SetOperation::Inter, destination, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "sunion",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, input| db.set_operation(SetOperation::Union, input),
+        },
+    );
+    map.insert_without_duplicate(
+        "sunionstore",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, mut input| {
+                let destination = input.remove(0);
+                db.set_operation_store(SetOperation::Union, destination, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "sdiff",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, input| db.set_operation(SetOperation::Diff, input),
+        },
+    );
+    map.insert_without_duplicate(
+        "sdiffstore",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, mut input| {
+                let destination = input.remove(0);
+                db.set_operation_store(SetOperation::Diff, destination, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "sintercard",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, input| match parse_intercard(input) {
+                Ok((keys, limit)) => db.sintercard(keys, limit),
+                Err(e) => e,
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "sscan",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Set, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, rest) = input.split_first().unwrap();
+                let args = parse_scan_args(rest, |_, _| {
+                    Err(OutputValue::Error(b"ERR syntax error".to_vec()))
+                });
+                match args {
+                    Ok((cursor, options)) => db.sscan(key.as_slice(), cursor, options),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map
+}
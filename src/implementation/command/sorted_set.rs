@@ -0,0 +1,1014 @@
+use std::collections::HashMap;
+use std::ops::Bound;
+
+use super::set::parse_intercard;
+use super::{
+    get_first, get_first_three, get_first_two, parse_mpop_args, parse_scan_args, BlockingCommand,
+    HashMapExt, SimpleCommand,
+};
+use crate::bstr::BStr;
+use crate::implementation::acl::AclCategory;
+use crate::implementation::blocking::{parse_timeout, Blocking};
+use crate::implementation::database::float;
+use crate::interface::database::map::{
+    Aggregate, LexBound, MapSortedSetCommands, ScoreComparison, SetCondition, SetOperation,
+    SortedSetEnd, SortedSetRange, ZaddOptions, ZrangeOptions,
+};
+use crate::interface::types::{InputValue, OutputValue};
+
+/// Parses a score as Redis does with `strtod`, accepting infinities and hexadecimal floats
+/// but not NaN.
+pub(super) fn parse_score(input: &[u8]) -> Option<f64> {
+    float::parse_double(input)
+}
+
+/// Parses a score range bound: `(` makes it exclusive.
+pub(super) fn parse_score_bound(input: &[u8]) -> Result<Bound<f64>, OutputValue> {
+    let bound = match input.strip_prefix(b"(") {
+        Some(score) => parse_score(score).map(Bound::Excluded),
+        None => parse_score(input).map(Bound::Included),
+    };
+    bound.ok_or_else(|| OutputValue::Error(b"ERR min or max is not a float".to_vec()))
+}
+
+/// Parses a BYLEX bound: `[` or `(` followed by a member, `-` or `+`.
+fn parse_lex_bound(input: &[u8]) -> Result<LexBound, OutputValue> {
+    match input.split_first() {
+        Some((b'-', [])) => Ok(LexBound::Min),
+        Some((b'+', [])) => Ok(LexBound::Max),
+        Some((b'[', member)) => Ok(LexBound::Included(member.to_vec())),
+        Some((b'(', member)) => Ok(LexBound::Excluded(member.to_vec())),
+        _ => Err(OutputValue::Error(
+            b"ERR min or max not valid string range item".to_vec(),
+        )),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// Parses `start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count] [WITHSCORES]`, the arguments
+/// of ZRANGE after the key. Returns the selection and whether scores were requested.
+fn parse_zrange(
+    start: &[u8],
+    stop: &[u8],
+    options: &[InputValue],
+    allow_withscores: bool,
+) -> Result<(ZrangeOptions, bool), OutputValue> {
+    let syntax_error = || OutputValue::Error(b"ERR syntax error".to_vec());
+    let mut kind = RangeKind::Rank;
+    let mut reverse = false;
+    let mut limit = None;
+    let mut with_scores = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.to_lower_string().as_deref() {
+            Some("byscore") => kind = RangeKind::Score,
+            Some("bylex") => kind = RangeKind::Lex,
+            Some("rev") => reverse = true,
+            Some("withscores") if allow_withscores => with_scores = true,
+            Some("limit") => {
+                let (Some(offset), Some(count)) = (options.next(), options.next()) else {
+                    return Err(syntax_error());
+                };
+                let (Some(offset), Some(count)) = (offset.parse_into(), count.parse_into()) else {
+                    return Err(OutputValue::Error(b"ERR value is not an integer".to_vec()));
+                };
+                limit = Some((offset, count));
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    if limit.is_some() && kind == RangeKind::Rank {
+        return Err(OutputValue::Error(
+            b"ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                .to_vec(),
+        ));
+    }
+    if with_scores && kind == RangeKind::Lex {
+        return Err(OutputValue::Error(
+            b"ERR syntax error, WITHSCORES not supported in combination with BYLEX".to_vec(),
+        ));
+    }
+    // with REV, score and lex ranges are given highest first
+    let (min, max) = if reverse && kind != RangeKind::Rank {
+        (stop, start)
+    } else {
+        (start, stop)
+    };
+    let range = match kind {
+        RangeKind::Rank => {
+            let (Some(start), Some(stop)) = (start.parse_into(), stop.parse_into()) else {
+                return Err(OutputValue::Error(b"ERR value is not an integer".to_vec()));
+            };
+            SortedSetRange::Rank(start, stop)
+        }
+        RangeKind::Score => SortedSetRange::Score(parse_score_bound(min)?, parse_score_bound(max)?),
+        RangeKind::Lex => SortedSetRange::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?),
+    };
+    let options = ZrangeOptions {
+        range,
+        reverse,
+        limit,
+    };
+    Ok((options, with_scores))
+}
+
+/// Runs ZRANGE with `implied` options placed before the user's, as the legacy variants do.
+fn zrange_handler<T: MapSortedSetCommands>(
+    db: &mut T,
+    input: Vec<InputValue>,
+    implied: &[&[u8]],
+) -> OutputValue {
+    let mut input = input.into_iter();
+    let key = input.next().unwrap();
+    let start = input.next().unwrap();
+    let stop = input.next().unwrap();
+    let options: Vec<_> = implied.iter().map(|o| o.to_vec()).chain(input).collect();
+    match parse_zrange(&start, &stop, &options, true) {
+        Ok((options, with_scores)) => db.zrange(key, options, with_scores),
+        Err(e) => e,
+    }
+}
+
+/// Handles ZUNION, ZINTER and ZDIFF, and with `store` their STORE variants.
+fn zset_operation_handler<T: MapSortedSetCommands>(
+    db: &mut T,
+    input: Vec<InputValue>,
+    command: &str,
+    operation: SetOperation,
+    store: bool,
+) -> OutputValue {
+    let syntax_error = || OutputValue::Error(b"ERR syntax error".to_vec());
+    let mut input = input.into_iter();
+    let destination = if store { input.next() } else { None };
+    let Some(numkeys) = input.next().unwrap().parse_into::<i64>() else {
+        return OutputValue::Error(b"ERR value is not an integer".to_vec());
+    };
+    if numkeys <= 0 {
+        return OutputValue::Error(
+            format!(
+                "ERR at least 1 input key is needed for '{}' command",
+                command
+            )
+            .into_bytes(),
+        );
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > input.len() {
+        return syntax_error();
+    }
+    let keys: Vec<_> = input.by_ref().take(numkeys).collect();
+    let mut weights = vec![1.0; numkeys];
+    let mut aggregate = Aggregate::Sum;
+    let mut with_scores = false;
+    let weighted = operation != SetOperation::Diff;
+    while let Some(option) = input.next() {
+        match option.to_lower_string().as_deref() {
+            Some("weights") if weighted => {
+                for weight in weights.iter_mut() {
+                    let Some(value) = input.next() else {
+                        return syntax_error();
+                    };
+                    let Some(value) = parse_score(&value) else {
+                        return OutputValue::Error(b"ERR weight value is not a float".to_vec());
+                    };
+                    *weight = value;
+                }
+            }
+            Some("aggregate") if weighted => {
+                aggregate = match input.next().and_then(|a| a.to_lower_string()).as_deref() {
+                    Some("sum") => Aggregate::Sum,
+                    Some("min") => Aggregate::Min,
+                    Some("max") => Aggregate::Max,
+                    _ => return syntax_error(),
+                };
+            }
+            Some("withscores") if !store => with_scores = true,
+            _ => return syntax_error(),
+        }
+    }
+    match destination {
+        Some(destination) => {
+            db.zset_operation_store(operation, destination, keys, weights, aggregate)
+        }
+        None => db.zset_operation(operation, keys, weights, aggregate, with_scores),
+    }
+}
+
+fn parse_sorted_set_end(input: &[u8]) -> Option<SortedSetEnd> {
+    match input.to_lower_string()?.as_str() {
+        "min" => Some(SortedSetEnd::Min),
+        "max" => Some(SortedSetEnd::Max),
+        _ => None,
+    }
+}
+
+/// Pops from the first non-empty sorted set in `keys`, or returns `None` if all of them are empty.
+fn zmpop<T: MapSortedSetCommands>(
+    db: &mut T,
+    keys: &[InputValue],
+    end: SortedSetEnd,
+    count: usize,
+) -> Option<OutputValue> {
+    for key in keys {
+        match db.zpop(key.as_slice(), end, Some(count)) {
+            OutputValue::Array(v) if v.is_empty() => continue,
+            OutputValue::Array(v) => {
+                // ZMPOP nests each member with its score
+                let mut v = v.into_iter();
+                let pairs = std::iter::from_fn(|| Some(vec![v.next()?, v.next()?]))
+                    .map(OutputValue::Array)
+                    .collect();
+                return Some(OutputValue::Array(vec![
+                    OutputValue::BulkString(key.clone()),
+                    OutputValue::Array(pairs),
+                ]));
+            }
+            e => return Some(e),
+        }
+    }
+    None
+}
+
+fn blocking_zpop<T: MapSortedSetCommands>(
+    db: &mut T,
+    input: &[InputValue],
+    end: SortedSetEnd,
+) -> Blocking {
+    let (timeout, keys) = input.split_last().unwrap();
+    let timeout = match parse_timeout(timeout) {
+        Ok(t) => t,
+        Err(e) => return Blocking::Ready(e),
+    };
+    for key in keys {
+        match db.zpop(key.as_slice(), end, None) {
+            OutputValue::Array(v) if v.is_empty() => continue,
+            OutputValue::Array(v) => {
+                let reply = std::iter::once(OutputValue::BulkString(key.clone()))
+                    .chain(v)
+                    .collect();
+                return Blocking::Ready(OutputValue::Array(reply));
+            }
+            e => return Blocking::Ready(e),
+        }
+    }
+    Blocking::Wait {
+        keys: keys.to_vec(),
+        timeout,
+        timeout_reply: OutputValue::NullArray,
+        retry_input: None,
+    }
+}
+
+fn zadd_handler<T: MapSortedSetCommands>(db: &mut T, input: Vec<InputValue>) -> OutputValue {
+    let mut input = input.into_iter().peekable();
+    let key = input.next().unwrap();
+    let mut options = ZaddOptions::default();
+    let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+    while let Some(option) = input.peek().and_then(|o| o.to_lower_string()) {
+        match option.as_str() {
+            "nx" => nx = true,
+            "xx" => xx = true,
+            "gt" => gt = true,
+            "lt" => lt = true,
+            "ch" => options.changed = true,
+            "incr" => options.incr = true,
+            _ => break,
+        }
+        input.next();
+    }
+    let rest: Vec<_> = input.collect();
+    if rest.is_empty() || !rest.len().is_multiple_of(2) {
+        return OutputValue::Error(b"ERR syntax error".to_vec());
+    }
+    options.condition = match (nx, xx) {
+        (true, true) => {
+            return OutputValue::Error(
+                b"ERR XX and NX options at the same time are not compatible".to_vec(),
+            )
+        }
+        (true, false) => SetCondition::Nx,
+        (false, true) => SetCondition::Xx,
+        (false, false) => SetCondition::Always,
+    };
+    if (gt && lt) || (nx && (gt || lt)) {
+        return OutputValue::Error(
+            b"ERR GT, LT, and/or NX options at the same time are not compatible".to_vec(),
+        );
+    }
+    options.comparison = match (gt, lt) {
+        (true, _) => ScoreComparison::Gt,
+        (_, true) => ScoreComparison::Lt,
+        _ => ScoreComparison::Any,
+    };
+    if options.incr && rest.len() > 2 {
+        return OutputValue::Error(
+            b"ERR INCR option supports a single increment-element pair".to_vec(),
+        );
+    }
+    let mut members = Vec::with_capacity(rest.len() / 2);
+    let mut rest = rest.into_iter();
+    while let (Some(score), Some(member)) = (rest.next(), rest.next()) {
+        let Some(score) = parse_score(&score) else {
+            return OutputValue::Error(b"ERR value is not a valid float".to_vec());
+        };
+        members.push((score, member));
+    }
+    db.zadd(key, options, members)
+}
+
+fn zrank_handler<T: MapSortedSetCommands>(
+    db: &mut T,
+    input: Vec<InputValue>,
+    reverse: bool,
+) -> OutputValue {
+    let mut input = input.into_iter();
+    let key = input.next().unwrap();
+    let member = input.next().unwrap();
+    let with_score = match input.next() {
+        None => false,
+        Some(v) if v.to_lower_string().as_deref() == Some("withscore") => true,
+        Some(_) => return OutputValue::Error(b"ERR syntax error".to_vec()),
+    };
+    db.zrank(key, member, reverse, with_score)
+}
+
+fn zpop_handler<T: MapSortedSetCommands>(
+    db: &mut T,
+    input: Vec<InputValue>,
+    end: SortedSetEnd,
+) -> OutputValue {
+    let mut input = input.into_iter();
+    let key = input.next().unwrap();
+    let count = match input.next().map(|c| c.parse_into::<i64>()) {
+        None => None,
+        Some(Some(c)) if c >= 0 => Some(c as usize),
+        Some(Some(_)) => {
+            return OutputValue::Error(b"ERR value is out of range, must be positive".to_vec())
+        }
+        Some(None) => return OutputValue::Error(b"ERR value is not an integer".to_vec()),
+    };
+    db.zpop(key, end, count)
+}
+
+pub(super) fn initialise_blocking_sorted_set_commands<T: MapSortedSetCommands>(
+) -> HashMap<&'static str, BlockingCommand<T>> {
+    let mut map = HashMap::<_, BlockingCommand<T>>::new();
+    map.insert_without_duplicate(
+        "bzpopmin",
+        BlockingCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Fast,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| blocking_zpop(db, input, SortedSetEnd::Min),
+        },
+    );
+    map.insert_without_duplicate(
+        "bzpopmax",
+        BlockingCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Fast,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| blocking_zpop(db, input, SortedSetEnd::Max),
+        },
+    );
+    map.insert_without_duplicate(
+        "bzmpop",
+        BlockingCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Slow,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| {
+                let timeout = match parse_timeout(&input[0]) {
+                    Ok(t) => t,
+                    Err(e) => return Blocking::Ready(e),
+                };
+                let (keys, end, count) = match parse_mpop_args(&input[1..], parse_sorted_set_end) {
+                    Ok(args) => args,
+                    Err(e) => return Blocking::Ready(e),
+                };
+                match zmpop(db, keys, end, count) {
+                    Some(v) => Blocking::Ready(v),
+                    None => Blocking::Wait {
+                        keys: keys.to_vec(),
+                        timeout,
+                        timeout_reply: OutputValue::NullArray,
+                        retry_input: None,
+                    },
+                }
+            },
+        },
+    );
+    map
+}
+
+pub(super) fn initialise_sorted_set_commands<T: MapSortedSetCommands>(
+) -> HashMap<&'static str, SimpleCommand<T>> {
+    let mut map = HashMap::<_, SimpleCommand<T>>::new();
+    map.insert_without_duplicate(
+        "zadd",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Fast,
+            ],
+            handler: &move |db, input| zadd_handler(db, input),
+        },
+    );
+    map.insert_without_duplicate(
+        "zincrby",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Fast,
+            ],
+            handler: &move |db, input| {
+                let (key, increment, member) = get_first_three(input);
+                let Some(increment) = parse_score(&increment) else {
+                    return OutputValue::Error(b"ERR value is not a valid float".to_vec());
+                };
+                db.zincrby(key, increment, member)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zscore",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(2),
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, member) = get_first_two(input);
+                db.zscore(key, member)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zmscore",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.zmscore(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zrank",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(3),
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Fast],
+            handler: &move |db, input| zrank_handler(db, input, false),
+        },
+    );
+    map.insert_without_duplicate(
+        "zrevrank",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(3),
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Fast],
+            handler: &move |db, input| zrank_handler(db, input, true),
+        },
+    );
+    map.insert_without_duplicate(
+        "zcard",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Fast],
+            handler: &move |db, input| db.zcard(get_first(input)),
+        },
+    );
+    map.insert_without_duplicate(
+        "zcount",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, min, max) = get_first_three(input);
+                match (parse_score_bound(&min), parse_score_bound(&max)) {
+                    (Ok(min), Ok(max)) => db.zcount(key, min, max),
+                    (Err(e), _) | (_, Err(e)) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zrem",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Fast,
+            ],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                db.zrem(key, input)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zpopmin",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(2),
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Fast,
+            ],
+            handler: &move |db, input| zpop_handler(db, input, SortedSetEnd::Min),
+        },
+    );
+    map.insert_without_duplicate(
+        "zpopmax",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(2),
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Fast,
+            ],
+            handler: &move |db, input| zpop_handler(db, input, SortedSetEnd::Max),
+        },
+    );
+    map.insert_without_duplicate(
+        "zrange",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Slow],
+            handler: &move |db, input| zrange_handler(db, input, &[]),
+        },
+    );
+    map.insert_without_duplicate(
+        "zrangebyscore",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Slow],
+            handler: &move |db, input| zrange_handler(db, input, &[b"byscore"]),
+        },
+    );
+    map.insert_without_duplicate(
+        "zrevrangebyscore",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Slow],
+            handler: &move |db, input| zrange_handler(db, input, &[b"byscore", b"rev"]),
+        },
+    );
+    map.insert_without_duplicate(
+        "zrangestore",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, input| {
+                let [destination, source, start, stop, options @ ..] = input.as_slice() else {
+                    unreachable!()
+                };
+                match parse_zrange(start, stop, options, false) {
+                    Ok((options, _)) => {
+                        db.zrangestore(destination.as_slice(), source.as_slice(), options)
+                    }
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zlexcount",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Fast],
+            handler: &move |db, input| {
+                let (key, min, max) = get_first_three(input);
+                match (parse_lex_bound(&min), parse_lex_bound(&max)) {
+                    (Ok(min), Ok(max)) => db.zlexcount(key, min, max),
+                    (Err(e), _) | (_, Err(e)) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zremrangebyrank",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, input| {
+                let (key, start, stop) = get_first_three(input);
+                match (start.parse_into(), stop.parse_into()) {
+                    (Some(start), Some(stop)) => {
+                        db.zremrange(key, SortedSetRange::Rank(start, stop))
+                    }
+                    _ => OutputValue::Error(b"ERR value is not an integer".to_vec()),
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zremrangebyscore",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, input| {
+                let (key, min, max) = get_first_three(input);
+                match (parse_score_bound(&min), parse_score_bound(&max)) {
+                    (Ok(min), Ok(max)) => db.zremrange(key, SortedSetRange::Score(min, max)),
+                    (Err(e), _) | (_, Err(e)) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zremrangebylex",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(3),
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, input| {
+                let (key, min, max) = get_first_three(input);
+                match (parse_lex_bound(&min), parse_lex_bound(&max)) {
+                    (Ok(min), Ok(max)) => db.zremrange(key, SortedSetRange::Lex(min, max)),
+                    (Err(e), _) | (_, Err(e)) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zunion",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Slow],
+            handler: &move |db, input| {
+                zset_operation_handler(db, input, "zunion", SetOperation::Union, false)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zunionstore",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, input| {
+                zset_operation_handler(db, input, "zunionstore", SetOperation::Union, true)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zinter",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Slow],
+            handler: &move |db, input| {
+                zset_operation_handler(db, input, "zinter", SetOperation::Inter, false)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zinterstore",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, input| {
+                zset_operation_handler(db, input, "zinterstore", SetOperation::Inter, true)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zdiff",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Slow],
+            handler: &move |db, input| {
+                zset_operation_handler(db, input, "zdiff", SetOperation::Diff, false)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zdiffstore",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, input| {
+                zset_operation_handler(db, input, "zdiffstore", SetOperation::Diff, true)
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zintercard",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Slow],
+            handler: &move |db, input| match parse_intercard(input) {
+                Ok((keys, limit)) => db.zintercard(keys, limit),
+                Err(e) => e,
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zmpop",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::SortedSet,
+                AclCategory::Slow,
+            ],
+            handler: &move |db, input| match parse_mpop_args(&input, parse_sorted_set_end) {
+                Ok((keys, end, count)) => {
+                    zmpop(db, keys, end, count).unwrap_or(OutputValue::NullArray)
+                }
+                Err(e) => e,
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "zscan",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::SortedSet, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (key, rest) = input.split_first().unwrap();
+                let mut with_scores = true;
+                let args = parse_scan_args(rest, |option, _| match option {
+                    "noscores" => {
+                        with_scores = false;
+                        Ok(())
+                    }
+                    _ => Err(OutputValue::Error(b"ERR syntax error".to_vec())),
+                });
+                match args {
+                    Ok((cursor, options)) => db.zscan(key.as_slice(), cursor, options, with_scores),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementation::database::Map;
+    use crate::interface::database::map::MapStringCommands;
+
+    fn args(items: &[&str]) -> Vec<InputValue> {
+        items.iter().map(|item| item.as_bytes().to_vec()).collect()
+    }
+
+    fn run(map: &mut Map, command: &str, items: &[&str]) -> OutputValue {
+        let commands = initialise_sorted_set_commands::<Map>();
+        (commands[command].handler)(map, args(items))
+    }
+
+    fn run_blocking(map: &mut Map, command: &str, items: &[&str]) -> Blocking {
+        let commands = initialise_blocking_sorted_set_commands::<Map>();
+        (commands[command].handler)(map, &args(items))
+    }
+
+    fn bulk(s: &str) -> OutputValue {
+        OutputValue::BulkString(s.as_bytes().to_vec())
+    }
+
+    /// The `[key, [[member, score], ...]]` reply of ZMPOP and BZMPOP.
+    fn nested(key: &str, pairs: &[[&str; 2]]) -> OutputValue {
+        let pairs = pairs
+            .iter()
+            .map(|pair| OutputValue::Array(pair.iter().map(|item| bulk(item)).collect()))
+            .collect();
+        OutputValue::Array(vec![bulk(key), OutputValue::Array(pairs)])
+    }
+
+    fn ready(blocking: Blocking) -> OutputValue {
+        match blocking {
+            Blocking::Ready(reply) => reply,
+            Blocking::Wait { .. } => panic!("expected a reply"),
+        }
+    }
+
+    #[test]
+    fn test_zmpop() {
+        let mut map = Map::default();
+        assert_eq!(
+            run(&mut map, "zmpop", &["2", "a", "b", "min"]),
+            OutputValue::NullArray
+        );
+        run(&mut map, "zadd", &["b", "1", "x", "2", "y", "3", "z"]);
+        assert_eq!(
+            run(&mut map, "zmpop", &["2", "a", "b", "min"]),
+            nested("b", &[["x", "1"]])
+        );
+        assert_eq!(
+            run(&mut map, "zmpop", &["2", "a", "b", "max", "count", "5"]),
+            nested("b", &[["z", "3"], ["y", "2"]])
+        );
+        assert_eq!(
+            run(&mut map, "zmpop", &["2", "a", "b", "min"]),
+            OutputValue::NullArray
+        );
+        for invalid in [
+            ["1", "b", "middle"].as_slice(),
+            &["0", "b", "min"],
+            &["2", "b", "min"],
+            &["1", "b", "min", "count", "0"],
+        ] {
+            assert!(matches!(
+                run(&mut map, "zmpop", invalid),
+                OutputValue::Error(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_scores_and_bounds_are_parsed_alike() {
+        let mut map = Map::default();
+        assert_eq!(
+            run(
+                &mut map,
+                "zadd",
+                &["z", "0x1p3", "a", "1e1", "b", "+inf", "c"]
+            ),
+            OutputValue::Integer(3)
+        );
+        assert_eq!(
+            run(&mut map, "zrangebyscore", &["z", "0x1p3", "(0xAp0"]),
+            OutputValue::Array(vec![bulk("a")])
+        );
+        assert_eq!(
+            run(&mut map, "zcount", &["z", "(8", "inf"]),
+            OutputValue::Integer(2)
+        );
+        for invalid in ["nan", " 1", "1e999", "0x"] {
+            assert_eq!(
+                run(&mut map, "zadd", &["z", invalid, "d"]),
+                OutputValue::Error(b"ERR value is not a valid float".to_vec())
+            );
+            assert_eq!(
+                run(&mut map, "zcount", &["z", invalid, "+inf"]),
+                OutputValue::Error(b"ERR min or max is not a float".to_vec())
+            );
+        }
+    }
+
+    #[test]
+    fn test_zadd_rejects_incompatible_options() {
+        let mut map = Map::default();
+        let incompatible = OutputValue::Error(
+            b"ERR GT, LT, and/or NX options at the same time are not compatible".to_vec(),
+        );
+        assert_eq!(
+            run(&mut map, "zadd", &["z", "nx", "gt", "1", "a"]),
+            incompatible
+        );
+        assert_eq!(
+            run(&mut map, "zadd", &["z", "gt", "lt", "1", "a"]),
+            incompatible
+        );
+        assert_eq!(
+            run(&mut map, "zadd", &["z", "LT", "nx", "1", "a"]),
+            incompatible
+        );
+        assert_eq!(
+            run(&mut map, "zadd", &["z", "xx", "nx", "1", "a"]),
+            OutputValue::Error(
+                b"ERR XX and NX options at the same time are not compatible".to_vec()
+            )
+        );
+        assert_eq!(map.zcard(b"z".as_slice()), OutputValue::Integer(0));
+        assert_eq!(
+            run(&mut map, "zadd", &["z", "xx", "gt", "1", "a"]),
+            OutputValue::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_bzpop() {
+        let mut map = Map::default();
+        run(&mut map, "zadd", &["z", "1", "a", "2", "b", "3", "c"]);
+        assert_eq!(
+            ready(run_blocking(&mut map, "bzpopmin", &["missing", "z", "0"])),
+            OutputValue::Array(vec![bulk("z"), bulk("a"), bulk("1")])
+        );
+        assert_eq!(
+            ready(run_blocking(&mut map, "bzpopmax", &["z", "0"])),
+            OutputValue::Array(vec![bulk("z"), bulk("c"), bulk("3")])
+        );
+        assert_eq!(
+            ready(run_blocking(
+                &mut map,
+                "bzmpop",
+                &["0", "1", "z", "max", "count", "2"]
+            )),
+            nested("z", &[["b", "2"]])
+        );
+        assert!(matches!(
+            ready(run_blocking(&mut map, "bzpopmin", &["z", "-1"])),
+            OutputValue::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_bzpop_waits_on_empty_keys() {
+        let mut map = Map::default();
+        match run_blocking(&mut map, "bzpopmax", &["a", "b", "1.5"]) {
+            Blocking::Wait {
+                keys,
+                timeout,
+                timeout_reply,
+                ..
+            } => {
+                assert_eq!(keys, args(&["a", "b"]));
+                assert_eq!(timeout, Some(std::time::Duration::from_millis(1500)));
+                assert_eq!(timeout_reply, OutputValue::NullArray);
+            }
+            Blocking::Ready(reply) => panic!("unexpected reply {:?}", reply),
+        }
+        assert!(matches!(
+            run_blocking(&mut map, "bzmpop", &["0", "1", "a", "min"]),
+            Blocking::Wait { .. }
+        ));
+        // a key of another type fails the command rather than waiting on it
+        map.append(b"s".to_vec(), b"v".to_vec());
+        assert!(matches!(
+            ready(run_blocking(&mut map, "bzpopmin", &["a", "s", "0"])),
+            OutputValue::Error(_)
+        ));
+    }
+}
@@ -0,0 +1,816 @@
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::time::Duration;
+
+use super::{get_first, BlockingCommand, HashMapExt, SimpleCommand};
+use crate::bstr::BStr;
+use crate::implementation::acl::AclCategory;
+use crate::implementation::blocking::{parse_timeout_ms, Blocking};
+use crate::interface::database::map::{
+    GroupStart, MapStreamCommands, ReadGroupId, StreamId, StreamTrim, StreamTrimStrategy, XaddId,
+    XautoclaimOptions, XclaimOptions, XpendingRange, XreadId,
+};
+use crate::interface::types::{InputValue, OutputValue};
+
+fn invalid_stream_id() -> OutputValue {
+    OutputValue::Error(b"ERR Invalid stream ID specified as stream command argument".to_vec())
+}
+
+/// Parses `<ms>-<seq>` or `<ms>`, in which case the sequence is `missing_seq`.
+pub(super) fn parse_stream_id(input: &[u8], missing_seq: u64) -> Option<StreamId> {
+    let (ms, seq) = match input.iter().position(|c| *c == b'-') {
+        Some(i) => (&input[..i], Some(&input[i + 1..])),
+        None => (input, None),
+    };
+    Some(StreamId {
+        ms: ms.parse_into()?,
+        seq: match seq {
+            Some(seq) => seq.parse_into()?,
+            None => missing_seq,
+        },
+    })
+}
+
+/// Parses an XRANGE bound: `-`, `+` or an ID, which `(` makes exclusive. An ID without a
+/// sequence covers the whole millisecond, so `missing_seq` is 0 for starts and the maximum
+/// for ends.
+fn parse_range_bound(input: &[u8], missing_seq: u64) -> Result<Bound<StreamId>, OutputValue> {
+    let bound = match input {
+        b"-" | b"+" => Some(Bound::Unbounded),
+        [b'(', id @ ..] => parse_stream_id(id, missing_seq).map(Bound::Excluded),
+        id => parse_stream_id(id, missing_seq).map(Bound::Included),
+    };
+    bound.ok_or_else(invalid_stream_id)
+}
+
+fn parse_xadd_id(input: &[u8]) -> Result<XaddId, OutputValue> {
+    let id = match input {
+        b"*" => XaddId::Auto,
+        [ms @ .., b'-', b'*'] => {
+            XaddId::AutoSequence(ms.parse_into().ok_or_else(invalid_stream_id)?)
+        }
+        id => match parse_stream_id(id, 0) {
+            None => return Err(invalid_stream_id()),
+            Some(StreamId::MIN) => {
+                return Err(OutputValue::Error(
+                    b"ERR The ID specified in XADD must be greater than 0-0".to_vec(),
+                ))
+            }
+            Some(id) => XaddId::Explicit(id),
+        },
+    };
+    Ok(id)
+}
+
+/// Parses the ID of XGROUP CREATE or SETID, where `$` stands for the last entry.
+fn parse_group_start(input: &[u8]) -> Result<GroupStart, OutputValue> {
+    match input {
+        b"$" => Ok(GroupStart::LastEntry),
+        id => parse_stream_id(id, 0)
+            .map(GroupStart::Id)
+            .ok_or_else(invalid_stream_id),
+    }
+}
+
+/// Parses the argument of ENTRIESREAD, where -1 means unknown.
+fn parse_entries_read(input: Option<&InputValue>) -> Result<Option<u64>, OutputValue> {
+    let Some(input) = input else {
+        return Err(OutputValue::Error(b"ERR syntax error".to_vec()));
+    };
+    match input.parse_into::<i64>() {
+        None => Err(OutputValue::Error(b"ERR value is not an integer".to_vec())),
+        Some(-1) => Ok(None),
+        Some(n) if n < 0 => Err(OutputValue::Error(
+            b"ERR value for ENTRIESREAD must be positive or -1".to_vec(),
+        )),
+        Some(n) => Ok(Some(n as u64)),
+    }
+}
+
+/// Parses an idle time or the like for XCLAIM or XAUTOCLAIM; negative values count as 0.
+fn parse_milliseconds(input: &[u8], argument: &str, command: &str) -> Result<u64, OutputValue> {
+    input
+        .parse_into::<i64>()
+        .map(|n| n.max(0) as u64)
+        .ok_or_else(|| {
+            OutputValue::Error(
+                format!("ERR Invalid {} argument for {}", argument, command).into_bytes(),
+            )
+        })
+}
+
+/// Parses the leading options of XADD (where `allow_nomkstream` is set) and XTRIM, up to the
+/// first argument that is not one. Returns NOMKSTREAM, the trimming and the options' length.
+fn parse_trim_options(
+    input: &[InputValue],
+    allow_nomkstream: bool,
+) -> Result<(bool, Option<StreamTrim>, usize), OutputValue> {
+    let syntax_error = || OutputValue::Error(b"ERR syntax error".to_vec());
+    let mut no_mkstream = false;
+    let mut strategy = None;
+    let mut approximate = false;
+    let mut limit = None;
+    let mut i = 0;
+    while let Some(option) = input.get(i) {
+        match option.to_lower_string().as_deref() {
+            Some("nomkstream") if allow_nomkstream => {
+                no_mkstream = true;
+                i += 1;
+            }
+            Some(kind @ ("maxlen" | "minid")) => {
+                i += 1;
+                approximate = match input.get(i).map(Vec::as_slice) {
+                    Some(b"=") => {
+                        i += 1;
+                        false
+                    }
+                    Some(b"~") => {
+                        i += 1;
+                        true
+                    }
+                    _ => false,
+                };
+                let Some(threshold) = input.get(i) else {
+                    return Err(syntax_error());
+                };
+                strategy = Some(if kind == "maxlen" {
+                    match threshold.parse_into::<i64>() {
+                        None => {
+                            return Err(OutputValue::Error(b"ERR value is not an integer".to_vec()))
+                        }
+                        Some(n) if n < 0 => {
+                            return Err(OutputValue::Error(
+                                b"ERR The MAXLEN argument must be >= 0.".to_vec(),
+                            ))
+                        }
+                        Some(n) => StreamTrimStrategy::MaxLen(n as usize),
+                    }
+                } else {
+                    StreamTrimStrategy::MinId(
+                        parse_stream_id(threshold, 0).ok_or_else(invalid_stream_id)?,
+                    )
+                });
+                i += 1;
+            }
+            Some("limit") => {
+                let Some(value) = input.get(i + 1) else {
+                    return Err(syntax_error());
+                };
+                limit = match value.parse_into::<i64>() {
+                    None => {
+                        return Err(OutputValue::Error(b"ERR value is not an integer".to_vec()))
+                    }
+                    Some(n) if n < 0 => {
+                        return Err(OutputValue::Error(
+                            b"ERR The LIMIT argument must be >= 0.".to_vec(),
+                        ))
+                    }
+                    Some(n) => Some(n as usize),
+                };
+                i += 2;
+            }
+            _ => break,
+        }
+    }
+    let trim =
+        match (strategy, limit) {
+            (None, None) => None,
+            (None, Some(_)) => return Err(OutputValue::Error(
+                b"ERR syntax error, LIMIT cannot be used without specifying a trimming strategy"
+                    .to_vec(),
+            )),
+            (Some(_), Some(_)) if !approximate => {
+                return Err(OutputValue::Error(
+                    b"ERR syntax error, LIMIT cannot be used without the special ~ option".to_vec(),
+                ))
+            }
+            (Some(strategy), limit) => Some(StreamTrim {
+                strategy,
+                approximate,
+                limit,
+            }),
+        };
+    Ok((no_mkstream, trim, i))
+}
+
+pub(super) fn initialise_stream_commands<T: MapStreamCommands>(
+) -> HashMap<&'static str, SimpleCommand<T>> {
+    let mut map = HashMap::<_, SimpleCommand<T>>::new();
+    map.insert_without_duplicate(
+        "xadd",
+        SimpleCommand {
+            arity_min: 4,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Stream, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                let (no_mkstream, trim, consumed) = match parse_trim_options(&input, true) {
+                    Ok(options) => options,
+                    Err(e) => return e,
+                };
+                let mut input = input.into_iter().skip(consumed);
+                let Some(id) = input.next() else {
+                    return OutputValue::Error(b"ERR syntax error".to_vec());
+                };
+                let fields: Vec<_> = input.collect();
+                if fields.is_empty() || fields.len() % 2 != 0 {
+                    return OutputValue::Error(
+                        b"ERR wrong number of arguments for 'xadd'".to_vec(),
+                    );
+                }
+                match parse_xadd_id(&id) {
+                    Ok(id) => db.xadd(key, id, no_mkstream, trim, fields),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "xtrim",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Stream, AclCategory::Slow],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                match parse_trim_options(&input, false) {
+                    Ok((_, Some(trim), consumed)) if consumed == input.len() => db.xtrim(key, trim),
+                    Ok(_) => OutputValue::Error(b"ERR syntax error".to_vec()),
+                    Err(e) => e,
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "xrange",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(5),
+            category: &[AclCategory::Read, AclCategory::Stream, AclCategory::Slow],
+            handler: &move |db, input| xrange_handler(db, input, false),
+        },
+    );
+    map.insert_without_duplicate(
+        "xrevrange",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: Some(5),
+            category: &[AclCategory::Read, AclCategory::Stream, AclCategory::Slow],
+            handler: &move |db, input| xrange_handler(db, input, true),
+        },
+    );
+    map.insert_without_duplicate(
+        "xlen",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: Some(1),
+            category: &[AclCategory::Read, AclCategory::Stream, AclCategory::Fast],
+            handler: &move |db, input| db.xlen(get_first(input)),
+        },
+    );
+    map.insert_without_duplicate(
+        "xdel",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Stream, AclCategory::Fast],
+            handler: &move |db, mut input| {
+                let key = input.remove(0);
+                let ids: Option<Vec<_>> = input.iter().map(|id| parse_stream_id(id, 0)).collect();
+                match ids {
+                    Some(ids) => db.xdel(key, ids),
+                    None => invalid_stream_id(),
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "xinfo",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Read, AclCategory::Stream, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (subcommand, rest) = input.split_first().unwrap();
+                match (subcommand.to_lower_string().as_deref(), rest) {
+                    (Some("stream"), [key]) => db.xinfo_stream(key.as_slice()),
+                    (Some("groups"), [key]) => db.xinfo_groups(key.as_slice()),
+                    (Some("consumers"), [key, group]) => {
+                        db.xinfo_consumers(key.as_slice(), group.clone())
+                    }
+                    (Some(subcommand @ ("stream" | "groups" | "consumers")), _) => {
+                        OutputValue::Error(
+                            format!("ERR wrong number of arguments for 'xinfo|{}'", subcommand)
+                                .into_bytes(),
+                        )
+                    }
+                    _ => OutputValue::Error(b"ERR unknown subcommand for 'xinfo'".to_vec()),
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "xgroup",
+        SimpleCommand {
+            arity_min: 1,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Stream, AclCategory::Slow],
+            handler: &move |db, input| {
+                let (subcommand, rest) = input.split_first().unwrap();
+                let subcommand = subcommand.to_lower_string();
+                match (subcommand.as_deref(), rest) {
+                    (Some("create"), [key, group, id, options @ ..]) => {
+                        let start = match parse_group_start(id) {
+                            Ok(start) => start,
+                            Err(e) => return e,
+                        };
+                        let mut mkstream = false;
+                        let mut entries_read = None;
+                        let mut options = options.iter();
+                        while let Some(option) = options.next() {
+                            match option.to_lower_string().as_deref() {
+                                Some("mkstream") => mkstream = true,
+                                Some("entriesread") => match parse_entries_read(options.next()) {
+                                    Ok(n) => entries_read = n,
+                                    Err(e) => return e,
+                                },
+                                _ => return OutputValue::Error(b"ERR syntax error".to_vec()),
+                            }
+                        }
+                        db.xgroup_create(
+                            key.as_slice(),
+                            group.clone(),
+                            start,
+                            mkstream,
+                            entries_read,
+                        )
+                    }
+                    (Some("setid"), [key, group, id, options @ ..]) => {
+                        let start = match parse_group_start(id) {
+                            Ok(start) => start,
+                            Err(e) => return e,
+                        };
+                        let entries_read = match options {
+                            [] => None,
+                            [option, n]
+                                if option.to_lower_string().as_deref() == Some("entriesread") =>
+                            {
+                                match parse_entries_read(Some(n)) {
+                                    Ok(n) => n,
+                                    Err(e) => return e,
+                                }
+                            }
+                            _ => return OutputValue::Error(b"ERR syntax error".to_vec()),
+                        };
+                        db.xgroup_setid(key.as_slice(), group.clone(), start, entries_read)
+                    }
+                    (Some("destroy"), [key, group]) => {
+                        db.xgroup_destroy(key.as_slice(), group.clone())
+                    }
+                    (Some("createconsumer"), [key, group, consumer]) => {
+                        db.xgroup_createconsumer(key.as_slice(), group.clone(), consumer.clone())
+                    }
+                    (Some("delconsumer"), [key, group, consumer]) => {
+                        db.xgroup_delconsumer(key.as_slice(), group.clone(), consumer.clone())
+                    }
+                    (
+                        Some(
+                            subcommand @ ("create" | "setid" | "destroy" | "createconsumer"
+                            | "delconsumer"),
+                        ),
+                        _,
+                    ) => OutputValue::Error(
+                        format!("ERR wrong number of arguments for 'xgroup|{}'", subcommand)
+                            .into_bytes(),
+                    ),
+                    _ => OutputValue::Error(b"ERR unknown subcommand for 'xgroup'".to_vec()),
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "xack",
+        SimpleCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Stream, AclCategory::Fast],
+            handler: &move |db, input| {
+                let [key, group, ids @ ..] = input.as_slice() else {
+                    unreachable!();
+                };
+                let ids: Option<Vec<_>> = ids.iter().map(|id| parse_stream_id(id, 0)).collect();
+                match ids {
+                    Some(ids) => db.xack(key.as_slice(), group.clone(), ids),
+                    None => invalid_stream_id(),
+                }
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "xpending",
+        SimpleCommand {
+            arity_min: 2,
+            arity_max: Some(8),
+            category: &[AclCategory::Read, AclCategory::Stream, AclCategory::Slow],
+            handler: &move |db, input| {
+                let [key, group, rest @ ..] = input.as_slice() else {
+                    unreachable!();
+                };
+                if rest.is_empty() {
+                    return db.xpending(key.as_slice(), group.clone(), None);
+                }
+                let (min_idle, rest) = match rest {
+                    [option, min_idle, rest @ ..]
+                        if option.to_lower_string().as_deref() == Some("idle") =>
+                    {
+                        match min_idle.parse_into::<i64>() {
+                            Some(n) => (n.max(0) as u64, rest),
+                            None => {
+                                return OutputValue::Error(b"ERR value is not an integer".to_vec())
+                            }
+                        }
+                    }
+                    rest => (0, rest),
+                };
+                let (start, end, count, consumer) = match rest {
+                    [start, end, count] => (start, end, count, None),
+                    [start, end, count, consumer] => (start, end, count, Some(consumer.clone())),
+                    _ => return OutputValue::Error(b"ERR syntax error".to_vec()),
+                };
+                let start = match parse_range_bound(start, 0) {
+                    Ok(bound) => bound,
+                    Err(e) => return e,
+                };
+                let end = match parse_range_bound(end, u64::MAX) {
+                    Ok(bound) => bound,
+                    Err(e) => return e,
+                };
+                let Some(count) = count.parse_into::<i64>() else {
+                    return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                };
+                let range = XpendingRange {
+                    min_idle,
+                    start,
+                    end,
+                    count: count.max(0) as usize,
+                    consumer,
+                };
+                db.xpending(key.as_slice(), group.clone(), Some(range))
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "xclaim",
+        SimpleCommand {
+            arity_min: 5,
+            arity_max: None,
+            category: &[AclCategory::Write, AclCategory::Stream, AclCategory::Fast],
+            handler: &move |db, input| {
+                let [key, group, consumer, min_idle, rest @ ..] = input.as_slice() else {
+                    unreachable!();
+                };
+                let min_idle = match parse_milliseconds(min_idle, "min-idle-time", "XCLAIM") {
+                    Ok(n) => n,
+                    Err(e) => return e,
+                };
+                // the IDs run up to the first argument that is not one
+                let ids: Vec<_> = rest.iter().map_while(|id| parse_stream_id(id, 0)).collect();
+                if ids.is_empty() {
+                    return invalid_stream_id();
+                }
+                let mut options = XclaimOptions::default();
+                let mut rest = rest[ids.len()..].iter();
+                while let Some(option) = rest.next() {
+                    let option = option.to_lower_string();
+                    match option.as_deref() {
+                        Some("force") => options.force = true,
+                        Some("justid") => options.just_id = true,
+                        Some(name @ ("idle" | "time" | "retrycount")) => {
+                            let Some(value) = rest.next() else {
+                                return OutputValue::Error(b"ERR syntax error".to_vec());
+                            };
+                            let argument = format!("{} option", name.to_ascii_uppercase());
+                            let value = match parse_milliseconds(value, &argument, "XCLAIM") {
+                                Ok(n) => n,
+                                Err(e) => return e,
+                            };
+                            match name {
+                                "idle" => options.idle = Some(value),
+                                "time" => options.time = Some(value),
+                                _ => options.retry_count = Some(value),
+                            }
+                        }
+                        Some("lastid") => {
+                            let Some(id) = rest.next().and_then(|id| parse_stream_id(id, 0)) else {
+                                return invalid_stream_id();
+                            };
+                            options.last_id = Some(id);
+                        }
+                        _ => {
+                            return OutputValue::Error(
+                                format!(
+                                    "ERR Unrecognized XCLAIM option '{}'",
+                                    option.unwrap_or_default()
+                                )
+                                .into_bytes(),
+                            )
+                        }
+                    }
+                }
+                db.xclaim(
+                    key.as_slice(),
+                    group.clone(),
+                    consumer.clone(),
+                    min_idle,
+                    ids,
+                    options,
+                )
+            },
+        },
+    );
+    map.insert_without_duplicate(
+        "xautoclaim",
+        SimpleCommand {
+            arity_min: 5,
+            arity_max: Some(8),
+            category: &[AclCategory::Write, AclCategory::Stream, AclCategory::Fast],
+            handler: &move |db, input| {
+                let [key, group, consumer, min_idle, start, options @ ..] = input.as_slice() else {
+                    unreachable!();
+                };
+                let min_idle = match parse_milliseconds(min_idle, "min-idle-time", "XAUTOCLAIM") {
+                    Ok(n) => n,
+                    Err(e) => return e,
+                };
+                let start = match parse_range_bound(start, 0) {
+                    Ok(Bound::Unbounded) => StreamId::MIN,
+                    Ok(Bound::Included(id)) => id,
+                    Ok(Bound::Excluded(id)) if id == StreamId::MAX => {
+                        return OutputValue::Error(
+                            b"ERR invalid start ID for the interval".to_vec(),
+                        )
+                    }
+                    Ok(Bound::Excluded(id)) => match id.seq.checked_add(1) {
+                        Some(seq) => StreamId { ms: id.ms, seq },
+                        None => StreamId {
+                            ms: id.ms + 1,
+                            seq: 0,
+                        },
+                    },
+                    Err(e) => return e,
+                };
+                let mut count = 100;
+                let mut just_id = false;
+                let mut options = options.iter();
+                while let Some(option) = options.next() {
+                    match option.to_lower_string().as_deref() {
+                        Some("count") => {
+                            let Some(n) = options.next().map(|n| n.parse_into::<i64>()) else {
+                                return OutputValue::Error(b"ERR syntax error".to_vec());
+                            };
+                            count = match n {
+                                None => {
+                                    return OutputValue::Error(
+                                        b"ERR value is not an integer".to_vec(),
+                                    )
+                                }
+                                Some(n) if !(1..=i64::MAX / 10).contains(&n) => {
+                                    return OutputValue::Error(b"ERR COUNT must be > 0".to_vec())
+                                }
+                                Some(n) => n as usize,
+                            };
+                        }
+                        Some("justid") => just_id = true,
+                        _ => return OutputValue::Error(b"ERR syntax error".to_vec()),
+                    }
+                }
+                db.xautoclaim(
+                    key.as_slice(),
+                    group.clone(),
+                    consumer.clone(),
+                    min_idle,
+                    start,
+                    XautoclaimOptions { count, just_id },
+                )
+            },
+        },
+    );
+    map
+}
+
+pub(super) fn initialise_blocking_stream_commands<T: MapStreamCommands>(
+) -> HashMap<&'static str, BlockingCommand<T>> {
+    let mut map = HashMap::<_, BlockingCommand<T>>::new();
+    map.insert_without_duplicate(
+        "xread",
+        BlockingCommand {
+            arity_min: 3,
+            arity_max: None,
+            category: &[
+                AclCategory::Read,
+                AclCategory::Stream,
+                AclCategory::Slow,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| xread_handler(db, input),
+        },
+    );
+    map.insert_without_duplicate(
+        "xreadgroup",
+        BlockingCommand {
+            arity_min: 6,
+            arity_max: None,
+            category: &[
+                AclCategory::Write,
+                AclCategory::Stream,
+                AclCategory::Slow,
+                AclCategory::Blocking,
+            ],
+            handler: &move |db, input| xreadgroup_handler(db, input),
+        },
+    );
+    map
+}
+
+/// The options of XREAD, or of XREADGROUP after its GROUP, ending with the streams.
+struct ReadOptions<'a> {
+    count: Option<usize>,
+    /// BLOCK with its timeout, where `None` means forever.
+    block: Option<Option<Duration>>,
+    no_ack: bool,
+    keys: &'a [InputValue],
+    ids: &'a [InputValue],
+}
+
+fn parse_read_options<'a>(
+    input: &'a [InputValue],
+    command: &str,
+) -> Result<ReadOptions<'a>, OutputValue> {
+    let syntax_error = || OutputValue::Error(b"ERR syntax error".to_vec());
+    let mut options = ReadOptions {
+        count: None,
+        block: None,
+        no_ack: false,
+        keys: &[],
+        ids: &[],
+    };
+    let mut rest = input;
+    let streams = loop {
+        let Some((option, tail)) = rest.split_first() else {
+            return Err(syntax_error());
+        };
+        rest = match option.to_lower_string().as_deref() {
+            Some("count") => {
+                let Some((n, tail)) = tail.split_first() else {
+                    return Err(syntax_error());
+                };
+                let Some(n) = n.parse_into::<i64>() else {
+                    return Err(OutputValue::Error(b"ERR value is not an integer".to_vec()));
+                };
+                options.count = Some(n.max(0) as usize);
+                tail
+            }
+            Some("block") => {
+                let Some((timeout, tail)) = tail.split_first() else {
+                    return Err(syntax_error());
+                };
+                options.block = Some(parse_timeout_ms(timeout)?);
+                tail
+            }
+            Some("noack") if command == "xreadgroup" => {
+                options.no_ack = true;
+                tail
+            }
+            Some("streams") => break tail,
+            _ => return Err(syntax_error()),
+        };
+    };
+    if streams.is_empty() || streams.len() % 2 != 0 {
+        let special = if command == "xread" { "$" } else { ">" };
+        return Err(OutputValue::Error(
+            format!(
+                "ERR Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be \
+specified.",
+                command, special
+            )
+            .into_bytes(),
+        ));
+    }
+    (options.keys, options.ids) = streams.split_at(streams.len() / 2);
+    Ok(options)
+}
+
+fn xread_handler<T: MapStreamCommands>(db: &mut T, input: &[InputValue]) -> Blocking {
+    let options = match parse_read_options(input, "xread") {
+        Ok(options) => options,
+        Err(e) => return Blocking::Ready(e),
+    };
+    let ids_start = input.len() - options.ids.len();
+    let mut retry_input = None;
+    let mut streams = Vec::with_capacity(options.keys.len());
+    for (i, (key, id)) in options.keys.iter().zip(options.ids).enumerate() {
+        let id = match id.as_slice() {
+            b"$" => match db.stream_last_id(key.as_slice()) {
+                Ok(last_id) => {
+                    // once blocked, only entries added from now on count
+                    retry_input.get_or_insert_with(|| input.to_vec())[ids_start + i] =
+                        last_id.to_string().into_bytes();
+                    XreadId::After(last_id)
+                }
+                Err(e) => return Blocking::Ready(e),
+            },
+            b"+" => XreadId::LastEntry,
+            id => match parse_stream_id(id, 0) {
+                Some(id) => XreadId::After(id),
+                None => return Blocking::Ready(invalid_stream_id()),
+            },
+        };
+        streams.push((key.clone(), id));
+    }
+    match (db.xread(streams, options.count), options.block) {
+        (OutputValue::NullArray, Some(timeout)) => Blocking::Wait {
+            keys: options.keys.to_vec(),
+            timeout,
+            timeout_reply: OutputValue::NullArray,
+            retry_input,
+        },
+        (output, _) => Blocking::Ready(output),
+    }
+}
+
+/// XREADGROUP only blocks when every ID is `>` and there is nothing new to deliver.
+fn xreadgroup_handler<T: MapStreamCommands>(db: &mut T, input: &[InputValue]) -> Blocking {
+    let [option, group, consumer, rest @ ..] = input else {
+        unreachable!();
+    };
+    if option.to_lower_string().as_deref() != Some("group") {
+        return Blocking::Ready(OutputValue::Error(b"ERR syntax error".to_vec()));
+    }
+    let options = match parse_read_options(rest, "xreadgroup") {
+        Ok(options) => options,
+        Err(e) => return Blocking::Ready(e),
+    };
+    let mut streams = Vec::with_capacity(options.keys.len());
+    for (key, id) in options.keys.iter().zip(options.ids) {
+        let id = match id.as_slice() {
+            b">" => ReadGroupId::New,
+            id => match parse_stream_id(id, 0) {
+                Some(id) => ReadGroupId::History(id),
+                None => return Blocking::Ready(invalid_stream_id()),
+            },
+        };
+        streams.push((key.clone(), id));
+    }
+    let output = db.xreadgroup(
+        group.clone(),
+        consumer.clone(),
+        streams,
+        options.count,
+        options.no_ack,
+    );
+    match (output, options.block) {
+        (OutputValue::NullArray, Some(timeout)) => Blocking::Wait {
+            keys: options.keys.to_vec(),
+            timeout,
+            timeout_reply: OutputValue::NullArray,
+            retry_input: None,
+        },
+        (output, _) => Blocking::Ready(output),
+    }
+}
+
+/// XRANGE takes `start end`, XREVRANGE `end start`, both followed by `[COUNT count]`.
+fn xrange_handler<T: MapStreamCommands>(
+    db: &mut T,
+    input: Vec<InputValue>,
+    reverse: bool,
+) -> OutputValue {
+    let (key, first, second) = match input.as_slice() {
+        [key, first, second, ..] => (key, first, second),
+        _ => unreachable!(),
+    };
+    let (start, end) = if reverse {
+        (second, first)
+    } else {
+        (first, second)
+    };
+    let start = match parse_range_bound(start, 0) {
+        Ok(bound) => bound,
+        Err(e) => return e,
+    };
+    let end = match parse_range_bound(end, u64::MAX) {
+        Ok(bound) => bound,
+        Err(e) => return e,
+    };
+    let count = match &input[3..] {
+        [] => None,
+        [option, count] if option.to_lower_string().as_deref() == Some("count") => {
+            match count.parse_into::<i64>() {
+                Some(count) => Some(count.max(0) as usize),
+                None => return OutputValue::Error(b"ERR value is not an integer".to_vec()),
+            }
+        }
+        _ => return OutputValue::Error(b"ERR syntax error".to_vec()),
+    };
+    db.xrange(key.as_slice(), start, end, count, reverse)
+}
@@ -0,0 +1,161 @@
+//! The parameters that CONFIG GET and CONFIG SET expose.
+
+use std::time::Duration;
+
+use super::glob;
+use crate::interface::types::OutputValue;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// How many times per second background housekeeping runs.
+    pub hz: u32,
+    /// From 1 to 10, how much work the active expire cycle may do to reclaim expired keys.
+    pub active_expire_effort: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            hz: 10,
+            active_expire_effort: 1,
+        }
+    }
+}
+
+impl Config {
+    fn parameters(&self) -> [(&'static str, u32); 2] {
+        [
+            ("hz", self.hz),
+            ("active-expire-effort", self.active_expire_effort),
+        ]
+    }
+
+    /// The parameters matching any of `patterns` and their values, flattened as CONFIG GET replies.
+    pub fn get(&self, patterns: &[Vec<u8>]) -> OutputValue {
+        let finders: Vec<_> = patterns.iter().map(|p| glob::Finder::new(p)).collect();
+        OutputValue::Array(
+            self.parameters()
+                .into_iter()
+                .filter(|(name, _)| finders.iter().any(|f| f.it_matches(name.as_bytes())))
+                .flat_map(|(name, value)| {
+                    [
+                        OutputValue::BulkString(name.as_bytes().to_vec()),
+                        OutputValue::BulkString(value.to_string().into_bytes()),
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    /// Applies `name value` pairs, all or none of them.
+    pub fn set(&mut self, pairs: &[Vec<u8>]) -> OutputValue {
+        let mut updated = self.clone();
+        for pair in pairs.chunks(2) {
+            let [name, value] = pair else {
+                unreachable!("the arguments come in pairs")
+            };
+            let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+            // values out of `range` are rejected, and those in it are then clamped to `limits`
+            let (field, range, limits) = match name.as_str() {
+                // like Redis, any int is taken and brought within what the timer supports
+                "hz" => (&mut updated.hz, 0..=i32::MAX as u32, 1..=500),
+                "active-expire-effort" => (&mut updated.active_expire_effort, 1..=10, 1..=10),
+                _ => {
+                    return OutputValue::Error(
+                        format!(
+                            "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                            name
+                        )
+                        .into_bytes(),
+                    )
+                }
+            };
+            match std::str::from_utf8(value).ok().and_then(|v| v.parse().ok()) {
+                Some(v) if range.contains(&v) => *field = v.clamp(*limits.start(), *limits.end()),
+                _ => {
+                    return OutputValue::Error(
+                        format!(
+                            "ERR CONFIG SET failed (possibly related to argument '{}') - argument \
+                             must be between {} and {} inclusive",
+                            name,
+                            range.start(),
+                            range.end()
+                        )
+                        .into_bytes(),
+                    )
+                }
+            }
+        }
+        *self = updated;
+        OutputValue::Ok
+    }
+
+    /// The time the active expire cycle may take each time it runs: a quarter of the
+    /// interval between runs, and a little more with every step of effort.
+    pub fn expire_cycle_budget(&self) -> Duration {
+        let percent = 25 + 2 * (self.active_expire_effort - 1);
+        Duration::from_micros(1_000_000 * percent as u64 / self.hz as u64 / 100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let mut config = Config::default();
+        assert_eq!(config.expire_cycle_budget(), Duration::from_millis(25));
+        assert_eq!(
+            config.set(&[b"HZ".to_vec(), b"100".to_vec()]),
+            OutputValue::Ok
+        );
+        assert_eq!(
+            config.get(&[b"h?".to_vec()]),
+            OutputValue::Array(vec![
+                OutputValue::BulkString(b"hz".to_vec()),
+                OutputValue::BulkString(b"100".to_vec()),
+            ])
+        );
+        // nothing is applied if any of the values is rejected
+        assert_eq!(
+            config.set(&[
+                b"hz".to_vec(),
+                b"10".to_vec(),
+                b"active-expire-effort".to_vec(),
+                b"11".to_vec(),
+            ]),
+            OutputValue::Error(
+                b"ERR CONFIG SET failed (possibly related to argument 'active-expire-effort') - \
+                  argument must be between 1 and 10 inclusive"
+                    .to_vec()
+            )
+        );
+        assert_eq!(config.hz, 100);
+        for (value, clamped) in [("600", 500), ("0", 1)] {
+            assert_eq!(
+                config.set(&[b"hz".to_vec(), value.as_bytes().to_vec()]),
+                OutputValue::Ok
+            );
+            assert_eq!(config.hz, clamped);
+        }
+        assert_eq!(
+            config.set(&[b"hz".to_vec(), b"-1".to_vec()]),
+            OutputValue::Error(
+                b"ERR CONFIG SET failed (possibly related to argument 'hz') - argument must be \
+                  between 0 and 2147483647 inclusive"
+                    .to_vec()
+            )
+        );
+        config.set(&[b"hz".to_vec(), b"100".to_vec()]);
+        assert_eq!(
+            config.set(&[b"active-expire-effort".to_vec(), b"10".to_vec()]),
+            OutputValue::Ok
+        );
+        assert_eq!(config.expire_cycle_budget(), Duration::from_micros(4_300));
+        assert_eq!(
+            config.get(&[b"maxmemory".to_vec()]),
+            OutputValue::Array(vec![])
+        );
+    }
+}
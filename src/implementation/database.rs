@@ -1,5 +1,16 @@
-use crate::interface::database::{map::IMap, IDatabase, IDatabaseWithInner};
+use std::time::Instant;
 
+use crate::interface::database::map::{ExpireCycleReport, IMap};
+use crate::interface::database::{IDatabase, IDatabaseWithInner};
+
+mod bitmap;
+mod clock;
+mod dict;
+mod expires;
+pub mod float;
+mod geohash;
+mod hyperloglog;
+mod lcs;
 mod map;
 mod value;
 
@@ -8,13 +19,48 @@ pub use map::Map;
 #[derive(Debug)]
 pub struct Database<I: Default> {
     db: Vec<I>,
+    /// The database the next active expire cycle starts from, so that one cut short by its
+    /// deadline doesn't starve the ones after it.
+    expire_cursor: usize,
 }
 
 impl<I: Default> Database<I> {
     pub fn new(db_count: usize) -> Self {
         Self {
             db: std::iter::repeat_with(I::default).take(db_count).collect(),
+            expire_cursor: 0,
+        }
+    }
+}
+
+impl<I: Default + IMap> Database<I> {
+    /// Runs the active expire cycle in every database in turn, until `deadline` passes.
+    pub fn active_expire_cycle(&mut self, effort: u32, deadline: Instant) -> ExpireCycleReport {
+        let mut total = ExpireCycleReport::default();
+        for _ in 0..self.db.len() {
+            let report = self.db[self.expire_cursor].active_expire_cycle(effort, deadline);
+            total.sampled += report.sampled;
+            total.expired += report.expired;
+            if report.timed_out {
+                total.timed_out = true;
+                break;
+            }
+            self.expire_cursor = (self.expire_cursor + 1) % self.db.len();
         }
+        total
+    }
+
+    /// Moves every database along in any resize of its keyspace until `deadline` passes,
+    /// although each of them takes at least one step.
+    pub fn rehash_keyspaces(&mut self, deadline: Instant) {
+        for db in &mut self.db {
+            db.rehash_keyspace(deadline);
+        }
+    }
+
+    /// How many keys have been deleted across all databases because their TTL ran out.
+    pub fn expired_keys(&self) -> u64 {
+        self.db.iter().map(IMap::expired_keys).sum()
     }
 }
 
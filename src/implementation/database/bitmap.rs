@@ -0,0 +1,241 @@
+//! Bit-level access to strings. As in Redis, bit 0 is the most significant bit of the first
+//! byte, and strings are considered to be padded with zeros forever.
+
+use crate::interface::database::map::{BitOperation, BitfieldOverflow, BitfieldType};
+
+fn byte_and_mask(offset: u64) -> (usize, u8) {
+    ((offset / 8) as usize, 0x80 >> (offset % 8))
+}
+
+pub fn get_bit(bytes: &[u8], offset: u64) -> bool {
+    let (byte, mask) = byte_and_mask(offset);
+    bytes.get(byte).is_some_and(|b| b & mask != 0)
+}
+
+/// Sets the bit at `offset`, growing `bytes` if needed, and returns its previous value.
+pub fn set_bit(bytes: &mut Vec<u8>, offset: u64, value: bool) -> bool {
+    let (byte, mask) = byte_and_mask(offset);
+    if byte >= bytes.len() {
+        bytes.resize(byte + 1, 0);
+    }
+    let old = bytes[byte] & mask != 0;
+    if value {
+        bytes[byte] |= mask;
+    } else {
+        bytes[byte] &= !mask;
+    }
+    old
+}
+
+/// Reads the field at `offset`, sign-extending it if it is signed.
+pub fn get_field(bytes: &[u8], offset: u64, field: BitfieldType) -> i64 {
+    let raw =
+        (0..field.bits as u64).fold(0u64, |acc, i| acc << 1 | get_bit(bytes, offset + i) as u64);
+    let unused = 64 - field.bits;
+    if field.signed {
+        ((raw << unused) as i64) >> unused
+    } else {
+        raw as i64
+    }
+}
+
+/// Writes the low bits of `value` to the field at `offset`, growing `bytes` if needed.
+pub fn set_field(bytes: &mut Vec<u8>, offset: u64, field: BitfieldType, value: i64) {
+    for i in 0..field.bits {
+        let bit = (value as u64 >> (field.bits - 1 - i)) & 1 != 0;
+        set_bit(bytes, offset + i as u64, bit);
+    }
+}
+
+/// Fits `value` into `field` according to `overflow`: wrapping around, saturating, or `None`
+/// for FAIL.
+pub fn fit_field(field: BitfieldType, value: i128, overflow: BitfieldOverflow) -> Option<i64> {
+    let (min, max) = if field.signed {
+        (
+            -(1i128 << (field.bits - 1)),
+            (1i128 << (field.bits - 1)) - 1,
+        )
+    } else {
+        (0, (1i128 << field.bits) - 1)
+    };
+    if (min..=max).contains(&value) {
+        return Some(value as i64);
+    }
+    match overflow {
+        BitfieldOverflow::Wrap => {
+            let unused = 128 - field.bits;
+            Some(if field.signed {
+                (value << unused) >> unused
+            } else {
+                ((value as u128) << unused >> unused) as i128
+            } as i64)
+        }
+        BitfieldOverflow::Sat => Some(if value > max { max } else { min } as i64),
+        BitfieldOverflow::Fail => None,
+    }
+}
+
+/// Iterates over the bytes holding the bits `first..=last`, each with a mask of the bits of
+/// the range it holds. The range must be within `bytes`.
+fn masked_bytes(bytes: &[u8], first: u64, last: u64) -> impl Iterator<Item = (usize, u8, u8)> + '_ {
+    let (first_byte, last_byte) = ((first / 8) as usize, (last / 8) as usize);
+    bytes[first_byte..=last_byte]
+        .iter()
+        .enumerate()
+        .map(move |(i, byte)| {
+            let i = first_byte + i;
+            let mut mask = 0xff;
+            if i == first_byte {
+                mask &= 0xff >> (first % 8);
+            }
+            if i == last_byte {
+                mask &= 0xff << (7 - last % 8);
+            }
+            (i, *byte, mask)
+        })
+}
+
+/// Counts the bits set in `first..=last`.
+pub fn count(bytes: &[u8], first: u64, last: u64) -> u64 {
+    masked_bytes(bytes, first, last)
+        .map(|(_, byte, mask)| (byte & mask).count_ones() as u64)
+        .sum()
+}
+
+/// Finds the first bit of `first..=last` that is `bit`.
+pub fn position(bytes: &[u8], bit: bool, first: u64, last: u64) -> Option<u64> {
+    masked_bytes(bytes, first, last).find_map(|(i, byte, mask)| {
+        let matching = if bit { byte } else { !byte } & mask;
+        (matching != 0).then(|| i as u64 * 8 + matching.leading_zeros() as u64)
+    })
+}
+
+/// Combines `sources` as BITOP does; shorter sources are padded with zeros.
+pub fn operate(operation: BitOperation, sources: &[&[u8]]) -> Vec<u8> {
+    let len = sources.iter().map(|s| s.len()).max().unwrap_or(0);
+    let byte = |source: &[u8], i: usize| source.get(i).copied().unwrap_or(0);
+    let (first, rest) = sources.split_first().expect("at least one source");
+    (0..len)
+        .map(|i| {
+            let others = || rest.iter().fold(0, |acc, s| acc | byte(s, i));
+            match operation {
+                BitOperation::And => sources.iter().fold(0xff, |acc, s| acc & byte(s, i)),
+                BitOperation::Or => sources.iter().fold(0, |acc, s| acc | byte(s, i)),
+                BitOperation::Xor => sources.iter().fold(0, |acc, s| acc ^ byte(s, i)),
+                BitOperation::Not => !byte(first, i),
+                BitOperation::Diff => byte(first, i) & !others(),
+                BitOperation::AndOr => byte(first, i) & others(),
+                BitOperation::One => {
+                    let (mut once, mut more) = (0, 0);
+                    for source in sources {
+                        let b = byte(source, i);
+                        more |= once & b;
+                        once = (once ^ b) & !more;
+                    }
+                    once
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_get_bit() {
+        let mut bytes = vec![];
+        assert!(!set_bit(&mut bytes, 7, true));
+        assert_eq!(bytes, [0x01]);
+        assert!(!set_bit(&mut bytes, 17, true));
+        assert_eq!(bytes, [0x01, 0x00, 0x40]);
+        assert!(set_bit(&mut bytes, 7, false));
+        assert!(!get_bit(&bytes, 7));
+        assert!(get_bit(&bytes, 17));
+        assert!(!get_bit(&bytes, 1000));
+    }
+
+    #[test]
+    fn test_fields() {
+        let (i8, u4, i64) = (
+            BitfieldType {
+                signed: true,
+                bits: 8,
+            },
+            BitfieldType {
+                signed: false,
+                bits: 4,
+            },
+            BitfieldType {
+                signed: true,
+                bits: 64,
+            },
+        );
+        let mut bytes = vec![];
+        set_field(&mut bytes, 4, i8, -2);
+        assert_eq!(bytes, [0x0f, 0xe0]);
+        assert_eq!(get_field(&bytes, 4, i8), -2);
+        assert_eq!(get_field(&bytes, 4, u4), 15);
+        set_field(&mut bytes, 0, i64, i64::MIN);
+        assert_eq!(get_field(&bytes, 0, i64), i64::MIN);
+    }
+
+    #[test]
+    fn test_fit_field() {
+        let (i8, u2) = (
+            BitfieldType {
+                signed: true,
+                bits: 8,
+            },
+            BitfieldType {
+                signed: false,
+                bits: 2,
+            },
+        );
+        let wrap = BitfieldOverflow::Wrap;
+        let sat = BitfieldOverflow::Sat;
+        assert_eq!(fit_field(i8, 127, wrap), Some(127));
+        assert_eq!(fit_field(i8, 128, wrap), Some(-128));
+        assert_eq!(fit_field(i8, -129, wrap), Some(127));
+        assert_eq!(fit_field(i8, 300, sat), Some(127));
+        assert_eq!(fit_field(i8, -300, sat), Some(-128));
+        assert_eq!(fit_field(u2, 5, wrap), Some(1));
+        assert_eq!(fit_field(u2, -1, wrap), Some(3));
+        assert_eq!(fit_field(u2, -1, sat), Some(0));
+        assert_eq!(fit_field(u2, 4, BitfieldOverflow::Fail), None);
+        let i64 = BitfieldType {
+            signed: true,
+            bits: 64,
+        };
+        assert_eq!(fit_field(i64, i64::MAX as i128 + 1, wrap), Some(i64::MIN));
+    }
+
+    #[test]
+    fn test_count_and_position() {
+        let bytes = b"\xff\xf0\x00";
+        assert_eq!(count(bytes, 0, 23), 12);
+        assert_eq!(count(bytes, 4, 9), 6);
+        assert_eq!(count(bytes, 12, 23), 0);
+        assert_eq!(position(bytes, false, 0, 23), Some(12));
+        assert_eq!(position(bytes, true, 3, 23), Some(3));
+        assert_eq!(position(bytes, true, 12, 23), None);
+        assert_eq!(position(bytes, false, 2, 10), None);
+    }
+
+    #[test]
+    fn test_operate() {
+        let (a, b, c) = (
+            b"\xff\x0f".as_slice(),
+            b"\xf0".as_slice(),
+            b"\x3c\x01".as_slice(),
+        );
+        assert_eq!(operate(BitOperation::And, &[a, b]), [0xf0, 0x00]);
+        assert_eq!(operate(BitOperation::Or, &[a, b]), [0xff, 0x0f]);
+        assert_eq!(operate(BitOperation::Xor, &[a, b]), [0x0f, 0x0f]);
+        assert_eq!(operate(BitOperation::Not, &[b]), [0x0f]);
+        assert_eq!(operate(BitOperation::Diff, &[a, b, c]), [0x03, 0x0e]);
+        assert_eq!(operate(BitOperation::AndOr, &[a, b, c]), [0xfc, 0x01]);
+        assert_eq!(operate(BitOperation::One, &[a, b, c]), [0x03, 0x0e]);
+    }
+}
@@ -0,0 +1,27 @@
+#[cfg(test)]
+use std::cell::Cell;
+#[cfg(test)]
+use std::rc::Rc;
+
+/// Source of the current time, in milliseconds since the Unix epoch.
+#[derive(Clone, Debug, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    /// A clock that only moves when the test tells it to.
+    #[cfg(test)]
+    Manual(Rc<Cell<u64>>),
+}
+
+impl Clock {
+    pub fn now_ms(&self) -> u64 {
+        match self {
+            Clock::System => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("the system clock should be after 1970")
+                .as_millis() as u64,
+            #[cfg(test)]
+            Clock::Manual(now) => now.get(),
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! A hash table that can be walked with a cursor while it changes, as the SCAN family does,
+//! and that is resized a few buckets at a time rather than all at once.
+//!
+//! The buckets are counted in powers of two, so a key's bucket is given by the low bits of its
+//! hash, and the cursor counts up from the high bits down. When the table grows, every bucket
+//! splits into buckets that share its low bits, which are all visited after it; when it
+//! shrinks, buckets merge into one that was not visited yet unless all of them were. Either
+//! way, a key that is in the table for the whole walk is returned, although some may be
+//! returned twice.
+//!
+//! A resize allocates a second table, and every write then moves one bucket of the first into
+//! it, as does the server cron through [`Dict::rehash_for`]. Until the first table is empty,
+//! lookups check both tables, new entries go to the second one, and a scan visits the cursor's
+//! bucket in the smaller table along with every bucket it expands to in the larger one. This
+//! is Redis's `dict` and `dictScan`.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::time::Instant;
+
+use rand::Rng;
+
+/// The fewest buckets a table has once something has been inserted.
+const MIN_BUCKETS: usize = 4;
+
+/// Empty buckets a rehash step may skip for every bucket it is asked to move, as in Redis, so
+/// that a sparse table does not make a step slow.
+const EMPTY_VISITS_PER_BUCKET: usize = 10;
+
+type Table<K, V> = Vec<Vec<(K, V)>>;
+
+#[derive(Clone)]
+pub struct Dict<K, V> {
+    /// The table in use, and the one being filled while resizing.
+    tables: [Table<K, V>; 2],
+    /// While resizing, the next bucket of the first table to move into the second.
+    rehash_index: Option<usize>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<K, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self {
+            tables: [Vec::new(), Vec::new()],
+            rehash_index: None,
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for Dict<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.tables = [Vec::new(), Vec::new()];
+        self.rehash_index = None;
+        self.len = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.tables.iter().flatten().flatten().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.tables.iter().flatten().flatten().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.tables.iter().flatten().flatten().map(|(_, v)| v)
+    }
+
+    /// Passes every entry of the buckets `cursor` points to to `f`, and returns the cursor of
+    /// the next buckets to visit, or 0 once the walk that started from 0 is over.
+    pub fn scan<'a>(&'a self, cursor: u64, mut f: impl FnMut(&'a K, &'a V)) -> u64 {
+        let mut visit = |bucket: &'a Vec<(K, V)>| {
+            for (k, v) in bucket {
+                f(k, v);
+            }
+        };
+        let [first, second] = &self.tables;
+        if first.is_empty() {
+            return 0;
+        }
+        if self.rehash_index.is_none() {
+            let mask = (first.len() - 1) as u64;
+            visit(&first[(cursor & mask) as usize]);
+            return next_cursor(cursor, mask);
+        }
+        let (small, large) = if first.len() <= second.len() {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        let (small_mask, large_mask) = ((small.len() - 1) as u64, (large.len() - 1) as u64);
+        visit(&small[(cursor & small_mask) as usize]);
+        // the buckets of the larger table whose low bits are those of the smaller one's
+        let mut cursor = cursor;
+        loop {
+            visit(&large[(cursor & large_mask) as usize]);
+            cursor = next_cursor(cursor, large_mask);
+            if cursor & (small_mask ^ large_mask) == 0 {
+                return cursor;
+            }
+        }
+    }
+
+    /// An entry picked at random, which is fair between buckets rather than between entries.
+    pub fn random(&self, rng: &mut impl Rng) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let [first, second] = &self.tables;
+        // the buckets of the first table before the rehash index have been emptied
+        let start = self.rehash_index.unwrap_or(0);
+        loop {
+            let i = rng.gen_range(start..first.len() + second.len());
+            let bucket = match first.get(i) {
+                Some(bucket) => bucket,
+                None => &second[i - first.len()],
+            };
+            if !bucket.is_empty() {
+                let (k, v) = &bucket[rng.gen_range(0..bucket.len())];
+                return Some((k, v));
+            }
+        }
+    }
+}
+
+/// Adds one to the bits of `cursor` under `mask`, starting from the highest of them.
+fn next_cursor(cursor: u64, mask: u64) -> u64 {
+    (cursor | !mask)
+        .reverse_bits()
+        .wrapping_add(1)
+        .reverse_bits()
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    fn bucket_of<Q: Hash + ?Sized>(&self, table: usize, key: &Q) -> usize {
+        self.hasher.hash_one(key) as usize & (self.tables[table].len() - 1)
+    }
+
+    /// Where `key` is, as the indices of its table, its bucket and its entry in that bucket.
+    fn find<Q>(&self, key: &Q) -> Option<(usize, usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let tables = if self.rehash_index.is_some() { 2 } else { 1 };
+        (0..tables)
+            .filter(|&table| !self.tables[table].is_empty())
+            .find_map(|table| {
+                let bucket = self.bucket_of(table, key);
+                let index = self.tables[table][bucket]
+                    .iter()
+                    .position(|(k, _)| k.borrow() == key)?;
+                Some((table, bucket, index))
+            })
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (table, bucket, index) = self.find(key)?;
+        Some(&self.tables[table][bucket][index].1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash(1);
+        let (table, bucket, index) = self.find(key)?;
+        Some(&mut self.tables[table][bucket][index].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Inserts `value` at `key`, returning the value it replaces.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(std::mem::replace(old, value));
+        }
+        self.push(key, value);
+        None
+    }
+
+    pub fn get_or_insert_with(&mut self, key: K, default: impl FnOnce() -> V) -> &mut V {
+        self.rehash(1);
+        let (table, bucket, index) = match self.find(&key) {
+            Some(found) => found,
+            None => self.push(key, default()),
+        };
+        &mut self.tables[table][bucket][index].1
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash(1);
+        let (table, bucket, index) = self.find(key)?;
+        let entry = self.tables[table][bucket].swap_remove(index);
+        self.len -= 1;
+        self.shrink_if_sparse();
+        Some(entry)
+    }
+
+    /// Like Redis, starts shrinking only once less than an eighth of the buckets are used.
+    fn shrink_if_sparse(&mut self) {
+        let buckets = self.tables[0].len();
+        if self.rehash_index.is_none() && buckets > MIN_BUCKETS && self.len * 8 < buckets {
+            self.start_resize(self.len.next_power_of_two().max(MIN_BUCKETS));
+        }
+    }
+
+    /// Adds an entry for a key known to be missing, starting to grow the table once there is
+    /// more than one entry per bucket on average. Returns where the entry went.
+    fn push(&mut self, key: K, value: V) -> (usize, usize, usize) {
+        if self.tables[0].is_empty() {
+            self.tables[0] = empty_table(MIN_BUCKETS);
+        } else if self.rehash_index.is_none() && self.len >= self.tables[0].len() {
+            self.start_resize((self.len + 1).next_power_of_two());
+        }
+        let table = if self.rehash_index.is_some() { 1 } else { 0 };
+        let bucket = self.bucket_of(table, &key);
+        self.tables[table][bucket].push((key, value));
+        self.len += 1;
+        (table, bucket, self.tables[table][bucket].len() - 1)
+    }
+
+    fn start_resize(&mut self, buckets: usize) {
+        debug_assert!(buckets.is_power_of_two());
+        self.tables[1] = empty_table(buckets);
+        self.rehash_index = Some(0);
+    }
+
+    /// Moves up to `buckets` non-empty buckets into the new table, if a resize is going on.
+    /// Returns whether there is more to move.
+    pub fn rehash(&mut self, buckets: usize) -> bool {
+        let Some(mut index) = self.rehash_index else {
+            return false;
+        };
+        let mut empty_visits = buckets * EMPTY_VISITS_PER_BUCKET;
+        let mut moved = 0;
+        while moved < buckets && index < self.tables[0].len() {
+            let bucket = std::mem::take(&mut self.tables[0][index]);
+            index += 1;
+            if bucket.is_empty() {
+                empty_visits -= 1;
+                if empty_visits == 0 {
+                    break;
+                }
+                continue;
+            }
+            for (key, value) in bucket {
+                let target = self.bucket_of(1, &key);
+                self.tables[1][target].push((key, value));
+            }
+            moved += 1;
+        }
+        if index < self.tables[0].len() {
+            self.rehash_index = Some(index);
+            return true;
+        }
+        self.tables[0] = std::mem::take(&mut self.tables[1]);
+        self.rehash_index = None;
+        // entries may have been removed faster than the table shrank
+        self.shrink_if_sparse();
+        self.rehash_index.is_some()
+    }
+
+    /// Moves buckets into the new table a hundred at a time until the resize is over or
+    /// `deadline` passes, as the server cron does for the keyspace.
+    pub fn rehash_for(&mut self, deadline: Instant) {
+        while self.rehash(100) && Instant::now() < deadline {}
+    }
+}
+
+fn empty_table<K, V>(buckets: usize) -> Table<K, V> {
+    (0..buckets).map(|_| Vec::new()).collect()
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for Dict<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut dict = Self::new();
+        for (key, value) in iter {
+            dict.insert(key, value);
+        }
+        dict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// Walks `dict` from cursor 0, calling `between` after every step.
+    fn walk(
+        dict: &mut Dict<u32, ()>,
+        mut between: impl FnMut(&mut Dict<u32, ()>, usize),
+    ) -> Vec<u32> {
+        let mut seen = vec![];
+        let mut cursor = 0;
+        let mut step = 0;
+        loop {
+            cursor = dict.scan(cursor, |k, _| seen.push(*k));
+            if cursor == 0 {
+                return seen;
+            }
+            between(dict, step);
+            step += 1;
+        }
+    }
+
+    fn finish_rehash<K: Hash + Eq, V>(dict: &mut Dict<K, V>) {
+        while dict.rehash(100) {}
+    }
+
+    #[test]
+    fn test_dict() {
+        let mut dict = Dict::new();
+        assert_eq!(dict.insert(b"a".to_vec(), 1), None);
+        assert_eq!(dict.insert(b"a".to_vec(), 2), Some(1));
+        *dict.get_or_insert_with(b"b".to_vec(), || 0) += 5;
+        *dict.get_or_insert_with(b"b".to_vec(), || 0) += 5;
+        assert_eq!(dict.get(b"a".as_slice()), Some(&2));
+        assert_eq!(dict.get(b"b".as_slice()), Some(&10));
+        assert_eq!(dict.len(), 2);
+        assert_eq!(dict.remove(b"a".as_slice()), Some(2));
+        assert!(!dict.contains_key(b"a".as_slice()));
+        assert_eq!(dict.len(), 1);
+    }
+
+    #[test]
+    fn test_dict_resizes() {
+        let mut dict: Dict<u32, ()> = (0..1000).map(|i| (i, ())).collect();
+        finish_rehash(&mut dict);
+        assert_eq!(dict.tables[0].len(), 1024);
+        for i in 0..990 {
+            dict.remove(&i);
+        }
+        finish_rehash(&mut dict);
+        assert_eq!(dict.tables[0].len(), 16);
+        assert_eq!(
+            dict.keys().copied().collect::<HashSet<_>>(),
+            (990..1000).collect()
+        );
+    }
+
+    #[test]
+    fn test_resize_is_spread_over_writes() {
+        let mut dict: Dict<u32, ()> = (0..512).map(|i| (i, ())).collect();
+        finish_rehash(&mut dict);
+        // the next key starts a resize, which each write then takes one bucket further
+        dict.insert(512, ());
+        assert_eq!(dict.tables[1].len(), 1024);
+        assert!(dict.rehash_index.is_some());
+        for i in 513..600 {
+            dict.insert(i, ());
+        }
+        assert!(!dict.tables[0].iter().all(Vec::is_empty));
+        assert!(!dict.tables[1].iter().all(Vec::is_empty));
+        assert!((0..600).all(|i| dict.contains_key(&i)));
+        assert_eq!(dict.iter().count(), 600);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let picked: HashSet<_> = (0..10_000)
+            .map(|_| *dict.random(&mut rng).unwrap().0)
+            .collect();
+        assert!(picked.iter().all(|k| *k < 600));
+        assert!(picked.len() > 500);
+        assert!(!dict.rehash(1024));
+        assert!(dict.tables[1].is_empty());
+        assert!((0..600).all(|i| dict.contains_key(&i)));
+    }
+
+    #[test]
+    fn test_scan_visits_every_key_once_without_changes() {
+        let mut dict: Dict<u32, ()> = (0..100).map(|i| (i, ())).collect();
+        finish_rehash(&mut dict);
+        let mut seen = walk(&mut dict, |_, _| {});
+        seen.sort_unstable();
+        assert_eq!(seen, (0..100).collect::<Vec<_>>());
+        assert_eq!(Dict::<u32, ()>::new().scan(0, |_, _| unreachable!()), 0);
+    }
+
+    #[test]
+    fn test_scan_visits_both_tables_while_resizing() {
+        for (len, grow) in [(64, true), (64, false)] {
+            let mut dict: Dict<u32, ()> = (0..len).map(|i| (i, ())).collect();
+            finish_rehash(&mut dict);
+            if grow {
+                dict.insert(len, ());
+            } else {
+                for i in 4..=len {
+                    dict.remove(&i);
+                }
+            }
+            assert!(dict.rehash_index.is_some());
+            let expected: HashSet<u32> = dict.keys().copied().collect();
+            let seen = walk(&mut dict, |_, _| {});
+            assert_eq!(seen.len(), expected.len());
+            assert_eq!(seen.into_iter().collect::<HashSet<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_scan_survives_growing() {
+        let mut dict: Dict<u32, ()> = (0..20).map(|i| (i, ())).collect();
+        let seen: HashSet<_> = walk(&mut dict, |dict, step| {
+            // the walk would never end if the table kept growing
+            if step < 10 {
+                for i in 0..50 {
+                    dict.insert(1000 + step as u32 * 50 + i, ());
+                }
+            }
+        })
+        .into_iter()
+        .collect();
+        finish_rehash(&mut dict);
+        assert!(dict.tables[0].len() >= 512);
+        assert!((0..20).all(|i| seen.contains(&i)));
+    }
+
+    #[test]
+    fn test_scan_survives_shrinking() {
+        let mut dict: Dict<u32, ()> = (0..1000).map(|i| (i, ())).collect();
+        let mut next = 100;
+        let seen: HashSet<_> = walk(&mut dict, |dict, _| {
+            for _ in 0..50 {
+                if next < 1000 {
+                    dict.remove(&next);
+                    next += 1;
+                }
+            }
+        })
+        .into_iter()
+        .collect();
+        finish_rehash(&mut dict);
+        assert!(dict.tables[0].len() < 1024);
+        assert!((0..100).all(|i| seen.contains(&i)));
+    }
+}
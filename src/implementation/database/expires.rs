@@ -0,0 +1,104 @@
+//! The deadlines of the keys of a database that have a TTL.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+/// Deadlines in milliseconds since the Unix epoch, kept in a vector as well so that the
+/// active expire cycle can pick keys at random without a scan.
+#[derive(Debug, Default)]
+pub struct Expires {
+    entries: Vec<(Vec<u8>, u64)>,
+    positions: HashMap<Vec<u8>, usize>,
+}
+
+impl Expires {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<u64> {
+        self.positions.get(key).map(|&i| self.entries[i].1)
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, at: u64) {
+        match self.positions.get(&key) {
+            Some(&i) => self.entries[i].1 = at,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, at));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<u64> {
+        let i = self.positions.remove(key)?;
+        let (_, at) = self.entries.swap_remove(i);
+        if let Some((moved, _)) = self.entries.get(i) {
+            *self.positions.get_mut(moved).unwrap() = i;
+        }
+        Some(at)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.positions.clear();
+    }
+
+    /// A key picked uniformly at random, along with its deadline.
+    pub fn random(&self, rng: &mut impl Rng) -> Option<(&[u8], u64)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let (key, at) = &self.entries[rng.gen_range(0..self.entries.len())];
+        Some((key, *at))
+    }
+
+    /// The keys whose deadline is before `now`.
+    pub fn due(&self, now: u64) -> Vec<Vec<u8>> {
+        self.entries
+            .iter()
+            .filter(|(_, at)| *at < now)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_expires() {
+        let mut expires = Expires::default();
+        expires.insert(b"a".to_vec(), 10);
+        expires.insert(b"b".to_vec(), 20);
+        expires.insert(b"c".to_vec(), 30);
+        expires.insert(b"a".to_vec(), 15);
+        assert_eq!(expires.len(), 3);
+        assert_eq!(expires.get(b"a"), Some(15));
+
+        // the last entry takes the place of the removed one
+        assert_eq!(expires.remove(b"a"), Some(15));
+        assert_eq!(expires.remove(b"a"), None);
+        assert_eq!(expires.get(b"c"), Some(30));
+        assert_eq!(expires.due(25), vec![b"b".to_vec()]);
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let (key, at) = expires.random(&mut rng).unwrap();
+            assert_eq!(expires.get(key), Some(at));
+        }
+        assert_eq!(expires.remove(b"c"), Some(30));
+        assert_eq!(expires.remove(b"b"), Some(20));
+        assert!(expires.is_empty());
+        assert_eq!(expires.random(&mut rng), None);
+    }
+}
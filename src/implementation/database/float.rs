@@ -0,0 +1,587 @@
+//! The floats INCRBYFLOAT and HINCRBYFLOAT keep in strings and hash fields.
+//!
+//! Redis reads them with `strtold`, adds them as long doubles and prints the sum with
+//! `%.17Lf` before trimming the trailing zeros. On x86-64 a long double has a 64-bit mantissa,
+//! so `0.1 + 0.2` is close enough to `0.3` to print as such, while huge sums print every digit
+//! of their binary value. Doubles can't reproduce either, so the arithmetic is done exactly on
+//! big integers and rounded the way the FPU and glibc round.
+//!
+//! Other floats, such as sorted set scores, are plain doubles that Redis prints with
+//! `d2string`.
+
+use num_bigint::BigUint;
+use num_integer::Integer;
+
+use crate::interface::database::map::LongDouble;
+
+/// Redis copies floats into a buffer of this size before parsing them, so longer ones are
+/// rejected.
+const MAX_LEN: usize = 5 * 1024;
+
+/// A binary floating point format, as far as rounding to it goes.
+struct Format {
+    /// Bits in the mantissa, counting the leading one.
+    mantissa_bits: u64,
+    /// The exponent of the lowest bit of the smallest subnormal.
+    min_exponent: i64,
+    /// The exponent of the lowest bit of the largest finite value.
+    max_exponent: i64,
+}
+
+/// An x86-64 long double, whose leading mantissa bit is explicit.
+const LONG_DOUBLE: Format = Format {
+    mantissa_bits: 64,
+    min_exponent: -16445,
+    max_exponent: 16320,
+};
+
+const DOUBLE: Format = Format {
+    mantissa_bits: 53,
+    min_exponent: -1074,
+    max_exponent: 971,
+};
+
+/// Exponents past this are clamped, as they overflow or underflow either way.
+const MAX_PARSED_EXPONENT: i64 = 1_000_000;
+
+/// The precision of `%.17Lf`.
+const FRACTION_DIGITS: u32 = 17;
+
+/// What a missing key or field counts as.
+pub const ZERO: LongDouble = LongDouble::Finite {
+    negative: false,
+    mantissa: 0,
+    exponent: 0,
+};
+
+/// A number worth `(digits + s) × 2^exponent`, where `0 <= s < 1` and `s` is nonzero exactly
+/// when `sticky` is set.
+struct Exact {
+    digits: BigUint,
+    exponent: i64,
+    sticky: bool,
+}
+
+impl Exact {
+    fn zero() -> Self {
+        Exact {
+            digits: BigUint::default(),
+            exponent: 0,
+            sticky: false,
+        }
+    }
+}
+
+/// `digits >> shift`, rounded to nearest with ties to even; `sticky` tells whether anything
+/// nonzero was already dropped below `digits`.
+fn shift_right_rounding(digits: &BigUint, shift: u64, sticky: bool) -> BigUint {
+    if shift == 0 {
+        return digits.clone();
+    }
+    let kept = digits >> shift;
+    let half = digits.bit(shift - 1);
+    let more = sticky || digits.trailing_zeros().is_some_and(|z| z < shift - 1);
+    if half && (more || kept.bit(0)) {
+        kept + 1u32
+    } else {
+        kept
+    }
+}
+
+/// Rounds `exact` to the nearest value of `format`, as a mantissa and the exponent of its
+/// lowest bit, or returns `None` if it is too large for the format, or so small that it
+/// rounds to zero.
+fn round_to(format: &Format, exact: Exact) -> Option<(u64, i64)> {
+    let Exact {
+        digits,
+        exponent,
+        sticky,
+    } = exact;
+    if digits.bits() == 0 && !sticky {
+        return Some((0, 0));
+    }
+    let mut lsb =
+        (exponent + digits.bits() as i64 - format.mantissa_bits as i64).max(format.min_exponent);
+    let mut mantissa = if lsb <= exponent {
+        digits << (exponent - lsb) as u64
+    } else {
+        shift_right_rounding(&digits, (lsb - exponent) as u64, sticky)
+    };
+    if mantissa.bits() > format.mantissa_bits {
+        // rounded up to the next power of two
+        mantissa >>= 1u32;
+        lsb += 1;
+    }
+    if mantissa.bits() == 0 || lsb > format.max_exponent {
+        return None;
+    }
+    Some((mantissa.iter_u64_digits().next().unwrap_or(0), lsb))
+}
+
+/// Rounds `exact` to the nearest long double, or returns `None` if it is too large for one,
+/// or so small that it rounds to zero.
+fn round(negative: bool, exact: Exact) -> Option<LongDouble> {
+    let (mantissa, exponent) = round_to(&LONG_DOUBLE, exact)?;
+    Some(LongDouble::Finite {
+        negative,
+        mantissa,
+        exponent: exponent as i32,
+    })
+}
+
+/// Parses the exponent after `e` or `p`, clamping it to `MAX_PARSED_EXPONENT`.
+fn parse_exponent(input: &[u8]) -> Option<i64> {
+    let (negative, digits) = match input {
+        [b'-', digits @ ..] => (true, digits),
+        [b'+', digits @ ..] => (false, digits),
+        digits => (false, digits),
+    };
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = digits.iter().fold(0, |acc: i64, d| {
+        (acc * 10 + (d - b'0') as i64).min(MAX_PARSED_EXPONENT)
+    });
+    Some(if negative { -value } else { value })
+}
+
+/// Splits `<int>[.<frac>][<marker><exponent>]` into its digits and exponent.
+fn split_number(input: &[u8], marker: u8) -> Option<(Vec<u8>, usize, i64)> {
+    let (mantissa, exponent) = match input.iter().position(|&c| c == marker) {
+        Some(i) => (&input[..i], parse_exponent(&input[i + 1..])?),
+        None => (input, 0),
+    };
+    let (int, frac) = match mantissa.iter().position(|&c| c == b'.') {
+        Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+        None => (mantissa, &[][..]),
+    };
+    if int.len() + frac.len() == 0 {
+        return None;
+    }
+    Some(([int, frac].concat(), frac.len(), exponent))
+}
+
+fn parse_decimal(input: &[u8]) -> Option<Exact> {
+    let (digits, frac_len, exponent) = split_number(input, b'e')?;
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let digits = BigUint::parse_bytes(&digits, 10)?;
+    if digits.bits() == 0 {
+        return Some(Exact::zero());
+    }
+    let exponent = exponent - frac_len as i64;
+    // rule out the values far outside the range of a long double before computing powers of
+    // ten for them, by a margin that leaves the ones near its edges to the exact arithmetic
+    let magnitude = digits.bits() as f64 * std::f64::consts::LOG10_2 + exponent as f64;
+    if magnitude > 4934.0 {
+        return Some(Exact {
+            digits: BigUint::from(1u32),
+            exponent: MAX_PARSED_EXPONENT,
+            sticky: false,
+        });
+    }
+    if magnitude < -4952.0 {
+        return Some(Exact {
+            digits: BigUint::from(1u32),
+            exponent: -MAX_PARSED_EXPONENT,
+            sticky: false,
+        });
+    }
+    let power = BigUint::from(10u32).pow(exponent.unsigned_abs() as u32);
+    if exponent >= 0 {
+        return Some(Exact {
+            digits: digits * power,
+            exponent: 0,
+            sticky: false,
+        });
+    }
+    // enough bits in the quotient to round it to any mantissa, with two to spare
+    let shift = (power.bits() + LONG_DOUBLE.mantissa_bits + 2).saturating_sub(digits.bits());
+    let (quotient, remainder) = (digits << shift).div_rem(&power);
+    Some(Exact {
+        digits: quotient,
+        exponent: -(shift as i64),
+        sticky: remainder.bits() != 0,
+    })
+}
+
+fn parse_hex(input: &[u8]) -> Option<Exact> {
+    let (digits, frac_len, exponent) = split_number(input, b'p')?;
+    if !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    Some(Exact {
+        digits: BigUint::parse_bytes(&digits, 16)?,
+        exponent: exponent - 4 * frac_len as i64,
+        sticky: false,
+    })
+}
+
+/// Reads what `strtold` and `strtod` take in full: a sign, then an infinity or an exact value.
+/// Longer inputs than Redis copies into its buffers are rejected, so that no one asks for a
+/// division by a power of ten with millions of digits.
+fn read(bytes: &[u8]) -> Option<(bool, Option<Exact>)> {
+    if bytes.is_empty() || bytes.len() >= MAX_LEN {
+        return None;
+    }
+    let (negative, rest) = match bytes {
+        [b'-', rest @ ..] => (true, rest),
+        [b'+', rest @ ..] => (false, rest),
+        rest => (false, rest),
+    };
+    let rest = rest.to_ascii_lowercase();
+    if rest == b"inf" || rest == b"infinity" {
+        return Some((negative, None));
+    }
+    let exact = match rest.strip_prefix(b"0x") {
+        Some(hex) => parse_hex(hex)?,
+        None => parse_decimal(&rest)?,
+    };
+    Some((negative, Some(exact)))
+}
+
+/// Parses a float as Redis's `string2ld` does: whatever `strtold` takes in full, including
+/// hexadecimal floats and infinities, but not NaN, leading spaces, or values that overflow or
+/// underflow to zero.
+pub fn parse(bytes: &[u8]) -> Option<LongDouble> {
+    match read(bytes)? {
+        (negative, None) => Some(LongDouble::Infinite { negative }),
+        (negative, Some(exact)) => round(negative, exact),
+    }
+}
+
+/// Parses a double as Redis's `getDoubleFromObject` does with `strtod`, with the same rules
+/// as [`parse`]: hexadecimal floats and infinities are taken, NaN, leading spaces and values
+/// out of range are not.
+pub fn parse_double(bytes: &[u8]) -> Option<f64> {
+    let (negative, exact) = read(bytes)?;
+    let value = match exact {
+        None => f64::INFINITY,
+        Some(exact) => {
+            let (mantissa, exponent) = round_to(&DOUBLE, exact)?;
+            if mantissa >> (DOUBLE.mantissa_bits - 1) == 0 {
+                // zero, or a subnormal whose lowest bit is that of the smallest one
+                f64::from_bits(mantissa)
+            } else {
+                let biased = (exponent + DOUBLE.mantissa_bits as i64 - 1 + 1023) as u64;
+                f64::from_bits(biased << 52 | (mantissa & ((1 << 52) - 1)))
+            }
+        }
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Adds two long doubles as the FPU does, or returns `None` if the sum is NaN or infinite,
+/// which INCRBYFLOAT and HINCRBYFLOAT refuse to store.
+pub fn add(a: LongDouble, b: LongDouble) -> Option<LongDouble> {
+    let (
+        LongDouble::Finite {
+            negative: a_negative,
+            mantissa: a_mantissa,
+            exponent: a_exponent,
+        },
+        LongDouble::Finite {
+            negative: b_negative,
+            mantissa: b_mantissa,
+            exponent: b_exponent,
+        },
+    ) = (a, b)
+    else {
+        return None;
+    };
+    let exponent = a_exponent.min(b_exponent);
+    let a_digits = BigUint::from(a_mantissa) << (a_exponent - exponent) as u64;
+    let b_digits = BigUint::from(b_mantissa) << (b_exponent - exponent) as u64;
+    let (negative, digits) = if a_negative == b_negative {
+        (a_negative, a_digits + b_digits)
+    } else if a_digits >= b_digits {
+        (a_negative, a_digits - b_digits)
+    } else {
+        (b_negative, b_digits - a_digits)
+    };
+    // an exact zero is negative only if both terms were
+    let negative = if digits.bits() == 0 {
+        a_negative && b_negative
+    } else {
+        negative
+    };
+    round(
+        negative,
+        Exact {
+            digits,
+            exponent: exponent as i64,
+            sticky: false,
+        },
+    )
+}
+
+/// Prints a long double as Redis's `ld2string` does for INCRBYFLOAT: `%.17Lf` without its
+/// trailing zeros, and with `-0` printed as `0`.
+pub fn format(value: LongDouble) -> Vec<u8> {
+    let (negative, mantissa, exponent) = match value {
+        LongDouble::Infinite { negative: false } => return b"inf".to_vec(),
+        LongDouble::Infinite { negative: true } => return b"-inf".to_vec(),
+        LongDouble::Finite {
+            negative,
+            mantissa,
+            exponent,
+        } => (negative, mantissa, exponent),
+    };
+    let scaled = BigUint::from(mantissa) * BigUint::from(10u32).pow(FRACTION_DIGITS);
+    let scaled = if exponent >= 0 {
+        scaled << exponent as u64
+    } else {
+        shift_right_rounding(&scaled, exponent.unsigned_abs() as u64, false)
+    };
+    let mut digits = scaled.to_string().into_bytes();
+    let min_len = FRACTION_DIGITS as usize + 1;
+    if digits.len() < min_len {
+        digits.splice(0..0, std::iter::repeat_n(b'0', min_len - digits.len()));
+    }
+    let mut frac = digits.split_off(digits.len() - FRACTION_DIGITS as usize);
+    while frac.last() == Some(&b'0') {
+        frac.pop();
+    }
+    let mut output = Vec::with_capacity(digits.len() + frac.len() + 2);
+    if negative && scaled.bits() != 0 {
+        output.push(b'-');
+    }
+    output.extend(digits);
+    if !frac.is_empty() {
+        output.push(b'.');
+        output.extend(frac);
+    }
+    output
+}
+
+/// Doubles Redis prints as integers, those within `±LLONG_MAX / 2`.
+const MAX_INTEGRAL: f64 = (1u64 << 62) as f64;
+
+/// Prints a double as Redis's `d2string` does: integral values as integers and others with
+/// their shortest round-tripping digits, in scientific notation when they are very large or
+/// very small, e.g. `1e+20`, `0.00001` or `1.5e-7`.
+pub fn format_double(value: f64) -> Vec<u8> {
+    if value.is_nan() {
+        return b"nan".to_vec();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 {
+            b"inf".to_vec()
+        } else {
+            b"-inf".to_vec()
+        };
+    }
+    if value == 0.0 {
+        return if value.is_sign_negative() {
+            b"-0".to_vec()
+        } else {
+            b"0".to_vec()
+        };
+    }
+    if (-MAX_INTEGRAL..=MAX_INTEGRAL).contains(&value) && value.fract() == 0.0 {
+        return (value as i64).to_string().into_bytes();
+    }
+    // `{:e}` gives the shortest digits as `d[.ddd]e<exponent>`
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let digits: Vec<u8> = mantissa.bytes().filter(|&b| b != b'.').collect();
+    let exponent: i32 = exponent.parse().unwrap();
+    let mut output = Vec::with_capacity(digits.len() + 8);
+    if value < 0.0 {
+        output.push(b'-');
+    }
+    emit_digits(&digits, exponent, &mut output);
+    output
+}
+
+/// Lays out `digits`, the first of which has weight `10^exponent`, as `fpconv_dtoa` does.
+fn emit_digits(digits: &[u8], exponent: i32, output: &mut Vec<u8>) {
+    let ndigits = digits.len() as i32;
+    // the exponent of the last digit
+    let last = exponent - ndigits + 1;
+    if last >= 0 && exponent.abs() < ndigits + 7 {
+        output.extend_from_slice(digits);
+        output.extend(std::iter::repeat_n(b'0', last as usize));
+    } else if last < 0 && (last > -7 || exponent.abs() < 4) {
+        let point = ndigits + last;
+        if point <= 0 {
+            output.extend_from_slice(b"0.");
+            output.extend(std::iter::repeat_n(b'0', point.unsigned_abs() as usize));
+            output.extend_from_slice(digits);
+        } else {
+            let (int, frac) = digits.split_at(point as usize);
+            output.extend_from_slice(int);
+            output.push(b'.');
+            output.extend_from_slice(frac);
+        }
+    } else {
+        output.push(digits[0]);
+        if digits.len() > 1 {
+            output.push(b'.');
+            output.extend_from_slice(&digits[1..]);
+        }
+        output.push(b'e');
+        output.push(if exponent < 0 { b'-' } else { b'+' });
+        output.extend_from_slice(exponent.unsigned_abs().to_string().as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// INCRBYFLOAT against a key holding `old`, or a missing one.
+    fn incr(old: Option<&str>, increment: &str) -> Option<String> {
+        let old = old.map_or(Some(ZERO), |old| parse(old.as_bytes()))?;
+        let sum = add(old, parse(increment.as_bytes())?)?;
+        Some(String::from_utf8(format(sum)).unwrap())
+    }
+
+    /// Replies of Redis's own `string2ld`, `value += incr` and `ld2string` on x86-64 glibc.
+    #[test]
+    fn test_incr() {
+        assert_eq!(incr(Some("0.1"), "0.2").as_deref(), Some("0.3"));
+        assert_eq!(incr(Some("10.50"), "0.1").as_deref(), Some("10.6"));
+        assert_eq!(incr(Some("10.6"), "-5").as_deref(), Some("5.6"));
+        assert_eq!(incr(Some("5.0e3"), "2.0e2").as_deref(), Some("5200"));
+        assert_eq!(
+            incr(Some("17179869184"), "1.5").as_deref(),
+            Some("17179869185.5")
+        );
+        assert_eq!(
+            incr(None, "1e21").as_deref(),
+            Some("1000000000000000000000")
+        );
+        assert_eq!(incr(None, "-1.5e-7").as_deref(), Some("-0.00000015"));
+        assert_eq!(incr(None, "1e-16").as_deref(), Some("0.0000000000000001"));
+        assert_eq!(incr(None, "1e-18").as_deref(), Some("0"));
+        assert_eq!(incr(None, "-1e-18").as_deref(), Some("0"));
+        assert_eq!(incr(None, "-0").as_deref(), Some("0"));
+        assert_eq!(incr(None, ".5").as_deref(), Some("0.5"));
+        assert_eq!(incr(None, "5.").as_deref(), Some("5"));
+        assert_eq!(incr(None, "0x1p-3").as_deref(), Some("0.125"));
+        assert_eq!(incr(None, "1e-4950").as_deref(), Some("0"));
+        assert_eq!(
+            incr(None, "1e300").as_deref(),
+            Some(
+                "10000000000000000000089973240795591938705239442732907479382600823212656465961809\
+35755849152083750497190350372508614274835903592556184672983913096260520748646287\
+32713564184365329408425510760601678972665293237003055138294762099454029477278188\
+9620606179267611627097410650567187386105690089424915104006144"
+            )
+        );
+        assert_eq!(
+            incr(Some("1.7976931348623157e308"), "1.7976931348623157e308").as_deref(),
+            Some(
+                "35953862697246313999184209354820886809677289388865835697062884153035325704624879\
+27094037372167741711288575868388510413786174167269022721107157945560529552341469\
+97955372576789671236326588091188869859074894581254961338834769297758245552436667\
+197907705664206565009503223382416235440319971852753604933726679072768"
+            )
+        );
+        // overflows the long double
+        assert_eq!(incr(Some("1e4932"), "1e4932"), None);
+        assert_eq!(incr(None, "inf"), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(parse(b"1.5").is_some());
+        assert!(parse(b"-1E+3").is_some());
+        assert!(parse(b"0X1.8P1").is_some());
+        assert!(parse(b"0e99999999999").is_some());
+        assert_eq!(
+            parse(b"-Infinity"),
+            Some(LongDouble::Infinite { negative: true })
+        );
+        for rejected in [
+            b"".as_slice(),
+            b"nan",
+            b" 1",
+            b"1 ",
+            b"1e",
+            b"1\0",
+            b"0x",
+            b"0xp1",
+            b".",
+            b"e5",
+            b"1e-4960",
+            b"1e5000",
+        ] {
+            assert_eq!(parse(rejected), None, "{:?}", rejected);
+        }
+        assert_eq!(parse(&vec![b'1'; MAX_LEN]), None);
+    }
+
+    #[test]
+    fn test_parse_double() {
+        assert_eq!(parse_double(b"1.5"), Some(1.5));
+        assert_eq!(parse_double(b"0x1p3"), Some(8.0));
+        assert_eq!(parse_double(b"-0X1.8P1"), Some(-3.0));
+        assert_eq!(parse_double(b"+.5e1"), Some(5.0));
+        assert_eq!(parse_double(b"-inf"), Some(f64::NEG_INFINITY));
+        assert_eq!(parse_double(b"Infinity"), Some(f64::INFINITY));
+        assert!(parse_double(b"-0").unwrap().is_sign_negative());
+        assert_eq!(parse_double(b"1.7976931348623157e308"), Some(f64::MAX));
+        assert_eq!(
+            parse_double(b"2.2250738585072014e-308"),
+            Some(f64::MIN_POSITIVE)
+        );
+        assert_eq!(parse_double(b"5e-324"), Some(5e-324));
+        assert_eq!(parse_double(b"1e-320"), Some(1e-320));
+        for rejected in [
+            b"".as_slice(),
+            b"nan",
+            b" 1",
+            b"1 ",
+            b"1e",
+            b"0x",
+            b"1e309",
+            b"1e-400",
+        ] {
+            assert_eq!(parse_double(rejected), None, "{:?}", rejected);
+        }
+        // agrees with Rust's own parser wherever both accept the input
+        let mut seed = 1u64;
+        for _ in 0..2000 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let digits = seed >> (seed % 40 + 4);
+            let exponent = (seed >> 50) as i64 % 700 - 350;
+            let input = format!("{}e{}", digits, exponent);
+            assert_eq!(
+                parse_double(input.as_bytes()),
+                input
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.is_finite() && *v != 0.0),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_double() {
+        let format = |value: f64| String::from_utf8(format_double(value)).unwrap();
+        assert_eq!(format(0.0), "0");
+        assert_eq!(format(-0.0), "-0");
+        assert_eq!(format(123.0), "123");
+        assert_eq!(format(-4611686018427387904.0), "-4611686018427387904");
+        assert_eq!(format(9223372036854775808.0), "9223372036854776000");
+        assert_eq!(format(1e20), "1e+20");
+        assert_eq!(format(1.5e300), "1.5e+300");
+        assert_eq!(format(0.1), "0.1");
+        assert_eq!(format(-2.5), "-2.5");
+        assert_eq!(format(1234567.125), "1234567.125");
+        assert_eq!(format(1e-5), "0.00001");
+        assert_eq!(format(1.5e-7), "1.5e-7");
+        assert_eq!(format(0.000123), "0.000123");
+        assert_eq!(format(1.234e-5), "1.234e-5");
+        assert_eq!(format(5e-324), "5e-324");
+        assert_eq!(format(f64::INFINITY), "inf");
+        assert_eq!(format(f64::NEG_INFINITY), "-inf");
+    }
+}
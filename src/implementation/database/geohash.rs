@@ -0,0 +1,425 @@
+//! The geohashes Redis stores as sorted set scores: 26 bits of latitude and 26 bits of
+//! longitude, interleaved with the longitude bits in the odd positions. Unlike standard
+//! geohashes, latitudes are limited to the ±85.05112878° of the Web Mercator projection.
+//!
+//! Searches look at the cell of the centre and its eight neighbours, at a precision where
+//! those cells cover the whole shape, and then check the distance of every point found.
+
+const LONGITUDE_MIN: f64 = -180.0;
+const LONGITUDE_MAX: f64 = 180.0;
+const LATITUDE_MIN: f64 = -85.051_128_78;
+const LATITUDE_MAX: f64 = 85.051_128_78;
+const STEP_MAX: u8 = 26;
+/// The Earth radius used by Redis, which is what the distances are computed with.
+const EARTH_RADIUS: f64 = 6_372_797.560_856;
+const MERCATOR_MAX: f64 = 20_037_726.37;
+const ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Range {
+    min: f64,
+    max: f64,
+}
+
+const LONGITUDE_RANGE: Range = Range {
+    min: LONGITUDE_MIN,
+    max: LONGITUDE_MAX,
+};
+const LATITUDE_RANGE: Range = Range {
+    min: LATITUDE_MIN,
+    max: LATITUDE_MAX,
+};
+
+/// A cell: the `2 * step` most significant bits of a geohash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cell {
+    bits: u64,
+    step: u8,
+}
+
+/// The coordinates a cell spans.
+#[derive(Clone, Copy, Debug)]
+struct Area {
+    longitude: Range,
+    latitude: Range,
+}
+
+/// Spreads the bits of `x` out to the even positions.
+fn spread(x: u32) -> u64 {
+    let mut x = x as u64;
+    x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+/// Gathers the bits in the even positions.
+fn squash(x: u64) -> u32 {
+    let mut x = x & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x >> 8)) & 0x0000_ffff_0000_ffff;
+    ((x | (x >> 16)) & 0x0000_0000_ffff_ffff) as u32
+}
+
+pub fn is_valid(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude)
+        && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude)
+}
+
+fn encode(longitude: f64, latitude: f64, latitude_range: Range, step: u8) -> Option<Cell> {
+    if !is_valid(longitude, latitude)
+        || latitude < latitude_range.min
+        || latitude > latitude_range.max
+    {
+        return None;
+    }
+    let scale = (1u64 << step) as f64;
+    let latitude = (latitude - latitude_range.min) / (latitude_range.max - latitude_range.min);
+    let longitude = (longitude - LONGITUDE_MIN) / (LONGITUDE_MAX - LONGITUDE_MIN);
+    let bits = spread((latitude * scale) as u32) | spread((longitude * scale) as u32) << 1;
+    Some(Cell { bits, step })
+}
+
+/// The score of a point, or `None` if it is out of the supported range.
+pub fn encode_score(longitude: f64, latitude: f64) -> Option<f64> {
+    encode(longitude, latitude, LATITUDE_RANGE, STEP_MAX).map(|cell| cell.bits as f64)
+}
+
+fn decode(cell: Cell) -> Area {
+    let scale = (1u64 << cell.step) as f64;
+    let (latitude, longitude) = (squash(cell.bits), squash(cell.bits >> 1));
+    let span = |range: Range, index: u32| Range {
+        min: range.min + (index as f64 / scale) * (range.max - range.min),
+        max: range.min + ((index as f64 + 1.0) / scale) * (range.max - range.min),
+    };
+    Area {
+        longitude: span(LONGITUDE_RANGE, longitude),
+        latitude: span(LATITUDE_RANGE, latitude),
+    }
+}
+
+/// The `(longitude, latitude)` a score stands for: the centre of its cell.
+pub fn decode_score(score: f64) -> (f64, f64) {
+    let area = decode(Cell {
+        bits: score as u64,
+        step: STEP_MAX,
+    });
+    let longitude =
+        ((area.longitude.min + area.longitude.max) / 2.0).clamp(LONGITUDE_MIN, LONGITUDE_MAX);
+    let latitude =
+        ((area.latitude.min + area.latitude.max) / 2.0).clamp(LATITUDE_MIN, LATITUDE_MAX);
+    (longitude, latitude)
+}
+
+/// The standard 11 character geohash of a score, as GEOHASH returns it.
+pub fn to_string(score: f64) -> Vec<u8> {
+    let (longitude, latitude) = decode_score(score);
+    let standard = Range {
+        min: -90.0,
+        max: 90.0,
+    };
+    let bits = encode(longitude, latitude, standard, STEP_MAX).map_or(0, |cell| cell.bits);
+    (0..11)
+        .map(|i| {
+            // 52 bits make 10 characters and a bit, so the last one is always the same
+            let index = if i == 10 {
+                0
+            } else {
+                (bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            ALPHABET[index as usize]
+        })
+        .collect()
+}
+
+fn latitude_distance(latitude1: f64, latitude2: f64) -> f64 {
+    EARTH_RADIUS * (latitude2.to_radians() - latitude1.to_radians()).abs()
+}
+
+/// The haversine distance in meters between two points.
+pub fn distance(longitude1: f64, latitude1: f64, longitude2: f64, latitude2: f64) -> f64 {
+    let v = ((longitude2.to_radians() - longitude1.to_radians()) / 2.0).sin();
+    // on the same meridian, the latitudes are enough
+    if v == 0.0 {
+        return latitude_distance(latitude1, latitude2);
+    }
+    let (latitude1, latitude2) = (latitude1.to_radians(), latitude2.to_radians());
+    let u = ((latitude2 - latitude1) / 2.0).sin();
+    let a = u * u + latitude1.cos() * latitude2.cos() * v * v;
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// The area of GEOSEARCH, in meters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Circle { radius: f64 },
+    Rectangle { width: f64, height: f64 },
+}
+
+/// A shape around `(longitude, latitude)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Search {
+    pub longitude: f64,
+    pub latitude: f64,
+    pub shape: Shape,
+}
+
+impl Search {
+    /// The distance in meters from the centre to a point, if the point is in the shape.
+    pub fn distance_to(&self, longitude: f64, latitude: f64) -> Option<f64> {
+        match self.shape {
+            Shape::Circle { radius } => {
+                let distance = distance(self.longitude, self.latitude, longitude, latitude);
+                (distance <= radius).then_some(distance)
+            }
+            Shape::Rectangle { width, height } => {
+                if latitude_distance(latitude, self.latitude) > height / 2.0 {
+                    return None;
+                }
+                if distance(longitude, latitude, self.longitude, latitude) > width / 2.0 {
+                    return None;
+                }
+                Some(distance(self.longitude, self.latitude, longitude, latitude))
+            }
+        }
+    }
+
+    /// `[min_longitude, min_latitude, max_longitude, max_latitude]` around the shape.
+    fn bounding_box(&self) -> [f64; 4] {
+        let (width, height) = match self.shape {
+            Shape::Circle { radius } => (radius, radius),
+            Shape::Rectangle { width, height } => (width / 2.0, height / 2.0),
+        };
+        let latitude_delta = (height / EARTH_RADIUS).to_degrees();
+        let longitude_delta =
+            |latitude: f64| (width / EARTH_RADIUS / latitude.to_radians().cos()).to_degrees();
+        // the meridians are closest on the side nearest to the pole
+        let longitude_delta = if self.latitude < 0.0 {
+            longitude_delta(self.latitude - latitude_delta)
+        } else {
+            longitude_delta(self.latitude + latitude_delta)
+        };
+        [
+            self.longitude - longitude_delta,
+            self.latitude - latitude_delta,
+            self.longitude + longitude_delta,
+            self.latitude + latitude_delta,
+        ]
+    }
+
+    /// The score ranges `min..max` to scan, in the order Redis scans them.
+    pub fn score_ranges(&self) -> Vec<(f64, f64)> {
+        let radius = match self.shape {
+            Shape::Circle { radius } => radius,
+            Shape::Rectangle { width, height } => (width / 2.0).hypot(height / 2.0),
+        };
+        let [min_longitude, min_latitude, max_longitude, max_latitude] = self.bounding_box();
+        let mut step = estimate_step(radius, self.latitude);
+        let cell_at = |step| {
+            encode(self.longitude, self.latitude, LATITUDE_RANGE, step)
+                .expect("the centre was validated")
+        };
+        let mut cell = cell_at(step);
+        let mut neighbours = Neighbours::of(cell);
+        // near the edges of a cell, its neighbours may not reach far enough
+        let too_small = decode(neighbours.north).latitude.max < max_latitude
+            || decode(neighbours.south).latitude.min > min_latitude
+            || decode(neighbours.east).longitude.max < max_longitude
+            || decode(neighbours.west).longitude.min > min_longitude;
+        if step > 1 && too_small {
+            step -= 1;
+            cell = cell_at(step);
+            neighbours = Neighbours::of(cell);
+        }
+        let area = decode(cell);
+        let mut cells = [
+            Some(cell),
+            Some(neighbours.north),
+            Some(neighbours.south),
+            Some(neighbours.east),
+            Some(neighbours.west),
+            Some(neighbours.north_east),
+            Some(neighbours.north_west),
+            Some(neighbours.south_east),
+            Some(neighbours.south_west),
+        ];
+        // neighbours entirely outside the shape can be skipped
+        if step >= 2 {
+            let mut exclude = |indices: [usize; 3]| indices.map(|i| cells[i] = None);
+            if area.latitude.min < min_latitude {
+                exclude([2, 7, 8]);
+            }
+            if area.latitude.max > max_latitude {
+                exclude([1, 5, 6]);
+            }
+            if area.longitude.min < min_longitude {
+                exclude([4, 6, 8]);
+            }
+            if area.longitude.max > max_longitude {
+                exclude([3, 5, 7]);
+            }
+        }
+        let mut ranges = vec![];
+        let mut last: Option<Cell> = None;
+        for (i, cell) in cells.into_iter().enumerate() {
+            let Some(cell) = cell else {
+                continue;
+            };
+            // with huge radii, neighbours can be the same cell
+            if last == Some(cell) {
+                continue;
+            }
+            let shift = 2 * (STEP_MAX - cell.step) as u32;
+            ranges.push((
+                (cell.bits << shift) as f64,
+                ((cell.bits + 1) << shift) as f64,
+            ));
+            if i > 0 {
+                last = Some(cell);
+            }
+        }
+        ranges
+    }
+}
+
+/// The precision at which the cells around a point cover `radius` meters.
+fn estimate_step(mut radius: f64, latitude: f64) -> u8 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    step -= 2;
+    // cells get narrower towards the poles
+    if !(-66.0..=66.0).contains(&latitude) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&latitude) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP_MAX as i32) as u8
+}
+
+struct Neighbours {
+    north: Cell,
+    south: Cell,
+    east: Cell,
+    west: Cell,
+    north_east: Cell,
+    north_west: Cell,
+    south_east: Cell,
+    south_west: Cell,
+}
+
+impl Neighbours {
+    fn of(cell: Cell) -> Self {
+        let at = |x, y| move_y(move_x(cell, x), y);
+        Neighbours {
+            north: at(0, 1),
+            south: at(0, -1),
+            east: at(1, 0),
+            west: at(-1, 0),
+            north_east: at(1, 1),
+            north_west: at(-1, 1),
+            south_east: at(1, -1),
+            south_west: at(-1, -1),
+        }
+    }
+}
+
+/// Moves a cell along the bits in the positions of `mask`, wrapping around.
+fn move_along(cell: Cell, direction: i8, mask: u64) -> Cell {
+    if direction == 0 {
+        return cell;
+    }
+    let unused = 64 - 2 * cell.step as u32;
+    let moving = cell.bits & mask;
+    let other = cell.bits & !mask;
+    // filling the other positions with ones carries the addition across them
+    let filler = !mask >> unused;
+    let moved = if direction > 0 {
+        moving.wrapping_add(filler + 1)
+    } else {
+        (moving | filler).wrapping_sub(filler + 1)
+    };
+    Cell {
+        bits: (moved & (mask >> unused)) | other,
+        step: cell.step,
+    }
+}
+
+fn move_x(cell: Cell, direction: i8) -> Cell {
+    move_along(cell, direction, 0xaaaa_aaaa_aaaa_aaaa)
+}
+
+fn move_y(cell: Cell, direction: i8) -> Cell {
+    move_along(cell, direction, 0x5555_5555_5555_5555)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_and_decode() {
+        let score = encode_score(13.361389, 38.115556).unwrap();
+        assert_eq!(score, 3_479_099_956_230_698.0);
+        let (longitude, latitude) = decode_score(score);
+        assert!((longitude - 13.361389).abs() < 1e-5);
+        assert!((latitude - 38.115556).abs() < 1e-5);
+        assert_eq!(encode_score(181.0, 0.0), None);
+        assert_eq!(encode_score(0.0, 86.0), None);
+    }
+
+    #[test]
+    fn test_to_string() {
+        let palermo = encode_score(13.361389, 38.115556).unwrap();
+        assert_eq!(to_string(palermo), b"sqc8b49rny0");
+        let catania = encode_score(15.087269, 37.502669).unwrap();
+        assert_eq!(to_string(catania), b"sqdtr74hyu0");
+    }
+
+    #[test]
+    fn test_distance() {
+        let distance = distance(13.361389, 38.115556, 15.087269, 37.502669);
+        assert!((distance - 166_274.151_6).abs() < 1.0, "{}", distance);
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let cell = Cell {
+            bits: 0b0011,
+            step: 2,
+        };
+        assert_eq!(move_x(cell, 1).bits, 0b1001);
+        assert_eq!(move_x(cell, -1).bits, 0b0001);
+        assert_eq!(move_y(cell, 1).bits, 0b0110);
+        assert_eq!(move_y(cell, -1).bits, 0b0010);
+        // moving past the edge wraps around
+        assert_eq!(move_y(move_y(cell, -1), -1).bits, 0b0111);
+    }
+
+    #[test]
+    fn test_score_ranges_cover_the_shape() {
+        let search = Search {
+            longitude: 15.0,
+            latitude: 37.0,
+            shape: Shape::Circle { radius: 200_000.0 },
+        };
+        let ranges = search.score_ranges();
+        assert!(!ranges.is_empty() && ranges.len() <= 9);
+        for (longitude, latitude) in [(13.361389, 38.115556), (15.087269, 37.502669)] {
+            let score = encode_score(longitude, latitude).unwrap();
+            assert!(search.distance_to(longitude, latitude).is_some());
+            assert!(ranges
+                .iter()
+                .any(|(min, max)| (*min..*max).contains(&score)));
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! HyperLogLog counters stored in strings, laid out exactly as Redis lays them out so that
+//! the values can be exchanged with it.
+//!
+//! A value starts with a 16 byte header: the magic `HYLL`, the encoding, three unused bytes
+//! and the cached cardinality as a little-endian `u64` whose most significant bit marks the
+//! cache as stale. The 16384 six-bit registers follow, either packed ("dense") or run-length
+//! encoded ("sparse") with these opcodes:
+//!
+//! * `00xxxxxx`: ZERO, `xxxxxx + 1` zero registers.
+//! * `01xxxxxx yyyyyyyy`: XZERO, `xxxxxxyyyyyyyy + 1` zero registers.
+//! * `1vvvvvxx`: VAL, `xx + 1` registers set to `vvvvv + 1`.
+
+const P: u32 = 14;
+const Q: u32 = 64 - P;
+pub const REGISTERS: usize = 1 << P;
+const BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << BITS) - 1;
+const HEADER_SIZE: usize = 16;
+const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * BITS).div_ceil(8);
+/// `hll-sparse-max-bytes`: larger sparse values are converted to the dense encoding.
+const SPARSE_MAX_BYTES: usize = 3000;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+const MAGIC: &[u8] = b"HYLL";
+const ENCODING_DENSE: u8 = 0;
+const ENCODING_SPARSE: u8 = 1;
+const ENCODING_OFFSET: usize = 4;
+const CARDINALITY_OFFSET: usize = 8;
+
+const SPARSE_ZERO_MAX_LEN: usize = 64;
+const SPARSE_VAL_MAX_VALUE: u8 = 32;
+const SPARSE_VAL_MAX_LEN: usize = 4;
+
+/// The value claims to be a HyperLogLog but its registers do not add up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Corrupted;
+
+/// A sparse opcode, decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Opcode {
+    Zero(usize),
+    XZero(usize),
+    Val(u8, usize),
+}
+
+impl Opcode {
+    fn decode(sparse: &[u8]) -> Option<Opcode> {
+        let first = *sparse.first()?;
+        Some(match first & 0xc0 {
+            0x00 => Opcode::Zero((first & 0x3f) as usize + 1),
+            0x40 => {
+                let second = *sparse.get(1)?;
+                Opcode::XZero((((first & 0x3f) as usize) << 8 | second as usize) + 1)
+            }
+            _ => Opcode::Val(((first >> 2) & 0x1f) + 1, (first & 0x03) as usize + 1),
+        })
+    }
+
+    /// A run of `len` zero registers, in the shortest form.
+    fn zeros(len: usize) -> Opcode {
+        if len > SPARSE_ZERO_MAX_LEN {
+            Opcode::XZero(len)
+        } else {
+            Opcode::Zero(len)
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Opcode::XZero(_) => 2,
+            _ => 1,
+        }
+    }
+
+    fn span(&self) -> usize {
+        match *self {
+            Opcode::Zero(len) | Opcode::XZero(len) | Opcode::Val(_, len) => len,
+        }
+    }
+
+    fn value(&self) -> u8 {
+        match *self {
+            Opcode::Val(value, _) => value,
+            _ => 0,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            Opcode::Zero(len) => out.push((len - 1) as u8),
+            Opcode::XZero(len) => {
+                let len = len - 1;
+                out.push((len >> 8) as u8 | 0x40);
+                out.push((len & 0xff) as u8);
+            }
+            Opcode::Val(value, len) => out.push(((value - 1) << 2 | (len - 1) as u8) | 0x80),
+        }
+    }
+}
+
+/// Iterates over the opcodes of sparse registers, with their offsets.
+fn opcodes(sparse: &[u8]) -> impl Iterator<Item = Result<(usize, Opcode), Corrupted>> + '_ {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        if offset >= sparse.len() {
+            return None;
+        }
+        let Some(opcode) = Opcode::decode(&sparse[offset..]) else {
+            offset = sparse.len();
+            return Some(Err(Corrupted));
+        };
+        let at = offset;
+        offset += opcode.size();
+        Some(Ok((at, opcode)))
+    })
+}
+
+/// The 64 bit MurmurHash2 by Austin Appleby, as Redis uses it to hash elements.
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in chunks.by_ref() {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// Picks the register of `element` and the value it proposes for it: the length of the
+/// run of zeros ending its hash, plus one.
+fn register_and_value(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, 0xadc8_3b19);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    // the extra bit guarantees a 1 is found
+    let hash = (hash >> P) | (1 << Q);
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let byte = index * BITS / 8;
+    let shift = index * BITS % 8;
+    let b0 = registers[byte] as u16;
+    let b1 = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+    (((b0 | b1 << 8) >> shift) as u8) & REGISTER_MAX
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let byte = index * BITS / 8;
+    let shift = index * BITS % 8;
+    let mask = (REGISTER_MAX as u16) << shift;
+    let bits = (value as u16) << shift;
+    registers[byte] = (registers[byte] & !(mask as u8)) | bits as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next = (*next & !((mask >> 8) as u8)) | (bits >> 8) as u8;
+    }
+}
+
+/// Whether `value` can be used as a HyperLogLog, as far as can be told without decoding it.
+pub fn is_valid(value: &[u8]) -> bool {
+    value.len() >= HEADER_SIZE
+        && value.starts_with(MAGIC)
+        && match value[ENCODING_OFFSET] {
+            ENCODING_DENSE => value.len() == DENSE_SIZE,
+            ENCODING_SPARSE => true,
+            _ => false,
+        }
+}
+
+/// An empty HyperLogLog, which is sparse with a valid cached cardinality of 0.
+pub fn new() -> Vec<u8> {
+    let mut value = MAGIC.to_vec();
+    value.extend([ENCODING_SPARSE, 0, 0, 0]);
+    value.extend([0; 8]);
+    let mut remaining = REGISTERS;
+    while remaining > 0 {
+        let len = remaining.min(1 << 14);
+        Opcode::zeros(len).encode(&mut value);
+        remaining -= len;
+    }
+    value
+}
+
+pub fn invalidate_cache(hll: &mut [u8]) {
+    hll[CARDINALITY_OFFSET + 7] |= 1 << 7;
+}
+
+fn cached_cardinality(hll: &[u8]) -> Option<u64> {
+    let card = u64::from_le_bytes(hll[CARDINALITY_OFFSET..HEADER_SIZE].try_into().unwrap());
+    (card >> 63 == 0).then_some(card)
+}
+
+pub fn is_dense(hll: &[u8]) -> bool {
+    hll[ENCODING_OFFSET] == ENCODING_DENSE
+}
+
+/// Converts sparse registers to the dense encoding; dense ones are left alone.
+pub fn to_dense(hll: &mut Vec<u8>) -> Result<(), Corrupted> {
+    if is_dense(hll) {
+        return Ok(());
+    }
+    let mut dense = hll[..HEADER_SIZE].to_vec();
+    dense[ENCODING_OFFSET] = ENCODING_DENSE;
+    dense.resize(DENSE_SIZE, 0);
+    let mut index = 0;
+    for opcode in opcodes(&hll[HEADER_SIZE..]) {
+        let (_, opcode) = opcode?;
+        if index + opcode.span() > REGISTERS {
+            return Err(Corrupted);
+        }
+        if let Opcode::Val(value, len) = opcode {
+            for i in index..index + len {
+                dense_set(&mut dense[HEADER_SIZE..], i, value);
+            }
+        }
+        index += opcode.span();
+    }
+    if index != REGISTERS {
+        return Err(Corrupted);
+    }
+    *hll = dense;
+    Ok(())
+}
+
+/// Raises register `index` to `value`, returning whether it changed. This follows Redis
+/// step by step so that both produce the same sparse encoding.
+pub fn set_register(hll: &mut Vec<u8>, index: usize, value: u8) -> Result<bool, Corrupted> {
+    if is_dense(hll) {
+        let registers = &mut hll[HEADER_SIZE..];
+        if dense_get(registers, index) >= value {
+            return Ok(false);
+        }
+        dense_set(registers, index, value);
+        return Ok(true);
+    }
+    if value > SPARSE_VAL_MAX_VALUE {
+        return promote(hll, index, value);
+    }
+
+    // find the opcode covering the register, and the one before it
+    let mut first = 0;
+    let mut previous = None;
+    let mut found = None;
+    for opcode in opcodes(&hll[HEADER_SIZE..]) {
+        let (offset, opcode) = opcode?;
+        if index < first + opcode.span() {
+            found = Some((offset, opcode));
+            break;
+        }
+        previous = Some(offset);
+        first += opcode.span();
+    }
+    let Some((offset, opcode)) = found else {
+        return Err(Corrupted);
+    };
+    let offset = HEADER_SIZE + offset;
+
+    match opcode {
+        Opcode::Val(old, _) if old >= value => return Ok(false),
+        Opcode::Val(_, 1) | Opcode::Zero(1) => {
+            let mut encoded = Vec::with_capacity(1);
+            Opcode::Val(value, 1).encode(&mut encoded);
+            hll[offset] = encoded[0];
+        }
+        _ => {
+            // split the run around the register
+            let last = first + opcode.span() - 1;
+            let mut sequence = Vec::with_capacity(5);
+            let surrounding = |len| match opcode {
+                Opcode::Val(old, _) => Opcode::Val(old, len),
+                _ => Opcode::zeros(len),
+            };
+            if index != first {
+                surrounding(index - first).encode(&mut sequence);
+            }
+            Opcode::Val(value, 1).encode(&mut sequence);
+            if index != last {
+                surrounding(last - index).encode(&mut sequence);
+            }
+            if sequence.len() > opcode.size()
+                && hll.len() - opcode.size() + sequence.len() > SPARSE_MAX_BYTES
+            {
+                return promote(hll, index, value);
+            }
+            hll.splice(offset..offset + opcode.size(), sequence);
+        }
+    }
+
+    // adjacent VAL opcodes with the same value may now be merged, as far as five opcodes on
+    let mut p = HEADER_SIZE + previous.unwrap_or(0);
+    let mut scan = 5;
+    while p < hll.len() && scan > 0 {
+        scan -= 1;
+        let current = Opcode::decode(&hll[p..]).ok_or(Corrupted)?;
+        if let (Opcode::Val(v1, len1), Some(Opcode::Val(v2, len2))) =
+            (current, Opcode::decode(&hll[p + 1..]))
+        {
+            if v1 == v2 && len1 + len2 <= SPARSE_VAL_MAX_LEN {
+                let mut merged = Vec::with_capacity(1);
+                Opcode::Val(v1, len1 + len2).encode(&mut merged);
+                hll.splice(p..p + 2, merged);
+                // the merged run may merge with the next one too
+                continue;
+            }
+        }
+        p += current.size();
+    }
+    invalidate_cache(hll);
+    Ok(true)
+}
+
+fn promote(hll: &mut Vec<u8>, index: usize, value: u8) -> Result<bool, Corrupted> {
+    to_dense(hll)?;
+    set_register(hll, index, value)
+}
+
+/// Adds `element`, returning whether any register changed.
+pub fn add(hll: &mut Vec<u8>, element: &[u8]) -> Result<bool, Corrupted> {
+    let (index, value) = register_and_value(element);
+    let changed = set_register(hll, index, value)?;
+    if changed {
+        invalidate_cache(hll);
+    }
+    Ok(changed)
+}
+
+/// Raises each of `registers` to the matching register of `hll`, as PFMERGE and a PFCOUNT
+/// of several keys do.
+pub fn merge_into(registers: &mut [u8; REGISTERS], hll: &[u8]) -> Result<(), Corrupted> {
+    if is_dense(hll) {
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = (*register).max(dense_get(&hll[HEADER_SIZE..], i));
+        }
+        return Ok(());
+    }
+    let mut index = 0;
+    for opcode in opcodes(&hll[HEADER_SIZE..]) {
+        let (_, opcode) = opcode?;
+        let Some(run) = registers.get_mut(index..index + opcode.span()) else {
+            return Err(Corrupted);
+        };
+        for register in run {
+            *register = (*register).max(opcode.value());
+        }
+        index += opcode.span();
+    }
+    if index != REGISTERS {
+        return Err(Corrupted);
+    }
+    Ok(())
+}
+
+/// Counts how many registers hold each value.
+fn histogram(hll: &[u8]) -> Result<[u32; 64], Corrupted> {
+    let mut histogram = [0; 64];
+    if is_dense(hll) {
+        for i in 0..REGISTERS {
+            histogram[dense_get(&hll[HEADER_SIZE..], i) as usize] += 1;
+        }
+        return Ok(histogram);
+    }
+    let mut index = 0;
+    for opcode in opcodes(&hll[HEADER_SIZE..]) {
+        let (_, opcode) = opcode?;
+        histogram[opcode.value() as usize] += opcode.span() as u32;
+        index += opcode.span();
+    }
+    if index != REGISTERS {
+        return Err(Corrupted);
+    }
+    Ok(histogram)
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// Otmar Ertl's improved estimator, which Redis uses as well.
+fn estimate(histogram: &[u32; 64]) -> u64 {
+    let m = REGISTERS as f64;
+    let q = Q as usize;
+    let mut z = m * tau((m - histogram[q + 1] as f64) / m);
+    for j in (1..=q).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+/// The estimated cardinality, which is cached in the header.
+pub fn count(hll: &mut [u8]) -> Result<u64, Corrupted> {
+    if let Some(cardinality) = cached_cardinality(hll) {
+        return Ok(cardinality);
+    }
+    let cardinality = estimate(&histogram(hll)?);
+    hll[CARDINALITY_OFFSET..HEADER_SIZE].copy_from_slice(&cardinality.to_le_bytes());
+    Ok(cardinality)
+}
+
+/// The estimated cardinality of registers gathered by `merge_into`.
+pub fn count_registers(registers: &[u8; REGISTERS]) -> u64 {
+    let mut histogram = [0; 64];
+    for register in registers {
+        histogram[*register as usize] += 1;
+    }
+    estimate(&histogram)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registers(hll: &[u8]) -> [u8; REGISTERS] {
+        let mut registers = [0; REGISTERS];
+        merge_into(&mut registers, hll).unwrap();
+        registers
+    }
+
+    #[test]
+    fn test_new_is_empty_and_sparse() {
+        let mut hll = new();
+        assert_eq!(hll.len(), HEADER_SIZE + 2);
+        assert_eq!(&hll[HEADER_SIZE..], &[0x7f, 0xff]);
+        assert!(is_valid(&hll));
+        assert_eq!(count(&mut hll), Ok(0));
+    }
+
+    #[test]
+    fn test_small_counts_are_exact() {
+        let mut hll = new();
+        for element in [b"a", b"b", b"c", b"d", b"e", b"f", b"g"] {
+            assert_eq!(add(&mut hll, element), Ok(true));
+        }
+        assert_eq!(add(&mut hll, b"a"), Ok(false));
+        assert!(!is_dense(&hll));
+        assert_eq!(count(&mut hll), Ok(7));
+        // the cache is used until the next change
+        assert_eq!(cached_cardinality(&hll), Some(7));
+        add(&mut hll, b"h").unwrap();
+        assert_eq!(cached_cardinality(&hll), None);
+    }
+
+    #[test]
+    fn test_sparse_and_dense_agree() {
+        let mut hll = new();
+        for i in 0..2000 {
+            add(&mut hll, format!("element:{}", i).as_bytes()).unwrap();
+        }
+        assert!(is_dense(&hll), "a sparse value should outgrow its limit");
+        let mut sparse = new();
+        for i in 0..500 {
+            add(&mut sparse, format!("element:{}", i).as_bytes()).unwrap();
+        }
+        assert!(!is_dense(&sparse));
+        let mut dense = sparse.clone();
+        to_dense(&mut dense).unwrap();
+        assert_eq!(dense.len(), DENSE_SIZE);
+        assert_eq!(registers(&sparse), registers(&dense));
+        assert_eq!(count(&mut sparse), count(&mut dense));
+    }
+
+    #[test]
+    fn test_error_is_small() {
+        let mut hll = new();
+        let n = 100_000;
+        for i in 0..n {
+            add(&mut hll, format!("{}", i).as_bytes()).unwrap();
+        }
+        let estimate = count(&mut hll).unwrap() as f64;
+        let error = (estimate - n as f64).abs() / n as f64;
+        assert!(error < 0.02, "estimated {} for {}", estimate, n);
+    }
+
+    #[test]
+    fn test_corrupted_sparse_registers() {
+        let mut hll = new();
+        hll.truncate(HEADER_SIZE + 1);
+        hll.push(0x00);
+        invalidate_cache(&mut hll);
+        assert_eq!(count(&mut hll), Err(Corrupted));
+        assert_eq!(to_dense(&mut hll), Err(Corrupted));
+    }
+}
@@ -0,0 +1,128 @@
+//! The longest common subsequence of two strings, as computed by Redis's LCS command.
+
+/// A run of consecutive bytes common to both strings, as inclusive offsets into each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+}
+
+impl Match {
+    pub fn match_len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+/// The lengths of the longest common subsequences of every pair of prefixes of `a` and `b`.
+pub struct Table {
+    lengths: Vec<u32>,
+    columns: usize,
+}
+
+impl Table {
+    /// The number of bytes the table of `a` and `b` takes, or `None` if it can't be addressed.
+    pub fn bytes_needed(a: &[u8], b: &[u8]) -> Option<usize> {
+        (a.len() + 1)
+            .checked_mul(b.len() + 1)?
+            .checked_mul(std::mem::size_of::<u32>())
+    }
+
+    pub fn new(a: &[u8], b: &[u8]) -> Self {
+        let columns = b.len() + 1;
+        let mut lengths = vec![0u32; (a.len() + 1) * columns];
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                lengths[i * columns + j] = if a[i - 1] == b[j - 1] {
+                    lengths[(i - 1) * columns + j - 1] + 1
+                } else {
+                    lengths[(i - 1) * columns + j].max(lengths[i * columns + j - 1])
+                };
+            }
+        }
+        Self { lengths, columns }
+    }
+
+    fn at(&self, i: usize, j: usize) -> u32 {
+        self.lengths[i * self.columns + j]
+    }
+
+    /// The length of the longest common subsequence of `a` and `b`.
+    pub fn longest(&self) -> usize {
+        self.lengths[self.lengths.len() - 1] as usize
+    }
+
+    /// Walks back from the end of both strings, returning the longest common subsequence and
+    /// the runs it is made of, last run first.
+    pub fn backtrack(&self, a: &[u8], b: &[u8]) -> (Vec<u8>, Vec<Match>) {
+        let mut subsequence = Vec::with_capacity(self.longest());
+        let mut matches = vec![];
+        let mut current: Option<Match> = None;
+        let (mut i, mut j) = (a.len(), b.len());
+        while i > 0 && j > 0 {
+            if a[i - 1] == b[j - 1] {
+                subsequence.push(a[i - 1]);
+                match &mut current {
+                    Some(m) if m.a.0 == i && m.b.0 == j => {
+                        m.a.0 -= 1;
+                        m.b.0 -= 1;
+                    }
+                    _ => {
+                        matches.extend(current.take());
+                        current = Some(Match {
+                            a: (i - 1, i - 1),
+                            b: (j - 1, j - 1),
+                        });
+                    }
+                }
+                i -= 1;
+                j -= 1;
+            } else {
+                matches.extend(current.take());
+                if self.at(i - 1, j) > self.at(i, j - 1) {
+                    i -= 1;
+                } else {
+                    j -= 1;
+                }
+            }
+        }
+        matches.extend(current);
+        subsequence.reverse();
+        (subsequence, matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lcs() {
+        let (a, b) = (b"ohmytext".as_slice(), b"mynewtext".as_slice());
+        let table = Table::new(a, b);
+        assert_eq!(table.longest(), 6);
+        let (subsequence, matches) = table.backtrack(a, b);
+        assert_eq!(subsequence, b"mytext");
+        assert_eq!(
+            matches,
+            [
+                Match {
+                    a: (4, 7),
+                    b: (5, 8)
+                },
+                Match {
+                    a: (2, 3),
+                    b: (0, 1)
+                },
+            ]
+        );
+        assert_eq!(matches[0].match_len(), 4);
+    }
+
+    #[test]
+    fn test_lcs_empty() {
+        let table = Table::new(b"", b"abc");
+        assert_eq!(table.longest(), 0);
+        assert_eq!(table.backtrack(b"", b"abc"), (vec![], vec![]));
+        assert_eq!(Table::bytes_needed(b"", b"abc"), Some(16));
+    }
+}
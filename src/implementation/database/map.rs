@@ -1,39 +1,359 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::time::Instant;
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
 
 use crate::interface::database::map::{
-    IMap, Key, MapAllCommands, MapMiscCommands, MapStringCommands,
+    ExpireCycleReport, ExpireFlags, ExpireTime, IMap, Key, LcsOutput, LongDouble, MapAllCommands,
+    MapMiscCommands, MapStringCommands, ScanOptions, SetCondition, TtlFormat, TtlUpdate,
 };
 use crate::interface::types::OutputValue;
 
 use super::super::glob;
-use super::value::Value;
+use super::clock::Clock;
+use super::dict::Dict;
+use super::expires::Expires;
+use super::float;
+use super::lcs::Table;
+use super::value::{StringValue, Value};
+
+mod bitmap;
+mod geo;
+mod hash;
+mod hyperloglog;
+mod list;
+mod set;
+mod sorted_set;
+mod stream;
+
+/// Strings are limited to 512MB, as with Redis's default proto-max-bulk-len.
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// Keys sampled at a time by the active expire cycle at the lowest effort.
+const EXPIRE_CYCLE_KEYS_PER_LOOP: usize = 20;
 
 #[derive(Debug, Default)]
 pub struct Map {
-    data: HashMap<Vec<u8>, Value>,
+    data: Dict<Vec<u8>, Value>,
+    expires: Expires,
+    /// Keys deleted because their TTL ran out, lazily or by the active expire cycle.
+    expired_keys: u64,
+    /// Keys of hashes that have (or had) fields with a TTL, visited by the active expire cycle.
+    volatile_hashes: HashSet<Vec<u8>>,
+    /// Keys written since the controller last asked, which clients blocked on them may now
+    /// be able to read, as Redis's `ready_keys`.
+    ready_keys: Vec<Vec<u8>>,
+    clock: Clock,
+}
+
+fn wrong_type(command: &str) -> OutputValue {
+    OutputValue::Error(format!("ERR wrong target type for '{}'", command).into_bytes())
+}
+
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new({
+        use std::hash::{BuildHasher, Hasher};
+        // `RandomState` is seeded by the OS, which saves us a dependency on `getrandom`
+        let seed = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        SmallRng::seed_from_u64(seed)
+    });
+}
+
+fn with_rng<R>(f: impl FnOnce(&mut SmallRng) -> R) -> R {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+/// An empty reply with room for `len` items, or an error if they cannot all be held, as for a
+/// negative count of HRANDFIELD or SRANDMEMBER that asks for far more items than there are.
+fn reply_buffer(len: usize) -> Result<Vec<OutputValue>, OutputValue> {
+    let mut reply = Vec::new();
+    match reply.try_reserve_exact(len) {
+        Ok(()) => Ok(reply),
+        Err(_) => Err(OutputValue::Error(b"ERR value is out of range".to_vec())),
+    }
+}
+
+/// Calls `step` with the cursor it returned last, starting from `cursor`, until it has found
+/// `count` items or the walk is over. Like Redis, it gives up after ten steps per item asked
+/// for, so that a sparse table does not hold the server up. Returns the cursor to resume from.
+fn scan_steps<T>(
+    mut cursor: u64,
+    count: usize,
+    mut step: impl FnMut(u64, &mut Vec<T>) -> u64,
+) -> (u64, Vec<T>) {
+    let mut found = Vec::new();
+    for _ in 0..count.saturating_mul(10) {
+        cursor = step(cursor, &mut found);
+        if cursor == 0 || found.len() >= count {
+            break;
+        }
+    }
+    (cursor, found)
+}
+
+/// The MATCH filter of the SCAN family, which lets everything through without a pattern.
+fn scan_filter(pattern: Option<&[u8]>) -> impl Fn(&[u8]) -> bool {
+    let finder = pattern.map(glob::Finder::new);
+    move |item| finder.as_ref().is_none_or(|f| f.it_matches(item))
+}
+
+/// The `[cursor, items]` reply of the SCAN family.
+fn scan_reply(cursor: u64, items: Vec<OutputValue>) -> OutputValue {
+    OutputValue::Array(vec![
+        OutputValue::BulkString(cursor.to_string().into_bytes()),
+        OutputValue::Array(items),
+    ])
+}
+
+/// Renders the deadline `at` the way TTL, PTTL, EXPIRETIME or PEXPIRETIME would.
+fn format_ttl(at: u64, now: u64, format: TtlFormat) -> i64 {
+    let remaining = at.saturating_sub(now) as i64;
+    match format {
+        TtlFormat::Seconds => (remaining + 500) / 1000,
+        TtlFormat::Milliseconds => remaining,
+        TtlFormat::UnixSeconds => (at / 1000) as i64,
+        TtlFormat::UnixMilliseconds => at as i64,
+    }
+}
+
+/// Converts Redis-style inclusive `start`/`stop` indices (negative ones count from the end)
+/// into a `start..=stop` pair of offsets, or `None` if the range is empty.
+fn normalise_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
 }
 
 impl Map {
-    fn incr_decr_check_key_value(&mut self, key: impl Key) -> Result<(), OutputValue> {
-        let key = key.as_ref();
-        if let Some(v) = self.data.get_mut(key) {
-            if let Value::String(s) = v {
-                if std::str::from_utf8(s)
-                    .ok()
-                    .and_then(|s| s.parse::<i64>().ok())
-                    .is_some()
-                {
-                    Ok(())
-                } else {
-                    Err(OutputValue::Error(b"ERR value is not an integer".to_vec()))
-                }
+    #[cfg(test)]
+    fn with_clock(clock: Clock) -> Self {
+        Self {
+            clock,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(test)]
+    fn set(&mut self, key: impl Key, value: Vec<u8>) -> OutputValue {
+        self.set_with_options(key, value, SetCondition::Always, false, TtlUpdate::Persist)
+    }
+
+    /// Resolves `at` into milliseconds since the Unix epoch, or `None` on overflow.
+    fn resolve_expire_time(&self, at: ExpireTime) -> Option<i64> {
+        match at {
+            ExpireTime::Relative(ms) => (self.clock.now_ms() as i64).checked_add(ms),
+            ExpireTime::Absolute(ms) => Some(ms),
+        }
+    }
+
+    /// Notes that clients blocked on `key` should retry, as Redis's `signalKeyAsReady`.
+    fn signal_key_as_ready(&mut self, key: &[u8]) {
+        self.ready_keys.push(key.to_vec());
+    }
+
+    /// Removes `key` along with its TTL.
+    fn remove_key(&mut self, key: &[u8]) -> Option<Value> {
+        self.expires.remove(key);
+        let old = self.data.remove(key);
+        if let Some(Value::Stream(_)) = old {
+            // XREADGROUP fails once its stream is gone
+            self.signal_key_as_ready(key);
+        }
+        old
+    }
+
+    /// Stores `value` at `key`, replacing whatever was there along with its TTL.
+    fn insert_key(&mut self, key: Vec<u8>, value: Value) {
+        self.expires.remove(&key);
+        self.replace_value(key, value);
+    }
+
+    /// Stores `value` at `key`, keeping the TTL of whatever was there. Clients blocked on
+    /// `key` are signalled if it becomes something they pop from or read, or if it was a
+    /// stream, which XREADGROUP fails on.
+    fn replace_value(&mut self, key: Vec<u8>, value: Value) {
+        let signal = matches!(
+            value,
+            Value::List(_) | Value::SortedSet(_) | Value::Stream(_)
+        ) || matches!(self.data.get(&key), Some(Value::Stream(_)));
+        if signal {
+            self.signal_key_as_ready(&key);
+        }
+        self.data.insert(key, value);
+    }
+
+    fn is_expired(&self, key: &[u8]) -> bool {
+        self.expires
+            .get(key)
+            .is_some_and(|at| at < self.clock.now_ms())
+    }
+
+    /// Deletes `key` if its TTL has run out, so that it is seen as missing from then on.
+    fn remove_if_expired(&mut self, key: &[u8]) {
+        if self.is_expired(key) {
+            self.remove_key(key);
+            self.expired_keys += 1;
+        }
+    }
+
+    /// Deletes every key whose TTL has run out.
+    fn remove_expired_keys(&mut self) {
+        for key in self.expires.due(self.clock.now_ms()) {
+            self.remove_key(&key);
+            self.expired_keys += 1;
+        }
+    }
+
+    /// The value at `key`, unless its TTL has run out.
+    fn lookup(&self, key: &[u8]) -> Option<&Value> {
+        self.data.get(key).filter(|_| !self.is_expired(key))
+    }
+
+    /// The value at `key`, deleting it first if its TTL has run out.
+    fn lookup_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+        self.remove_if_expired(key);
+        self.data.get_mut(key)
+    }
+
+    /// Makes the existing `key` expire at `at`, deleting it right away if that is in the past.
+    fn expire_key_at(&mut self, key: &[u8], at: u64) {
+        if at < self.clock.now_ms() {
+            self.remove_key(key);
+        } else {
+            self.expires.insert(key.to_vec(), at);
+        }
+    }
+
+    /// Resolves the deadline of a key about to be given a TTL by `command`.
+    fn resolve_key_expire_time(&self, at: ExpireTime, command: &str) -> Result<u64, OutputValue> {
+        match self.resolve_expire_time(at) {
+            Some(at) if at > 0 => Ok(at as u64),
+            _ => Err(OutputValue::Error(
+                format!("ERR invalid expire time in '{}' command", command).into_bytes(),
+            )),
+        }
+    }
+
+    /// Backs SET and its legacy variants, which differ in their options and error messages.
+    fn set_generic(
+        &mut self,
+        key: &[u8],
+        value: Vec<u8>,
+        condition: SetCondition,
+        get: bool,
+        ttl: TtlUpdate,
+        command: &str,
+    ) -> OutputValue {
+        let at = match ttl {
+            TtlUpdate::Expire(at) => match self.resolve_key_expire_time(at, command) {
+                Ok(at) => Some(at),
+                Err(e) => return e,
+            },
+            TtlUpdate::Keep | TtlUpdate::Persist => None,
+        };
+        self.remove_if_expired(key);
+        let old = self.data.get(key);
+        let reply = match old {
+            _ if !get => OutputValue::Ok,
+            None => OutputValue::NullBulkString,
+            Some(Value::String(s)) => OutputValue::BulkString(s.as_bytes().into_owned()),
+            Some(_) => return wrong_type(command),
+        };
+        let applies = match condition {
+            SetCondition::Always => true,
+            SetCondition::Nx => old.is_none(),
+            SetCondition::Xx => old.is_some(),
+        };
+        if !applies {
+            return if get {
+                reply
             } else {
-                Err(OutputValue::Error(b"ERR value is not an integer".to_vec()))
-            }
+                OutputValue::NullBulkString
+            };
+        }
+
+        let value = Value::String(StringValue::new(value));
+        if ttl == TtlUpdate::Keep {
+            self.replace_value(key.to_vec(), value);
         } else {
-            self.data
-                .insert(key.to_owned(), Value::String(b"0".to_vec()));
-            Ok(())
+            self.insert_key(key.to_vec(), value);
+        }
+        if let Some(at) = at {
+            self.expire_key_at(key, at);
+        }
+        reply
+    }
+
+    fn get_string(&self, key: &[u8], command: &str) -> Result<Option<&StringValue>, OutputValue> {
+        match self.lookup(key) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    /// Looks up a string to modify its bytes in place, which drops its integer encoding.
+    fn get_or_create_string(
+        &mut self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<&mut Vec<u8>, OutputValue> {
+        self.remove_if_expired(key);
+        match self
+            .data
+            .get_or_insert_with(key.to_vec(), || Value::String(StringValue::Raw(Vec::new())))
+        {
+            Value::String(s) => Ok(s.bytes_mut()),
+            _ => Err(wrong_type(command)),
+        }
+    }
+
+    /// Backs INCRBY and DECRBY: `op` computes the new value, or `None` on overflow. Integers
+    /// are updated in place.
+    fn update_integer(
+        &mut self,
+        key: &[u8],
+        op: impl FnOnce(i64) -> Option<i64>,
+        command: &str,
+    ) -> OutputValue {
+        let overflow = || OutputValue::Error(b"ERR integer overflow".to_vec());
+        match self.lookup_mut(key) {
+            None => {
+                let Some(new_value) = op(0) else {
+                    return overflow();
+                };
+                self.data
+                    .insert(key.to_vec(), Value::String(StringValue::Int(new_value)));
+                OutputValue::Integer(new_value)
+            }
+            Some(Value::String(s)) => {
+                let Some(old_value) = s.as_int() else {
+                    return OutputValue::Error(b"ERR value is not an integer".to_vec());
+                };
+                let Some(new_value) = op(old_value) else {
+                    return overflow();
+                };
+                *s = StringValue::Int(new_value);
+                OutputValue::Integer(new_value)
+            }
+            Some(_) => wrong_type(command),
         }
     }
 }
@@ -41,31 +361,91 @@ impl Map {
 impl IMap for Map {
     fn flushdb(&mut self) -> OutputValue {
         // TODO: support async
+        let streams: Vec<_> = self
+            .data
+            .iter()
+            .filter(|(_, value)| matches!(value, Value::Stream(_)))
+            .map(|(key, _)| key.clone())
+            .collect();
+        self.ready_keys.extend(streams);
         self.data.clear();
+        self.expires.clear();
+        self.volatile_hashes.clear();
         OutputValue::Ok
     }
+
+    fn active_expire_cycle(&mut self, effort: u32, deadline: Instant) -> ExpireCycleReport {
+        debug_assert!((1..=10).contains(&effort));
+        let keys_per_loop = EXPIRE_CYCLE_KEYS_PER_LOOP * (3 + effort as usize) / 4;
+        let now = self.clock.now_ms();
+        let mut report = ExpireCycleReport::default();
+        // as in Redis, another sample is taken as long as more than a quarter of the last
+        // one was stale, as it suggests that many more keys are
+        while !self.expires.is_empty() {
+            let sampled = keys_per_loop.min(self.expires.len());
+            let mut expired = 0;
+            for _ in 0..sampled {
+                let Some((key, at)) =
+                    with_rng(|rng| self.expires.random(rng).map(|(key, at)| (key.to_vec(), at)))
+                else {
+                    break;
+                };
+                if at < now {
+                    self.remove_key(&key);
+                    expired += 1;
+                }
+            }
+            report.sampled += sampled;
+            report.expired += expired;
+            if Instant::now() >= deadline {
+                report.timed_out = true;
+                break;
+            }
+            if expired * 4 <= sampled {
+                break;
+            }
+        }
+        self.expired_keys += report.expired as u64;
+        self.expire_hash_fields();
+        report
+    }
+
+    fn expired_keys(&self) -> u64 {
+        self.expired_keys
+    }
+
+    fn rehash_keyspace(&mut self, deadline: Instant) {
+        self.data.rehash_for(deadline);
+    }
+
+    fn take_ready_keys(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.ready_keys)
+    }
 }
 
 impl MapStringCommands for Map {
-    fn get(&self, key: impl Key) -> OutputValue {
-        let Some(data) = self.data.get(key.as_ref()) else {
+    fn get(&mut self, key: impl Key) -> OutputValue {
+        let key = key.as_ref();
+        let Some(data) = self.lookup_mut(key) else {
             return OutputValue::NullBulkString;
         };
         if let Value::String(s) = data {
-            OutputValue::BulkString(s.clone())
+            OutputValue::BulkString(s.as_bytes().into_owned())
         } else {
             OutputValue::Error(b"ERR wrong target type for 'get'".to_vec())
         }
     }
 
-    fn mget(&self, key: Vec<impl Key>) -> OutputValue {
+    fn mget(&mut self, key: Vec<impl Key>) -> OutputValue {
         OutputValue::Array(
             key.into_iter()
                 .map(|key| {
-                    if let Some(Value::String(s)) = self.data.get(key.as_ref()) {
-                        OutputValue::BulkString(s.clone())
-                    } else {
-                        OutputValue::NullBulkString
+                    let key = key.as_ref();
+                    match self.lookup_mut(key) {
+                        Some(Value::String(s)) => {
+                            OutputValue::BulkString(s.as_bytes().into_owned())
+                        }
+                        _ => OutputValue::NullBulkString,
                     }
                 })
                 .collect(),
@@ -73,42 +453,134 @@ impl MapStringCommands for Map {
     }
 
     fn mset(&mut self, key_values: Vec<Vec<u8>>) -> OutputValue {
-        debug_assert!(key_values.len() % 2 == 0);
+        debug_assert!(key_values.len().is_multiple_of(2));
         for i in (0..key_values.len()).step_by(2) {
             let key = key_values[i].clone();
             let value = key_values[i + 1].clone();
-            self.data.insert(key, Value::String(value));
+            self.insert_key(key, Value::String(StringValue::new(value)));
         }
         OutputValue::Ok
     }
 
     fn msetnx(&mut self, key_values: Vec<Vec<u8>>) -> OutputValue {
-        debug_assert!(key_values.len() % 2 == 0);
+        debug_assert!(key_values.len().is_multiple_of(2));
         let any_key_exists = key_values
             .iter()
             .step_by(2)
-            .any(|k| self.data.contains_key(k));
+            .any(|k| self.lookup(k).is_some());
         if !any_key_exists {
             return OutputValue::Integer(0);
         }
         for i in (0..key_values.len()).step_by(2) {
             let key = key_values[i].clone();
             let value = key_values[i + 1].clone();
-            self.data.insert(key, Value::String(value));
+            self.insert_key(key, Value::String(StringValue::new(value)));
         }
         OutputValue::Integer(1)
     }
 
-    fn set(&mut self, key: impl Key, value: Vec<u8>) -> OutputValue {
-        self.data
-            .insert(key.as_ref().to_vec(), Value::String(value));
-        OutputValue::Ok
+    fn set_with_options(
+        &mut self,
+        key: impl Key,
+        value: Vec<u8>,
+        condition: SetCondition,
+        get: bool,
+        ttl: TtlUpdate,
+    ) -> OutputValue {
+        self.set_generic(key.as_ref(), value, condition, get, ttl, "set")
+    }
+
+    fn getset(&mut self, key: impl Key, value: Vec<u8>) -> OutputValue {
+        self.set_generic(
+            key.as_ref(),
+            value,
+            SetCondition::Always,
+            true,
+            TtlUpdate::Persist,
+            "getset",
+        )
+    }
+
+    fn setnx(&mut self, key: impl Key, value: Vec<u8>) -> OutputValue {
+        let key = key.as_ref();
+        match self.set_generic(
+            key,
+            value,
+            SetCondition::Nx,
+            false,
+            TtlUpdate::Persist,
+            "setnx",
+        ) {
+            OutputValue::Ok => OutputValue::Integer(1),
+            _ => OutputValue::Integer(0),
+        }
+    }
+
+    fn setex(&mut self, key: impl Key, ttl: ExpireTime, value: Vec<u8>) -> OutputValue {
+        let ttl = TtlUpdate::Expire(ttl);
+        self.set_generic(
+            key.as_ref(),
+            value,
+            SetCondition::Always,
+            false,
+            ttl,
+            "setex",
+        )
+    }
+
+    fn psetex(&mut self, key: impl Key, ttl: ExpireTime, value: Vec<u8>) -> OutputValue {
+        let ttl = TtlUpdate::Expire(ttl);
+        self.set_generic(
+            key.as_ref(),
+            value,
+            SetCondition::Always,
+            false,
+            ttl,
+            "psetex",
+        )
+    }
+
+    fn getdel(&mut self, key: impl Key) -> OutputValue {
+        let key = key.as_ref();
+        match self.get_string(key, "getdel") {
+            Ok(None) => OutputValue::NullBulkString,
+            Ok(Some(_)) => match self.remove_key(key) {
+                Some(Value::String(s)) => OutputValue::BulkString(s.into_bytes()),
+                _ => unreachable!(),
+            },
+            Err(e) => e,
+        }
+    }
+
+    fn getex(&mut self, key: impl Key, ttl: TtlUpdate) -> OutputValue {
+        let key = key.as_ref();
+        let at = match ttl {
+            TtlUpdate::Expire(at) => match self.resolve_key_expire_time(at, "getex") {
+                Ok(at) => Some(at),
+                Err(e) => return e,
+            },
+            TtlUpdate::Keep | TtlUpdate::Persist => None,
+        };
+        let reply = match self.get_string(key, "getex") {
+            Ok(None) => return OutputValue::NullBulkString,
+            Ok(Some(s)) => OutputValue::BulkString(s.as_bytes().into_owned()),
+            Err(e) => return e,
+        };
+        match (ttl, at) {
+            (_, Some(at)) => self.expire_key_at(key, at),
+            (TtlUpdate::Persist, _) => {
+                self.expires.remove(key);
+            }
+            _ => {}
+        }
+        reply
     }
 
     fn append(&mut self, key: impl Key, value: Vec<u8>) -> OutputValue {
         let key = key.as_ref();
-        if let Some(v) = self.data.get_mut(key) {
+        if let Some(v) = self.lookup_mut(key) {
             if let Value::String(ref mut v) = v {
+                let v = v.bytes_mut();
                 v.extend(value);
                 OutputValue::Integer(v.len() as i64)
             } else {
@@ -116,13 +588,13 @@ impl MapStringCommands for Map {
             }
         } else {
             let len = value.len();
-            self.data.insert(key.to_vec(), Value::String(value));
+            self.insert_key(key.to_vec(), Value::String(StringValue::new(value)));
             OutputValue::Integer(len as i64)
         }
     }
 
-    fn strlen(&self, key: impl Key) -> OutputValue {
-        if let Some(v) = self.data.get(key.as_ref()) {
+    fn strlen(&mut self, key: impl Key) -> OutputValue {
+        if let Some(v) = self.lookup_mut(key.as_ref()) {
             if let Value::String(ref v) = v {
                 OutputValue::Integer(v.len() as i64)
             } else {
@@ -133,71 +605,144 @@ impl MapStringCommands for Map {
         }
     }
 
-    fn incrby(&mut self, key: impl Key, n: i64) -> OutputValue {
-        let key = key.as_ref();
-        if let Err(e) = self.incr_decr_check_key_value(key) {
-            return e;
-        }
-        if let Some(Value::String(s)) = self.data.get_mut(key) {
-            let old_value: i64 = std::str::from_utf8(s).unwrap().parse().unwrap();
-            let new_value = old_value.checked_add(n);
-            if let Some(i) = new_value {
-                *s = i.to_string().into_bytes();
-                OutputValue::Integer(i)
+    fn getrange(&mut self, key: impl Key, start: i64, end: i64) -> OutputValue {
+        self.remove_if_expired(key.as_ref());
+        let s = match self.get_string(key.as_ref(), "getrange") {
+            Ok(s) => s.map(|s| s.as_bytes()).unwrap_or_default(),
+            Err(e) => return e,
+        };
+        // unlike LRANGE, an end before the start of the string is clamped to the first byte,
+        // unless both ends are negative and reversed
+        if s.is_empty() || (start < 0 && end < 0 && start > end) {
+            return OutputValue::BulkString(vec![]);
+        }
+        let len = s.len() as i64;
+        let resolve = |index: i64| {
+            if index < 0 {
+                (len + index).max(0)
             } else {
-                OutputValue::Error(b"ERR integer overflow".to_vec())
+                index
             }
-        } else {
-            unreachable!()
+        };
+        let (start, end) = (resolve(start), resolve(end).min(len - 1));
+        if start > end {
+            return OutputValue::BulkString(vec![]);
         }
+        OutputValue::BulkString(s[start as usize..=end as usize].to_vec())
     }
 
-    fn decrby(&mut self, key: impl Key, n: i64) -> OutputValue {
+    fn setrange(&mut self, key: impl Key, offset: usize, value: Vec<u8>) -> OutputValue {
         let key = key.as_ref();
-        if let Err(e) = self.incr_decr_check_key_value(key) {
-            return e;
-        }
-        if let Some(Value::String(s)) = self.data.get_mut(key) {
-            let old_value: i64 = std::str::from_utf8(s).unwrap().parse().unwrap();
-            let new_value = old_value.checked_sub(n);
-            if let Some(i) = new_value {
-                *s = i.to_string().into_bytes();
-                OutputValue::Integer(i)
-            } else {
-                OutputValue::Error(b"ERR integer overflow".to_vec())
+        if value.is_empty() {
+            // nothing is written, and a missing key isn't created
+            return match self.get_string(key, "setrange") {
+                Ok(s) => OutputValue::Integer(s.map_or(0, |s| s.len()) as i64),
+                Err(e) => e,
+            };
+        }
+        if offset.saturating_add(value.len()) > MAX_STRING_LEN {
+            return OutputValue::Error(
+                b"ERR string exceeds maximum allowed size (proto-max-bulk-len)".to_vec(),
+            );
+        }
+        let s = match self.get_or_create_string(key, "setrange") {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+        let end = offset + value.len();
+        if s.len() < end {
+            s.resize(end, 0);
+        }
+        s[offset..end].copy_from_slice(&value);
+        OutputValue::Integer(s.len() as i64)
+    }
+
+    fn lcs(&mut self, key1: impl Key, key2: impl Key, output: LcsOutput) -> OutputValue {
+        self.remove_if_expired(key1.as_ref());
+        self.remove_if_expired(key2.as_ref());
+        let (a, b) = match (
+            self.get_string(key1.as_ref(), "lcs"),
+            self.get_string(key2.as_ref(), "lcs"),
+        ) {
+            (Ok(a), Ok(b)) => (
+                a.map(|a| a.as_bytes()).unwrap_or_default(),
+                b.map(|b| b.as_bytes()).unwrap_or_default(),
+            ),
+            (Err(e), _) | (_, Err(e)) => return e,
+        };
+        let (a, b) = (a.as_ref(), b.as_ref());
+        if Table::bytes_needed(a, b).is_none_or(|size| size > MAX_STRING_LEN) {
+            return OutputValue::Error(
+                b"ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len"
+                    .to_vec(),
+            );
+        }
+        let table = Table::new(a, b);
+        match output {
+            LcsOutput::Len => OutputValue::Integer(table.longest() as i64),
+            LcsOutput::String => OutputValue::BulkString(table.backtrack(a, b).0),
+            LcsOutput::Idx {
+                min_match_len,
+                with_match_len,
+            } => {
+                let range = |(start, end): (usize, usize)| {
+                    OutputValue::Array(vec![
+                        OutputValue::Integer(start as i64),
+                        OutputValue::Integer(end as i64),
+                    ])
+                };
+                let matches = table
+                    .backtrack(a, b)
+                    .1
+                    .into_iter()
+                    .filter(|m| m.match_len() >= min_match_len)
+                    .map(|m| {
+                        let mut reply = vec![range(m.a), range(m.b)];
+                        if with_match_len {
+                            reply.push(OutputValue::Integer(m.match_len() as i64));
+                        }
+                        OutputValue::Array(reply)
+                    })
+                    .collect();
+                OutputValue::Array(vec![
+                    OutputValue::BulkString(b"matches".to_vec()),
+                    OutputValue::Array(matches),
+                    OutputValue::BulkString(b"len".to_vec()),
+                    OutputValue::Integer(table.longest() as i64),
+                ])
             }
-        } else {
-            unreachable!()
         }
     }
 
-    fn incrbyfloat(&mut self, key: impl Key, n: f64) -> OutputValue {
+    fn incrby(&mut self, key: impl Key, n: i64) -> OutputValue {
+        self.update_integer(key.as_ref(), |i| i.checked_add(n), "incrby")
+    }
+
+    fn decrby(&mut self, key: impl Key, n: i64) -> OutputValue {
+        self.update_integer(key.as_ref(), |i| i.checked_sub(n), "decrby")
+    }
+
+    fn incrbyfloat(&mut self, key: impl Key, n: LongDouble) -> OutputValue {
         let key = key.as_ref();
-        if let Some(v) = self.data.get_mut(key) {
-            if let Value::String(s) = v {
-                if let Some(old_value) = std::str::from_utf8(s)
-                    .ok()
-                    .and_then(|s| s.parse::<f64>().ok())
-                {
-                    let new_value = old_value + n;
-                    let new_s = new_value.to_string().into_bytes();
-                    *s = new_s.clone();
-                    OutputValue::BulkString(new_s)
-                } else {
-                    OutputValue::Error(b"ERR value is not an integer".to_vec())
-                }
-            } else {
-                OutputValue::Error(b"ERR value is not an integer".to_vec())
-            }
-        } else {
-            self.data
-                .insert(key.to_vec(), Value::String(n.to_string().into_bytes()));
-            if let Value::String(s) = self.data.get(key).unwrap() {
-                OutputValue::BulkString(s.clone())
-            } else {
-                unreachable!()
-            }
+        let old_value = match self.get_string(key, "incrbyfloat") {
+            Err(e) => return e,
+            Ok(None) => float::ZERO,
+            Ok(Some(s)) => match float::parse(&s.as_bytes()) {
+                Some(f) => f,
+                None => return OutputValue::Error(b"ERR value is not a valid float".to_vec()),
+            },
+        };
+        let Some(new_value) = float::add(old_value, n) else {
+            return OutputValue::Error(b"ERR increment would produce NaN or Infinity".to_vec());
+        };
+        // Redis never gives the result the integer encoding, even when it reads as one
+        let new_s = float::format(new_value);
+        let value = StringValue::Bytes(new_s.clone());
+        match self.lookup_mut(key) {
+            Some(Value::String(s)) => *s = value,
+            _ => self.insert_key(key.to_vec(), Value::String(value)),
         }
+        OutputValue::BulkString(new_s)
     }
 }
 
@@ -205,12 +750,16 @@ impl MapMiscCommands for Map {
     fn del(&mut self, keys: Vec<impl Key>) -> OutputValue {
         OutputValue::Integer(
             keys.into_iter()
-                .filter_map(|k| self.data.remove(k.as_ref()))
+                .filter_map(|k| {
+                    self.remove_if_expired(k.as_ref());
+                    self.remove_key(k.as_ref())
+                })
                 .count() as i64,
         )
     }
 
-    fn keys(&self, pattern: impl Key) -> OutputValue {
+    fn keys(&mut self, pattern: impl Key) -> OutputValue {
+        self.remove_expired_keys();
         let finder = glob::Finder::new(pattern.as_ref());
         OutputValue::Array(
             self.data
@@ -221,15 +770,106 @@ impl MapMiscCommands for Map {
                 .collect(),
         )
     }
-    fn exists(&self, keys: Vec<impl Key>) -> OutputValue {
+    fn exists(&mut self, keys: Vec<impl Key>) -> OutputValue {
         let len = keys
             .into_iter()
-            .filter(|k| self.data.contains_key(k.as_ref()))
+            .filter(|k| self.lookup_mut(k.as_ref()).is_some())
             .count();
         OutputValue::Integer(len as i64)
     }
 
-    fn len(&self) -> usize {
+    fn expire(
+        &mut self,
+        key: impl Key,
+        at: ExpireTime,
+        flags: ExpireFlags,
+        command: &str,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        let Some(at) = self.resolve_expire_time(at) else {
+            return OutputValue::Error(
+                format!("ERR invalid expire time in '{}' command", command).into_bytes(),
+            );
+        };
+        if self.lookup_mut(key).is_none() {
+            return OutputValue::Integer(0);
+        }
+        let current = self.expires.get(key).map(|c| c as i64);
+        let applies = (!flags.nx || current.is_none())
+            && (!flags.xx || current.is_some())
+            // no TTL counts as an infinite one
+            && (!flags.gt || current.is_some_and(|c| at > c))
+            && (!flags.lt || current.is_none_or(|c| at < c));
+        if !applies {
+            return OutputValue::Integer(0);
+        }
+        if at <= self.clock.now_ms() as i64 {
+            self.remove_key(key);
+        } else {
+            self.expires.insert(key.to_vec(), at as u64);
+        }
+        OutputValue::Integer(1)
+    }
+
+    fn ttl(&mut self, key: impl Key, format: TtlFormat) -> OutputValue {
+        let key = key.as_ref();
+        if self.lookup_mut(key).is_none() {
+            return OutputValue::Integer(-2);
+        }
+        let now = self.clock.now_ms();
+        OutputValue::Integer(
+            self.expires
+                .get(key)
+                .map_or(-1, |at| format_ttl(at, now, format)),
+        )
+    }
+
+    fn persist(&mut self, key: impl Key) -> OutputValue {
+        let key = key.as_ref();
+        self.remove_if_expired(key);
+        OutputValue::Integer(self.expires.remove(key).is_some() as i64)
+    }
+
+    fn object_encoding(&mut self, key: impl Key) -> OutputValue {
+        match self.lookup_mut(key.as_ref()) {
+            Some(value) => OutputValue::BulkString(value.encoding().as_bytes().to_vec()),
+            None => OutputValue::NullBulkString,
+        }
+    }
+
+    fn scan(
+        &mut self,
+        cursor: u64,
+        options: ScanOptions,
+        value_type: Option<Vec<u8>>,
+    ) -> OutputValue {
+        let (cursor, keys) = scan_steps(cursor, options.count, |cursor, keys| {
+            self.data.scan(cursor, |key, _| keys.push(key.clone()))
+        });
+        let matches = scan_filter(options.pattern.as_deref());
+        let mut matched = Vec::new();
+        for key in keys {
+            self.remove_if_expired(&key);
+            let Some(value) = self.data.get(&key) else {
+                continue;
+            };
+            if !matches(&key) {
+                continue;
+            }
+            // an unknown type matches no key
+            if value_type
+                .as_ref()
+                .is_some_and(|t| !t.eq_ignore_ascii_case(value.type_name().as_bytes()))
+            {
+                continue;
+            }
+            matched.push(OutputValue::BulkString(key));
+        }
+        scan_reply(cursor, matched)
+    }
+
+    fn len(&mut self) -> usize {
+        self.remove_expired_keys();
         self.data.len()
     }
 }
@@ -238,7 +878,12 @@ impl MapAllCommands for Map {}
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
     use super::*;
+    use crate::interface::database::map::{MapBitmapCommands, MapSetCommands};
 
     #[test]
     fn test_set() {
@@ -274,4 +919,729 @@ mod tests {
             OutputValue::BulkString(b"bazz".to_vec())
         );
     }
+
+    #[test]
+    fn test_set_conditions_and_get() {
+        let mut map = Map::default();
+        let set = |map: &mut Map, value: &[u8], condition, get| {
+            map.set_with_options(
+                b"k".as_slice(),
+                value.to_vec(),
+                condition,
+                get,
+                TtlUpdate::Persist,
+            )
+        };
+        assert_eq!(
+            set(&mut map, b"a", SetCondition::Xx, false),
+            OutputValue::NullBulkString
+        );
+        assert_eq!(
+            set(&mut map, b"a", SetCondition::Nx, false),
+            OutputValue::Ok
+        );
+        assert_eq!(
+            set(&mut map, b"b", SetCondition::Nx, true),
+            OutputValue::BulkString(b"a".to_vec())
+        );
+        assert_eq!(
+            set(&mut map, b"c", SetCondition::Xx, true),
+            OutputValue::BulkString(b"a".to_vec())
+        );
+        assert_eq!(
+            map.get(b"k".as_slice()),
+            OutputValue::BulkString(b"c".to_vec())
+        );
+
+        map.sadd(b"s".as_slice(), vec![b"m".to_vec()]);
+        assert_eq!(
+            map.set_with_options(
+                b"s".as_slice(),
+                b"v".to_vec(),
+                SetCondition::Always,
+                true,
+                TtlUpdate::Persist
+            ),
+            wrong_type("set")
+        );
+        assert_eq!(map.set(b"s".as_slice(), b"v".to_vec()), OutputValue::Ok);
+    }
+
+    #[test]
+    fn test_set_ttl() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        let set = |map: &mut Map, condition, ttl| {
+            map.set_with_options(b"k".as_slice(), b"v".to_vec(), condition, false, ttl)
+        };
+        let px = |ms| TtlUpdate::Expire(ExpireTime::Relative(ms));
+        assert_eq!(set(&mut map, SetCondition::Nx, px(100)), OutputValue::Ok);
+        assert_eq!(
+            set(&mut map, SetCondition::Nx, px(100)),
+            OutputValue::NullBulkString
+        );
+        assert_eq!(
+            set(&mut map, SetCondition::Always, TtlUpdate::Keep),
+            OutputValue::Ok
+        );
+        now.set(1_000_101);
+        assert_eq!(map.get(b"k".as_slice()), OutputValue::NullBulkString);
+        // the expired key no longer stands in the way of NX
+        assert_eq!(set(&mut map, SetCondition::Nx, px(100)), OutputValue::Ok);
+        assert_eq!(
+            set(&mut map, SetCondition::Always, TtlUpdate::Persist),
+            OutputValue::Ok
+        );
+        now.set(2_000_000);
+        assert_eq!(
+            map.get(b"k".as_slice()),
+            OutputValue::BulkString(b"v".to_vec())
+        );
+
+        let at = |ms| TtlUpdate::Expire(ExpireTime::Absolute(ms));
+        assert_eq!(set(&mut map, SetCondition::Always, at(1)), OutputValue::Ok);
+        assert_eq!(map.len(), 0);
+        assert_eq!(
+            set(&mut map, SetCondition::Always, px(i64::MAX)),
+            OutputValue::Error(b"ERR invalid expire time in 'set' command".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_active_expire_cycle_drops_keys() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        map.set_with_options(
+            b"k".as_slice(),
+            b"v".to_vec(),
+            SetCondition::Always,
+            false,
+            TtlUpdate::Expire(ExpireTime::Relative(10)),
+        );
+        map.set(b"other".as_slice(), b"v".to_vec());
+        map.active_expire_cycle(1, Instant::now() + Duration::from_secs(1));
+        assert_eq!(map.len(), 2);
+        now.set(1_000_011);
+        map.active_expire_cycle(1, Instant::now() + Duration::from_secs(1));
+        assert_eq!(map.len(), 1);
+        assert!(map.expires.is_empty());
+    }
+
+    #[test]
+    fn test_active_expire_cycle_samples_keys() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        for i in 0..100 {
+            let key = format!("key:{}", i).into_bytes();
+            map.set(key.as_slice(), b"v".to_vec());
+            map.expire(
+                key.as_slice(),
+                ExpireTime::Relative(10),
+                ExpireFlags::default(),
+                "pexpire",
+            );
+        }
+        now.set(1_000_011);
+        // past its deadline, the cycle stops after a single sample
+        assert_eq!(
+            map.active_expire_cycle(1, Instant::now()),
+            ExpireCycleReport {
+                sampled: 20,
+                expired: 20,
+                timed_out: true,
+            }
+        );
+        assert_eq!(map.data.len(), 80);
+        // a sample that is all stale calls for another, until none are left
+        let report = map.active_expire_cycle(10, Instant::now() + Duration::from_secs(1));
+        assert_eq!(report.expired, 80);
+        assert!(!report.timed_out);
+        assert!(map.data.is_empty());
+        assert_eq!(map.expired_keys(), 100);
+    }
+
+    #[test]
+    fn test_expire() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        let expire = |map: &mut Map, ms, condition| {
+            map.expire(
+                b"k".as_slice(),
+                ExpireTime::Relative(ms),
+                condition,
+                "pexpire",
+            )
+        };
+        let ttl = |map: &mut Map, format| map.ttl(b"k".as_slice(), format);
+        assert_eq!(
+            expire(&mut map, 100, ExpireFlags::default()),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(ttl(&mut map, TtlFormat::Seconds), OutputValue::Integer(-2));
+
+        map.set(b"k".as_slice(), b"v".to_vec());
+        assert_eq!(ttl(&mut map, TtlFormat::Seconds), OutputValue::Integer(-1));
+        assert_eq!(
+            expire(
+                &mut map,
+                100,
+                ExpireFlags {
+                    xx: true,
+                    ..ExpireFlags::default()
+                }
+            ),
+            OutputValue::Integer(0)
+        );
+        // XX still asks for a TTL when combined with LT, for which none counts as infinite
+        let xx_lt = ExpireFlags {
+            xx: true,
+            lt: true,
+            ..ExpireFlags::default()
+        };
+        assert_eq!(expire(&mut map, 100, xx_lt), OutputValue::Integer(0));
+        // no TTL counts as an infinite one
+        assert_eq!(
+            expire(
+                &mut map,
+                100,
+                ExpireFlags {
+                    gt: true,
+                    ..ExpireFlags::default()
+                }
+            ),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            expire(
+                &mut map,
+                2_500,
+                ExpireFlags {
+                    lt: true,
+                    ..ExpireFlags::default()
+                }
+            ),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            expire(
+                &mut map,
+                100,
+                ExpireFlags {
+                    nx: true,
+                    ..ExpireFlags::default()
+                }
+            ),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            expire(
+                &mut map,
+                3_000,
+                ExpireFlags {
+                    lt: true,
+                    ..ExpireFlags::default()
+                }
+            ),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            expire(
+                &mut map,
+                3_000,
+                ExpireFlags {
+                    gt: true,
+                    ..ExpireFlags::default()
+                }
+            ),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(ttl(&mut map, TtlFormat::Seconds), OutputValue::Integer(3));
+        assert_eq!(
+            ttl(&mut map, TtlFormat::Milliseconds),
+            OutputValue::Integer(3_000)
+        );
+        assert_eq!(
+            ttl(&mut map, TtlFormat::UnixSeconds),
+            OutputValue::Integer(1_003)
+        );
+        assert_eq!(
+            ttl(&mut map, TtlFormat::UnixMilliseconds),
+            OutputValue::Integer(1_003_000)
+        );
+        assert_eq!(expire(&mut map, 1_000, xx_lt), OutputValue::Integer(1));
+        assert_eq!(
+            ttl(&mut map, TtlFormat::Milliseconds),
+            OutputValue::Integer(1_000)
+        );
+
+        assert_eq!(map.persist(b"k".as_slice()), OutputValue::Integer(1));
+        assert_eq!(map.persist(b"k".as_slice()), OutputValue::Integer(0));
+        assert_eq!(ttl(&mut map, TtlFormat::Seconds), OutputValue::Integer(-1));
+
+        assert_eq!(
+            expire(&mut map, i64::MAX, ExpireFlags::default()),
+            OutputValue::Error(b"ERR invalid expire time in 'pexpire' command".to_vec())
+        );
+        // a deadline that has already passed deletes the key
+        assert_eq!(
+            expire(&mut map, 0, ExpireFlags::default()),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(map.exists(vec![b"k".as_slice()]), OutputValue::Integer(0));
+    }
+
+    #[test]
+    fn test_expired_keys_are_deleted_on_access() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        map.set(b"string".as_slice(), b"v".to_vec());
+        map.sadd(b"set".as_slice(), vec![b"a".to_vec()]);
+        map.set(b"kept".as_slice(), b"v".to_vec());
+        for key in [b"string".as_slice(), b"set".as_slice()] {
+            let at = ExpireTime::Relative(10);
+            map.expire(key, at, ExpireFlags::default(), "pexpire");
+        }
+        now.set(1_000_011);
+        // nothing has touched the keys yet, so they are still stored
+        assert_eq!(map.data.len(), 3);
+        assert_eq!(
+            map.exists(vec![b"string".as_slice(), b"kept".as_slice()]),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(map.data.len(), 2);
+        assert_eq!(map.scard(b"set".as_slice()), OutputValue::Integer(0));
+        assert_eq!(map.len(), 1);
+        assert_eq!(
+            map.keys(b"*".as_slice()),
+            OutputValue::Array(vec![OutputValue::BulkString(b"kept".to_vec())])
+        );
+        assert!(map.expires.is_empty());
+    }
+
+    #[test]
+    fn test_getrange() {
+        let mut map = Map::default();
+        map.set(b"k".as_slice(), b"This is a string".to_vec());
+        let mut getrange = |start, end| match map.getrange(b"k".as_slice(), start, end) {
+            OutputValue::BulkString(s) => String::from_utf8(s).unwrap(),
+            reply => panic!("{:?}", reply),
+        };
+        assert_eq!(getrange(0, 3), "This");
+        assert_eq!(getrange(-3, -1), "ing");
+        assert_eq!(getrange(0, -1), "This is a string");
+        assert_eq!(getrange(10, 100), "string");
+        assert_eq!(getrange(0, -100), "T");
+        assert_eq!(getrange(-1, -5), "");
+        assert_eq!(getrange(20, 30), "");
+        assert_eq!(
+            map.getrange(b"missing".as_slice(), 0, -1),
+            OutputValue::BulkString(vec![])
+        );
+    }
+
+    #[test]
+    fn test_setrange() {
+        let mut map = Map::default();
+        assert_eq!(
+            map.setrange(b"k".as_slice(), 3, vec![]),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.len(), 0);
+        assert_eq!(
+            map.setrange(b"k".as_slice(), 3, b"ab".to_vec()),
+            OutputValue::Integer(5)
+        );
+        assert_eq!(
+            map.get(b"k".as_slice()),
+            OutputValue::BulkString(b"\0\0\0ab".to_vec())
+        );
+        assert_eq!(
+            map.setrange(b"k".as_slice(), 0, b"xyz".to_vec()),
+            OutputValue::Integer(5)
+        );
+        assert_eq!(
+            map.get(b"k".as_slice()),
+            OutputValue::BulkString(b"xyzab".to_vec())
+        );
+        assert_eq!(
+            map.setrange(b"k".as_slice(), MAX_STRING_LEN, b"a".to_vec()),
+            OutputValue::Error(
+                b"ERR string exceeds maximum allowed size (proto-max-bulk-len)".to_vec()
+            )
+        );
+    }
+
+    #[test]
+    fn test_lcs() {
+        let mut map = Map::default();
+        map.set(b"a".as_slice(), b"ohmytext".to_vec());
+        map.set(b"b".as_slice(), b"mynewtext".to_vec());
+        let lcs = |map: &mut Map, output| map.lcs(b"a".as_slice(), b"b".as_slice(), output);
+        assert_eq!(
+            lcs(&mut map, LcsOutput::String),
+            OutputValue::BulkString(b"mytext".to_vec())
+        );
+        assert_eq!(lcs(&mut map, LcsOutput::Len), OutputValue::Integer(6));
+        let range = |start, end| {
+            OutputValue::Array(vec![OutputValue::Integer(start), OutputValue::Integer(end)])
+        };
+        assert_eq!(
+            lcs(
+                &mut map,
+                LcsOutput::Idx {
+                    min_match_len: 4,
+                    with_match_len: true
+                }
+            ),
+            OutputValue::Array(vec![
+                OutputValue::BulkString(b"matches".to_vec()),
+                OutputValue::Array(vec![OutputValue::Array(vec![
+                    range(4, 7),
+                    range(5, 8),
+                    OutputValue::Integer(4),
+                ])]),
+                OutputValue::BulkString(b"len".to_vec()),
+                OutputValue::Integer(6),
+            ])
+        );
+        assert_eq!(
+            map.lcs(b"a".as_slice(), b"missing".as_slice(), LcsOutput::Len),
+            OutputValue::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_legacy_set_variants() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        assert_eq!(
+            map.setnx(b"k".as_slice(), b"a".to_vec()),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            map.setnx(b"k".as_slice(), b"b".to_vec()),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            map.getset(b"k".as_slice(), b"c".to_vec()),
+            OutputValue::BulkString(b"a".to_vec())
+        );
+        assert_eq!(
+            map.getset(b"new".as_slice(), b"v".to_vec()),
+            OutputValue::NullBulkString
+        );
+        assert_eq!(
+            map.setex(b"k".as_slice(), ExpireTime::Relative(1000), b"d".to_vec()),
+            OutputValue::Ok
+        );
+        assert_eq!(
+            map.psetex(
+                b"k".as_slice(),
+                ExpireTime::Relative(i64::MAX),
+                b"d".to_vec()
+            ),
+            OutputValue::Error(b"ERR invalid expire time in 'psetex' command".to_vec())
+        );
+        now.set(1_001_001);
+        assert_eq!(map.get(b"k".as_slice()), OutputValue::NullBulkString);
+        assert_eq!(
+            map.setnx(b"k".as_slice(), b"e".to_vec()),
+            OutputValue::Integer(1)
+        );
+    }
+
+    #[test]
+    fn test_getdel_and_getex() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        map.set(b"k".as_slice(), b"v".to_vec());
+        assert_eq!(
+            map.getex(
+                b"k".as_slice(),
+                TtlUpdate::Expire(ExpireTime::Relative(100))
+            ),
+            OutputValue::BulkString(b"v".to_vec())
+        );
+        assert_eq!(map.expires.get(b"k".as_slice()), Some(1_000_100));
+        assert_eq!(
+            map.getex(b"k".as_slice(), TtlUpdate::Keep),
+            OutputValue::BulkString(b"v".to_vec())
+        );
+        assert_eq!(map.expires.get(b"k".as_slice()), Some(1_000_100));
+        map.getex(b"k".as_slice(), TtlUpdate::Persist);
+        assert!(map.expires.is_empty());
+        // a deadline in the past deletes the key after replying with its value
+        assert_eq!(
+            map.getex(b"k".as_slice(), TtlUpdate::Expire(ExpireTime::Absolute(1))),
+            OutputValue::BulkString(b"v".to_vec())
+        );
+        assert_eq!(map.len(), 0);
+        assert_eq!(
+            map.getex(b"k".as_slice(), TtlUpdate::Keep),
+            OutputValue::NullBulkString
+        );
+
+        map.set(b"k".as_slice(), b"v".to_vec());
+        assert_eq!(
+            map.getdel(b"k".as_slice()),
+            OutputValue::BulkString(b"v".to_vec())
+        );
+        assert_eq!(map.getdel(b"k".as_slice()), OutputValue::NullBulkString);
+        map.sadd(b"s".as_slice(), vec![b"m".to_vec()]);
+        assert_eq!(map.getdel(b"s".as_slice()), wrong_type("getdel"));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_incrbyfloat() {
+        let mut map = Map::default();
+        let ld = |s: &str| float::parse(s.as_bytes()).unwrap();
+        map.set(b"k".as_slice(), b"10.50".to_vec());
+        assert_eq!(
+            map.incrbyfloat(b"k".as_slice(), ld("0.1")),
+            OutputValue::BulkString(b"10.6".to_vec())
+        );
+        assert_eq!(
+            map.incrbyfloat(b"k".as_slice(), ld("-10.6")),
+            OutputValue::BulkString(b"0".to_vec())
+        );
+        assert_eq!(
+            map.incrbyfloat(b"k".as_slice(), ld("inf")),
+            OutputValue::Error(b"ERR increment would produce NaN or Infinity".to_vec())
+        );
+        // the sum is a long double, which goes far beyond a double
+        map.set(b"k".as_slice(), b"1e308".to_vec());
+        assert!(matches!(
+            map.incrbyfloat(b"k".as_slice(), ld("1e308")),
+            OutputValue::BulkString(s) if s.len() == 309
+        ));
+        map.set(b"k".as_slice(), b"1e4932".to_vec());
+        assert_eq!(
+            map.incrbyfloat(b"k".as_slice(), ld("1e4932")),
+            OutputValue::Error(b"ERR increment would produce NaN or Infinity".to_vec())
+        );
+        map.set(b"k".as_slice(), b"abc".to_vec());
+        assert_eq!(
+            map.incrbyfloat(b"k".as_slice(), ld("1")),
+            OutputValue::Error(b"ERR value is not a valid float".to_vec())
+        );
+        assert_eq!(
+            map.incrbyfloat(b"new".as_slice(), ld("5.0e3")),
+            OutputValue::BulkString(b"5000".to_vec())
+        );
+        assert_eq!(
+            map.incrbyfloat(b"new".as_slice(), ld("0.1")),
+            OutputValue::BulkString(b"5000.10000000000000009".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_integer_encoding() {
+        let mut map = Map::default();
+        let encoding = |map: &mut Map, key: &[u8]| map.object_encoding(key);
+        let bulk = |s: &[u8]| OutputValue::BulkString(s.to_vec());
+        map.set(b"n".as_slice(), b"-123".to_vec());
+        assert_eq!(encoding(&mut map, b"n"), bulk(b"int"));
+        assert_eq!(map.incrby(b"n".as_slice(), 23), OutputValue::Integer(-100));
+        assert_eq!(map.get(b"n".as_slice()), bulk(b"-100"));
+        assert_eq!(map.strlen(b"n".as_slice()), OutputValue::Integer(4));
+        assert_eq!(
+            map.append(b"n".as_slice(), b"0".to_vec()),
+            OutputValue::Integer(5)
+        );
+        assert_eq!(encoding(&mut map, b"n"), bulk(b"raw"));
+        // digits that aren't an i64's canonical form stay strings
+        for s in [
+            b"007".as_slice(),
+            b"+1",
+            b"-0",
+            b" 1",
+            b"9223372036854775808",
+        ] {
+            map.set(b"s".as_slice(), s.to_vec());
+            assert_eq!(encoding(&mut map, b"s"), bulk(b"embstr"));
+            assert_eq!(
+                map.incrby(b"s".as_slice(), 1),
+                OutputValue::Error(b"ERR value is not an integer".to_vec())
+            );
+        }
+        map.set(b"s".as_slice(), vec![b'a'; 45]);
+        assert_eq!(encoding(&mut map, b"s"), bulk(b"raw"));
+
+        assert_eq!(map.incrby(b"new".as_slice(), 5), OutputValue::Integer(5));
+        assert_eq!(encoding(&mut map, b"new"), bulk(b"int"));
+        assert_eq!(
+            map.decrby(b"new".as_slice(), i64::MIN),
+            OutputValue::Error(b"ERR integer overflow".to_vec())
+        );
+        map.sadd(b"set".as_slice(), vec![b"m".to_vec()]);
+        assert_eq!(encoding(&mut map, b"set"), bulk(b"hashtable"));
+        assert_eq!(encoding(&mut map, b"missing"), OutputValue::NullBulkString);
+    }
+
+    #[test]
+    fn test_in_place_writes_leave_int_encoding() {
+        let mut map = Map::default();
+        let encoding = |map: &mut Map, key: &[u8]| map.object_encoding(key);
+        let bulk = |s: &[u8]| OutputValue::BulkString(s.to_vec());
+        map.set(b"a".as_slice(), b"12".to_vec());
+        map.set(b"b".as_slice(), b"12".to_vec());
+        assert_eq!(map.incrby(b"b".as_slice(), 1), OutputValue::Integer(13));
+        assert_eq!(encoding(&mut map, b"a"), bulk(b"int"));
+        assert_eq!(encoding(&mut map, b"b"), bulk(b"int"));
+
+        assert_eq!(
+            map.setrange(b"a".as_slice(), 1, b"3".to_vec()),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(encoding(&mut map, b"a"), bulk(b"raw"));
+        assert_eq!(map.get(b"a".as_slice()), bulk(b"13"));
+        // the bytes still spell an integer, so INCR reads them and stores an int again
+        assert_eq!(map.incrby(b"a".as_slice(), 1), OutputValue::Integer(14));
+        assert_eq!(encoding(&mut map, b"a"), bulk(b"int"));
+
+        assert_eq!(
+            map.setbit(b"b".as_slice(), 7, false),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(encoding(&mut map, b"b"), bulk(b"raw"));
+        assert_eq!(map.get(b"b".as_slice()), bulk(b"03"));
+        assert_eq!(
+            map.incrby(b"b".as_slice(), 1),
+            OutputValue::Error(b"ERR value is not an integer".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_incr_respects_ttl() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        let ttl = |map: &mut Map| map.ttl(b"n".as_slice(), TtlFormat::Milliseconds);
+        map.set_with_options(
+            b"n".as_slice(),
+            b"10".to_vec(),
+            SetCondition::Always,
+            false,
+            TtlUpdate::Expire(ExpireTime::Relative(100)),
+        );
+        // a live key keeps its TTL
+        assert_eq!(map.incrby(b"n".as_slice(), 1), OutputValue::Integer(11));
+        assert_eq!(ttl(&mut map), OutputValue::Integer(100));
+        // an expired one counts from zero and is recreated without a TTL
+        now.set(1_000_101);
+        assert_eq!(map.incrby(b"n".as_slice(), 1), OutputValue::Integer(1));
+        assert_eq!(ttl(&mut map), OutputValue::Integer(-1));
+        now.set(2_000_000);
+        assert_eq!(
+            map.get(b"n".as_slice()),
+            OutputValue::BulkString(b"1".to_vec())
+        );
+    }
+
+    /// Calls SCAN once, returning the next cursor and the keys it gave.
+    fn scan_page(
+        map: &mut Map,
+        cursor: u64,
+        options: ScanOptions,
+        value_type: Option<&[u8]>,
+    ) -> (u64, Vec<Vec<u8>>) {
+        let reply = map.scan(cursor, options, value_type.map(<[u8]>::to_vec));
+        let OutputValue::Array(reply) = reply else {
+            panic!("expected an array, got {:?}", reply)
+        };
+        let [OutputValue::BulkString(next), OutputValue::Array(page)] = reply.as_slice() else {
+            panic!("expected a cursor and a page, got {:?}", reply)
+        };
+        let keys = page
+            .iter()
+            .map(|k| match k {
+                OutputValue::BulkString(k) => k.clone(),
+                _ => panic!("expected a key, got {:?}", k),
+            })
+            .collect();
+        (std::str::from_utf8(next).unwrap().parse().unwrap(), keys)
+    }
+
+    /// Runs a whole SCAN, returning the keys in the order they came.
+    fn scan_all(map: &mut Map, options: ScanOptions, value_type: Option<&[u8]>) -> Vec<Vec<u8>> {
+        let mut keys = vec![];
+        let mut cursor = 0;
+        loop {
+            let (next, page) = scan_page(map, cursor, options.clone(), value_type);
+            keys.extend(page);
+            cursor = next;
+            if cursor == 0 {
+                return keys;
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        for i in 0..100 {
+            map.set(format!("key:{}", i).as_bytes(), b"v".to_vec());
+        }
+        map.sadd(b"set".as_slice(), vec![b"m".to_vec()]);
+        map.set(b"volatile".as_slice(), b"v".to_vec());
+        map.expire(
+            b"volatile".as_slice(),
+            ExpireTime::Relative(10),
+            ExpireFlags::default(),
+            "pexpire",
+        );
+        now.set(1_000_011);
+
+        let mut keys = scan_all(&mut map, ScanOptions::default(), None);
+        keys.sort_unstable();
+        let mut expected: Vec<_> = (0..100)
+            .map(|i| format!("key:{}", i).into_bytes())
+            .chain([b"set".to_vec()])
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(keys, expected);
+        // the expired key was deleted when it came up
+        assert_eq!(map.data.len(), 101);
+
+        let options = ScanOptions {
+            pattern: Some(b"key:1?".to_vec()),
+            count: 3,
+        };
+        assert_eq!(scan_all(&mut map, options, None).len(), 10);
+        assert_eq!(
+            scan_all(&mut map, ScanOptions::default(), Some(b"SET")),
+            vec![b"set".to_vec()]
+        );
+        assert!(scan_all(&mut map, ScanOptions::default(), Some(b"nosuchtype")).is_empty());
+    }
+
+    #[test]
+    fn test_scan_returns_keys_present_throughout() {
+        let mut map = Map::default();
+        for i in 0..50 {
+            map.set(format!("old:{}", i).as_bytes(), b"v".to_vec());
+        }
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let options = ScanOptions {
+            pattern: None,
+            count: 5,
+        };
+        for step in 0.. {
+            let (next, page) = scan_page(&mut map, cursor, options.clone(), None);
+            seen.extend(page);
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+            // grow the keyspace between calls, so that it is rehashed along the way
+            if step < 5 {
+                for i in 0..100 {
+                    map.set(format!("new:{}:{}", step, i).as_bytes(), b"v".to_vec());
+                }
+            }
+        }
+        assert!((0..50).all(|i| seen.contains(format!("old:{}", i).as_bytes())));
+    }
 }
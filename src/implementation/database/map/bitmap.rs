@@ -0,0 +1,407 @@
+use crate::interface::database::map::{
+    BitOperation, BitRange, BitUnit, BitfieldOperation, BitfieldSubcommand, Key, MapBitmapCommands,
+};
+use crate::interface::types::OutputValue;
+
+use super::super::bitmap;
+use super::super::value::{StringValue, Value};
+use super::Map;
+
+/// Resolves `range` against a string of `len` bytes into inclusive bit offsets, clamping it
+/// the way Redis does, or `None` if it is empty. A missing end means the end of the string.
+fn bit_range(range: BitRange, len: usize) -> Option<(u64, u64)> {
+    let total = match range.unit {
+        BitUnit::Byte => len as i64,
+        BitUnit::Bit => len as i64 * 8,
+    };
+    let resolve = |index: i64| if index < 0 { index + total } else { index };
+    let start = resolve(range.start).max(0);
+    let end = resolve(range.end.unwrap_or(-1)).max(0).min(total - 1);
+    if start > end {
+        return None;
+    }
+    let (start, end) = (start as u64, end as u64);
+    Some(match range.unit {
+        BitUnit::Byte => (start * 8, end * 8 + 7),
+        BitUnit::Bit => (start, end),
+    })
+}
+
+impl MapBitmapCommands for Map {
+    fn setbit(&mut self, key: impl Key, offset: u64, value: bool) -> OutputValue {
+        match self.get_or_create_string(key.as_ref(), "setbit") {
+            Ok(s) => OutputValue::Integer(bitmap::set_bit(s, offset, value) as i64),
+            Err(e) => e,
+        }
+    }
+
+    fn getbit(&self, key: impl Key, offset: u64) -> OutputValue {
+        match self.get_string(key.as_ref(), "getbit") {
+            Ok(s) => OutputValue::Integer(
+                s.is_some_and(|s| bitmap::get_bit(&s.as_bytes(), offset)) as i64,
+            ),
+            Err(e) => e,
+        }
+    }
+
+    fn bitcount(&self, key: impl Key, range: Option<BitRange>) -> OutputValue {
+        let s = match self.get_string(key.as_ref(), "bitcount") {
+            Ok(Some(s)) => s.as_bytes(),
+            Ok(None) => return OutputValue::Integer(0),
+            Err(e) => return e,
+        };
+        let range = range.unwrap_or(BitRange {
+            start: 0,
+            end: None,
+            unit: BitUnit::Byte,
+        });
+        if let (start, Some(end)) = (range.start, range.end) {
+            // both counting from the end and reversed, which clamping would hide
+            if start < 0 && end < 0 && start > end {
+                return OutputValue::Integer(0);
+            }
+        }
+        match bit_range(range, s.len()) {
+            Some((first, last)) => OutputValue::Integer(bitmap::count(&s, first, last) as i64),
+            None => OutputValue::Integer(0),
+        }
+    }
+
+    fn bitpos(&self, key: impl Key, bit: bool, range: Option<BitRange>) -> OutputValue {
+        let s = match self.get_string(key.as_ref(), "bitpos") {
+            Ok(Some(s)) => s.as_bytes(),
+            // a missing key is an empty string, padded with zeros
+            Ok(None) => return OutputValue::Integer(if bit { -1 } else { 0 }),
+            Err(e) => return e,
+        };
+        let range = range.unwrap_or(BitRange {
+            start: 0,
+            end: None,
+            unit: BitUnit::Byte,
+        });
+        let Some((first, last)) = bit_range(range, s.len()) else {
+            return OutputValue::Integer(-1);
+        };
+        match bitmap::position(&s, bit, first, last) {
+            Some(position) => OutputValue::Integer(position as i64),
+            // without an explicit end, the string goes on with zeros
+            None if !bit && range.end.is_none() => OutputValue::Integer(s.len() as i64 * 8),
+            None => OutputValue::Integer(-1),
+        }
+    }
+
+    fn bitop(
+        &mut self,
+        operation: BitOperation,
+        destination: impl Key,
+        sources: Vec<impl Key>,
+    ) -> OutputValue {
+        let sources: Result<Vec<_>, _> = sources
+            .iter()
+            .map(|k| {
+                self.get_string(k.as_ref(), "bitop")
+                    .map(|s| s.map(|s| s.as_bytes()).unwrap_or_default())
+            })
+            .collect();
+        let result = match sources {
+            Ok(sources) => {
+                let sources: Vec<&[u8]> = sources.iter().map(|s| s.as_ref()).collect();
+                bitmap::operate(operation, &sources)
+            }
+            Err(e) => return e,
+        };
+        let len = result.len();
+        if result.is_empty() {
+            self.remove_key(destination.as_ref());
+        } else {
+            self.insert_key(
+                destination.as_ref().to_vec(),
+                Value::String(StringValue::new(result)),
+            );
+        }
+        OutputValue::Integer(len as i64)
+    }
+
+    fn bitfield(&mut self, key: impl Key, subcommands: Vec<BitfieldSubcommand>) -> OutputValue {
+        // reads alone neither create nor grow the string
+        let Some(last_bit) = subcommands
+            .iter()
+            .filter(|s| s.operation != BitfieldOperation::Get)
+            .map(|s| s.offset + s.field.bits as u64 - 1)
+            .max()
+        else {
+            return self.bitfield_ro(key, subcommands);
+        };
+        let s = match self.get_or_create_string(key.as_ref(), "bitfield") {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+        // the string grows to fit every field written, even those that fail to be
+        let len = (last_bit / 8 + 1) as usize;
+        if s.len() < len {
+            s.resize(len, 0);
+        }
+        OutputValue::Array(
+            subcommands
+                .into_iter()
+                .map(|subcommand| {
+                    let BitfieldSubcommand {
+                        operation,
+                        field,
+                        offset,
+                        overflow,
+                    } = subcommand;
+                    let old = bitmap::get_field(s, offset, field);
+                    let (new, reply) = match operation {
+                        BitfieldOperation::Get => return OutputValue::Integer(old),
+                        BitfieldOperation::Set(value) => {
+                            // unsigned fields take the value as the bits it is made of
+                            let value = if field.signed {
+                                value as i128
+                            } else {
+                                value as u64 as i128
+                            };
+                            match bitmap::fit_field(field, value, overflow) {
+                                Some(new) => (new, old),
+                                None => return OutputValue::NullBulkString,
+                            }
+                        }
+                        BitfieldOperation::IncrBy(increment) => {
+                            let value = old as i128 + increment as i128;
+                            match bitmap::fit_field(field, value, overflow) {
+                                Some(new) => (new, new),
+                                None => return OutputValue::NullBulkString,
+                            }
+                        }
+                    };
+                    bitmap::set_field(s, offset, field, new);
+                    OutputValue::Integer(reply)
+                })
+                .collect(),
+        )
+    }
+
+    fn bitfield_ro(&self, key: impl Key, subcommands: Vec<BitfieldSubcommand>) -> OutputValue {
+        let s = match self.get_string(key.as_ref(), "bitfield") {
+            Ok(s) => s.map(|s| s.as_bytes()).unwrap_or_default(),
+            Err(e) => return e,
+        };
+        OutputValue::Array(
+            subcommands
+                .into_iter()
+                .map(|subcommand| {
+                    OutputValue::Integer(bitmap::get_field(&s, subcommand.offset, subcommand.field))
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::database::map::{BitfieldOverflow, BitfieldType, MapStringCommands};
+
+    fn bytes(start: i64, end: i64) -> Option<BitRange> {
+        Some(BitRange {
+            start,
+            end: Some(end),
+            unit: BitUnit::Byte,
+        })
+    }
+
+    fn bits(start: i64, end: i64) -> Option<BitRange> {
+        Some(BitRange {
+            start,
+            end: Some(end),
+            unit: BitUnit::Bit,
+        })
+    }
+
+    #[test]
+    fn test_setbit_getbit() {
+        let mut map = Map::default();
+        assert_eq!(map.setbit(b"k".to_vec(), 7, true), OutputValue::Integer(0));
+        assert_eq!(map.setbit(b"k".to_vec(), 7, false), OutputValue::Integer(1));
+        assert_eq!(
+            map.setbit(b"k".to_vec(), 100, true),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.strlen(b"k".to_vec()), OutputValue::Integer(13));
+        assert_eq!(map.getbit(b"k".to_vec(), 100), OutputValue::Integer(1));
+        assert_eq!(map.getbit(b"k".to_vec(), 10_000), OutputValue::Integer(0));
+        assert_eq!(map.getbit(b"missing".to_vec(), 0), OutputValue::Integer(0));
+    }
+
+    #[test]
+    fn test_bitcount() {
+        let mut map = Map::default();
+        map.set(b"k".to_vec(), b"foobar".to_vec());
+        assert_eq!(map.bitcount(b"k".to_vec(), None), OutputValue::Integer(26));
+        assert_eq!(
+            map.bitcount(b"k".to_vec(), bytes(0, 0)),
+            OutputValue::Integer(4)
+        );
+        assert_eq!(
+            map.bitcount(b"k".to_vec(), bytes(1, 1)),
+            OutputValue::Integer(6)
+        );
+        assert_eq!(
+            map.bitcount(b"k".to_vec(), bytes(-2, -1)),
+            OutputValue::Integer(7)
+        );
+        assert_eq!(
+            map.bitcount(b"k".to_vec(), bytes(-1, -2)),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            map.bitcount(b"k".to_vec(), bytes(4, 2)),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            map.bitcount(b"k".to_vec(), bits(5, 30)),
+            OutputValue::Integer(17)
+        );
+        assert_eq!(
+            map.bitcount(b"missing".to_vec(), None),
+            OutputValue::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_bitpos() {
+        let mut map = Map::default();
+        map.set(b"k".to_vec(), b"\xff\xf0\x00".to_vec());
+        assert_eq!(
+            map.bitpos(b"k".to_vec(), false, None),
+            OutputValue::Integer(12)
+        );
+        map.set(b"k".to_vec(), b"\x00\xff\xf0".to_vec());
+        assert_eq!(
+            map.bitpos(b"k".to_vec(), true, bytes(0, -1)),
+            OutputValue::Integer(8)
+        );
+        assert_eq!(
+            map.bitpos(b"k".to_vec(), true, bytes(2, -1)),
+            OutputValue::Integer(16)
+        );
+        assert_eq!(
+            map.bitpos(b"k".to_vec(), true, bits(7, 15)),
+            OutputValue::Integer(8)
+        );
+        assert_eq!(
+            map.bitpos(b"k".to_vec(), false, bits(8, 15)),
+            OutputValue::Integer(-1)
+        );
+        map.set(b"k".to_vec(), b"\xff\xff".to_vec());
+        // the zeros padding the string are only found when no end is given
+        assert_eq!(
+            map.bitpos(b"k".to_vec(), false, None),
+            OutputValue::Integer(16)
+        );
+        assert_eq!(
+            map.bitpos(b"k".to_vec(), false, bytes(0, -1)),
+            OutputValue::Integer(-1)
+        );
+        assert_eq!(
+            map.bitpos(b"missing".to_vec(), false, None),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            map.bitpos(b"missing".to_vec(), true, None),
+            OutputValue::Integer(-1)
+        );
+    }
+
+    fn field(
+        operation: BitfieldOperation,
+        type_: &str,
+        offset: u64,
+        overflow: BitfieldOverflow,
+    ) -> BitfieldSubcommand {
+        BitfieldSubcommand {
+            operation,
+            field: BitfieldType {
+                signed: type_.starts_with('i'),
+                bits: type_[1..].parse().unwrap(),
+            },
+            offset,
+            overflow,
+        }
+    }
+
+    #[test]
+    fn test_bitfield() {
+        use BitfieldOperation::{Get, IncrBy, Set};
+        use BitfieldOverflow::{Fail, Sat, Wrap};
+        let mut map = Map::default();
+        assert_eq!(
+            map.bitfield(b"k".to_vec(), vec![field(Get, "u8", 0, Wrap)]),
+            OutputValue::Array(vec![OutputValue::Integer(0)])
+        );
+        assert_eq!(map.get(b"k".to_vec()), OutputValue::NullBulkString);
+        assert_eq!(
+            map.bitfield(
+                b"k".to_vec(),
+                vec![
+                    field(Set(255), "u8", 0, Wrap),
+                    field(IncrBy(1), "u8", 0, Wrap),
+                    field(IncrBy(-1), "u8", 0, Sat),
+                    field(IncrBy(200), "i8", 8, Sat),
+                    field(IncrBy(100), "i8", 8, Fail),
+                    field(Get, "i8", 8, Fail),
+                ]
+            ),
+            OutputValue::Array(vec![
+                OutputValue::Integer(0),
+                OutputValue::Integer(0),
+                OutputValue::Integer(0),
+                OutputValue::Integer(127),
+                OutputValue::NullBulkString,
+                OutputValue::Integer(127),
+            ])
+        );
+        assert_eq!(
+            map.get(b"k".to_vec()),
+            OutputValue::BulkString(b"\x00\x7f".to_vec())
+        );
+        // a failed write still grows the string
+        assert_eq!(
+            map.bitfield(b"k".to_vec(), vec![field(Set(-1), "u4", 20, Fail)]),
+            OutputValue::Array(vec![OutputValue::NullBulkString])
+        );
+        assert_eq!(map.strlen(b"k".to_vec()), OutputValue::Integer(3));
+        assert_eq!(
+            map.bitfield_ro(b"k".to_vec(), vec![field(Get, "i16", 0, Wrap)]),
+            OutputValue::Array(vec![OutputValue::Integer(127)])
+        );
+    }
+
+    #[test]
+    fn test_bitop() {
+        let mut map = Map::default();
+        map.set(b"a".to_vec(), b"foobar".to_vec());
+        map.set(b"b".to_vec(), b"abcdef".to_vec());
+        assert_eq!(
+            map.bitop(
+                BitOperation::And,
+                b"dest".to_vec(),
+                vec![b"a".to_vec(), b"b".to_vec()]
+            ),
+            OutputValue::Integer(6)
+        );
+        assert_eq!(
+            map.get(b"dest".to_vec()),
+            OutputValue::BulkString(b"`bc`ab".to_vec())
+        );
+        assert_eq!(
+            map.bitop(
+                BitOperation::Or,
+                b"dest".to_vec(),
+                vec![b"missing".to_vec()]
+            ),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.get(b"dest".to_vec()), OutputValue::NullBulkString);
+    }
+}
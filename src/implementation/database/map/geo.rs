@@ -0,0 +1,439 @@
+use std::ops::Bound;
+
+use crate::interface::database::map::{
+    GeoOrigin, GeoSearch, GeoShape, GeoSort, GeoUnit, Key, MapGeoCommands, MapSortedSetCommands,
+    ZaddOptions,
+};
+use crate::interface::types::OutputValue;
+
+use super::super::geohash::{self, Search, Shape};
+use super::super::value::{SortedSet, Value};
+use super::Map;
+
+/// A point found by GEOSEARCH.
+struct GeoMatch {
+    member: Vec<u8>,
+    score: f64,
+    /// In meters.
+    distance: f64,
+    longitude: f64,
+    latitude: f64,
+}
+
+/// Coordinates are replied with all the digits of their nearest double, like Redis does.
+fn format_coordinate(coordinate: f64) -> Vec<u8> {
+    let formatted = format!("{:.17}", coordinate);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    formatted.as_bytes().to_vec()
+}
+
+fn format_distance(distance: f64, unit: GeoUnit) -> Vec<u8> {
+    format!("{:.4}", distance / unit.in_meters()).into_bytes()
+}
+
+fn invalid_position(longitude: f64, latitude: f64) -> OutputValue {
+    OutputValue::Error(
+        format!(
+            "ERR invalid longitude,latitude pair {:.6},{:.6}",
+            longitude, latitude
+        )
+        .into_bytes(),
+    )
+}
+
+fn format_position(longitude: f64, latitude: f64) -> OutputValue {
+    OutputValue::Array(vec![
+        OutputValue::BulkString(format_coordinate(longitude)),
+        OutputValue::BulkString(format_coordinate(latitude)),
+    ])
+}
+
+impl Map {
+    fn geo_search(
+        &self,
+        key: &[u8],
+        search: &GeoSearch,
+        command: &str,
+    ) -> Result<Vec<GeoMatch>, OutputValue> {
+        let Some(zset) = self.get_sorted_set(key, command)? else {
+            return Ok(vec![]);
+        };
+        let (longitude, latitude) = match &search.origin {
+            GeoOrigin::LonLat(longitude, latitude) if geohash::is_valid(*longitude, *latitude) => {
+                (*longitude, *latitude)
+            }
+            GeoOrigin::LonLat(longitude, latitude) => {
+                return Err(invalid_position(*longitude, *latitude))
+            }
+            GeoOrigin::Member(member) => match zset.score(member) {
+                Some(score) => geohash::decode_score(score),
+                None => {
+                    return Err(OutputValue::Error(
+                        b"ERR could not decode requested zset member".to_vec(),
+                    ))
+                }
+            },
+        };
+        let unit = search.unit.in_meters();
+        let shape = match search.shape {
+            GeoShape::Radius(radius) => Shape::Circle {
+                radius: radius * unit,
+            },
+            GeoShape::Box { width, height } => Shape::Rectangle {
+                width: width * unit,
+                height: height * unit,
+            },
+        };
+        let area = Search {
+            longitude,
+            latitude,
+            shape,
+        };
+        let limit = search.count.filter(|_| search.any);
+        let mut matches = vec![];
+        'ranges: for (min, max) in area.score_ranges() {
+            for (member, score) in zset.range_by_score(Bound::Included(min), Bound::Excluded(max)) {
+                if limit.is_some_and(|limit| matches.len() >= limit) {
+                    break 'ranges;
+                }
+                let (longitude, latitude) = geohash::decode_score(score);
+                if let Some(distance) = area.distance_to(longitude, latitude) {
+                    matches.push(GeoMatch {
+                        member: member.clone(),
+                        score,
+                        distance,
+                        longitude,
+                        latitude,
+                    });
+                }
+            }
+        }
+        // the closest ones are wanted when only some of them are
+        let sort = match search.sort {
+            None if search.count.is_some() && !search.any => Some(GeoSort::Asc),
+            sort => sort,
+        };
+        match sort {
+            Some(GeoSort::Asc) => matches.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(GeoSort::Desc) => matches.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+            None => {}
+        }
+        if let Some(count) = search.count {
+            matches.truncate(count);
+        }
+        Ok(matches)
+    }
+}
+
+impl MapGeoCommands for Map {
+    fn geoadd(
+        &mut self,
+        key: impl Key,
+        options: ZaddOptions,
+        points: Vec<(f64, f64, Vec<u8>)>,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        if let Err(e) = self.get_sorted_set(key, "geoadd") {
+            return e;
+        }
+        let mut members = Vec::with_capacity(points.len());
+        for (longitude, latitude, member) in points {
+            let Some(score) = geohash::encode_score(longitude, latitude) else {
+                return invalid_position(longitude, latitude);
+            };
+            members.push((score, member));
+        }
+        self.zadd(key, options, members)
+    }
+
+    fn geopos(&self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue {
+        let zset = match self.get_sorted_set(key.as_ref(), "geopos") {
+            Ok(z) => z,
+            Err(e) => return e,
+        };
+        OutputValue::Array(
+            members
+                .iter()
+                .map(|member| match zset.and_then(|z| z.score(member)) {
+                    Some(score) => {
+                        let (longitude, latitude) = geohash::decode_score(score);
+                        format_position(longitude, latitude)
+                    }
+                    None => OutputValue::NullArray,
+                })
+                .collect(),
+        )
+    }
+
+    fn geodist(
+        &self,
+        key: impl Key,
+        member1: Vec<u8>,
+        member2: Vec<u8>,
+        unit: GeoUnit,
+    ) -> OutputValue {
+        let zset = match self.get_sorted_set(key.as_ref(), "geodist") {
+            Ok(z) => z,
+            Err(e) => return e,
+        };
+        let position = |member| {
+            zset.and_then(|z| z.score(member))
+                .map(geohash::decode_score)
+        };
+        let (Some((longitude1, latitude1)), Some((longitude2, latitude2))) =
+            (position(&member1), position(&member2))
+        else {
+            return OutputValue::NullBulkString;
+        };
+        let distance = geohash::distance(longitude1, latitude1, longitude2, latitude2);
+        OutputValue::BulkString(format_distance(distance, unit))
+    }
+
+    fn geohash(&self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue {
+        let zset = match self.get_sorted_set(key.as_ref(), "geohash") {
+            Ok(z) => z,
+            Err(e) => return e,
+        };
+        OutputValue::Array(
+            members
+                .iter()
+                .map(|member| match zset.and_then(|z| z.score(member)) {
+                    Some(score) => OutputValue::BulkString(geohash::to_string(score)),
+                    None => OutputValue::NullBulkString,
+                })
+                .collect(),
+        )
+    }
+
+    fn geosearch(&self, key: impl Key, search: GeoSearch) -> OutputValue {
+        let matches = match self.geo_search(key.as_ref(), &search, "geosearch") {
+            Ok(matches) => matches,
+            Err(e) => return e,
+        };
+        let plain = !(search.with_coord || search.with_dist || search.with_hash);
+        OutputValue::Array(
+            matches
+                .into_iter()
+                .map(|m| {
+                    if plain {
+                        return OutputValue::BulkString(m.member);
+                    }
+                    let mut reply = vec![OutputValue::BulkString(m.member)];
+                    if search.with_dist {
+                        reply.push(OutputValue::BulkString(format_distance(
+                            m.distance,
+                            search.unit,
+                        )));
+                    }
+                    if search.with_hash {
+                        reply.push(OutputValue::Integer(m.score as i64));
+                    }
+                    if search.with_coord {
+                        reply.push(format_position(m.longitude, m.latitude));
+                    }
+                    OutputValue::Array(reply)
+                })
+                .collect(),
+        )
+    }
+
+    fn geosearchstore(
+        &mut self,
+        destination: impl Key,
+        source: impl Key,
+        search: GeoSearch,
+        store_dist: bool,
+    ) -> OutputValue {
+        let matches = match self.geo_search(source.as_ref(), &search, "geosearchstore") {
+            Ok(matches) => matches,
+            Err(e) => return e,
+        };
+        let destination = destination.as_ref();
+        if matches.is_empty() {
+            self.remove_key(destination);
+            return OutputValue::Integer(0);
+        }
+        let mut zset = SortedSet::default();
+        for m in matches {
+            let score = if store_dist {
+                m.distance / search.unit.in_meters()
+            } else {
+                m.score
+            };
+            zset.insert(m.member, score);
+        }
+        let len = zset.len();
+        self.insert_key(destination.to_vec(), Value::SortedSet(zset));
+        OutputValue::Integer(len as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sicily() -> Map {
+        let mut map = Map::default();
+        map.geoadd(
+            b"Sicily".to_vec(),
+            ZaddOptions::default(),
+            vec![
+                (13.361389, 38.115556, b"Palermo".to_vec()),
+                (15.087269, 37.502669, b"Catania".to_vec()),
+            ],
+        );
+        map.geoadd(
+            b"Sicily".to_vec(),
+            ZaddOptions::default(),
+            vec![
+                (12.758489, 38.788135, b"edge1".to_vec()),
+                (17.241510, 38.788135, b"edge2".to_vec()),
+            ],
+        );
+        map
+    }
+
+    fn search(origin: GeoOrigin, shape: GeoShape, unit: GeoUnit) -> GeoSearch {
+        GeoSearch {
+            origin,
+            shape,
+            unit,
+            sort: Some(GeoSort::Asc),
+            count: None,
+            any: false,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+        }
+    }
+
+    fn members(names: &[&str]) -> OutputValue {
+        OutputValue::Array(
+            names
+                .iter()
+                .map(|n| OutputValue::BulkString(n.as_bytes().to_vec()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_geopos_geodist_geohash() {
+        let map = sicily();
+        assert_eq!(
+            map.geopos(
+                b"Sicily".to_vec(),
+                vec![b"Palermo".to_vec(), b"missing".to_vec()]
+            ),
+            OutputValue::Array(vec![
+                OutputValue::Array(vec![
+                    OutputValue::BulkString(b"13.36138933897018433".to_vec()),
+                    OutputValue::BulkString(b"38.11555639549629859".to_vec()),
+                ]),
+                OutputValue::NullArray,
+            ])
+        );
+        assert_eq!(
+            map.geodist(
+                b"Sicily".to_vec(),
+                b"Palermo".to_vec(),
+                b"Catania".to_vec(),
+                GeoUnit::Kilometers
+            ),
+            OutputValue::BulkString(b"166.2742".to_vec())
+        );
+        assert_eq!(
+            map.geodist(
+                b"Sicily".to_vec(),
+                b"Palermo".to_vec(),
+                b"missing".to_vec(),
+                GeoUnit::Meters
+            ),
+            OutputValue::NullBulkString
+        );
+        assert_eq!(
+            map.geohash(b"Sicily".to_vec(), vec![b"Catania".to_vec()]),
+            members(&["sqdtr74hyu0"])
+        );
+    }
+
+    #[test]
+    fn test_geosearch() {
+        let map = sicily();
+        let from_lonlat = GeoOrigin::LonLat(15.0, 37.0);
+        assert_eq!(
+            map.geosearch(
+                b"Sicily".to_vec(),
+                search(
+                    from_lonlat.clone(),
+                    GeoShape::Radius(200.0),
+                    GeoUnit::Kilometers
+                )
+            ),
+            members(&["Catania", "Palermo"])
+        );
+        assert_eq!(
+            map.geosearch(
+                b"Sicily".to_vec(),
+                search(
+                    from_lonlat.clone(),
+                    GeoShape::Box {
+                        width: 400.0,
+                        height: 400.0
+                    },
+                    GeoUnit::Kilometers
+                )
+            ),
+            members(&["Catania", "Palermo", "edge2", "edge1"])
+        );
+        let mut with_dist = search(
+            GeoOrigin::Member(b"Palermo".to_vec()),
+            GeoShape::Radius(200.0),
+            GeoUnit::Kilometers,
+        );
+        with_dist.sort = Some(GeoSort::Desc);
+        with_dist.with_dist = true;
+        with_dist.count = Some(1);
+        assert_eq!(
+            map.geosearch(b"Sicily".to_vec(), with_dist),
+            OutputValue::Array(vec![OutputValue::Array(vec![
+                OutputValue::BulkString(b"Catania".to_vec()),
+                OutputValue::BulkString(b"166.2742".to_vec()),
+            ])])
+        );
+        assert_eq!(
+            map.geosearch(
+                b"Sicily".to_vec(),
+                search(
+                    GeoOrigin::Member(b"missing".to_vec()),
+                    GeoShape::Radius(1.0),
+                    GeoUnit::Meters
+                )
+            ),
+            OutputValue::Error(b"ERR could not decode requested zset member".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_geosearchstore() {
+        let mut map = sicily();
+        let search = search(
+            GeoOrigin::LonLat(15.0, 37.0),
+            GeoShape::Radius(100.0),
+            GeoUnit::Kilometers,
+        );
+        assert_eq!(
+            map.geosearchstore(b"dest".to_vec(), b"Sicily".to_vec(), search.clone(), true),
+            OutputValue::Integer(1)
+        );
+        // the distance is stored unrounded
+        assert!(matches!(
+            map.zscore(b"dest".to_vec(), b"Catania".to_vec()),
+            OutputValue::BulkString(s) if s.starts_with(b"56.4412578701")
+        ));
+        assert_eq!(
+            map.geosearchstore(b"dest".to_vec(), b"missing".to_vec(), search, false),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.zcard(b"dest".to_vec()), OutputValue::Integer(0));
+    }
+}
@@ -0,0 +1,942 @@
+use rand::Rng;
+
+use crate::interface::database::map::{
+    ExpireCondition, ExpireTime, Key, LongDouble, MapHashCommands, ScanOptions, SetCondition,
+    TtlFormat, TtlUpdate,
+};
+use crate::interface::types::OutputValue;
+
+use super::super::float;
+use super::super::value::{Hash, Value};
+use super::{
+    format_ttl, reply_buffer, scan_filter, scan_reply, scan_steps, with_rng, wrong_type, Map,
+};
+
+/// Field TTLs are limited to 2^48 - 1 milliseconds since the Unix epoch, as in Redis.
+const HASH_FIELD_EXPIRE_TIME_MAX: i64 = (1 << 48) - 1;
+
+impl Map {
+    /// Looks up a hash after dropping its expired fields, and the key itself if none remain.
+    fn get_hash_mut(
+        &mut self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<Option<&mut Hash>, OutputValue> {
+        let now = self.clock.now_ms();
+        match self.lookup_mut(key) {
+            None => return Ok(None),
+            Some(Value::Hash(h)) => {
+                if h.remove_expired(now) > 0 && h.is_empty() {
+                    self.remove_key(key);
+                    return Ok(None);
+                }
+            }
+            Some(_) => return Err(wrong_type(command)),
+        }
+        let Some(Value::Hash(h)) = self.data.get_mut(key) else {
+            unreachable!()
+        };
+        Ok(Some(h))
+    }
+
+    fn get_or_create_hash(&mut self, key: &[u8], command: &str) -> Result<&mut Hash, OutputValue> {
+        if self.get_hash_mut(key, command)?.is_none() {
+            self.insert_key(key.to_vec(), Value::Hash(Hash::default()));
+        }
+        Ok(self.get_hash_mut(key, command)?.unwrap())
+    }
+
+    /// Hashes are never left empty in the keyspace.
+    fn remove_hash_if_empty(&mut self, key: &[u8]) {
+        if let Some(Value::Hash(h)) = self.data.get(key) {
+            if h.is_empty() {
+                self.remove_key(key);
+            }
+        }
+    }
+
+    /// Applies `ttl` to the existing `fields` of the hash at `key`; deadlines in the past
+    /// delete the fields right away.
+    fn update_field_ttls(&mut self, key: &[u8], ttl: TtlUpdate, fields: &[Vec<u8>]) {
+        let at = match ttl {
+            TtlUpdate::Keep => return,
+            TtlUpdate::Persist => None,
+            TtlUpdate::Expire(at) => self.resolve_expire_time(at),
+        };
+        let now = self.clock.now_ms() as i64;
+        let Some(Value::Hash(hash)) = self.data.get_mut(key) else {
+            return;
+        };
+        for field in fields.iter() {
+            if !hash.contains_key(field) {
+                continue;
+            }
+            match at {
+                None => {
+                    hash.persist(field);
+                }
+                Some(at) if at <= now => {
+                    hash.remove(field);
+                }
+                Some(at) => hash.set_expire_at(field, at as u64),
+            }
+        }
+        if hash.has_volatile_fields() {
+            self.volatile_hashes.insert(key.to_vec());
+        }
+        self.remove_hash_if_empty(key);
+    }
+
+    /// Sweeps the hashes known to have fields with a TTL.
+    pub(super) fn expire_hash_fields(&mut self) {
+        let now = self.clock.now_ms();
+        let (data, expires) = (&mut self.data, &mut self.expires);
+        self.volatile_hashes.retain(|key| {
+            let Some(Value::Hash(h)) = data.get_mut(key) else {
+                return false;
+            };
+            h.remove_expired(now);
+            if h.is_empty() {
+                data.remove(key);
+                expires.remove(key);
+                return false;
+            }
+            h.has_volatile_fields()
+        });
+    }
+}
+
+impl MapHashCommands for Map {
+    fn hset(&mut self, key: impl Key, field_values: Vec<Vec<u8>>) -> OutputValue {
+        debug_assert!(field_values.len().is_multiple_of(2));
+        let hash = match self.get_or_create_hash(key.as_ref(), "hset") {
+            Ok(h) => h,
+            Err(e) => return e,
+        };
+        let mut it = field_values.into_iter();
+        let mut added = 0;
+        while let (Some(field), Some(value)) = (it.next(), it.next()) {
+            if hash.insert(field, value).is_none() {
+                added += 1;
+            }
+        }
+        OutputValue::Integer(added)
+    }
+
+    fn hsetnx(&mut self, key: impl Key, field: Vec<u8>, value: Vec<u8>) -> OutputValue {
+        let hash = match self.get_or_create_hash(key.as_ref(), "hsetnx") {
+            Ok(h) => h,
+            Err(e) => return e,
+        };
+        if hash.contains_key(&field) {
+            return OutputValue::Integer(0);
+        }
+        hash.insert(field, value);
+        OutputValue::Integer(1)
+    }
+
+    fn hget(&mut self, key: impl Key, field: Vec<u8>) -> OutputValue {
+        match self.get_hash_mut(key.as_ref(), "hget") {
+            Err(e) => e,
+            Ok(h) => h
+                .and_then(|h| h.get(&field))
+                .cloned()
+                .map(OutputValue::BulkString)
+                .unwrap_or(OutputValue::NullBulkString),
+        }
+    }
+
+    fn hmget(&mut self, key: impl Key, fields: Vec<Vec<u8>>) -> OutputValue {
+        let hash = match self.get_hash_mut(key.as_ref(), "hmget") {
+            Err(e) => return e,
+            Ok(h) => h.map(|h| &*h),
+        };
+        OutputValue::Array(
+            fields
+                .iter()
+                .map(|field| {
+                    hash.and_then(|h| h.get(field))
+                        .cloned()
+                        .map(OutputValue::BulkString)
+                        .unwrap_or(OutputValue::NullBulkString)
+                })
+                .collect(),
+        )
+    }
+
+    fn hdel(&mut self, key: impl Key, fields: Vec<Vec<u8>>) -> OutputValue {
+        let key = key.as_ref();
+        let hash = match self.get_hash_mut(key, "hdel") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Integer(0),
+            Ok(Some(h)) => h,
+        };
+        let removed = fields
+            .iter()
+            .filter(|field| hash.remove(field).is_some())
+            .count();
+        self.remove_hash_if_empty(key);
+        OutputValue::Integer(removed as i64)
+    }
+
+    fn hlen(&mut self, key: impl Key) -> OutputValue {
+        match self.get_hash_mut(key.as_ref(), "hlen") {
+            Err(e) => e,
+            Ok(h) => OutputValue::Integer(h.map_or(0, |h| h.len()) as i64),
+        }
+    }
+
+    fn hexists(&mut self, key: impl Key, field: Vec<u8>) -> OutputValue {
+        match self.get_hash_mut(key.as_ref(), "hexists") {
+            Err(e) => e,
+            Ok(h) => OutputValue::Integer(h.is_some_and(|h| h.contains_key(&field)) as i64),
+        }
+    }
+
+    fn hkeys(&mut self, key: impl Key) -> OutputValue {
+        match self.get_hash_mut(key.as_ref(), "hkeys") {
+            Err(e) => e,
+            Ok(h) => OutputValue::Array(
+                h.into_iter()
+                    .flat_map(|h| h.keys().cloned().map(OutputValue::BulkString))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn hvals(&mut self, key: impl Key) -> OutputValue {
+        match self.get_hash_mut(key.as_ref(), "hvals") {
+            Err(e) => e,
+            Ok(h) => OutputValue::Array(
+                h.into_iter()
+                    .flat_map(|h| h.values().cloned().map(OutputValue::BulkString))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn hgetall(&mut self, key: impl Key) -> OutputValue {
+        match self.get_hash_mut(key.as_ref(), "hgetall") {
+            Err(e) => e,
+            Ok(h) => OutputValue::Array(
+                h.into_iter()
+                    .flat_map(|h| h.iter())
+                    .flat_map(|(f, v)| {
+                        [
+                            OutputValue::BulkString(f.clone()),
+                            OutputValue::BulkString(v.clone()),
+                        ]
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn hstrlen(&mut self, key: impl Key, field: Vec<u8>) -> OutputValue {
+        match self.get_hash_mut(key.as_ref(), "hstrlen") {
+            Err(e) => e,
+            Ok(h) => OutputValue::Integer(h.and_then(|h| h.get(&field)).map_or(0, Vec::len) as i64),
+        }
+    }
+
+    fn hincrby(&mut self, key: impl Key, field: Vec<u8>, n: i64) -> OutputValue {
+        let hash = match self.get_or_create_hash(key.as_ref(), "hincrby") {
+            Ok(h) => h,
+            Err(e) => return e,
+        };
+        let old_value = match hash.get(&field) {
+            None => 0,
+            Some(v) => match std::str::from_utf8(v)
+                .ok()
+                .and_then(|s| s.parse::<i64>().ok())
+            {
+                Some(i) => i,
+                None => {
+                    return OutputValue::Error(b"ERR hash value is not an integer".to_vec());
+                }
+            },
+        };
+        let Some(new_value) = old_value.checked_add(n) else {
+            return OutputValue::Error(b"ERR increment or decrement would overflow".to_vec());
+        };
+        let new_s = new_value.to_string().into_bytes();
+        match hash.get_mut(&field) {
+            Some(v) => *v = new_s,
+            None => {
+                hash.insert(field, new_s);
+            }
+        }
+        OutputValue::Integer(new_value)
+    }
+
+    fn hincrbyfloat(&mut self, key: impl Key, field: Vec<u8>, n: LongDouble) -> OutputValue {
+        let key = key.as_ref();
+        let old_value = match self.get_hash_mut(key, "hincrbyfloat") {
+            Err(e) => return e,
+            Ok(hash) => match hash.and_then(|h| h.get(&field)) {
+                None => float::ZERO,
+                Some(v) => match float::parse(v) {
+                    Some(f) => f,
+                    None => return OutputValue::Error(b"ERR hash value is not a float".to_vec()),
+                },
+            },
+        };
+        let Some(new_value) = float::add(old_value, n) else {
+            return OutputValue::Error(b"ERR increment would produce NaN or Infinity".to_vec());
+        };
+        let new_s = float::format(new_value);
+        let hash = match self.get_or_create_hash(key, "hincrbyfloat") {
+            Ok(h) => h,
+            Err(e) => return e,
+        };
+        match hash.get_mut(&field) {
+            Some(v) => *v = new_s.clone(),
+            None => {
+                hash.insert(field, new_s.clone());
+            }
+        }
+        OutputValue::BulkString(new_s)
+    }
+
+    fn hrandfield(&mut self, key: impl Key, count: Option<i64>, with_values: bool) -> OutputValue {
+        let hash = match self.get_hash_mut(key.as_ref(), "hrandfield") {
+            Err(e) => return e,
+            Ok(None) if count.is_some() => return OutputValue::Array(vec![]),
+            Ok(None) => return OutputValue::NullBulkString,
+            Ok(Some(h)) => h,
+        };
+        let entries: Vec<_> = hash.iter().collect();
+        let Some(count) = count else {
+            let i = with_rng(|rng| rng.gen_range(0..entries.len()));
+            return OutputValue::BulkString(entries[i].0.clone());
+        };
+        let (count, unique) = if count >= 0 {
+            ((count as usize).min(entries.len()), true)
+        } else {
+            (count.unsigned_abs() as usize, false)
+        };
+        let per_entry = if with_values { 2 } else { 1 };
+        let mut reply = match reply_buffer(count.saturating_mul(per_entry)) {
+            Ok(reply) => reply,
+            Err(e) => return e,
+        };
+        let picked: Vec<_> = with_rng(|rng| {
+            if unique {
+                rand::seq::index::sample(rng, entries.len(), count).into_vec()
+            } else {
+                (0..count)
+                    .map(|_| rng.gen_range(0..entries.len()))
+                    .collect()
+            }
+        });
+        for i in picked {
+            let (field, value) = entries[i];
+            reply.push(OutputValue::BulkString(field.clone()));
+            if with_values {
+                reply.push(OutputValue::BulkString(value.clone()));
+            }
+        }
+        OutputValue::Array(reply)
+    }
+
+    fn hexpire(
+        &mut self,
+        key: impl Key,
+        at: ExpireTime,
+        condition: ExpireCondition,
+        fields: Vec<Vec<u8>>,
+        command: &str,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        let Some(at) = self
+            .resolve_expire_time(at)
+            .filter(|at| *at <= HASH_FIELD_EXPIRE_TIME_MAX)
+        else {
+            return OutputValue::Error(
+                format!("ERR invalid expire time in '{}' command", command).into_bytes(),
+            );
+        };
+        let now = self.clock.now_ms() as i64;
+        let hash = match self.get_hash_mut(key, command) {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Array(vec![OutputValue::Integer(-2); fields.len()]),
+            Ok(Some(h)) => h,
+        };
+        let results = fields
+            .iter()
+            .map(|field| {
+                if !hash.contains_key(field) {
+                    return OutputValue::Integer(-2);
+                }
+                let current = hash.expire_at(field).map(|c| c as i64);
+                let applies = match condition {
+                    ExpireCondition::Always => true,
+                    ExpireCondition::Nx => current.is_none(),
+                    ExpireCondition::Xx => current.is_some(),
+                    // no TTL counts as an infinite one
+                    ExpireCondition::Gt => current.is_some_and(|c| at > c),
+                    ExpireCondition::Lt => current.is_none_or(|c| at < c),
+                };
+                if !applies {
+                    OutputValue::Integer(0)
+                } else if at <= now {
+                    hash.remove(field);
+                    OutputValue::Integer(2)
+                } else {
+                    hash.set_expire_at(field, at as u64);
+                    OutputValue::Integer(1)
+                }
+            })
+            .collect();
+        if hash.has_volatile_fields() {
+            self.volatile_hashes.insert(key.to_vec());
+        }
+        self.remove_hash_if_empty(key);
+        OutputValue::Array(results)
+    }
+
+    fn httl(&mut self, key: impl Key, format: TtlFormat, fields: Vec<Vec<u8>>) -> OutputValue {
+        let now = self.clock.now_ms();
+        let hash = match self.get_hash_mut(key.as_ref(), "httl") {
+            Err(e) => return e,
+            Ok(h) => h,
+        };
+        OutputValue::Array(
+            fields
+                .iter()
+                .map(|field| match hash.as_ref() {
+                    Some(h) if h.contains_key(field) => OutputValue::Integer(
+                        h.expire_at(field)
+                            .map_or(-1, |at| format_ttl(at, now, format)),
+                    ),
+                    _ => OutputValue::Integer(-2),
+                })
+                .collect(),
+        )
+    }
+
+    fn hpersist(&mut self, key: impl Key, fields: Vec<Vec<u8>>) -> OutputValue {
+        let mut hash = match self.get_hash_mut(key.as_ref(), "hpersist") {
+            Err(e) => return e,
+            Ok(h) => h,
+        };
+        OutputValue::Array(
+            fields
+                .iter()
+                .map(|field| match hash {
+                    Some(ref mut h) if h.contains_key(field) => {
+                        OutputValue::Integer(if h.persist(field) { 1 } else { -1 })
+                    }
+                    _ => OutputValue::Integer(-2),
+                })
+                .collect(),
+        )
+    }
+
+    fn hgetdel(&mut self, key: impl Key, fields: Vec<Vec<u8>>) -> OutputValue {
+        let key = key.as_ref();
+        let hash = match self.get_hash_mut(key, "hgetdel") {
+            Err(e) => return e,
+            Ok(h) => h,
+        };
+        let values = match hash {
+            None => vec![OutputValue::NullBulkString; fields.len()],
+            Some(h) => fields
+                .iter()
+                .map(|field| {
+                    h.remove(field)
+                        .map(OutputValue::BulkString)
+                        .unwrap_or(OutputValue::NullBulkString)
+                })
+                .collect(),
+        };
+        self.remove_hash_if_empty(key);
+        OutputValue::Array(values)
+    }
+
+    fn hgetex(&mut self, key: impl Key, ttl: TtlUpdate, fields: Vec<Vec<u8>>) -> OutputValue {
+        let key = key.as_ref();
+        if let TtlUpdate::Expire(at) = ttl {
+            if self
+                .resolve_expire_time(at)
+                .is_none_or(|at| at > HASH_FIELD_EXPIRE_TIME_MAX)
+            {
+                return OutputValue::Error(b"ERR invalid expire time in 'hgetex' command".to_vec());
+            }
+        }
+        let values = match self.get_hash_mut(key, "hgetex") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Array(vec![OutputValue::NullBulkString; fields.len()]),
+            Ok(Some(h)) => fields
+                .iter()
+                .map(|field| {
+                    h.get(field)
+                        .cloned()
+                        .map(OutputValue::BulkString)
+                        .unwrap_or(OutputValue::NullBulkString)
+                })
+                .collect(),
+        };
+        self.update_field_ttls(key, ttl, &fields);
+        OutputValue::Array(values)
+    }
+
+    fn hsetex(
+        &mut self,
+        key: impl Key,
+        condition: SetCondition,
+        ttl: TtlUpdate,
+        field_values: Vec<Vec<u8>>,
+    ) -> OutputValue {
+        debug_assert!(field_values.len().is_multiple_of(2));
+        let key = key.as_ref();
+        if let TtlUpdate::Expire(at) = ttl {
+            if self
+                .resolve_expire_time(at)
+                .is_none_or(|at| at > HASH_FIELD_EXPIRE_TIME_MAX)
+            {
+                return OutputValue::Error(b"ERR invalid expire time in 'hsetex' command".to_vec());
+            }
+        }
+        let fields: Vec<_> = field_values.iter().step_by(2).cloned().collect();
+        let hash = match self.get_hash_mut(key, "hsetex") {
+            Err(e) => return e,
+            Ok(h) => h,
+        };
+        let applies = match condition {
+            SetCondition::Always => true,
+            SetCondition::Nx => !hash.is_some_and(|h| fields.iter().any(|f| h.contains_key(f))),
+            SetCondition::Xx => hash.is_some_and(|h| fields.iter().all(|f| h.contains_key(f))),
+        };
+        if !applies {
+            return OutputValue::Integer(0);
+        }
+
+        let hash = match self.get_or_create_hash(key, "hsetex") {
+            Ok(h) => h,
+            Err(e) => return e,
+        };
+        let mut it = field_values.into_iter();
+        while let (Some(field), Some(value)) = (it.next(), it.next()) {
+            match hash.get_mut(&field) {
+                Some(v) if ttl == TtlUpdate::Keep => *v = value,
+                _ => {
+                    hash.insert(field, value);
+                }
+            }
+        }
+        self.update_field_ttls(key, ttl, &fields);
+        OutputValue::Integer(1)
+    }
+
+    fn hscan(
+        &mut self,
+        key: impl Key,
+        cursor: u64,
+        options: ScanOptions,
+        with_values: bool,
+    ) -> OutputValue {
+        let hash = match self.get_hash_mut(key.as_ref(), "hscan") {
+            Err(e) => return e,
+            Ok(None) => return scan_reply(0, vec![]),
+            Ok(Some(h)) => h,
+        };
+        let (cursor, entries) = scan_steps(cursor, options.count, |cursor, entries| {
+            hash.scan(cursor, |field, value| entries.push((field, value)))
+        });
+        let matches = scan_filter(options.pattern.as_deref());
+        let mut items = Vec::new();
+        for (field, value) in entries.into_iter().filter(|(f, _)| matches(f)) {
+            items.push(OutputValue::BulkString(field.clone()));
+            if with_values {
+                items.push(OutputValue::BulkString(value.clone()));
+            }
+        }
+        scan_reply(cursor, items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use super::super::super::clock::Clock;
+    use super::*;
+    use crate::interface::database::map::{IMap, MapMiscCommands};
+
+    fn fv(items: &[&[u8]]) -> Vec<Vec<u8>> {
+        items.iter().map(|v| v.to_vec()).collect()
+    }
+
+    #[test]
+    fn test_hset_and_hget() {
+        let mut map = Map::default();
+        assert_eq!(
+            map.hset(b"h".as_slice(), fv(&[b"a", b"1", b"b", b"2"])),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(
+            map.hset(b"h".as_slice(), fv(&[b"a", b"3", b"c", b"4"])),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            map.hget(b"h".as_slice(), b"a".to_vec()),
+            OutputValue::BulkString(b"3".to_vec())
+        );
+        assert_eq!(
+            map.hmget(b"h".as_slice(), fv(&[b"b", b"nope"])),
+            OutputValue::Array(vec![
+                OutputValue::BulkString(b"2".to_vec()),
+                OutputValue::NullBulkString
+            ])
+        );
+        assert_eq!(map.hlen(b"h".as_slice()), OutputValue::Integer(3));
+    }
+
+    #[test]
+    fn test_hdel_removes_empty_hash() {
+        let mut map = Map::default();
+        map.hset(b"h".as_slice(), fv(&[b"a", b"1"]));
+        assert_eq!(
+            map.hdel(b"h".as_slice(), fv(&[b"a", b"b"])),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut map = Map::default();
+        map.set(b"s".as_slice(), b"v".to_vec());
+        assert!(matches!(
+            map.hset(b"s".as_slice(), fv(&[b"a", b"1"])),
+            OutputValue::Error(_)
+        ));
+        assert!(matches!(
+            map.hgetall(b"s".as_slice()),
+            OutputValue::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_hincrby() {
+        let mut map = Map::default();
+        assert_eq!(
+            map.hincrby(b"h".as_slice(), b"n".to_vec(), 5),
+            OutputValue::Integer(5)
+        );
+        assert_eq!(
+            map.hincrby(b"h".as_slice(), b"n".to_vec(), -7),
+            OutputValue::Integer(-2)
+        );
+        assert!(matches!(
+            map.hincrby(b"h".as_slice(), b"n".to_vec(), i64::MIN),
+            OutputValue::Error(_)
+        ));
+        map.hset(b"h".as_slice(), fv(&[b"s", b"abc"]));
+        assert!(matches!(
+            map.hincrby(b"h".as_slice(), b"s".to_vec(), 1),
+            OutputValue::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_hincrbyfloat() {
+        let mut map = Map::default();
+        let ld = |s: &str| float::parse(s.as_bytes()).unwrap();
+        assert_eq!(
+            map.hincrbyfloat(b"h".as_slice(), b"f".to_vec(), ld("10.5")),
+            OutputValue::BulkString(b"10.5".to_vec())
+        );
+        assert_eq!(
+            map.hincrbyfloat(b"h".as_slice(), b"f".to_vec(), ld("0.1")),
+            OutputValue::BulkString(b"10.6".to_vec())
+        );
+        assert_eq!(
+            map.hincrbyfloat(b"new".as_slice(), b"f".to_vec(), ld("inf")),
+            OutputValue::Error(b"ERR increment would produce NaN or Infinity".to_vec())
+        );
+        assert_eq!(map.len(), 1);
+        map.hset(b"h".as_slice(), fv(&[b"s", b"nan"]));
+        assert_eq!(
+            map.hincrbyfloat(b"h".as_slice(), b"s".to_vec(), ld("1")),
+            OutputValue::Error(b"ERR hash value is not a float".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_hrandfield() {
+        let mut map = Map::default();
+        map.hset(b"h".as_slice(), fv(&[b"a", b"1", b"b", b"2", b"c", b"3"]));
+        let OutputValue::Array(v) = map.hrandfield(b"h".as_slice(), Some(10), true) else {
+            panic!()
+        };
+        assert_eq!(v.len(), 6);
+        let OutputValue::Array(v) = map.hrandfield(b"h".as_slice(), Some(-10), false) else {
+            panic!()
+        };
+        assert_eq!(v.len(), 10);
+        let OutputValue::Array(v) = map.hrandfield(b"h".as_slice(), Some(-4), true) else {
+            panic!()
+        };
+        assert_eq!(v.len(), 8);
+        assert_eq!(
+            map.hrandfield(b"nope".as_slice(), None, false),
+            OutputValue::NullBulkString
+        );
+        // more repeats than could ever be held are refused rather than allocated
+        for count in [i64::MIN, -i64::MAX] {
+            assert_eq!(
+                map.hrandfield(b"h".as_slice(), Some(count), false),
+                OutputValue::Error(b"ERR value is out of range".to_vec())
+            );
+        }
+    }
+
+    fn map_at(now: u64) -> (Map, Rc<Cell<u64>>) {
+        let now = Rc::new(Cell::new(now));
+        (Map::with_clock(Clock::Manual(now.clone())), now)
+    }
+
+    #[test]
+    fn test_hexpire_and_httl() {
+        let (mut map, now) = map_at(1_000_000);
+        map.hset(b"h".as_slice(), fv(&[b"a", b"1", b"b", b"2"]));
+        assert_eq!(
+            map.hexpire(
+                b"h".as_slice(),
+                ExpireTime::Relative(10_000),
+                ExpireCondition::Always,
+                fv(&[b"a", b"nope"]),
+                "hexpire",
+            ),
+            OutputValue::Array(vec![OutputValue::Integer(1), OutputValue::Integer(-2)])
+        );
+        // GT never applies to a field without a TTL
+        assert_eq!(
+            map.hexpire(
+                b"h".as_slice(),
+                ExpireTime::Relative(20_000),
+                ExpireCondition::Gt,
+                fv(&[b"b"]),
+                "hexpire",
+            ),
+            OutputValue::Array(vec![OutputValue::Integer(0)])
+        );
+        assert_eq!(
+            map.httl(b"h".as_slice(), TtlFormat::Seconds, fv(&[b"a", b"b", b"c"])),
+            OutputValue::Array(vec![
+                OutputValue::Integer(10),
+                OutputValue::Integer(-1),
+                OutputValue::Integer(-2)
+            ])
+        );
+        assert_eq!(
+            map.httl(b"h".as_slice(), TtlFormat::UnixMilliseconds, fv(&[b"a"])),
+            OutputValue::Array(vec![OutputValue::Integer(1_010_000)])
+        );
+
+        now.set(1_010_000);
+        assert_eq!(
+            map.hget(b"h".as_slice(), b"a".to_vec()),
+            OutputValue::NullBulkString
+        );
+        assert_eq!(map.hlen(b"h".as_slice()), OutputValue::Integer(1));
+
+        // a deadline in the past deletes the field right away, and the hash with it
+        assert_eq!(
+            map.hexpire(
+                b"h".as_slice(),
+                ExpireTime::Absolute(0),
+                ExpireCondition::Always,
+                fv(&[b"b"]),
+                "hexpire",
+            ),
+            OutputValue::Array(vec![OutputValue::Integer(2)])
+        );
+        assert_eq!(map.len(), 0);
+
+        // errors name the command that was called
+        assert_eq!(
+            map.hexpire(
+                b"h".as_slice(),
+                ExpireTime::Absolute(i64::MAX),
+                ExpireCondition::Always,
+                fv(&[b"a"]),
+                "hpexpireat",
+            ),
+            OutputValue::Error(b"ERR invalid expire time in 'hpexpireat' command".to_vec())
+        );
+        map.set(b"s".as_slice(), b"v".to_vec());
+        assert_eq!(
+            map.hexpire(
+                b"s".as_slice(),
+                ExpireTime::Relative(1000),
+                ExpireCondition::Always,
+                fv(&[b"a"]),
+                "hpexpire",
+            ),
+            wrong_type("hpexpire")
+        );
+    }
+
+    #[test]
+    fn test_hpersist() {
+        let (mut map, _) = map_at(0);
+        map.hset(b"h".as_slice(), fv(&[b"a", b"1", b"b", b"2"]));
+        map.hexpire(
+            b"h".as_slice(),
+            ExpireTime::Relative(1000),
+            ExpireCondition::Always,
+            fv(&[b"a"]),
+            "hexpire",
+        );
+        assert_eq!(
+            map.hpersist(b"h".as_slice(), fv(&[b"a", b"b", b"c"])),
+            OutputValue::Array(vec![
+                OutputValue::Integer(1),
+                OutputValue::Integer(-1),
+                OutputValue::Integer(-2)
+            ])
+        );
+        assert_eq!(
+            map.httl(b"h".as_slice(), TtlFormat::Milliseconds, fv(&[b"a"])),
+            OutputValue::Array(vec![OutputValue::Integer(-1)])
+        );
+    }
+
+    #[test]
+    fn test_hgetex_and_hgetdel() {
+        let (mut map, now) = map_at(0);
+        map.hset(b"h".as_slice(), fv(&[b"a", b"1", b"b", b"2"]));
+        assert_eq!(
+            map.hgetex(
+                b"h".as_slice(),
+                TtlUpdate::Expire(ExpireTime::Relative(500)),
+                fv(&[b"a", b"c"])
+            ),
+            OutputValue::Array(vec![
+                OutputValue::BulkString(b"1".to_vec()),
+                OutputValue::NullBulkString
+            ])
+        );
+        now.set(500);
+        assert_eq!(
+            map.hexists(b"h".as_slice(), b"a".to_vec()),
+            OutputValue::Integer(0)
+        );
+
+        assert_eq!(
+            map.hgetdel(b"h".as_slice(), fv(&[b"b"])),
+            OutputValue::Array(vec![OutputValue::BulkString(b"2".to_vec())])
+        );
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn test_hsetex() {
+        let (mut map, _) = map_at(0);
+        assert_eq!(
+            map.hsetex(
+                b"h".as_slice(),
+                SetCondition::Xx,
+                TtlUpdate::Persist,
+                fv(&[b"a", b"1"])
+            ),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            map.hsetex(
+                b"h".as_slice(),
+                SetCondition::Nx,
+                TtlUpdate::Expire(ExpireTime::Relative(2000)),
+                fv(&[b"a", b"1", b"b", b"2"])
+            ),
+            OutputValue::Integer(1)
+        );
+        // FNX fails as soon as one field exists
+        assert_eq!(
+            map.hsetex(
+                b"h".as_slice(),
+                SetCondition::Nx,
+                TtlUpdate::Persist,
+                fv(&[b"a", b"1", b"c", b"3"])
+            ),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            map.hsetex(
+                b"h".as_slice(),
+                SetCondition::Xx,
+                TtlUpdate::Keep,
+                fv(&[b"a", b"9"])
+            ),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            map.httl(b"h".as_slice(), TtlFormat::Milliseconds, fv(&[b"a", b"c"])),
+            OutputValue::Array(vec![OutputValue::Integer(2000), OutputValue::Integer(-2)])
+        );
+    }
+
+    #[test]
+    fn test_active_expire_cycle_drops_fields() {
+        let (mut map, now) = map_at(0);
+        map.hset(b"h".as_slice(), fv(&[b"a", b"1"]));
+        map.hset(b"g".as_slice(), fv(&[b"a", b"1", b"b", b"2"]));
+        for key in [b"h", b"g"] {
+            map.hexpire(
+                key.as_slice(),
+                ExpireTime::Relative(100),
+                ExpireCondition::Always,
+                fv(&[b"a"]),
+                "hexpire",
+            );
+        }
+        now.set(100);
+        map.active_expire_cycle(1, Instant::now() + Duration::from_secs(1));
+        assert_eq!(map.len(), 1);
+        assert_eq!(
+            map.hgetall(b"g".as_slice()),
+            OutputValue::Array(vec![
+                OutputValue::BulkString(b"b".to_vec()),
+                OutputValue::BulkString(b"2".to_vec())
+            ])
+        );
+        assert!(map.volatile_hashes.is_empty());
+    }
+
+    #[test]
+    fn test_hscan() {
+        let now = Rc::new(Cell::new(1_000_000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        map.hset(b"h".as_slice(), fv(&[b"f1", b"v1", b"f2", b"v2"]));
+        map.hexpire(
+            b"h".as_slice(),
+            ExpireTime::Relative(10),
+            ExpireCondition::Always,
+            fv(&[b"f2"]),
+            "hexpire",
+        );
+        now.set(1_000_011);
+        let bulk = |s: &[u8]| OutputValue::BulkString(s.to_vec());
+        assert_eq!(
+            map.hscan(b"h".as_slice(), 0, ScanOptions::default(), true),
+            OutputValue::Array(vec![
+                bulk(b"0"),
+                OutputValue::Array(vec![bulk(b"f1"), bulk(b"v1")]),
+            ])
+        );
+        assert_eq!(
+            map.hscan(b"h".as_slice(), 0, ScanOptions::default(), false),
+            OutputValue::Array(vec![bulk(b"0"), OutputValue::Array(vec![bulk(b"f1")])])
+        );
+        map.set(b"s".as_slice(), b"v".to_vec());
+        assert_eq!(
+            map.hscan(b"s".as_slice(), 0, ScanOptions::default(), true),
+            wrong_type("hscan")
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use crate::interface::database::map::{Key, MapHyperLogLogCommands};
+use crate::interface::types::OutputValue;
+
+use super::super::hyperloglog::{self, Corrupted};
+use super::super::value::{StringValue, Value};
+use super::{wrong_type, Map};
+
+fn invalid_hll() -> OutputValue {
+    OutputValue::Error(b"WRONGTYPE Key is not a valid HyperLogLog string value.".to_vec())
+}
+
+impl From<Corrupted> for OutputValue {
+    fn from(_: Corrupted) -> Self {
+        OutputValue::Error(b"INVALIDOBJ Corrupted HLL object detected".to_vec())
+    }
+}
+
+impl Map {
+    /// HyperLogLogs are strings in a format of their own, so any other string is rejected.
+    fn get_hll_mut(
+        &mut self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<Option<&mut Vec<u8>>, OutputValue> {
+        match self.lookup_mut(key) {
+            None => Ok(None),
+            Some(Value::String(s)) if s.bytes().is_some_and(|s| hyperloglog::is_valid(s)) => {
+                Ok(Some(s.bytes_mut()))
+            }
+            Some(Value::String(_)) => Err(invalid_hll()),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    fn get_hll(&self, key: &[u8], command: &str) -> Result<Option<&Vec<u8>>, OutputValue> {
+        match self.lookup(key) {
+            None => Ok(None),
+            Some(Value::String(s)) => match s.bytes() {
+                Some(s) if hyperloglog::is_valid(s) => Ok(Some(s)),
+                _ => Err(invalid_hll()),
+            },
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    /// Gathers the highest value of every register across `keys`.
+    fn union_registers(
+        &self,
+        keys: &[&[u8]],
+        command: &str,
+    ) -> Result<Box<[u8; hyperloglog::REGISTERS]>, OutputValue> {
+        let mut registers = Box::new([0; hyperloglog::REGISTERS]);
+        for key in keys {
+            if let Some(hll) = self.get_hll(key, command)? {
+                hyperloglog::merge_into(&mut registers, hll)?;
+            }
+        }
+        Ok(registers)
+    }
+}
+
+impl MapHyperLogLogCommands for Map {
+    fn pfadd(&mut self, key: impl Key, elements: Vec<Vec<u8>>) -> OutputValue {
+        let key = key.as_ref();
+        let (hll, mut updated) = match self.get_hll_mut(key, "pfadd") {
+            Err(e) => return e,
+            Ok(Some(hll)) => (hll, false),
+            Ok(None) => {
+                self.insert_key(
+                    key.to_vec(),
+                    Value::String(StringValue::Raw(hyperloglog::new())),
+                );
+                match self.data.get_mut(key) {
+                    Some(Value::String(hll)) => (hll.bytes_mut(), true),
+                    _ => unreachable!(),
+                }
+            }
+        };
+        for element in elements {
+            match hyperloglog::add(hll, &element) {
+                Ok(changed) => updated |= changed,
+                Err(e) => return e.into(),
+            }
+        }
+        OutputValue::Integer(updated as i64)
+    }
+
+    fn pfcount(&mut self, keys: Vec<impl Key>) -> OutputValue {
+        if let [key] = keys.as_slice() {
+            // the cached cardinality is refreshed, so this writes even though it reads
+            return match self.get_hll_mut(key.as_ref(), "pfcount") {
+                Err(e) => e,
+                Ok(None) => OutputValue::Integer(0),
+                Ok(Some(hll)) => match hyperloglog::count(hll) {
+                    Ok(count) => OutputValue::Integer(count as i64),
+                    Err(e) => e.into(),
+                },
+            };
+        }
+        let keys: Vec<_> = keys.iter().map(|k| k.as_ref()).collect();
+        match self.union_registers(&keys, "pfcount") {
+            Ok(registers) => OutputValue::Integer(hyperloglog::count_registers(&registers) as i64),
+            Err(e) => e,
+        }
+    }
+
+    fn pfmerge(&mut self, destination: impl Key, sources: Vec<impl Key>) -> OutputValue {
+        let destination = destination.as_ref();
+        let mut keys = vec![destination];
+        keys.extend(sources.iter().map(|k| k.as_ref()));
+        let registers = match self.union_registers(&keys, "pfmerge") {
+            Ok(registers) => registers,
+            Err(e) => return e,
+        };
+        // the result is dense as soon as any of the inputs is
+        let dense = keys
+            .iter()
+            .filter_map(|k| self.get_hll(k, "pfmerge").ok().flatten())
+            .any(|hll| hyperloglog::is_dense(hll));
+        let hll = match self.lookup_mut(destination) {
+            Some(Value::String(hll)) => hll.bytes_mut(),
+            _ => {
+                self.insert_key(
+                    destination.to_vec(),
+                    Value::String(StringValue::Raw(hyperloglog::new())),
+                );
+                match self.data.get_mut(destination) {
+                    Some(Value::String(hll)) => hll.bytes_mut(),
+                    _ => unreachable!(),
+                }
+            }
+        };
+        let result = (|| -> Result<(), Corrupted> {
+            if dense {
+                hyperloglog::to_dense(hll)?;
+            }
+            for (index, value) in registers.iter().enumerate() {
+                if *value != 0 {
+                    hyperloglog::set_register(hll, index, *value)?;
+                }
+            }
+            hyperloglog::invalidate_cache(hll);
+            Ok(())
+        })();
+        match result {
+            Ok(()) => OutputValue::Ok,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::database::map::{MapSetCommands, MapStringCommands};
+
+    #[test]
+    fn test_pfadd_pfcount() {
+        let mut map = Map::default();
+        let elements = [b"a", b"b", b"c", b"d", b"e", b"f", b"g"].map(|e| e.to_vec());
+        assert_eq!(
+            map.pfadd(b"hll".to_vec(), elements.to_vec()),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            map.pfadd(b"hll".to_vec(), vec![b"a".to_vec()]),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.pfcount(vec![b"hll".to_vec()]), OutputValue::Integer(7));
+        assert_eq!(
+            map.pfcount(vec![b"missing".to_vec()]),
+            OutputValue::Integer(0)
+        );
+        // creating an empty HyperLogLog counts as an update
+        assert_eq!(
+            map.pfadd(b"empty".to_vec(), vec![]),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            map.pfadd(b"empty".to_vec(), vec![]),
+            OutputValue::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_pfmerge() {
+        let mut map = Map::default();
+        map.pfadd(
+            b"h1".to_vec(),
+            (0..3000).map(|i| format!("{}", i).into_bytes()).collect(),
+        );
+        map.pfadd(b"h2".to_vec(), vec![b"x".to_vec(), b"y".to_vec()]);
+        map.pfadd(b"h3".to_vec(), vec![b"y".to_vec(), b"z".to_vec()]);
+        let OutputValue::Integer(union) = map.pfcount(vec![b"h1".to_vec(), b"h2".to_vec()]) else {
+            panic!();
+        };
+        assert_eq!(
+            map.pfmerge(b"dest".to_vec(), vec![b"h1".to_vec(), b"h2".to_vec()]),
+            OutputValue::Ok
+        );
+        assert_eq!(
+            map.pfcount(vec![b"dest".to_vec()]),
+            OutputValue::Integer(union)
+        );
+        assert!(
+            matches!(map.get(b"dest".to_vec()), OutputValue::BulkString(s) if hyperloglog::is_dense(&s))
+        );
+
+        assert_eq!(
+            map.pfmerge(b"small".to_vec(), vec![b"h2".to_vec(), b"h3".to_vec()]),
+            OutputValue::Ok
+        );
+        assert_eq!(
+            map.pfcount(vec![b"small".to_vec()]),
+            OutputValue::Integer(3)
+        );
+        assert!(
+            matches!(map.get(b"small".to_vec()), OutputValue::BulkString(s) if !hyperloglog::is_dense(&s))
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let mut map = Map::default();
+        map.set(b"s".to_vec(), b"not a HyperLogLog".to_vec());
+        assert_eq!(map.pfadd(b"s".to_vec(), vec![b"a".to_vec()]), invalid_hll());
+        assert_eq!(map.pfcount(vec![b"s".to_vec()]), invalid_hll());
+        map.sadd(b"set".to_vec(), vec![b"a".to_vec()]);
+        assert_eq!(map.pfcount(vec![b"set".to_vec()]), wrong_type("pfcount"));
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::VecDeque;
+
+use crate::interface::database::map::{Key, ListEnd, MapListCommands};
+use crate::interface::types::OutputValue;
+
+use super::super::value::Value;
+use super::{normalise_range, wrong_type, Map};
+
+impl Map {
+    fn get_list(
+        &self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<Option<&VecDeque<Vec<u8>>>, OutputValue> {
+        match self.lookup(key) {
+            None => Ok(None),
+            Some(Value::List(l)) => Ok(Some(l)),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    fn get_list_mut(
+        &mut self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<Option<&mut VecDeque<Vec<u8>>>, OutputValue> {
+        match self.lookup_mut(key) {
+            None => Ok(None),
+            Some(Value::List(l)) => Ok(Some(l)),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    /// Lists are never left empty in the keyspace.
+    fn remove_list_if_empty(&mut self, key: &[u8]) {
+        if let Some(Value::List(l)) = self.data.get(key) {
+            if l.is_empty() {
+                self.remove_key(key);
+            }
+        }
+    }
+}
+
+fn pop_one(list: &mut VecDeque<Vec<u8>>, end: ListEnd) -> Option<Vec<u8>> {
+    match end {
+        ListEnd::Left => list.pop_front(),
+        ListEnd::Right => list.pop_back(),
+    }
+}
+
+fn push_one(list: &mut VecDeque<Vec<u8>>, end: ListEnd, value: Vec<u8>) {
+    match end {
+        ListEnd::Left => list.push_front(value),
+        ListEnd::Right => list.push_back(value),
+    }
+}
+
+impl MapListCommands for Map {
+    fn push(
+        &mut self,
+        key: impl Key,
+        values: Vec<Vec<u8>>,
+        end: ListEnd,
+        only_if_exists: bool,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        let command = match (end, only_if_exists) {
+            (ListEnd::Left, false) => "lpush",
+            (ListEnd::Right, false) => "rpush",
+            (ListEnd::Left, true) => "lpushx",
+            (ListEnd::Right, true) => "rpushx",
+        };
+        let list = match self.get_list_mut(key, command) {
+            Err(e) => return e,
+            Ok(Some(l)) => l,
+            Ok(None) if only_if_exists => return OutputValue::Integer(0),
+            Ok(None) => {
+                self.insert_key(key.to_vec(), Value::List(VecDeque::new()));
+                let Some(Value::List(l)) = self.data.get_mut(key) else {
+                    unreachable!()
+                };
+                l
+            }
+        };
+        for value in values {
+            push_one(list, end, value);
+        }
+        OutputValue::Integer(list.len() as i64)
+    }
+
+    fn pop(&mut self, key: impl Key, end: ListEnd, count: Option<usize>) -> OutputValue {
+        let key = key.as_ref();
+        let command = match end {
+            ListEnd::Left => "lpop",
+            ListEnd::Right => "rpop",
+        };
+        let list = match self.get_list_mut(key, command) {
+            Err(e) => return e,
+            Ok(Some(l)) => l,
+            Ok(None) if count.is_some() => return OutputValue::NullArray,
+            Ok(None) => return OutputValue::NullBulkString,
+        };
+        let ret = match count {
+            None => pop_one(list, end)
+                .map(OutputValue::BulkString)
+                .unwrap_or(OutputValue::NullBulkString),
+            Some(count) => OutputValue::Array(
+                std::iter::from_fn(|| pop_one(list, end))
+                    .take(count)
+                    .map(OutputValue::BulkString)
+                    .collect(),
+            ),
+        };
+        self.remove_list_if_empty(key);
+        ret
+    }
+
+    fn lrange(&self, key: impl Key, start: i64, stop: i64) -> OutputValue {
+        let list = match self.get_list(key.as_ref(), "lrange") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Array(vec![]),
+            Ok(Some(l)) => l,
+        };
+        let Some((start, stop)) = normalise_range(start, stop, list.len()) else {
+            return OutputValue::Array(vec![]);
+        };
+        OutputValue::Array(
+            list.range(start..=stop)
+                .cloned()
+                .map(OutputValue::BulkString)
+                .collect(),
+        )
+    }
+
+    fn llen(&self, key: impl Key) -> OutputValue {
+        match self.get_list(key.as_ref(), "llen") {
+            Err(e) => e,
+            Ok(l) => OutputValue::Integer(l.map_or(0, VecDeque::len) as i64),
+        }
+    }
+
+    fn lindex(&self, key: impl Key, index: i64) -> OutputValue {
+        let list = match self.get_list(key.as_ref(), "lindex") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::NullBulkString,
+            Ok(Some(l)) => l,
+        };
+        let index = if index < 0 {
+            list.len() as i64 + index
+        } else {
+            index
+        };
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| list.get(i))
+            .cloned()
+            .map(OutputValue::BulkString)
+            .unwrap_or(OutputValue::NullBulkString)
+    }
+
+    fn lset(&mut self, key: impl Key, index: i64, value: Vec<u8>) -> OutputValue {
+        let list = match self.get_list_mut(key.as_ref(), "lset") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Error(b"ERR no such key".to_vec()),
+            Ok(Some(l)) => l,
+        };
+        let index = if index < 0 {
+            list.len() as i64 + index
+        } else {
+            index
+        };
+        match usize::try_from(index).ok().and_then(|i| list.get_mut(i)) {
+            Some(item) => {
+                *item = value;
+                OutputValue::Ok
+            }
+            None => OutputValue::Error(b"ERR index out of range".to_vec()),
+        }
+    }
+
+    fn lrem(&mut self, key: impl Key, count: i64, value: Vec<u8>) -> OutputValue {
+        let key = key.as_ref();
+        let list = match self.get_list_mut(key, "lrem") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Integer(0),
+            Ok(Some(l)) => l,
+        };
+        let limit = if count == 0 {
+            usize::MAX
+        } else {
+            count.unsigned_abs() as usize
+        };
+        let mut removed = 0;
+        if count >= 0 {
+            list.retain(|item| {
+                if removed < limit && *item == value {
+                    removed += 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        } else {
+            let mut i = list.len();
+            while i > 0 && removed < limit {
+                i -= 1;
+                if list[i] == value {
+                    list.remove(i);
+                    removed += 1;
+                }
+            }
+        }
+        self.remove_list_if_empty(key);
+        OutputValue::Integer(removed as i64)
+    }
+
+    fn ltrim(&mut self, key: impl Key, start: i64, stop: i64) -> OutputValue {
+        let key = key.as_ref();
+        let list = match self.get_list_mut(key, "ltrim") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Ok,
+            Ok(Some(l)) => l,
+        };
+        match normalise_range(start, stop, list.len()) {
+            Some((start, stop)) => {
+                list.truncate(stop + 1);
+                list.drain(..start);
+            }
+            None => list.clear(),
+        }
+        self.remove_list_if_empty(key);
+        OutputValue::Ok
+    }
+
+    fn linsert(
+        &mut self,
+        key: impl Key,
+        before: bool,
+        pivot: Vec<u8>,
+        value: Vec<u8>,
+    ) -> OutputValue {
+        let list = match self.get_list_mut(key.as_ref(), "linsert") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Integer(0),
+            Ok(Some(l)) => l,
+        };
+        let Some(pos) = list.iter().position(|item| *item == pivot) else {
+            return OutputValue::Integer(-1);
+        };
+        list.insert(if before { pos } else { pos + 1 }, value);
+        OutputValue::Integer(list.len() as i64)
+    }
+
+    fn lpos(
+        &self,
+        key: impl Key,
+        element: Vec<u8>,
+        rank: i64,
+        count: Option<usize>,
+        maxlen: usize,
+    ) -> OutputValue {
+        debug_assert!(rank != 0);
+        let list = match self.get_list(key.as_ref(), "lpos") {
+            Err(e) => return e,
+            Ok(None) if count.is_some() => return OutputValue::Array(vec![]),
+            Ok(None) => return OutputValue::NullBulkString,
+            Ok(Some(l)) => l,
+        };
+        let scan_len = if maxlen == 0 { list.len() } else { maxlen };
+        let indices: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+            Box::new((0..list.len()).take(scan_len))
+        } else {
+            Box::new((0..list.len()).rev().take(scan_len))
+        };
+        let mut matches = indices
+            .filter(|&i| list[i] == element)
+            .skip(rank.unsigned_abs() as usize - 1)
+            .map(|i| OutputValue::Integer(i as i64));
+        match count {
+            None => matches.next().unwrap_or(OutputValue::NullBulkString),
+            Some(0) => OutputValue::Array(matches.collect()),
+            Some(count) => OutputValue::Array(matches.take(count).collect()),
+        }
+    }
+
+    fn lmove(
+        &mut self,
+        source: impl Key,
+        destination: impl Key,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> OutputValue {
+        let (source, destination) = (source.as_ref(), destination.as_ref());
+        match self.get_list(source, "lmove") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::NullBulkString,
+            Ok(Some(_)) => {}
+        }
+        if let Err(e) = self.get_list(destination, "lmove") {
+            return e;
+        }
+
+        let Ok(Some(list)) = self.get_list_mut(source, "lmove") else {
+            unreachable!()
+        };
+        let value = pop_one(list, from).expect("lists in the keyspace are never empty");
+        self.remove_list_if_empty(source);
+        let list = match self.lookup_mut(destination) {
+            Some(Value::List(l)) => l,
+            _ => {
+                self.insert_key(destination.to_vec(), Value::List(VecDeque::new()));
+                let Some(Value::List(l)) = self.data.get_mut(destination) else {
+                    unreachable!()
+                };
+                l
+            }
+        };
+        push_one(list, to, value.clone());
+        OutputValue::BulkString(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::database::map::MapMiscCommands;
+
+    fn bulk_array(items: &[&[u8]]) -> OutputValue {
+        OutputValue::Array(
+            items
+                .iter()
+                .map(|v| OutputValue::BulkString(v.to_vec()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_push_and_range() {
+        let mut map = Map::default();
+        map.rpush(b"l".as_slice(), vec![b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(
+            map.lpush(b"l".as_slice(), vec![b"a".to_vec()]),
+            OutputValue::Integer(3)
+        );
+        assert_eq!(
+            map.lrange(b"l".as_slice(), 0, -1),
+            bulk_array(&[b"a", b"b", b"c"])
+        );
+        assert_eq!(
+            map.lrange(b"l".as_slice(), -2, 10),
+            bulk_array(&[b"b", b"c"])
+        );
+        assert_eq!(map.lrange(b"l".as_slice(), 2, 1), bulk_array(&[]));
+    }
+
+    #[test]
+    fn test_pushx_requires_existing_key() {
+        let mut map = Map::default();
+        assert_eq!(
+            map.lpushx(b"l".as_slice(), vec![b"a".to_vec()]),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn test_pop_removes_empty_list() {
+        let mut map = Map::default();
+        map.rpush(b"l".as_slice(), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(
+            map.rpop(b"l".as_slice(), Some(5)),
+            bulk_array(&[b"b", b"a"])
+        );
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.lpop(b"l".as_slice(), None), OutputValue::NullBulkString);
+        assert_eq!(map.lpop(b"l".as_slice(), Some(1)), OutputValue::NullArray);
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut map = Map::default();
+        map.set(b"s".as_slice(), b"v".to_vec());
+        assert!(matches!(
+            map.lpush(b"s".as_slice(), vec![b"a".to_vec()]),
+            OutputValue::Error(_)
+        ));
+        assert!(matches!(
+            map.rpoplpush(b"nope".as_slice(), b"s".as_slice()),
+            OutputValue::NullBulkString
+        ));
+        map.rpush(b"l".as_slice(), vec![b"a".to_vec()]);
+        assert!(matches!(
+            map.rpoplpush(b"l".as_slice(), b"s".as_slice()),
+            OutputValue::Error(_)
+        ));
+        assert_eq!(map.llen(b"l".as_slice()), OutputValue::Integer(1));
+    }
+
+    #[test]
+    fn test_lrem() {
+        let mut map = Map::default();
+        let items = [b"a", b"x", b"b", b"x", b"c", b"x"].map(|v| v.to_vec());
+        map.rpush(b"l".as_slice(), items.to_vec());
+        assert_eq!(
+            map.lrem(b"l".as_slice(), -2, b"x".to_vec()),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(
+            map.lrange(b"l".as_slice(), 0, -1),
+            bulk_array(&[b"a", b"x", b"b", b"c"])
+        );
+        assert_eq!(
+            map.lrem(b"l".as_slice(), 0, b"x".to_vec()),
+            OutputValue::Integer(1)
+        );
+    }
+
+    #[test]
+    fn test_ltrim_and_linsert() {
+        let mut map = Map::default();
+        let items = [b"a", b"b", b"c", b"d"].map(|v| v.to_vec());
+        map.rpush(b"l".as_slice(), items.to_vec());
+        map.ltrim(b"l".as_slice(), 1, -2);
+        assert_eq!(
+            map.lrange(b"l".as_slice(), 0, -1),
+            bulk_array(&[b"b", b"c"])
+        );
+        assert_eq!(
+            map.linsert(b"l".as_slice(), false, b"b".to_vec(), b"z".to_vec()),
+            OutputValue::Integer(3)
+        );
+        assert_eq!(
+            map.linsert(b"l".as_slice(), true, b"nope".to_vec(), b"z".to_vec()),
+            OutputValue::Integer(-1)
+        );
+        map.ltrim(b"l".as_slice(), 5, 10);
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn test_lpos() {
+        let mut map = Map::default();
+        let items = [b"a", b"b", b"c", b"1", b"2", b"3", b"c", b"c"].map(|v| v.to_vec());
+        map.rpush(b"l".as_slice(), items.to_vec());
+        assert_eq!(
+            map.lpos(b"l".as_slice(), b"c".to_vec(), 1, None, 0),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(
+            map.lpos(b"l".as_slice(), b"c".to_vec(), -1, Some(2), 0),
+            OutputValue::Array(vec![OutputValue::Integer(7), OutputValue::Integer(6)])
+        );
+        assert_eq!(
+            map.lpos(b"l".as_slice(), b"c".to_vec(), 2, Some(0), 0),
+            OutputValue::Array(vec![OutputValue::Integer(6), OutputValue::Integer(7)])
+        );
+        assert_eq!(
+            map.lpos(b"l".as_slice(), b"c".to_vec(), 2, None, 3),
+            OutputValue::NullBulkString
+        );
+    }
+
+    #[test]
+    fn test_lmove_rotates_same_list() {
+        let mut map = Map::default();
+        let items = [b"a", b"b", b"c"].map(|v| v.to_vec());
+        map.rpush(b"l".as_slice(), items.to_vec());
+        assert_eq!(
+            map.lmove(
+                b"l".as_slice(),
+                b"l".as_slice(),
+                ListEnd::Left,
+                ListEnd::Right
+            ),
+            OutputValue::BulkString(b"a".to_vec())
+        );
+        assert_eq!(
+            map.lrange(b"l".as_slice(), 0, -1),
+            bulk_array(&[b"b", b"c", b"a"])
+        );
+    }
+}
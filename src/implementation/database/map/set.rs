@@ -0,0 +1,584 @@
+use std::collections::HashSet;
+
+use rand::rngs::SmallRng;
+
+use crate::interface::database::map::{Key, MapSetCommands, ScanOptions, SetOperation};
+use crate::interface::types::OutputValue;
+
+use super::super::value::{Set, Value};
+use super::{reply_buffer, scan_filter, scan_reply, scan_steps, with_rng, wrong_type, Map};
+
+impl Map {
+    fn get_set(&self, key: &[u8], command: &str) -> Result<Option<&Set>, OutputValue> {
+        match self.lookup(key) {
+            None => Ok(None),
+            Some(Value::Set(s)) => Ok(Some(s)),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    fn get_set_mut(&mut self, key: &[u8], command: &str) -> Result<Option<&mut Set>, OutputValue> {
+        match self.lookup_mut(key) {
+            None => Ok(None),
+            Some(Value::Set(s)) => Ok(Some(s)),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    fn get_or_create_set(&mut self, key: &[u8], command: &str) -> Result<&mut Set, OutputValue> {
+        match self
+            .data
+            .get_or_insert_with(key.to_vec(), || Value::Set(Set::default()))
+        {
+            Value::Set(s) => Ok(s),
+            _ => Err(wrong_type(command)),
+        }
+    }
+
+    /// Looks up every key as a set, failing if any of them holds another type.
+    fn get_sets(&self, keys: &[Vec<u8>], command: &str) -> Result<Vec<Option<&Set>>, OutputValue> {
+        keys.iter().map(|k| self.get_set(k, command)).collect()
+    }
+
+    fn compute_set_operation(
+        &self,
+        operation: SetOperation,
+        keys: &[Vec<u8>],
+        command: &str,
+    ) -> Result<Set, OutputValue> {
+        let sets = self.get_sets(keys, command)?;
+        Ok(match operation {
+            SetOperation::Inter => intersection(sets).cloned().collect(),
+            SetOperation::Union => sets
+                .into_iter()
+                .flatten()
+                .flat_map(Set::iter)
+                .cloned()
+                .collect(),
+            SetOperation::Diff => {
+                let (first, rest) = sets.split_first().expect("at least one key");
+                first.map_or_else(Set::default, |first| {
+                    first
+                        .iter()
+                        .filter(|m| !rest.iter().flatten().any(|s| s.contains(m)))
+                        .cloned()
+                        .collect()
+                })
+            }
+        })
+    }
+
+    /// Sets are never left empty in the keyspace.
+    fn remove_set_if_empty(&mut self, key: &[u8]) {
+        if let Some(Value::Set(s)) = self.data.get(key) {
+            if s.is_empty() {
+                self.remove_key(key);
+            }
+        }
+    }
+}
+
+/// Members common to all `sets`, found by probing the others with each member of the smallest.
+fn intersection<'a>(sets: Vec<Option<&'a Set>>) -> impl Iterator<Item = &'a Vec<u8>> + 'a {
+    // a missing key is an empty set, which empties the whole intersection
+    let mut sets: Vec<_> = if sets.iter().any(Option::is_none) {
+        vec![]
+    } else {
+        sets.into_iter().flatten().collect()
+    };
+    sets.sort_unstable_by_key(|s| s.len());
+    let (smallest, rest) = match sets.split_first() {
+        Some((smallest, rest)) => (Some(*smallest), rest.to_vec()),
+        None => (None, vec![]),
+    };
+    smallest
+        .into_iter()
+        .flat_map(Set::iter)
+        .filter(move |m| rest.iter().all(|s| s.contains(m)))
+}
+
+fn operation_command(operation: SetOperation, store: bool) -> &'static str {
+    match (operation, store) {
+        (SetOperation::Inter, false) => "sinter",
+        (SetOperation::Union, false) => "sunion",
+        (SetOperation::Diff, false) => "sdiff",
+        (SetOperation::Inter, true) => "sinterstore",
+        (SetOperation::Union, true) => "sunionstore",
+        (SetOperation::Diff, true) => "sdiffstore",
+    }
+}
+
+fn members_array<'a>(members: impl Iterator<Item = &'a Vec<u8>>) -> OutputValue {
+    OutputValue::Array(members.cloned().map(OutputValue::BulkString).collect())
+}
+
+impl MapSetCommands for Map {
+    fn sadd(&mut self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue {
+        let set = match self.get_or_create_set(key.as_ref(), "sadd") {
+            Err(e) => return e,
+            Ok(s) => s,
+        };
+        let added = members
+            .into_iter()
+            .filter(|m| set.insert(m.clone()))
+            .count();
+        OutputValue::Integer(added as i64)
+    }
+
+    fn srem(&mut self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue {
+        let key = key.as_ref();
+        let set = match self.get_set_mut(key, "srem") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Integer(0),
+            Ok(Some(s)) => s,
+        };
+        let removed = members.iter().filter(|m| set.remove(m)).count();
+        self.remove_set_if_empty(key);
+        OutputValue::Integer(removed as i64)
+    }
+
+    fn sismember(&self, key: impl Key, member: Vec<u8>) -> OutputValue {
+        match self.get_set(key.as_ref(), "sismember") {
+            Err(e) => e,
+            Ok(s) => OutputValue::Integer(s.is_some_and(|s| s.contains(&member)) as i64),
+        }
+    }
+
+    fn smismember(&self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue {
+        let set = match self.get_set(key.as_ref(), "smismember") {
+            Err(e) => return e,
+            Ok(s) => s,
+        };
+        OutputValue::Array(
+            members
+                .iter()
+                .map(|m| OutputValue::Integer(set.is_some_and(|s| s.contains(m)) as i64))
+                .collect(),
+        )
+    }
+
+    fn smembers(&self, key: impl Key) -> OutputValue {
+        match self.get_set(key.as_ref(), "smembers") {
+            Err(e) => e,
+            Ok(None) => OutputValue::Array(vec![]),
+            Ok(Some(s)) => members_array(s.iter()),
+        }
+    }
+
+    fn scard(&self, key: impl Key) -> OutputValue {
+        match self.get_set(key.as_ref(), "scard") {
+            Err(e) => e,
+            Ok(s) => OutputValue::Integer(s.map_or(0, Set::len) as i64),
+        }
+    }
+
+    fn smove(&mut self, source: impl Key, destination: impl Key, member: Vec<u8>) -> OutputValue {
+        let (source, destination) = (source.as_ref(), destination.as_ref());
+        if let Err(e) = self.get_set(destination, "smove") {
+            return e;
+        }
+        let set = match self.get_set_mut(source, "smove") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Integer(0),
+            Ok(Some(s)) => s,
+        };
+        if source == destination {
+            return OutputValue::Integer(set.contains(&member) as i64);
+        }
+        if !set.remove(&member) {
+            return OutputValue::Integer(0);
+        }
+        self.remove_set_if_empty(source);
+        match self.get_or_create_set(destination, "smove") {
+            Err(e) => e,
+            Ok(s) => {
+                s.insert(member);
+                OutputValue::Integer(1)
+            }
+        }
+    }
+
+    fn srandmember(&self, key: impl Key, count: Option<i64>) -> OutputValue {
+        let set = match self.get_set(key.as_ref(), "srandmember") {
+            Err(e) => return e,
+            Ok(None) if count.is_some() => return OutputValue::Array(vec![]),
+            Ok(None) => return OutputValue::NullBulkString,
+            Ok(Some(s)) => s,
+        };
+        let random = |rng: &mut SmallRng| set.random(rng).expect("sets are never empty");
+        let Some(count) = count else {
+            return OutputValue::BulkString(with_rng(random).clone());
+        };
+        if count < 0 {
+            let count = count.unsigned_abs() as usize;
+            let mut reply = match reply_buffer(count) {
+                Ok(reply) => reply,
+                Err(e) => return e,
+            };
+            with_rng(|rng| {
+                for _ in 0..count {
+                    reply.push(OutputValue::BulkString(random(rng).clone()));
+                }
+            });
+            return OutputValue::Array(reply);
+        }
+        let count = count as usize;
+        if count >= set.len() {
+            return members_array(set.iter());
+        }
+        // like Redis, only go through every member when most of them are asked for, and
+        // otherwise draw members until there are enough different ones
+        if count * 3 > set.len() {
+            let members: Vec<_> = set.iter().collect();
+            let picked = with_rng(|rng| rand::seq::index::sample(rng, members.len(), count));
+            return members_array(picked.into_iter().map(|i| members[i]));
+        }
+        let mut seen = HashSet::with_capacity(count);
+        let mut picked = Vec::with_capacity(count);
+        with_rng(|rng| {
+            while picked.len() < count {
+                let member = random(rng);
+                if seen.insert(member) {
+                    picked.push(member);
+                }
+            }
+        });
+        members_array(picked.into_iter())
+    }
+
+    fn spop(&mut self, key: impl Key, count: Option<usize>) -> OutputValue {
+        let key = key.as_ref();
+        let set = match self.get_set_mut(key, "spop") {
+            Err(e) => return e,
+            Ok(None) if count.is_some() => return OutputValue::Array(vec![]),
+            Ok(None) => return OutputValue::NullBulkString,
+            Ok(Some(s)) => s,
+        };
+        if count.is_some_and(|count| count >= set.len()) {
+            let ret = members_array(set.iter());
+            self.remove_key(key);
+            return ret;
+        }
+        let picked: Vec<_> = with_rng(|rng| {
+            (0..count.unwrap_or(1))
+                .map(|_| {
+                    let member = set.random(rng).expect("sets are never empty").clone();
+                    set.remove(&member);
+                    member
+                })
+                .collect()
+        });
+        let ret = match count {
+            None => OutputValue::BulkString(picked.into_iter().next().unwrap()),
+            Some(_) => {
+                OutputValue::Array(picked.into_iter().map(OutputValue::BulkString).collect())
+            }
+        };
+        self.remove_set_if_empty(key);
+        ret
+    }
+
+    fn set_operation(&self, operation: SetOperation, keys: Vec<Vec<u8>>) -> OutputValue {
+        match self.compute_set_operation(operation, &keys, operation_command(operation, false)) {
+            Err(e) => e,
+            Ok(result) => members_array(result.iter()),
+        }
+    }
+
+    fn set_operation_store(
+        &mut self,
+        operation: SetOperation,
+        destination: Vec<u8>,
+        keys: Vec<Vec<u8>>,
+    ) -> OutputValue {
+        let result = match self.compute_set_operation(
+            operation,
+            &keys,
+            operation_command(operation, true),
+        ) {
+            Err(e) => return e,
+            Ok(result) => result,
+        };
+        let len = result.len();
+        // the destination is overwritten whatever it held before
+        if result.is_empty() {
+            self.remove_key(&destination);
+        } else {
+            self.insert_key(destination, Value::Set(result));
+        }
+        OutputValue::Integer(len as i64)
+    }
+
+    fn sintercard(&self, keys: Vec<Vec<u8>>, limit: usize) -> OutputValue {
+        let sets = match self.get_sets(&keys, "sintercard") {
+            Err(e) => return e,
+            Ok(sets) => sets,
+        };
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        OutputValue::Integer(intersection(sets).take(limit).count() as i64)
+    }
+
+    fn sscan(&self, key: impl Key, cursor: u64, options: ScanOptions) -> OutputValue {
+        let set = match self.get_set(key.as_ref(), "sscan") {
+            Err(e) => return e,
+            Ok(None) => return scan_reply(0, vec![]),
+            Ok(Some(s)) => s,
+        };
+        let (cursor, members) = scan_steps(cursor, options.count, |cursor, members| {
+            set.scan(cursor, |member| members.push(member))
+        });
+        let matches = scan_filter(options.pattern.as_deref());
+        scan_reply(
+            cursor,
+            members
+                .into_iter()
+                .filter(|m| matches(m))
+                .cloned()
+                .map(OutputValue::BulkString)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::database::map::MapMiscCommands;
+
+    fn members(items: &[&[u8]]) -> Vec<Vec<u8>> {
+        items.iter().map(|v| v.to_vec()).collect()
+    }
+
+    fn array_len(value: OutputValue) -> usize {
+        let OutputValue::Array(v) = value else {
+            panic!("expected an array, got {:?}", value)
+        };
+        v.len()
+    }
+
+    #[test]
+    fn test_sadd_and_srem() {
+        let mut map = Map::default();
+        assert_eq!(
+            map.sadd(b"s".as_slice(), members(&[b"a", b"b", b"a"])),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(
+            map.sadd(b"s".as_slice(), members(&[b"b", b"c"])),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(map.scard(b"s".as_slice()), OutputValue::Integer(3));
+        assert_eq!(
+            map.smismember(b"s".as_slice(), members(&[b"a", b"z"])),
+            OutputValue::Array(vec![OutputValue::Integer(1), OutputValue::Integer(0)])
+        );
+        assert_eq!(
+            map.srem(b"s".as_slice(), members(&[b"a", b"b", b"c", b"z"])),
+            OutputValue::Integer(3)
+        );
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn test_smove() {
+        let mut map = Map::default();
+        map.sadd(b"src".as_slice(), members(&[b"a"]));
+        map.set(b"str".as_slice(), b"x".to_vec());
+        assert!(matches!(
+            map.smove(b"src".as_slice(), b"str".as_slice(), b"a".to_vec()),
+            OutputValue::Error(_)
+        ));
+        assert_eq!(
+            map.smove(b"src".as_slice(), b"dst".as_slice(), b"z".to_vec()),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            map.smove(b"src".as_slice(), b"dst".as_slice(), b"a".to_vec()),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(map.scard(b"src".as_slice()), OutputValue::Integer(0));
+        assert_eq!(
+            map.smembers(b"dst".as_slice()),
+            OutputValue::Array(vec![OutputValue::BulkString(b"a".to_vec())])
+        );
+    }
+
+    #[test]
+    fn test_srandmember_and_spop() {
+        let mut map = Map::default();
+        map.sadd(b"s".as_slice(), members(&[b"a", b"b", b"c"]));
+        assert_eq!(array_len(map.srandmember(b"s".as_slice(), Some(5))), 3);
+        assert_eq!(array_len(map.srandmember(b"s".as_slice(), Some(-5))), 5);
+        assert_eq!(
+            map.srandmember(b"nope".as_slice(), None),
+            OutputValue::NullBulkString
+        );
+        // more repeats than could ever be held are refused rather than allocated
+        assert_eq!(
+            map.srandmember(b"s".as_slice(), Some(-i64::MAX)),
+            OutputValue::Error(b"ERR value is out of range".to_vec())
+        );
+        let many: Vec<_> = (0..100).map(|i| i.to_string().into_bytes()).collect();
+        map.sadd(b"many".as_slice(), many.clone());
+        // few members are drawn at random, and more than a third of them by sampling
+        for count in [1, 10, 40, 99] {
+            let picked = set_of(map.srandmember(b"many".as_slice(), Some(count)));
+            assert_eq!(picked.len(), count as usize);
+            assert!(picked.iter().all(|m| many.contains(m)));
+        }
+        let popped = set_of(map.spop(b"many".as_slice(), Some(30)));
+        assert_eq!(popped.len(), 30);
+        assert_eq!(map.scard(b"many".as_slice()), OutputValue::Integer(70));
+        assert_eq!(array_len(map.spop(b"many".as_slice(), Some(100))), 70);
+        assert_eq!(
+            map.exists(vec![b"many".as_slice()]),
+            OutputValue::Integer(0)
+        );
+
+        assert_eq!(array_len(map.spop(b"s".as_slice(), Some(2))), 2);
+        assert_eq!(map.scard(b"s".as_slice()), OutputValue::Integer(1));
+        assert!(matches!(
+            map.spop(b"s".as_slice(), None),
+            OutputValue::BulkString(_)
+        ));
+        assert_eq!(map.len(), 0);
+        assert_eq!(
+            map.spop(b"s".as_slice(), Some(1)),
+            OutputValue::Array(vec![])
+        );
+    }
+
+    fn set_of(value: OutputValue) -> HashSet<Vec<u8>> {
+        let OutputValue::Array(v) = value else {
+            panic!("expected an array, got {:?}", value)
+        };
+        v.into_iter()
+            .map(|m| match m {
+                OutputValue::BulkString(m) => m,
+                m => panic!("expected a bulk string, got {:?}", m),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let mut map = Map::default();
+        map.sadd(b"a".as_slice(), members(&[b"1", b"2", b"3", b"4"]));
+        map.sadd(b"b".as_slice(), members(&[b"2", b"3", b"5"]));
+        map.sadd(b"c".as_slice(), members(&[b"3", b"6"]));
+        let keys = members(&[b"a", b"b", b"c"]);
+        assert_eq!(
+            set_of(map.set_operation(SetOperation::Inter, keys.clone())),
+            HashSet::from([b"3".to_vec()])
+        );
+        assert_eq!(
+            set_of(map.set_operation(SetOperation::Union, keys.clone())).len(),
+            6
+        );
+        assert_eq!(
+            set_of(map.set_operation(SetOperation::Diff, keys.clone())),
+            HashSet::from([b"1".to_vec(), b"4".to_vec()])
+        );
+        assert_eq!(
+            set_of(map.set_operation(SetOperation::Inter, members(&[b"a", b"nope"]))),
+            HashSet::new()
+        );
+        assert_eq!(
+            set_of(map.set_operation(SetOperation::Diff, members(&[b"nope", b"a"]))),
+            HashSet::new()
+        );
+    }
+
+    #[test]
+    fn test_set_operation_store() {
+        let mut map = Map::default();
+        map.sadd(b"a".as_slice(), members(&[b"1", b"2"]));
+        map.sadd(b"b".as_slice(), members(&[b"2", b"3"]));
+        map.set(b"dst".as_slice(), b"x".to_vec());
+        assert_eq!(
+            map.set_operation_store(SetOperation::Union, b"dst".to_vec(), members(&[b"a", b"b"])),
+            OutputValue::Integer(3)
+        );
+        assert_eq!(map.scard(b"dst".as_slice()), OutputValue::Integer(3));
+        // a source may also be the destination
+        assert_eq!(
+            map.set_operation_store(SetOperation::Diff, b"a".to_vec(), members(&[b"a", b"b"])),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            map.smembers(b"a".as_slice()),
+            OutputValue::Array(vec![OutputValue::BulkString(b"1".to_vec())])
+        );
+        assert_eq!(
+            map.set_operation_store(SetOperation::Inter, b"dst".to_vec(), members(&[b"a", b"b"])),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.exists(vec![b"dst".as_slice()]), OutputValue::Integer(0));
+        // a wrong type among the sources leaves the destination alone
+        map.set(b"str".as_slice(), b"x".to_vec());
+        assert!(matches!(
+            map.set_operation_store(SetOperation::Union, b"a".to_vec(), members(&[b"b", b"str"])),
+            OutputValue::Error(_)
+        ));
+        assert_eq!(map.scard(b"a".as_slice()), OutputValue::Integer(1));
+    }
+
+    #[test]
+    fn test_sintercard() {
+        let mut map = Map::default();
+        map.sadd(b"a".as_slice(), members(&[b"1", b"2", b"3", b"4"]));
+        map.sadd(b"b".as_slice(), members(&[b"1", b"2", b"3"]));
+        assert_eq!(
+            map.sintercard(members(&[b"a", b"b"]), 0),
+            OutputValue::Integer(3)
+        );
+        assert_eq!(
+            map.sintercard(members(&[b"a", b"b"]), 2),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(
+            map.sintercard(members(&[b"a", b"nope"]), 0),
+            OutputValue::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut map = Map::default();
+        map.set(b"str".as_slice(), b"x".to_vec());
+        assert_eq!(
+            map.sadd(b"str".as_slice(), members(&[b"a"])),
+            OutputValue::Error(b"ERR wrong target type for 'sadd'".to_vec())
+        );
+        assert_eq!(
+            map.smembers(b"str".as_slice()),
+            OutputValue::Error(b"ERR wrong target type for 'smembers'".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_sscan() {
+        let mut map = Map::default();
+        map.sadd(b"s".as_slice(), members(&[b"a1", b"a2", b"b1"]));
+        let options = ScanOptions {
+            pattern: Some(b"a*".to_vec()),
+            count: 10,
+        };
+        let OutputValue::Array(reply) = map.sscan(b"s".as_slice(), 0, options) else {
+            panic!("expected an array")
+        };
+        // a small set is walked in one call
+        assert_eq!(reply[0], OutputValue::BulkString(b"0".to_vec()));
+        assert_eq!(
+            set_of(reply[1].clone()),
+            HashSet::from([b"a1".to_vec(), b"a2".to_vec()])
+        );
+        assert_eq!(
+            map.sscan(b"missing".as_slice(), 0, ScanOptions::default()),
+            OutputValue::Array(vec![
+                OutputValue::BulkString(b"0".to_vec()),
+                OutputValue::Array(vec![]),
+            ])
+        );
+    }
+}
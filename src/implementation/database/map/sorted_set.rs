@@ -0,0 +1,1135 @@
+use std::collections::HashMap;
+use std::ops::{Bound, Range};
+
+use crate::interface::database::map::{
+    Aggregate, Key, LexBound, MapSortedSetCommands, ScanOptions, ScoreComparison, SetCondition,
+    SetOperation, SortedSetEnd, SortedSetRange, ZaddOptions, ZrangeOptions,
+};
+use crate::interface::types::OutputValue;
+
+use super::super::float::format_double;
+use super::super::value::{Set, SortedSet, Value};
+use super::{normalise_range, scan_filter, scan_reply, scan_steps, wrong_type, Map};
+
+impl Map {
+    pub(super) fn get_sorted_set(
+        &self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<Option<&SortedSet>, OutputValue> {
+        match self.lookup(key) {
+            None => Ok(None),
+            Some(Value::SortedSet(z)) => Ok(Some(z)),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    fn get_sorted_set_mut(
+        &mut self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<Option<&mut SortedSet>, OutputValue> {
+        match self.lookup_mut(key) {
+            None => Ok(None),
+            Some(Value::SortedSet(z)) => Ok(Some(z)),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    fn get_or_create_sorted_set(
+        &mut self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<&mut SortedSet, OutputValue> {
+        if !self.data.contains_key(key) {
+            self.signal_key_as_ready(key);
+        }
+        match self
+            .data
+            .get_or_insert_with(key.to_vec(), || Value::SortedSet(SortedSet::default()))
+        {
+            Value::SortedSet(z) => Ok(z),
+            _ => Err(wrong_type(command)),
+        }
+    }
+
+    /// Looks up every key as a sorted set or a plain set, as ZUNION and friends accept both.
+    fn get_scored_sets(
+        &self,
+        keys: &[Vec<u8>],
+        command: &str,
+    ) -> Result<Vec<Option<ScoredSet<'_>>>, OutputValue> {
+        keys.iter()
+            .map(|key| match self.lookup(key) {
+                None => Ok(None),
+                Some(Value::SortedSet(z)) => Ok(Some(ScoredSet::Sorted(z))),
+                Some(Value::Set(s)) => Ok(Some(ScoredSet::Plain(s))),
+                Some(_) => Err(wrong_type(command)),
+            })
+            .collect()
+    }
+
+    fn compute_zset_operation(
+        &self,
+        operation: SetOperation,
+        keys: &[Vec<u8>],
+        weights: &[f64],
+        aggregate: Aggregate,
+        command: &str,
+    ) -> Result<SortedSet, OutputValue> {
+        let sets = self.get_scored_sets(keys, command)?;
+        let mut result = SortedSet::default();
+        match operation {
+            SetOperation::Union => {
+                let mut scores = HashMap::<&Vec<u8>, f64>::new();
+                for (set, weight) in sets.iter().zip(weights) {
+                    for (member, score) in set.iter().flat_map(ScoredSet::iter) {
+                        let score = weighted(score, *weight);
+                        scores
+                            .entry(member)
+                            .and_modify(|s| *s = aggregate_scores(*s, score, aggregate))
+                            .or_insert(score);
+                    }
+                }
+                for (member, score) in scores {
+                    result.insert(member.clone(), score);
+                }
+            }
+            SetOperation::Inter => {
+                let Some(mut sets) = sets
+                    .into_iter()
+                    .zip(weights.iter().copied())
+                    .map(|(set, weight)| Some((set?, weight)))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    // a missing key is an empty set, which empties the whole intersection
+                    return Ok(result);
+                };
+                sets.sort_by_key(|(set, _)| set.len());
+                let ((smallest, weight), rest) = sets.split_first().expect("at least one key");
+                'members: for (member, score) in smallest.iter() {
+                    let mut score = weighted(score, *weight);
+                    for (set, weight) in rest {
+                        let Some(other) = set.score(member) else {
+                            continue 'members;
+                        };
+                        score = aggregate_scores(score, weighted(other, *weight), aggregate);
+                    }
+                    result.insert(member.clone(), score);
+                }
+            }
+            SetOperation::Diff => {
+                let (first, rest) = sets.split_first().expect("at least one key");
+                for (member, score) in first.iter().flat_map(ScoredSet::iter) {
+                    if !rest.iter().flatten().any(|s| s.score(member).is_some()) {
+                        result.insert(member.clone(), score);
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Sorted sets are never left empty in the keyspace.
+    fn remove_sorted_set_if_empty(&mut self, key: &[u8]) {
+        if let Some(Value::SortedSet(z)) = self.data.get(key) {
+            if z.is_empty() {
+                self.remove_key(key);
+            }
+        }
+    }
+}
+
+/// An input of ZUNION, ZINTER or ZDIFF.
+enum ScoredSet<'a> {
+    Sorted(&'a SortedSet),
+    Plain(&'a Set),
+}
+
+impl<'a> ScoredSet<'a> {
+    fn len(&self) -> usize {
+        match self {
+            ScoredSet::Sorted(z) => z.len(),
+            ScoredSet::Plain(s) => s.len(),
+        }
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            ScoredSet::Sorted(z) => z.score(member),
+            ScoredSet::Plain(s) => s.contains(member).then_some(1.0),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&'a Vec<u8>, f64)> + 'a> {
+        match self {
+            ScoredSet::Sorted(z) => Box::new(z.iter()),
+            ScoredSet::Plain(s) => Box::new(s.iter().map(|m| (m, 1.0))),
+        }
+    }
+}
+
+/// `inf * 0` is taken to be 0 rather than NaN, as in Redis.
+fn weighted(score: f64, weight: f64) -> f64 {
+    let score = score * weight;
+    if score.is_nan() {
+        0.0
+    } else {
+        score
+    }
+}
+
+fn aggregate_scores(a: f64, b: f64, aggregate: Aggregate) -> f64 {
+    match aggregate {
+        // `inf + -inf` is taken to be 0 as well
+        Aggregate::Sum => weighted(a + b, 1.0),
+        Aggregate::Min => a.min(b),
+        Aggregate::Max => a.max(b),
+    }
+}
+
+fn zset_operation_command(operation: SetOperation, store: bool) -> &'static str {
+    match (operation, store) {
+        (SetOperation::Inter, false) => "zinter",
+        (SetOperation::Union, false) => "zunion",
+        (SetOperation::Diff, false) => "zdiff",
+        (SetOperation::Inter, true) => "zinterstore",
+        (SetOperation::Union, true) => "zunionstore",
+        (SetOperation::Diff, true) => "zdiffstore",
+    }
+}
+
+/// The ascending positions of the members a ZRANGE-like command selects.
+fn select_ranks(zset: &SortedSet, options: &ZrangeOptions) -> Range<usize> {
+    let ranks = match &options.range {
+        SortedSetRange::Rank(start, stop) => {
+            let Some((start, stop)) = normalise_range(*start, *stop, zset.len()) else {
+                return 0..0;
+            };
+            // reversed ranks count from the top, so turn them into ascending positions
+            let last = zset.len() - 1;
+            if options.reverse {
+                last - stop..last - start + 1
+            } else {
+                start..stop + 1
+            }
+        }
+        SortedSetRange::Score(min, max) => zset.ranks_by_score(*min, *max),
+        // lexicographical ranges assume that all members share the same score, as in Redis
+        SortedSetRange::Lex(min, max) => zset.ranks_by_lex(min, max),
+    };
+    let Some((offset, count)) = options.limit else {
+        return ranks;
+    };
+    let Ok(offset) = usize::try_from(offset) else {
+        return 0..0;
+    };
+    let count = usize::try_from(count).unwrap_or(usize::MAX);
+    // the limit applies in reply order
+    if options.reverse {
+        let end = ranks.end.saturating_sub(offset).max(ranks.start);
+        end - count.min(end - ranks.start)..end
+    } else {
+        let start = ranks.start.saturating_add(offset).min(ranks.end);
+        start..start + count.min(ranks.end - start)
+    }
+}
+
+/// The members a ZRANGE-like command selects, in reply order.
+fn select_range<'a>(zset: &'a SortedSet, options: &ZrangeOptions) -> Vec<(&'a Vec<u8>, f64)> {
+    let entries = zset.range(select_ranks(zset, options));
+    if options.reverse {
+        entries.rev().collect()
+    } else {
+        entries.collect()
+    }
+}
+
+/// Flattens `(member, score)` pairs into the `member score member score ...` reply of RESP2.
+fn members_with_scores(entries: impl Iterator<Item = (Vec<u8>, f64)>) -> OutputValue {
+    OutputValue::Array(
+        entries
+            .flat_map(|(member, score)| {
+                [
+                    OutputValue::BulkString(member),
+                    OutputValue::BulkString(format_double(score)),
+                ]
+            })
+            .collect(),
+    )
+}
+
+impl MapSortedSetCommands for Map {
+    fn zadd(
+        &mut self,
+        key: impl Key,
+        options: ZaddOptions,
+        members: Vec<(f64, Vec<u8>)>,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        let command = if options.incr { "zincrby" } else { "zadd" };
+        let aborted = if options.incr {
+            OutputValue::NullBulkString
+        } else {
+            OutputValue::Integer(0)
+        };
+        match self.get_sorted_set(key, command) {
+            Err(e) => return e,
+            Ok(None) if options.condition == SetCondition::Xx => return aborted,
+            Ok(_) => {}
+        }
+        let zset = match self.get_or_create_sorted_set(key, command) {
+            Err(e) => return e,
+            Ok(z) => z,
+        };
+        let mut added = 0;
+        let mut changed = 0;
+        let mut incr_result = None;
+        let mut error = None;
+        for (score, member) in members {
+            match zset.score(&member) {
+                None => {
+                    if options.condition == SetCondition::Xx {
+                        continue;
+                    }
+                    zset.insert(member, score);
+                    added += 1;
+                    incr_result = Some(score);
+                }
+                Some(old) => {
+                    if options.condition == SetCondition::Nx {
+                        continue;
+                    }
+                    let new = if options.incr { old + score } else { score };
+                    if new.is_nan() {
+                        error = Some(OutputValue::Error(
+                            b"ERR resulting score is not a number (NaN)".to_vec(),
+                        ));
+                        break;
+                    }
+                    let allowed = match options.comparison {
+                        ScoreComparison::Any => true,
+                        ScoreComparison::Gt => new > old,
+                        ScoreComparison::Lt => new < old,
+                    };
+                    if !allowed {
+                        continue;
+                    }
+                    if new != old {
+                        zset.insert(member, new);
+                        changed += 1;
+                    }
+                    incr_result = Some(new);
+                }
+            }
+        }
+        // NX or a failed INCR may leave the set we just created empty
+        self.remove_sorted_set_if_empty(key);
+        if let Some(e) = error {
+            return e;
+        }
+        if options.incr {
+            return incr_result
+                .map(|s| OutputValue::BulkString(format_double(s)))
+                .unwrap_or(aborted);
+        }
+        if options.changed {
+            added += changed;
+        }
+        OutputValue::Integer(added)
+    }
+
+    fn zscore(&self, key: impl Key, member: Vec<u8>) -> OutputValue {
+        match self.get_sorted_set(key.as_ref(), "zscore") {
+            Err(e) => e,
+            Ok(z) => z
+                .and_then(|z| z.score(&member))
+                .map(|s| OutputValue::BulkString(format_double(s)))
+                .unwrap_or(OutputValue::NullBulkString),
+        }
+    }
+
+    fn zmscore(&self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue {
+        let zset = match self.get_sorted_set(key.as_ref(), "zmscore") {
+            Err(e) => return e,
+            Ok(z) => z,
+        };
+        OutputValue::Array(
+            members
+                .iter()
+                .map(|m| {
+                    zset.and_then(|z| z.score(m))
+                        .map(|s| OutputValue::BulkString(format_double(s)))
+                        .unwrap_or(OutputValue::NullBulkString)
+                })
+                .collect(),
+        )
+    }
+
+    fn zrank(
+        &self,
+        key: impl Key,
+        member: Vec<u8>,
+        reverse: bool,
+        with_score: bool,
+    ) -> OutputValue {
+        let command = if reverse { "zrevrank" } else { "zrank" };
+        let zset = match self.get_sorted_set(key.as_ref(), command) {
+            Err(e) => return e,
+            Ok(z) => z,
+        };
+        let Some((zset, rank)) = zset.and_then(|z| Some((z, z.rank(&member)?))) else {
+            return if with_score {
+                OutputValue::NullArray
+            } else {
+                OutputValue::NullBulkString
+            };
+        };
+        let rank = if reverse { zset.len() - 1 - rank } else { rank };
+        if with_score {
+            OutputValue::Array(vec![
+                OutputValue::Integer(rank as i64),
+                OutputValue::BulkString(format_double(zset.score(&member).unwrap())),
+            ])
+        } else {
+            OutputValue::Integer(rank as i64)
+        }
+    }
+
+    fn zcard(&self, key: impl Key) -> OutputValue {
+        match self.get_sorted_set(key.as_ref(), "zcard") {
+            Err(e) => e,
+            Ok(z) => OutputValue::Integer(z.map_or(0, SortedSet::len) as i64),
+        }
+    }
+
+    fn zcount(&self, key: impl Key, min: Bound<f64>, max: Bound<f64>) -> OutputValue {
+        match self.get_sorted_set(key.as_ref(), "zcount") {
+            Err(e) => e,
+            Ok(z) => OutputValue::Integer(z.map_or(0, |z| z.ranks_by_score(min, max).len()) as i64),
+        }
+    }
+
+    fn zrem(&mut self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue {
+        let key = key.as_ref();
+        let zset = match self.get_sorted_set_mut(key, "zrem") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Integer(0),
+            Ok(Some(z)) => z,
+        };
+        let removed = members.iter().filter(|m| zset.remove(m).is_some()).count();
+        self.remove_sorted_set_if_empty(key);
+        OutputValue::Integer(removed as i64)
+    }
+
+    fn zpop(&mut self, key: impl Key, end: SortedSetEnd, count: Option<usize>) -> OutputValue {
+        let key = key.as_ref();
+        let command = match end {
+            SortedSetEnd::Min => "zpopmin",
+            SortedSetEnd::Max => "zpopmax",
+        };
+        let zset = match self.get_sorted_set_mut(key, command) {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Array(vec![]),
+            Ok(Some(z)) => z,
+        };
+        let popped: Vec<_> = std::iter::from_fn(|| match end {
+            SortedSetEnd::Min => zset.pop_first(),
+            SortedSetEnd::Max => zset.pop_last(),
+        })
+        .take(count.unwrap_or(1))
+        .collect();
+        self.remove_sorted_set_if_empty(key);
+        members_with_scores(popped.into_iter())
+    }
+
+    fn zrange(&self, key: impl Key, options: ZrangeOptions, with_scores: bool) -> OutputValue {
+        let zset = match self.get_sorted_set(key.as_ref(), "zrange") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Array(vec![]),
+            Ok(Some(z)) => z,
+        };
+        let selected = select_range(zset, &options);
+        if with_scores {
+            members_with_scores(selected.into_iter().map(|(m, s)| (m.clone(), s)))
+        } else {
+            OutputValue::Array(
+                selected
+                    .into_iter()
+                    .map(|(m, _)| OutputValue::BulkString(m.clone()))
+                    .collect(),
+            )
+        }
+    }
+
+    fn zrangestore(
+        &mut self,
+        destination: impl Key,
+        source: impl Key,
+        options: ZrangeOptions,
+    ) -> OutputValue {
+        let result = match self.get_sorted_set(source.as_ref(), "zrangestore") {
+            Err(e) => return e,
+            Ok(None) => SortedSet::default(),
+            Ok(Some(z)) => {
+                let mut result = SortedSet::default();
+                for (member, score) in select_range(z, &options) {
+                    result.insert(member.clone(), score);
+                }
+                result
+            }
+        };
+        let len = result.len();
+        // the destination is overwritten whatever it held before
+        if result.is_empty() {
+            self.remove_key(destination.as_ref());
+        } else {
+            self.insert_key(destination.as_ref().to_vec(), Value::SortedSet(result));
+        }
+        OutputValue::Integer(len as i64)
+    }
+
+    fn zlexcount(&self, key: impl Key, min: LexBound, max: LexBound) -> OutputValue {
+        let zset = match self.get_sorted_set(key.as_ref(), "zlexcount") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Integer(0),
+            Ok(Some(z)) => z,
+        };
+        let options = ZrangeOptions {
+            range: SortedSetRange::Lex(min, max),
+            reverse: false,
+            limit: None,
+        };
+        OutputValue::Integer(select_ranks(zset, &options).len() as i64)
+    }
+
+    fn zremrange(&mut self, key: impl Key, range: SortedSetRange) -> OutputValue {
+        let key = key.as_ref();
+        let command = match range {
+            SortedSetRange::Rank(..) => "zremrangebyrank",
+            SortedSetRange::Score(..) => "zremrangebyscore",
+            SortedSetRange::Lex(..) => "zremrangebylex",
+        };
+        let options = ZrangeOptions {
+            range,
+            reverse: false,
+            limit: None,
+        };
+        let zset = match self.get_sorted_set_mut(key, command) {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Integer(0),
+            Ok(Some(z)) => z,
+        };
+        let removed = zset.remove_range(select_ranks(zset, &options));
+        self.remove_sorted_set_if_empty(key);
+        OutputValue::Integer(removed as i64)
+    }
+
+    fn zset_operation(
+        &self,
+        operation: SetOperation,
+        keys: Vec<Vec<u8>>,
+        weights: Vec<f64>,
+        aggregate: Aggregate,
+        with_scores: bool,
+    ) -> OutputValue {
+        let command = zset_operation_command(operation, false);
+        let result =
+            match self.compute_zset_operation(operation, &keys, &weights, aggregate, command) {
+                Err(e) => return e,
+                Ok(result) => result,
+            };
+        if with_scores {
+            members_with_scores(result.iter().map(|(m, s)| (m.clone(), s)))
+        } else {
+            OutputValue::Array(
+                result
+                    .iter()
+                    .map(|(m, _)| OutputValue::BulkString(m.clone()))
+                    .collect(),
+            )
+        }
+    }
+
+    fn zset_operation_store(
+        &mut self,
+        operation: SetOperation,
+        destination: Vec<u8>,
+        keys: Vec<Vec<u8>>,
+        weights: Vec<f64>,
+        aggregate: Aggregate,
+    ) -> OutputValue {
+        let command = zset_operation_command(operation, true);
+        let result =
+            match self.compute_zset_operation(operation, &keys, &weights, aggregate, command) {
+                Err(e) => return e,
+                Ok(result) => result,
+            };
+        let len = result.len();
+        // the destination is overwritten whatever it held before
+        if result.is_empty() {
+            self.remove_key(&destination);
+        } else {
+            self.insert_key(destination, Value::SortedSet(result));
+        }
+        OutputValue::Integer(len as i64)
+    }
+
+    fn zintercard(&self, keys: Vec<Vec<u8>>, limit: usize) -> OutputValue {
+        let sets = match self.get_scored_sets(&keys, "zintercard") {
+            Err(e) => return e,
+            Ok(sets) => sets,
+        };
+        let Some(mut sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+            return OutputValue::Integer(0);
+        };
+        sets.sort_by_key(ScoredSet::len);
+        let (smallest, rest) = sets.split_first().expect("at least one key");
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let count = smallest
+            .iter()
+            .filter(|(m, _)| rest.iter().all(|s| s.score(m).is_some()))
+            .take(limit)
+            .count();
+        OutputValue::Integer(count as i64)
+    }
+
+    fn zscan(
+        &self,
+        key: impl Key,
+        cursor: u64,
+        options: ScanOptions,
+        with_scores: bool,
+    ) -> OutputValue {
+        let zset = match self.get_sorted_set(key.as_ref(), "zscan") {
+            Err(e) => return e,
+            Ok(None) => return scan_reply(0, vec![]),
+            Ok(Some(z)) => z,
+        };
+        let (cursor, entries) = scan_steps(cursor, options.count, |cursor, entries| {
+            zset.scan(cursor, |member, score| entries.push((member, score)))
+        });
+        let matches = scan_filter(options.pattern.as_deref());
+        let mut items = Vec::new();
+        for (member, score) in entries.into_iter().filter(|(m, _)| matches(m)) {
+            items.push(OutputValue::BulkString(member.clone()));
+            if with_scores {
+                items.push(OutputValue::BulkString(format_double(score)));
+            }
+        }
+        scan_reply(cursor, items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::database::map::MapMiscCommands;
+
+    fn pairs(items: &[(f64, &[u8])]) -> Vec<(f64, Vec<u8>)> {
+        items.iter().map(|(s, m)| (*s, m.to_vec())).collect()
+    }
+
+    fn bulk(s: &[u8]) -> OutputValue {
+        OutputValue::BulkString(s.to_vec())
+    }
+
+    #[test]
+    fn test_zadd_and_zscore() {
+        let mut map = Map::default();
+        assert_eq!(
+            map.zadd(
+                b"z".as_slice(),
+                ZaddOptions::default(),
+                pairs(&[(1.0, b"a"), (2.5, b"b"), (3.0, b"a")])
+            ),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(map.zscore(b"z".as_slice(), b"a".to_vec()), bulk(b"3"));
+        assert_eq!(
+            map.zmscore(b"z".as_slice(), vec![b"b".to_vec(), b"c".to_vec()]),
+            OutputValue::Array(vec![bulk(b"2.5"), OutputValue::NullBulkString])
+        );
+        assert_eq!(map.zcard(b"z".as_slice()), OutputValue::Integer(2));
+    }
+
+    #[test]
+    fn test_zadd_options() {
+        let mut map = Map::default();
+        let xx = ZaddOptions {
+            condition: SetCondition::Xx,
+            ..Default::default()
+        };
+        assert_eq!(
+            map.zadd(b"z".as_slice(), xx, pairs(&[(1.0, b"a")])),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.len(), 0);
+
+        map.zadd(
+            b"z".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[(1.0, b"a"), (5.0, b"b")]),
+        );
+        let gt_ch = ZaddOptions {
+            comparison: ScoreComparison::Gt,
+            changed: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            map.zadd(
+                b"z".as_slice(),
+                gt_ch,
+                pairs(&[(2.0, b"a"), (4.0, b"b"), (0.0, b"c")])
+            ),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(map.zscore(b"z".as_slice(), b"b".to_vec()), bulk(b"5"));
+
+        let nx = ZaddOptions {
+            condition: SetCondition::Nx,
+            ..Default::default()
+        };
+        assert_eq!(
+            map.zadd(b"z".as_slice(), nx, pairs(&[(9.0, b"a"), (9.0, b"d")])),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(map.zscore(b"z".as_slice(), b"a".to_vec()), bulk(b"2"));
+    }
+
+    #[test]
+    fn test_zincrby() {
+        let mut map = Map::default();
+        assert_eq!(
+            map.zincrby(b"z".as_slice(), 1.5, b"a".to_vec()),
+            bulk(b"1.5")
+        );
+        assert_eq!(
+            map.zincrby(b"z".as_slice(), -0.5, b"a".to_vec()),
+            bulk(b"1")
+        );
+        map.zincrby(b"z".as_slice(), f64::INFINITY, b"a".to_vec());
+        assert_eq!(
+            map.zincrby(b"z".as_slice(), f64::NEG_INFINITY, b"a".to_vec()),
+            OutputValue::Error(b"ERR resulting score is not a number (NaN)".to_vec())
+        );
+        let incr_lt = ZaddOptions {
+            comparison: ScoreComparison::Lt,
+            incr: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            map.zadd(b"z".as_slice(), incr_lt, pairs(&[(1.0, b"a")])),
+            OutputValue::NullBulkString
+        );
+    }
+
+    #[test]
+    fn test_zrank_and_zcount() {
+        let mut map = Map::default();
+        map.zadd(
+            b"z".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[(1.0, b"a"), (2.0, b"b"), (2.0, b"c"), (3.0, b"d")]),
+        );
+        assert_eq!(
+            map.zrank(b"z".as_slice(), b"c".to_vec(), false, false),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(
+            map.zrank(b"z".as_slice(), b"c".to_vec(), true, true),
+            OutputValue::Array(vec![OutputValue::Integer(1), bulk(b"2")])
+        );
+        assert_eq!(
+            map.zrank(b"z".as_slice(), b"x".to_vec(), false, false),
+            OutputValue::NullBulkString
+        );
+        assert_eq!(
+            map.zcount(b"z".as_slice(), Bound::Included(2.0), Bound::Included(3.0)),
+            OutputValue::Integer(3)
+        );
+        assert_eq!(
+            map.zcount(b"z".as_slice(), Bound::Excluded(1.0), Bound::Excluded(3.0)),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(
+            map.zcount(
+                b"z".as_slice(),
+                Bound::Included(f64::NEG_INFINITY),
+                Bound::Included(f64::INFINITY)
+            ),
+            OutputValue::Integer(4)
+        );
+        assert_eq!(
+            map.zcount(b"z".as_slice(), Bound::Excluded(3.0), Bound::Included(1.0)),
+            OutputValue::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_zpop_and_zrem() {
+        let mut map = Map::default();
+        map.zadd(
+            b"z".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[(1.0, b"a"), (2.0, b"b"), (3.0, b"c")]),
+        );
+        assert_eq!(
+            map.zpop(b"z".as_slice(), SortedSetEnd::Max, None),
+            OutputValue::Array(vec![bulk(b"c"), bulk(b"3")])
+        );
+        assert_eq!(
+            map.zpop(b"z".as_slice(), SortedSetEnd::Min, Some(1)),
+            OutputValue::Array(vec![bulk(b"a"), bulk(b"1")])
+        );
+        assert_eq!(
+            map.zrem(b"z".as_slice(), vec![b"b".to_vec(), b"x".to_vec()]),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(map.len(), 0);
+        assert_eq!(
+            map.zpop(b"z".as_slice(), SortedSetEnd::Min, None),
+            OutputValue::Array(vec![])
+        );
+    }
+
+    fn range(range: SortedSetRange, reverse: bool, limit: Option<(i64, i64)>) -> ZrangeOptions {
+        ZrangeOptions {
+            range,
+            reverse,
+            limit,
+        }
+    }
+
+    fn abcde() -> Map {
+        let mut map = Map::default();
+        map.zadd(
+            b"z".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[
+                (1.0, b"a"),
+                (2.0, b"b"),
+                (3.0, b"c"),
+                (4.0, b"d"),
+                (5.0, b"e"),
+            ]),
+        );
+        map
+    }
+
+    #[test]
+    fn test_zrange_by_rank() {
+        let map = abcde();
+        assert_eq!(
+            map.zrange(
+                b"z".as_slice(),
+                range(SortedSetRange::Rank(1, -2), false, None),
+                false
+            ),
+            OutputValue::Array(vec![bulk(b"b"), bulk(b"c"), bulk(b"d")])
+        );
+        assert_eq!(
+            map.zrange(
+                b"z".as_slice(),
+                range(SortedSetRange::Rank(0, 1), true, None),
+                true
+            ),
+            OutputValue::Array(vec![bulk(b"e"), bulk(b"5"), bulk(b"d"), bulk(b"4")])
+        );
+        assert_eq!(
+            map.zrange(
+                b"z".as_slice(),
+                range(SortedSetRange::Rank(3, 1), false, None),
+                false
+            ),
+            OutputValue::Array(vec![])
+        );
+    }
+
+    #[test]
+    fn test_zrange_by_score() {
+        let map = abcde();
+        assert_eq!(
+            map.zrange(
+                b"z".as_slice(),
+                range(
+                    SortedSetRange::Score(Bound::Excluded(1.0), Bound::Included(4.0)),
+                    true,
+                    Some((1, 2))
+                ),
+                false
+            ),
+            OutputValue::Array(vec![bulk(b"c"), bulk(b"b")])
+        );
+        assert_eq!(
+            map.zrange(
+                b"z".as_slice(),
+                range(
+                    SortedSetRange::Score(Bound::Included(f64::NEG_INFINITY), Bound::Excluded(3.0)),
+                    false,
+                    Some((0, -1))
+                ),
+                false
+            ),
+            OutputValue::Array(vec![bulk(b"a"), bulk(b"b")])
+        );
+    }
+
+    #[test]
+    fn test_zrange_by_lex() {
+        let mut map = Map::default();
+        map.zadd(
+            b"z".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[(0.0, b"a"), (0.0, b"b"), (0.0, b"c"), (0.0, b"d")]),
+        );
+        assert_eq!(
+            map.zrange(
+                b"z".as_slice(),
+                range(
+                    SortedSetRange::Lex(LexBound::Excluded(b"a".to_vec()), LexBound::Max),
+                    true,
+                    None
+                ),
+                false
+            ),
+            OutputValue::Array(vec![bulk(b"d"), bulk(b"c"), bulk(b"b")])
+        );
+        assert_eq!(
+            map.zlexcount(
+                b"z".as_slice(),
+                LexBound::Min,
+                LexBound::Included(b"b".to_vec())
+            ),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(
+            map.zlexcount(b"z".as_slice(), LexBound::Max, LexBound::Min),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            map.zrange(
+                b"z".as_slice(),
+                range(
+                    SortedSetRange::Lex(LexBound::Included(b"b".to_vec()), LexBound::Max),
+                    false,
+                    Some((1, 5))
+                ),
+                false
+            ),
+            OutputValue::Array(vec![bulk(b"c"), bulk(b"d")])
+        );
+        assert_eq!(
+            map.zrange(
+                b"z".as_slice(),
+                range(
+                    SortedSetRange::Lex(LexBound::Min, LexBound::Max),
+                    true,
+                    Some((4, 1))
+                ),
+                false
+            ),
+            OutputValue::Array(vec![])
+        );
+    }
+
+    #[test]
+    fn test_scores_are_printed_like_redis() {
+        let mut map = Map::default();
+        map.zadd(
+            b"z".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[(1e20, b"a"), (-0.0, b"b"), (1.5e-7, b"c")]),
+        );
+        assert_eq!(map.zscore(b"z".as_slice(), b"a".to_vec()), bulk(b"1e+20"));
+        assert_eq!(map.zscore(b"z".as_slice(), b"b".to_vec()), bulk(b"-0"));
+        assert_eq!(map.zscore(b"z".as_slice(), b"c".to_vec()), bulk(b"1.5e-7"));
+    }
+
+    #[test]
+    fn test_zrangestore_and_zremrange() {
+        let mut map = abcde();
+        assert_eq!(
+            map.zrangestore(
+                b"dst".as_slice(),
+                b"z".as_slice(),
+                range(SortedSetRange::Rank(0, 2), false, None)
+            ),
+            OutputValue::Integer(3)
+        );
+        assert_eq!(map.zcard(b"dst".as_slice()), OutputValue::Integer(3));
+        assert_eq!(
+            map.zremrange(
+                b"z".as_slice(),
+                SortedSetRange::Score(Bound::Included(2.0), Bound::Included(3.0))
+            ),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(
+            map.zremrange(b"z".as_slice(), SortedSetRange::Rank(-1, -1)),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            map.zremrange(
+                b"z".as_slice(),
+                SortedSetRange::Lex(LexBound::Min, LexBound::Max)
+            ),
+            OutputValue::Integer(2)
+        );
+        assert_eq!(map.len(), 1);
+        assert_eq!(
+            map.zrangestore(
+                b"dst".as_slice(),
+                b"z".as_slice(),
+                range(SortedSetRange::Rank(0, -1), false, None)
+            ),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn test_zset_operations() {
+        let mut map = Map::default();
+        map.zadd(
+            b"a".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[(1.0, b"x"), (2.0, b"y"), (3.0, b"z")]),
+        );
+        map.zadd(
+            b"b".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[(10.0, b"y"), (20.0, b"z"), (30.0, b"w")]),
+        );
+        let keys = vec![b"a".to_vec(), b"b".to_vec()];
+        assert_eq!(
+            map.zset_operation(
+                SetOperation::Union,
+                keys.clone(),
+                vec![1.0, 2.0],
+                Aggregate::Sum,
+                true
+            ),
+            OutputValue::Array(vec![
+                bulk(b"x"),
+                bulk(b"1"),
+                bulk(b"y"),
+                bulk(b"22"),
+                bulk(b"z"),
+                bulk(b"43"),
+                bulk(b"w"),
+                bulk(b"60")
+            ])
+        );
+        assert_eq!(
+            map.zset_operation(
+                SetOperation::Inter,
+                keys.clone(),
+                vec![1.0, 1.0],
+                Aggregate::Min,
+                true
+            ),
+            OutputValue::Array(vec![bulk(b"y"), bulk(b"2"), bulk(b"z"), bulk(b"3")])
+        );
+        assert_eq!(
+            map.zset_operation(
+                SetOperation::Diff,
+                keys.clone(),
+                vec![1.0, 1.0],
+                Aggregate::Sum,
+                false
+            ),
+            OutputValue::Array(vec![bulk(b"x")])
+        );
+        assert_eq!(map.zintercard(keys, 1), OutputValue::Integer(1));
+    }
+
+    #[test]
+    fn test_zset_operations_accept_plain_sets() {
+        use crate::interface::database::map::MapSetCommands;
+
+        let mut map = Map::default();
+        map.zadd(
+            b"z".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[(5.0, b"x"), (6.0, b"y")]),
+        );
+        map.sadd(b"s".as_slice(), vec![b"x".to_vec(), b"q".to_vec()]);
+        assert_eq!(
+            map.zset_operation_store(
+                SetOperation::Inter,
+                b"dst".to_vec(),
+                vec![b"z".to_vec(), b"s".to_vec()],
+                vec![1.0, 1.0],
+                Aggregate::Max,
+            ),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(map.zscore(b"dst".as_slice(), b"x".to_vec()), bulk(b"5"));
+        assert_eq!(
+            map.zset_operation_store(
+                SetOperation::Inter,
+                b"dst".to_vec(),
+                vec![b"z".to_vec(), b"nope".to_vec()],
+                vec![1.0, 1.0],
+                Aggregate::Sum,
+            ),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(map.exists(vec![b"dst".as_slice()]), OutputValue::Integer(0));
+        map.set(b"str".as_slice(), b"v".to_vec());
+        assert_eq!(
+            map.zset_operation(
+                SetOperation::Union,
+                vec![b"z".to_vec(), b"str".to_vec()],
+                vec![1.0, 1.0],
+                Aggregate::Sum,
+                false
+            ),
+            OutputValue::Error(b"ERR wrong target type for 'zunion'".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut map = Map::default();
+        map.set(b"str".as_slice(), b"x".to_vec());
+        assert_eq!(
+            map.zadd(
+                b"str".as_slice(),
+                ZaddOptions::default(),
+                pairs(&[(1.0, b"a")])
+            ),
+            OutputValue::Error(b"ERR wrong target type for 'zadd'".to_vec())
+        );
+        assert_eq!(
+            map.zcard(b"str".as_slice()),
+            OutputValue::Error(b"ERR wrong target type for 'zcard'".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_zscan() {
+        let mut map = Map::default();
+        map.zadd(
+            b"z".as_slice(),
+            ZaddOptions::default(),
+            pairs(&[(1.5, b"a"), (2.0, b"b")]),
+        );
+        let options = ScanOptions {
+            pattern: Some(b"a".to_vec()),
+            count: 10,
+        };
+        assert_eq!(
+            map.zscan(b"z".as_slice(), 0, options.clone(), true),
+            OutputValue::Array(vec![
+                bulk(b"0"),
+                OutputValue::Array(vec![bulk(b"a"), bulk(b"1.5")]),
+            ])
+        );
+        assert_eq!(
+            map.zscan(b"z".as_slice(), 0, options, false),
+            OutputValue::Array(vec![bulk(b"0"), OutputValue::Array(vec![bulk(b"a")])])
+        );
+    }
+}
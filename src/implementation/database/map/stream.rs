@@ -0,0 +1,1215 @@
+use std::ops::Bound;
+
+use crate::interface::database::map::{
+    GroupStart, Key, MapStreamCommands, ReadGroupId, StreamId, StreamTrim, XaddId,
+    XautoclaimOptions, XclaimOptions, XpendingRange, XreadId,
+};
+use crate::interface::types::OutputValue;
+
+use super::super::value::{ConsumerGroup, Stream, Value};
+use super::{wrong_type, Map};
+
+impl Map {
+    fn get_stream(&self, key: &[u8], command: &str) -> Result<Option<&Stream>, OutputValue> {
+        match self.lookup(key) {
+            None => Ok(None),
+            Some(Value::Stream(s)) => Ok(Some(s)),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    fn get_stream_mut(
+        &mut self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<Option<&mut Stream>, OutputValue> {
+        match self.lookup_mut(key) {
+            None => Ok(None),
+            Some(Value::Stream(s)) => Ok(Some(s)),
+            Some(_) => Err(wrong_type(command)),
+        }
+    }
+
+    fn get_or_create_stream(
+        &mut self,
+        key: &[u8],
+        command: &str,
+    ) -> Result<&mut Stream, OutputValue> {
+        match self
+            .data
+            .get_or_insert_with(key.to_vec(), || Value::Stream(Stream::default()))
+        {
+            Value::Stream(s) => Ok(s),
+            _ => Err(wrong_type(command)),
+        }
+    }
+
+    /// Looks up a stream that has `group`, as the commands working on pending entries need.
+    fn get_stream_with_group_mut(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        command: &str,
+    ) -> Result<&mut Stream, OutputValue> {
+        match self.get_stream_mut(key, command)? {
+            Some(stream) if stream.groups().contains_key(group) => Ok(stream),
+            _ => Err(no_such_key_or_group(key, group)),
+        }
+    }
+}
+
+fn no_such_key_or_group(key: &[u8], group: &[u8]) -> OutputValue {
+    OutputValue::Error(
+        format!(
+            "NOGROUP No such key '{}' or consumer group '{}'",
+            String::from_utf8_lossy(key),
+            String::from_utf8_lossy(group)
+        )
+        .into_bytes(),
+    )
+}
+
+fn no_such_group(key: &[u8], group: &[u8]) -> OutputValue {
+    OutputValue::Error(
+        format!(
+            "NOGROUP No such consumer group '{}' for key name '{}'",
+            String::from_utf8_lossy(group),
+            String::from_utf8_lossy(key)
+        )
+        .into_bytes(),
+    )
+}
+
+fn xgroup_requires_key() -> OutputValue {
+    OutputValue::Error(
+        b"ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want \
+to use the MKSTREAM option to create an empty stream automatically."
+            .to_vec(),
+    )
+}
+
+fn format_id(id: &StreamId) -> OutputValue {
+    OutputValue::BulkString(id.to_string().into_bytes())
+}
+
+/// Renders an entry as `[id, [field, value, ...]]`.
+fn format_entry(id: &StreamId, fields: &[Vec<u8>]) -> OutputValue {
+    OutputValue::Array(vec![
+        format_id(id),
+        OutputValue::Array(
+            fields
+                .iter()
+                .cloned()
+                .map(OutputValue::BulkString)
+                .collect(),
+        ),
+    ])
+}
+
+impl MapStreamCommands for Map {
+    fn xadd(
+        &mut self,
+        key: impl Key,
+        id: XaddId,
+        no_mkstream: bool,
+        trim: Option<StreamTrim>,
+        fields: Vec<Vec<u8>>,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        let now = self.clock.now_ms();
+        let id =
+            match self.get_stream(key, "xadd") {
+                Err(e) => return e,
+                Ok(None) if no_mkstream => return OutputValue::NullBulkString,
+                Ok(None) => Stream::default().next_id(id, now),
+                Ok(Some(stream)) if stream.last_id() == StreamId::MAX => return OutputValue::Error(
+                    b"ERR The stream has exhausted the last possible ID, unable to add more items"
+                        .to_vec(),
+                ),
+                Ok(Some(stream)) => stream.next_id(id, now),
+            };
+        let Some(id) = id else {
+            return OutputValue::Error(
+                b"ERR The ID specified in XADD is equal or smaller than the target stream top item"
+                    .to_vec(),
+            );
+        };
+        let stream = match self.get_or_create_stream(key, "xadd") {
+            Err(e) => return e,
+            Ok(s) => s,
+        };
+        stream.insert(id, fields);
+        if let Some(trim) = trim {
+            stream.trim(&trim);
+        }
+        self.signal_key_as_ready(key);
+        format_id(&id)
+    }
+
+    fn xrange(
+        &self,
+        key: impl Key,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+        count: Option<usize>,
+        reverse: bool,
+    ) -> OutputValue {
+        let command = if reverse { "xrevrange" } else { "xrange" };
+        let stream = match self.get_stream(key.as_ref(), command) {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Array(vec![]),
+            Ok(Some(s)) => s,
+        };
+        let count = count.unwrap_or(usize::MAX);
+        let range = stream.range(start, end);
+        let entries: Box<dyn Iterator<Item = _>> = if reverse {
+            Box::new(range.rev())
+        } else {
+            Box::new(range)
+        };
+        OutputValue::Array(
+            entries
+                .take(count)
+                .map(|(id, fields)| format_entry(id, fields))
+                .collect(),
+        )
+    }
+
+    fn xlen(&self, key: impl Key) -> OutputValue {
+        match self.get_stream(key.as_ref(), "xlen") {
+            Err(e) => e,
+            Ok(s) => OutputValue::Integer(s.map_or(0, Stream::len) as i64),
+        }
+    }
+
+    fn xdel(&mut self, key: impl Key, ids: Vec<StreamId>) -> OutputValue {
+        match self.get_stream_mut(key.as_ref(), "xdel") {
+            Err(e) => e,
+            Ok(None) => OutputValue::Integer(0),
+            Ok(Some(stream)) => {
+                OutputValue::Integer(ids.iter().filter(|id| stream.remove(id)).count() as i64)
+            }
+        }
+    }
+
+    fn xtrim(&mut self, key: impl Key, trim: StreamTrim) -> OutputValue {
+        match self.get_stream_mut(key.as_ref(), "xtrim") {
+            Err(e) => e,
+            Ok(None) => OutputValue::Integer(0),
+            Ok(Some(stream)) => OutputValue::Integer(stream.trim(&trim) as i64),
+        }
+    }
+
+    fn xinfo_stream(&self, key: impl Key) -> OutputValue {
+        let stream = match self.get_stream(key.as_ref(), "xinfo") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Error(b"ERR no such key".to_vec()),
+            Ok(Some(s)) => s,
+        };
+        let field = |name: &str| OutputValue::BulkString(name.as_bytes().to_vec());
+        let entry = |entry: Option<(&StreamId, &Vec<Vec<u8>>)>| {
+            entry.map_or(OutputValue::NullBulkString, |(id, fields)| {
+                format_entry(id, fields)
+            })
+        };
+        let first_id = stream.first_entry().map_or(StreamId::MIN, |(id, _)| *id);
+        OutputValue::Array(vec![
+            field("length"),
+            OutputValue::Integer(stream.len() as i64),
+            field("radix-tree-keys"),
+            OutputValue::Integer(stream.node_count() as i64),
+            field("radix-tree-nodes"),
+            OutputValue::Integer(stream.node_count() as i64 + 1),
+            field("last-generated-id"),
+            format_id(&stream.last_id()),
+            field("max-deleted-entry-id"),
+            format_id(&stream.max_deleted_id()),
+            field("entries-added"),
+            OutputValue::Integer(stream.entries_added() as i64),
+            field("recorded-first-entry-id"),
+            format_id(&first_id),
+            field("groups"),
+            OutputValue::Integer(stream.groups().len() as i64),
+            field("first-entry"),
+            entry(stream.first_entry()),
+            field("last-entry"),
+            entry(stream.last_entry()),
+        ])
+    }
+
+    fn xread(&self, streams: Vec<(Vec<u8>, XreadId)>, count: Option<usize>) -> OutputValue {
+        let count = count.filter(|c| *c > 0).unwrap_or(usize::MAX);
+        let mut replies = vec![];
+        for (key, id) in streams {
+            let stream = match self.get_stream(&key, "xread") {
+                Err(e) => return e,
+                Ok(None) => continue,
+                Ok(Some(s)) => s,
+            };
+            let entries: Vec<_> = match id {
+                XreadId::After(id) => stream
+                    .range(Bound::Excluded(id), Bound::Unbounded)
+                    .take(count)
+                    .map(|(id, fields)| format_entry(id, fields))
+                    .collect(),
+                XreadId::LastEntry => stream
+                    .last_entry()
+                    .map(|(id, fields)| format_entry(id, fields))
+                    .into_iter()
+                    .collect(),
+            };
+            if !entries.is_empty() {
+                replies.push(OutputValue::Array(vec![
+                    OutputValue::BulkString(key),
+                    OutputValue::Array(entries),
+                ]));
+            }
+        }
+        if replies.is_empty() {
+            OutputValue::NullArray
+        } else {
+            OutputValue::Array(replies)
+        }
+    }
+
+    fn stream_last_id(&self, key: impl Key) -> Result<StreamId, OutputValue> {
+        Ok(self
+            .get_stream(key.as_ref(), "xread")?
+            .map_or(StreamId::MIN, Stream::last_id))
+    }
+
+    fn xgroup_create(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        start: GroupStart,
+        mkstream: bool,
+        entries_read: Option<u64>,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        match self.get_stream(key, "xgroup") {
+            Err(e) => return e,
+            Ok(None) if !mkstream => return xgroup_requires_key(),
+            Ok(_) => {}
+        }
+        let stream = match self.get_or_create_stream(key, "xgroup") {
+            Err(e) => return e,
+            Ok(s) => s,
+        };
+        let id = match start {
+            GroupStart::LastEntry => stream.last_id(),
+            GroupStart::Id(id) => id,
+        };
+        if stream.create_group(&group, ConsumerGroup::new(id, entries_read)) {
+            OutputValue::Ok
+        } else {
+            OutputValue::Error(b"BUSYGROUP Consumer Group name already exists".to_vec())
+        }
+    }
+
+    fn xgroup_setid(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        start: GroupStart,
+        entries_read: Option<u64>,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        let stream = match self.get_stream_mut(key, "xgroup") {
+            Err(e) => return e,
+            Ok(None) => return xgroup_requires_key(),
+            Ok(Some(s)) => s,
+        };
+        let id = match start {
+            GroupStart::LastEntry => stream.last_id(),
+            GroupStart::Id(id) => id,
+        };
+        match stream.group_mut(&group) {
+            None => no_such_group(key, &group),
+            Some(group) => {
+                group.set_last_delivered_id(id, entries_read);
+                OutputValue::Ok
+            }
+        }
+    }
+
+    fn xgroup_destroy(&mut self, key: impl Key, group: Vec<u8>) -> OutputValue {
+        match self.get_stream_mut(key.as_ref(), "xgroup") {
+            Err(e) => e,
+            Ok(None) => xgroup_requires_key(),
+            Ok(Some(stream)) => {
+                let destroyed = stream.destroy_group(&group);
+                if destroyed {
+                    // clients reading through the group now fail
+                    self.signal_key_as_ready(key.as_ref());
+                }
+                OutputValue::Integer(destroyed as i64)
+            }
+        }
+    }
+
+    fn xgroup_createconsumer(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        let now = self.clock.now_ms();
+        match self.get_stream_mut(key, "xgroup") {
+            Err(e) => e,
+            Ok(None) => xgroup_requires_key(),
+            Ok(Some(stream)) => match stream.group_mut(&group) {
+                None => no_such_group(key, &group),
+                Some(group) => OutputValue::Integer(group.create_consumer(&consumer, now) as i64),
+            },
+        }
+    }
+
+    fn xgroup_delconsumer(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+    ) -> OutputValue {
+        let key = key.as_ref();
+        match self.get_stream_mut(key, "xgroup") {
+            Err(e) => e,
+            Ok(None) => xgroup_requires_key(),
+            Ok(Some(stream)) => match stream.group_mut(&group) {
+                None => no_such_group(key, &group),
+                Some(group) => {
+                    OutputValue::Integer(group.delete_consumer(&consumer).unwrap_or(0) as i64)
+                }
+            },
+        }
+    }
+
+    fn xreadgroup(
+        &mut self,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+        streams: Vec<(Vec<u8>, ReadGroupId)>,
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> OutputValue {
+        let now = self.clock.now_ms();
+        // nothing is delivered unless every stream has the group
+        for (key, _) in streams.iter() {
+            match self.get_stream(key, "xreadgroup") {
+                Err(e) => return e,
+                Ok(Some(stream)) if stream.groups().contains_key(&group) => {}
+                Ok(_) => {
+                    return OutputValue::Error(
+                        format!(
+                            "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with \
+GROUP option",
+                            String::from_utf8_lossy(key),
+                            String::from_utf8_lossy(&group)
+                        )
+                        .into_bytes(),
+                    )
+                }
+            }
+        }
+        let count = count.filter(|c| *c > 0).unwrap_or(usize::MAX);
+        let mut replies = vec![];
+        for (key, id) in streams {
+            let Ok(Some(stream)) = self.get_stream_mut(&key, "xreadgroup") else {
+                unreachable!("the streams were checked above");
+            };
+            let entries = match id {
+                ReadGroupId::New => {
+                    let entries = stream
+                        .read_group(&group, &consumer, count, no_ack, now)
+                        .unwrap();
+                    if entries.is_empty() {
+                        continue;
+                    }
+                    entries
+                        .iter()
+                        .map(|(id, fields)| format_entry(id, fields))
+                        .collect()
+                }
+                ReadGroupId::History(start) => {
+                    let ids: Vec<_> = stream
+                        .group_mut(&group)
+                        .unwrap()
+                        .touch_consumer(&consumer, now)
+                        .pending()
+                        .range((Bound::Excluded(start), Bound::Unbounded))
+                        .take(count)
+                        .copied()
+                        .collect();
+                    let entries: Vec<_> = ids
+                        .into_iter()
+                        .map(|id| (id, stream.get(&id).map(|fields| format_entry(&id, fields))))
+                        .collect();
+                    let group = stream.group_mut(&group).unwrap();
+                    entries
+                        .into_iter()
+                        .map(|(id, entry)| match entry {
+                            Some(entry) => {
+                                group.redeliver(&id, now);
+                                entry
+                            }
+                            // deleted from the stream while still pending
+                            None => {
+                                OutputValue::Array(vec![format_id(&id), OutputValue::NullArray])
+                            }
+                        })
+                        .collect()
+                }
+            };
+            replies.push(OutputValue::Array(vec![
+                OutputValue::BulkString(key),
+                OutputValue::Array(entries),
+            ]));
+        }
+        if replies.is_empty() {
+            OutputValue::NullArray
+        } else {
+            OutputValue::Array(replies)
+        }
+    }
+
+    fn xack(&mut self, key: impl Key, group: Vec<u8>, ids: Vec<StreamId>) -> OutputValue {
+        let group = match self.get_stream_mut(key.as_ref(), "xack") {
+            Err(e) => return e,
+            Ok(stream) => stream.and_then(|s| s.group_mut(&group)),
+        };
+        let acked = group.map_or(0, |group| ids.iter().filter(|id| group.ack(id)).count());
+        OutputValue::Integer(acked as i64)
+    }
+
+    fn xpending(&self, key: impl Key, group: Vec<u8>, range: Option<XpendingRange>) -> OutputValue {
+        let key = key.as_ref();
+        let group_state = match self.get_stream(key, "xpending") {
+            Err(e) => return e,
+            Ok(stream) => stream.and_then(|s| s.groups().get(&group)),
+        };
+        let Some(group) = group_state else {
+            return no_such_key_or_group(key, &group);
+        };
+        let Some(range) = range else {
+            let pending = group.pending();
+            let (Some((first, _)), Some((last, _))) =
+                (pending.first_key_value(), pending.last_key_value())
+            else {
+                return OutputValue::Array(vec![
+                    OutputValue::Integer(0),
+                    OutputValue::NullBulkString,
+                    OutputValue::NullBulkString,
+                    OutputValue::NullArray,
+                ]);
+            };
+            let consumers = group
+                .consumers()
+                .iter()
+                .filter(|(_, consumer)| !consumer.pending().is_empty())
+                .map(|(name, consumer)| {
+                    OutputValue::Array(vec![
+                        OutputValue::BulkString(name.clone()),
+                        OutputValue::BulkString(consumer.pending().len().to_string().into_bytes()),
+                    ])
+                })
+                .collect();
+            return OutputValue::Array(vec![
+                OutputValue::Integer(pending.len() as i64),
+                format_id(first),
+                format_id(last),
+                OutputValue::Array(consumers),
+            ]);
+        };
+        let now = self.clock.now_ms();
+        OutputValue::Array(
+            group
+                .pending_range(range.start, range.end)
+                .filter(|(_, entry)| {
+                    range
+                        .consumer
+                        .as_ref()
+                        .is_none_or(|consumer| *consumer == entry.consumer)
+                        && now.saturating_sub(entry.delivery_time) >= range.min_idle
+                })
+                .take(range.count)
+                .map(|(id, entry)| {
+                    OutputValue::Array(vec![
+                        format_id(id),
+                        OutputValue::BulkString(entry.consumer.clone()),
+                        OutputValue::Integer(now.saturating_sub(entry.delivery_time) as i64),
+                        OutputValue::Integer(entry.delivery_count as i64),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    fn xclaim(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+        min_idle: u64,
+        ids: Vec<StreamId>,
+        options: XclaimOptions,
+    ) -> OutputValue {
+        let now = self.clock.now_ms();
+        let stream = match self.get_stream_with_group_mut(key.as_ref(), &group, "xclaim") {
+            Err(e) => return e,
+            Ok(s) => s,
+        };
+        let delivery_time = options
+            .time
+            .unwrap_or_else(|| now.saturating_sub(options.idle.unwrap_or(0)))
+            .min(now);
+        let group_state = stream.group_mut(&group).unwrap();
+        if let Some(last_id) = options.last_id {
+            if last_id > group_state.last_delivered_id() {
+                let entries_read = group_state.entries_read();
+                group_state.set_last_delivered_id(last_id, entries_read);
+            }
+        }
+        group_state.touch_consumer(&consumer, now);
+        let mut claimed = vec![];
+        for id in ids {
+            let fields = stream.get(&id).cloned();
+            let group = stream.group_mut(&group).unwrap();
+            let delivery_count = match group.pending().get(&id) {
+                None if options.force && fields.is_some() => 0,
+                None => continue,
+                Some(entry) if now.saturating_sub(entry.delivery_time) < min_idle => continue,
+                Some(entry) => entry.delivery_count,
+            };
+            let Some(fields) = fields else {
+                // deleted from the stream, so there is nothing left to claim
+                group.ack(&id);
+                continue;
+            };
+            let delivery_count = match options.retry_count {
+                Some(retry_count) => retry_count,
+                None if options.just_id => delivery_count,
+                None => delivery_count + 1,
+            };
+            group.assign(id, &consumer, delivery_time, delivery_count);
+            group.touch_consumer(&consumer, now).active_time = Some(now);
+            claimed.push(if options.just_id {
+                format_id(&id)
+            } else {
+                format_entry(&id, &fields)
+            });
+        }
+        OutputValue::Array(claimed)
+    }
+
+    fn xautoclaim(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+        min_idle: u64,
+        start: StreamId,
+        options: XautoclaimOptions,
+    ) -> OutputValue {
+        let XautoclaimOptions { count, just_id } = options;
+        // the number of pending entries looked at is bounded, much as in Redis
+        const ATTEMPTS_FACTOR: usize = 10;
+        let now = self.clock.now_ms();
+        let stream = match self.get_stream_with_group_mut(key.as_ref(), &group, "xautoclaim") {
+            Err(e) => return e,
+            Ok(s) => s,
+        };
+        let group_state = stream.group_mut(&group).unwrap();
+        group_state.touch_consumer(&consumer, now);
+        let attempts = count.saturating_mul(ATTEMPTS_FACTOR);
+        let candidates: Vec<_> = group_state
+            .pending_range(Bound::Included(start), Bound::Unbounded)
+            .take(attempts.saturating_add(1))
+            .map(|(id, entry)| (*id, entry.delivery_time, entry.delivery_count))
+            .collect();
+        let mut candidates = candidates.into_iter();
+        let mut claimed = vec![];
+        let mut deleted = vec![];
+        for (id, delivery_time, delivery_count) in candidates.by_ref().take(attempts) {
+            if now.saturating_sub(delivery_time) < min_idle {
+                continue;
+            }
+            let fields = stream.get(&id).cloned();
+            let group = stream.group_mut(&group).unwrap();
+            let Some(fields) = fields else {
+                group.ack(&id);
+                deleted.push(format_id(&id));
+                continue;
+            };
+            let delivery_count = if just_id {
+                delivery_count
+            } else {
+                delivery_count + 1
+            };
+            group.assign(id, &consumer, now, delivery_count);
+            group.touch_consumer(&consumer, now).active_time = Some(now);
+            claimed.push(if just_id {
+                format_id(&id)
+            } else {
+                format_entry(&id, &fields)
+            });
+            if claimed.len() == count {
+                break;
+            }
+        }
+        let next = candidates.next().map_or(StreamId::MIN, |(id, _, _)| id);
+        OutputValue::Array(vec![
+            format_id(&next),
+            OutputValue::Array(claimed),
+            OutputValue::Array(deleted),
+        ])
+    }
+
+    fn xinfo_groups(&self, key: impl Key) -> OutputValue {
+        let stream = match self.get_stream(key.as_ref(), "xinfo") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Error(b"ERR no such key".to_vec()),
+            Ok(Some(s)) => s,
+        };
+        let field = |name: &str| OutputValue::BulkString(name.as_bytes().to_vec());
+        let optional = |n: Option<u64>| {
+            n.map_or(OutputValue::NullBulkString, |n| {
+                OutputValue::Integer(n as i64)
+            })
+        };
+        OutputValue::Array(
+            stream
+                .groups()
+                .iter()
+                .map(|(name, group)| {
+                    OutputValue::Array(vec![
+                        field("name"),
+                        OutputValue::BulkString(name.clone()),
+                        field("consumers"),
+                        OutputValue::Integer(group.consumers().len() as i64),
+                        field("pending"),
+                        OutputValue::Integer(group.pending().len() as i64),
+                        field("last-delivered-id"),
+                        format_id(&group.last_delivered_id()),
+                        field("entries-read"),
+                        optional(group.entries_read()),
+                        field("lag"),
+                        optional(stream.lag(group)),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    fn xinfo_consumers(&self, key: impl Key, group: Vec<u8>) -> OutputValue {
+        let key = key.as_ref();
+        let stream = match self.get_stream(key, "xinfo") {
+            Err(e) => return e,
+            Ok(None) => return OutputValue::Error(b"ERR no such key".to_vec()),
+            Ok(Some(s)) => s,
+        };
+        let Some(group) = stream.groups().get(&group) else {
+            return no_such_group(key, &group);
+        };
+        let now = self.clock.now_ms();
+        let field = |name: &str| OutputValue::BulkString(name.as_bytes().to_vec());
+        OutputValue::Array(
+            group
+                .consumers()
+                .iter()
+                .map(|(name, consumer)| {
+                    OutputValue::Array(vec![
+                        field("name"),
+                        OutputValue::BulkString(name.clone()),
+                        field("pending"),
+                        OutputValue::Integer(consumer.pending().len() as i64),
+                        field("idle"),
+                        OutputValue::Integer(now.saturating_sub(consumer.seen_time) as i64),
+                        field("inactive"),
+                        OutputValue::Integer(
+                            consumer
+                                .active_time
+                                .map_or(-1, |t| now.saturating_sub(t) as i64),
+                        ),
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::super::super::clock::Clock;
+    use super::*;
+    use crate::interface::database::map::StreamTrimStrategy;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    fn bulk(s: &str) -> OutputValue {
+        OutputValue::BulkString(s.as_bytes().to_vec())
+    }
+
+    fn add(map: &mut Map, xadd_id: XaddId) -> OutputValue {
+        map.xadd(
+            b"s".as_slice(),
+            xadd_id,
+            false,
+            None,
+            vec![b"f".to_vec(), b"v".to_vec()],
+        )
+    }
+
+    fn ids(output: OutputValue) -> Vec<OutputValue> {
+        let OutputValue::Array(entries) = output else {
+            panic!("expected an array, got {:?}", output);
+        };
+        entries
+            .into_iter()
+            .map(|entry| match entry {
+                OutputValue::Array(mut pair) => pair.remove(0),
+                other => panic!("expected an entry, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_xadd_ids() {
+        let now = Rc::new(Cell::new(1000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        assert_eq!(add(&mut map, XaddId::Auto), bulk("1000-0"));
+        assert_eq!(add(&mut map, XaddId::Auto), bulk("1000-1"));
+        // a clock going backwards keeps the IDs increasing
+        now.set(900);
+        assert_eq!(add(&mut map, XaddId::Auto), bulk("1000-2"));
+        assert_eq!(add(&mut map, XaddId::AutoSequence(1000)), bulk("1000-3"));
+        assert_eq!(add(&mut map, XaddId::AutoSequence(2000)), bulk("2000-0"));
+        assert_eq!(
+            add(&mut map, XaddId::Explicit(id(2000, 0))),
+            OutputValue::Error(
+                b"ERR The ID specified in XADD is equal or smaller than the target stream top item"
+                    .to_vec()
+            )
+        );
+        assert_eq!(add(&mut map, XaddId::Explicit(id(2000, 5))), bulk("2000-5"));
+        assert_eq!(map.xlen(b"s".as_slice()), OutputValue::Integer(6));
+
+        let mut map = Map::default();
+        assert_eq!(add(&mut map, XaddId::AutoSequence(0)), bulk("0-1"));
+        assert_eq!(
+            map.xadd(
+                b"t".as_slice(),
+                XaddId::Auto,
+                true,
+                None,
+                vec![b"f".to_vec(), b"v".to_vec()]
+            ),
+            OutputValue::NullBulkString
+        );
+        assert_eq!(map.xlen(b"t".as_slice()), OutputValue::Integer(0));
+    }
+
+    #[test]
+    fn test_xrange() {
+        let mut map = Map::default();
+        for ms in 1..=5 {
+            add(&mut map, XaddId::Explicit(id(ms, 0)));
+        }
+        assert_eq!(
+            ids(map.xrange(
+                b"s".as_slice(),
+                Bound::Unbounded,
+                Bound::Unbounded,
+                Some(2),
+                false
+            )),
+            vec![bulk("1-0"), bulk("2-0")]
+        );
+        assert_eq!(
+            ids(map.xrange(
+                b"s".as_slice(),
+                Bound::Excluded(id(2, 0)),
+                Bound::Included(id(4, u64::MAX)),
+                None,
+                true
+            )),
+            vec![bulk("4-0"), bulk("3-0")]
+        );
+        assert_eq!(
+            ids(map.xrange(
+                b"s".as_slice(),
+                Bound::Excluded(id(3, 0)),
+                Bound::Excluded(id(3, 0)),
+                None,
+                false
+            )),
+            vec![]
+        );
+        assert_eq!(
+            map.xrange(
+                b"s".as_slice(),
+                Bound::Unbounded,
+                Bound::Unbounded,
+                Some(1),
+                false
+            ),
+            OutputValue::Array(vec![OutputValue::Array(vec![
+                bulk("1-0"),
+                OutputValue::Array(vec![bulk("f"), bulk("v")])
+            ])])
+        );
+    }
+
+    #[test]
+    fn test_xdel_keeps_last_id() {
+        let mut map = Map::default();
+        add(&mut map, XaddId::Explicit(id(1, 0)));
+        add(&mut map, XaddId::Explicit(id(2, 0)));
+        assert_eq!(
+            map.xdel(b"s".as_slice(), vec![id(2, 0), id(3, 0)]),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            add(&mut map, XaddId::Explicit(id(2, 0))),
+            OutputValue::Error(
+                b"ERR The ID specified in XADD is equal or smaller than the target stream top item"
+                    .to_vec()
+            )
+        );
+        let OutputValue::Array(info) = map.xinfo_stream(b"s".as_slice()) else {
+            panic!("expected an array");
+        };
+        assert_eq!(info[1], OutputValue::Integer(1));
+        assert_eq!(info[7], bulk("2-0"));
+        assert_eq!(info[9], bulk("2-0"));
+        assert_eq!(info[11], OutputValue::Integer(2));
+    }
+
+    #[test]
+    fn test_xtrim() {
+        let mut map = Map::default();
+        for ms in 1..=250 {
+            add(&mut map, XaddId::Explicit(id(ms, 0)));
+        }
+        let approximate = |strategy| StreamTrim {
+            strategy,
+            approximate: true,
+            limit: None,
+        };
+        // only whole nodes of 100 entries go
+        assert_eq!(
+            map.xtrim(
+                b"s".as_slice(),
+                approximate(StreamTrimStrategy::MaxLen(120))
+            ),
+            OutputValue::Integer(100)
+        );
+        assert_eq!(
+            map.xtrim(
+                b"s".as_slice(),
+                StreamTrim {
+                    limit: Some(50),
+                    ..approximate(StreamTrimStrategy::MaxLen(0))
+                }
+            ),
+            OutputValue::Integer(0)
+        );
+        assert_eq!(
+            map.xtrim(
+                b"s".as_slice(),
+                StreamTrim {
+                    strategy: StreamTrimStrategy::MinId(id(200, 0)),
+                    approximate: false,
+                    limit: None,
+                }
+            ),
+            OutputValue::Integer(99)
+        );
+        assert_eq!(
+            map.xtrim(
+                b"s".as_slice(),
+                StreamTrim {
+                    strategy: StreamTrimStrategy::MaxLen(10),
+                    approximate: false,
+                    limit: None,
+                }
+            ),
+            OutputValue::Integer(41)
+        );
+        assert_eq!(map.xlen(b"s".as_slice()), OutputValue::Integer(10));
+    }
+
+    #[test]
+    fn test_xread() {
+        let mut map = Map::default();
+        assert_eq!(map.stream_last_id(b"s".as_slice()), Ok(StreamId::MIN));
+        assert_eq!(
+            map.xread(vec![(b"s".to_vec(), XreadId::LastEntry)], None),
+            OutputValue::NullArray
+        );
+        for ms in 1..=3 {
+            add(&mut map, XaddId::Explicit(id(ms, 0)));
+        }
+        map.xdel(b"s".as_slice(), vec![id(3, 0)]);
+        assert_eq!(map.stream_last_id(b"s".as_slice()), Ok(id(3, 0)));
+        let read = |map: &Map, xread_id| {
+            let OutputValue::Array(mut streams) =
+                map.xread(vec![(b"s".to_vec(), xread_id)], Some(1))
+            else {
+                panic!("expected an array");
+            };
+            let OutputValue::Array(mut stream) = streams.remove(0) else {
+                panic!("expected an array");
+            };
+            ids(stream.remove(1))
+        };
+        assert_eq!(read(&map, XreadId::After(StreamId::MIN)), vec![bulk("1-0")]);
+        assert_eq!(read(&map, XreadId::LastEntry), vec![bulk("2-0")]);
+        assert_eq!(
+            map.xread(vec![(b"s".to_vec(), XreadId::After(id(2, 0)))], None),
+            OutputValue::NullArray
+        );
+    }
+
+    fn read_new(map: &mut Map, consumer: &str) -> OutputValue {
+        map.xreadgroup(
+            b"g".to_vec(),
+            consumer.as_bytes().to_vec(),
+            vec![(b"s".to_vec(), ReadGroupId::New)],
+            Some(1),
+            false,
+        )
+    }
+
+    fn group_info(map: &Map) -> Vec<OutputValue> {
+        let OutputValue::Array(mut groups) = map.xinfo_groups(b"s".as_slice()) else {
+            panic!("expected an array");
+        };
+        let OutputValue::Array(info) = groups.remove(0) else {
+            panic!("expected an array");
+        };
+        info
+    }
+
+    #[test]
+    fn test_xreadgroup_and_xack() {
+        let mut map = Map::default();
+        assert_eq!(
+            map.xgroup_create(
+                b"s".as_slice(),
+                b"g".to_vec(),
+                GroupStart::LastEntry,
+                false,
+                None
+            ),
+            xgroup_requires_key()
+        );
+        assert_eq!(
+            map.xgroup_create(
+                b"s".as_slice(),
+                b"g".to_vec(),
+                GroupStart::LastEntry,
+                true,
+                None
+            ),
+            OutputValue::Ok
+        );
+        for ms in 1..=3 {
+            add(&mut map, XaddId::Explicit(id(ms, 0)));
+        }
+        assert_eq!(ids_of_read(read_new(&mut map, "alice")), vec![bulk("1-0")]);
+        assert_eq!(ids_of_read(read_new(&mut map, "bob")), vec![bulk("2-0")]);
+        assert_eq!(
+            map.xpending(b"s".as_slice(), b"g".to_vec(), None),
+            OutputValue::Array(vec![
+                OutputValue::Integer(2),
+                bulk("1-0"),
+                bulk("2-0"),
+                OutputValue::Array(vec![
+                    OutputValue::Array(vec![bulk("alice"), bulk("1")]),
+                    OutputValue::Array(vec![bulk("bob"), bulk("1")]),
+                ]),
+            ])
+        );
+        // the history only holds the consumer's own entries
+        assert_eq!(
+            ids_of_read(map.xreadgroup(
+                b"g".to_vec(),
+                b"alice".to_vec(),
+                vec![(b"s".to_vec(), ReadGroupId::History(StreamId::MIN))],
+                None,
+                false,
+            )),
+            vec![bulk("1-0")]
+        );
+        assert_eq!(
+            map.xack(b"s".as_slice(), b"g".to_vec(), vec![id(1, 0), id(3, 0)]),
+            OutputValue::Integer(1)
+        );
+        let info = group_info(&map);
+        assert_eq!(info[5], OutputValue::Integer(1));
+        assert_eq!(info[7], bulk("2-0"));
+        assert_eq!(info[9], OutputValue::Integer(2));
+        assert_eq!(info[11], OutputValue::Integer(1));
+        assert_eq!(ids_of_read(read_new(&mut map, "alice")), vec![bulk("3-0")]);
+        assert_eq!(read_new(&mut map, "alice"), OutputValue::NullArray);
+        assert_eq!(
+            map.xgroup_delconsumer(b"s".as_slice(), b"g".to_vec(), b"bob".to_vec()),
+            OutputValue::Integer(1)
+        );
+        assert_eq!(
+            map.xreadgroup(
+                b"nope".to_vec(),
+                b"alice".to_vec(),
+                vec![(b"s".to_vec(), ReadGroupId::New)],
+                None,
+                false,
+            ),
+            OutputValue::Error(
+                b"NOGROUP No such key 's' or consumer group 'nope' in XREADGROUP with GROUP option"
+                    .to_vec()
+            )
+        );
+    }
+
+    #[test]
+    fn test_lag_after_deletion() {
+        let mut map = Map::default();
+        for ms in 1..=4 {
+            add(&mut map, XaddId::Explicit(id(ms, 0)));
+        }
+        map.xgroup_create(
+            b"s".as_slice(),
+            b"g".to_vec(),
+            GroupStart::Id(StreamId::MIN),
+            false,
+            None,
+        );
+        assert_eq!(group_info(&map)[11], OutputValue::Integer(4));
+        read_new(&mut map, "c");
+        assert_eq!(group_info(&map)[9], OutputValue::Integer(1));
+        assert_eq!(group_info(&map)[11], OutputValue::Integer(3));
+        // a tombstone ahead of the group makes the lag unknown
+        map.xdel(b"s".as_slice(), vec![id(3, 0)]);
+        assert_eq!(group_info(&map)[11], OutputValue::NullBulkString);
+        read_new(&mut map, "c");
+        read_new(&mut map, "c");
+        assert_eq!(group_info(&map)[11], OutputValue::Integer(0));
+    }
+
+    #[test]
+    fn test_xclaim_and_xautoclaim() {
+        let now = Rc::new(Cell::new(1000));
+        let mut map = Map::with_clock(Clock::Manual(now.clone()));
+        for ms in 1..=3 {
+            add(&mut map, XaddId::Explicit(id(ms, 0)));
+        }
+        map.xgroup_create(
+            b"s".as_slice(),
+            b"g".to_vec(),
+            GroupStart::Id(StreamId::MIN),
+            false,
+            None,
+        );
+        for _ in 0..3 {
+            read_new(&mut map, "alice");
+        }
+        now.set(1500);
+        let claim = |map: &mut Map, min_idle| {
+            map.xclaim(
+                b"s".as_slice(),
+                b"g".to_vec(),
+                b"bob".to_vec(),
+                min_idle,
+                vec![id(1, 0)],
+                XclaimOptions {
+                    just_id: true,
+                    ..Default::default()
+                },
+            )
+        };
+        assert_eq!(claim(&mut map, 1000), OutputValue::Array(vec![]));
+        assert_eq!(claim(&mut map, 500), OutputValue::Array(vec![bulk("1-0")]));
+        let range = XpendingRange {
+            min_idle: 0,
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+            count: 10,
+            consumer: Some(b"bob".to_vec()),
+        };
+        assert_eq!(
+            map.xpending(b"s".as_slice(), b"g".to_vec(), Some(range)),
+            OutputValue::Array(vec![OutputValue::Array(vec![
+                bulk("1-0"),
+                bulk("bob"),
+                OutputValue::Integer(0),
+                OutputValue::Integer(1),
+            ])])
+        );
+
+        map.xdel(b"s".as_slice(), vec![id(2, 0)]);
+        now.set(3000);
+        assert_eq!(
+            map.xautoclaim(
+                b"s".as_slice(),
+                b"g".to_vec(),
+                b"carol".to_vec(),
+                1000,
+                StreamId::MIN,
+                XautoclaimOptions {
+                    count: 1,
+                    just_id: true,
+                },
+            ),
+            OutputValue::Array(vec![
+                bulk("2-0"),
+                OutputValue::Array(vec![bulk("1-0")]),
+                OutputValue::Array(vec![]),
+            ])
+        );
+        // deleted entries leave the pending list without counting towards COUNT
+        assert_eq!(
+            map.xautoclaim(
+                b"s".as_slice(),
+                b"g".to_vec(),
+                b"carol".to_vec(),
+                1000,
+                id(2, 0),
+                XautoclaimOptions {
+                    count: 1,
+                    just_id: true,
+                },
+            ),
+            OutputValue::Array(vec![
+                bulk("0-0"),
+                OutputValue::Array(vec![bulk("3-0")]),
+                OutputValue::Array(vec![bulk("2-0")]),
+            ])
+        );
+        assert_eq!(
+            map.xpending(b"s".as_slice(), b"g".to_vec(), None),
+            OutputValue::Array(vec![
+                OutputValue::Integer(2),
+                bulk("1-0"),
+                bulk("3-0"),
+                OutputValue::Array(vec![OutputValue::Array(vec![bulk("carol"), bulk("2")])]),
+            ])
+        );
+    }
+
+    /// The IDs of the entries XREADGROUP returned for its only stream.
+    fn ids_of_read(output: OutputValue) -> Vec<OutputValue> {
+        let OutputValue::Array(mut streams) = output else {
+            panic!("expected an array, got {:?}", output);
+        };
+        let OutputValue::Array(mut stream) = streams.remove(0) else {
+            panic!("expected an array");
+        };
+        ids(stream.remove(1))
+    }
+}
@@ -1,11 +1,424 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::ops::{Bound, Range};
+
+use rand::Rng;
+
+use crate::interface::database::map::LexBound;
+
+use super::dict::Dict;
+
+mod skiplist;
+mod stream;
+
+use skiplist::SkipList;
+pub use stream::{ConsumerGroup, Stream};
 
 type RedisString = Vec<u8>;
 
 #[derive(Clone, Debug)]
 pub enum Value {
-    String(RedisString),
-    Hash(HashMap<RedisString, RedisString>),
+    String(StringValue),
+    Hash(Hash),
     List(VecDeque<RedisString>),
+    Set(Set),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// The encoding OBJECT ENCODING reports, named after the Redis one closest to how the
+    /// value is held.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(s) => s.encoding(),
+            Value::Hash(_) | Value::Set(_) => "hashtable",
+            Value::List(_) => "quicklist",
+            Value::SortedSet(_) => "skiplist",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// The name TYPE gives to the kind of the value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Hash(_) => "hash",
+            Value::List(_) => "list",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+}
+
+/// Strings longer than this are reported as `raw` rather than `embstr`, as in Redis.
+const EMBSTR_MAX_LEN: usize = 44;
+
+/// A string, kept as an integer when it is the canonical form of one so that counters are
+/// updated without parsing or allocating.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StringValue {
+    Int(i64),
+    /// Bytes written as a whole.
+    Bytes(RedisString),
+    /// Bytes modified in place, by APPEND, SETRANGE or the bitmap commands.
+    Raw(RedisString),
+}
+
+/// Parses `bytes` if it is exactly how `i64::to_string` would render the number: no sign
+/// other than `-`, no leading zeros and no `-0`, like Redis's `string2ll`.
+fn parse_canonical_int(bytes: &[u8]) -> Option<i64> {
+    let digits = bytes.strip_prefix(b"-").unwrap_or(bytes);
+    match digits {
+        [b'1'..=b'9', ..] => {}
+        [b'0'] if digits.len() == bytes.len() => {}
+        _ => return None,
+    }
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+impl StringValue {
+    pub fn new(bytes: RedisString) -> Self {
+        match parse_canonical_int(&bytes) {
+            Some(i) => StringValue::Int(i),
+            None => StringValue::Bytes(bytes),
+        }
+    }
+
+    /// The value as an integer, as INCR and DECR read it.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            StringValue::Int(i) => Some(*i),
+            StringValue::Bytes(bytes) | StringValue::Raw(bytes) => parse_canonical_int(bytes),
+        }
+    }
+
+    /// The bytes, unless the value is held as an integer.
+    pub fn bytes(&self) -> Option<&RedisString> {
+        match self {
+            StringValue::Int(_) => None,
+            StringValue::Bytes(bytes) | StringValue::Raw(bytes) => Some(bytes),
+        }
+    }
+
+    /// Renders integers on demand.
+    pub fn as_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            StringValue::Int(i) => Cow::Owned(i.to_string().into_bytes()),
+            StringValue::Bytes(bytes) | StringValue::Raw(bytes) => Cow::Borrowed(bytes),
+        }
+    }
+
+    pub fn into_bytes(self) -> RedisString {
+        match self {
+            StringValue::Int(i) => i.to_string().into_bytes(),
+            StringValue::Bytes(bytes) | StringValue::Raw(bytes) => bytes,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            StringValue::Int(i) => {
+                let digits = i.unsigned_abs().checked_ilog10().unwrap_or(0) as usize + 1;
+                digits + (*i < 0) as usize
+            }
+            StringValue::Bytes(bytes) | StringValue::Raw(bytes) => bytes.len(),
+        }
+    }
+
+    /// Turns the value into raw bytes so that they can be modified in place.
+    pub fn bytes_mut(&mut self) -> &mut RedisString {
+        match self {
+            StringValue::Int(i) => *self = StringValue::Raw(i.to_string().into_bytes()),
+            StringValue::Bytes(bytes) => *self = StringValue::Raw(std::mem::take(bytes)),
+            StringValue::Raw(_) => {}
+        }
+        match self {
+            StringValue::Raw(bytes) => bytes,
+            StringValue::Int(_) | StringValue::Bytes(_) => unreachable!(),
+        }
+    }
+
+    /// Redis allocates short strings along with their object (`embstr`), and has to move
+    /// them out of it (`raw`) before modifying them.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            StringValue::Int(_) => "int",
+            StringValue::Bytes(bytes) if bytes.len() <= EMBSTR_MAX_LEN => "embstr",
+            StringValue::Bytes(_) | StringValue::Raw(_) => "raw",
+        }
+    }
+}
+
+/// A set of distinct members.
+#[derive(Clone, Debug, Default)]
+pub struct Set {
+    members: Dict<RedisString, ()>,
+}
+
+impl Set {
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.members.contains_key(member)
+    }
+
+    /// Adds `member`, returning whether it was missing.
+    pub fn insert(&mut self, member: RedisString) -> bool {
+        self.members.insert(member, ()).is_none()
+    }
+
+    /// Removes `member`, returning whether it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        self.members.remove(member).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RedisString> {
+        self.members.keys()
+    }
+
+    /// A member picked at random, which is fair between buckets rather than between members.
+    pub fn random(&self, rng: &mut impl Rng) -> Option<&RedisString> {
+        self.members.random(rng).map(|(member, _)| member)
+    }
+
+    /// One step of SSCAN; see [`Dict::scan`].
+    pub fn scan<'a>(&'a self, cursor: u64, mut f: impl FnMut(&'a RedisString)) -> u64 {
+        self.members.scan(cursor, |member, _| f(member))
+    }
+}
+
+impl FromIterator<RedisString> for Set {
+    fn from_iter<I: IntoIterator<Item = RedisString>>(iter: I) -> Self {
+        Self {
+            members: iter.into_iter().map(|member| (member, ())).collect(),
+        }
+    }
+}
+
+/// A hash whose fields may expire individually.
+#[derive(Clone, Debug, Default)]
+pub struct Hash {
+    fields: Dict<RedisString, RedisString>,
+    expires: HashMap<RedisString, u64>,
+    /// The same deadlines as `expires`, ordered so that due fields are found without a scan.
+    expiry_order: BTreeSet<(u64, RedisString)>,
+}
+
+impl Hash {
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&RedisString> {
+        self.fields.get(field)
+    }
+
+    /// Modifying a value in place keeps its TTL, as HINCRBY does.
+    pub fn get_mut(&mut self, field: &[u8]) -> Option<&mut RedisString> {
+        self.fields.get_mut(field)
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&RedisString, &RedisString)> {
+        self.fields.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &RedisString> {
+        self.fields.keys()
+    }
+
+    /// One step of HSCAN; see [`Dict::scan`].
+    pub fn scan<'a>(&'a self, cursor: u64, f: impl FnMut(&'a RedisString, &'a RedisString)) -> u64 {
+        self.fields.scan(cursor, f)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &RedisString> {
+        self.fields.values()
+    }
+
+    /// Overwriting a field discards its TTL.
+    pub fn insert(&mut self, field: RedisString, value: RedisString) -> Option<RedisString> {
+        self.persist(&field);
+        self.fields.insert(field, value)
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<RedisString> {
+        self.persist(field);
+        self.fields.remove(field)
+    }
+
+    pub fn expire_at(&self, field: &[u8]) -> Option<u64> {
+        self.expires.get(field).copied()
+    }
+
+    pub fn set_expire_at(&mut self, field: &[u8], at: u64) {
+        debug_assert!(self.fields.contains_key(field));
+        if let Some(old) = self.expires.insert(field.to_vec(), at) {
+            self.expiry_order.remove(&(old, field.to_vec()));
+        }
+        self.expiry_order.insert((at, field.to_vec()));
+    }
+
+    /// Removes the TTL of `field`, returning whether it had one.
+    pub fn persist(&mut self, field: &[u8]) -> bool {
+        match self.expires.remove(field) {
+            Some(at) => {
+                self.expiry_order.remove(&(at, field.to_vec()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn has_volatile_fields(&self) -> bool {
+        !self.expires.is_empty()
+    }
+
+    /// Drops every field whose deadline is at or before `now`, returning how many were dropped.
+    pub fn remove_expired(&mut self, now: u64) -> usize {
+        let mut removed = 0;
+        while let Some((at, _)) = self.expiry_order.first() {
+            if *at > now {
+                break;
+            }
+            let (_, field) = self.expiry_order.pop_first().unwrap();
+            self.expires.remove(&field);
+            self.fields.remove(&field);
+            removed += 1;
+        }
+        removed
+    }
+}
+
+/// Members ordered by score then by member, with an index from member to score.
+#[derive(Clone, Debug, Default)]
+pub struct SortedSet {
+    scores: Dict<RedisString, f64>,
+    order: SkipList,
+}
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Adds `member` or updates its score, returning the previous score.
+    pub fn insert(&mut self, member: RedisString, score: f64) -> Option<f64> {
+        debug_assert!(!score.is_nan());
+        let old = self.scores.insert(member.clone(), score);
+        if let Some(old) = old {
+            self.order.remove(old, &member);
+        }
+        self.order.insert(member, score);
+        old
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.order.remove(score, member);
+        Some(score)
+    }
+
+    /// The 0-based position of `member` in ascending order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        self.order.rank(self.score(member)?, member)
+    }
+
+    pub fn iter(&self) -> skiplist::Iter<'_> {
+        self.order.range(0..self.len())
+    }
+
+    /// The members whose 0-based positions in ascending order lie in `ranks`.
+    pub fn range(&self, ranks: Range<usize>) -> skiplist::Iter<'_> {
+        self.order.range(ranks)
+    }
+
+    /// The positions of the members whose score lies between `min` and `max`.
+    pub fn ranks_by_score(&self, min: Bound<f64>, max: Bound<f64>) -> Range<usize> {
+        let start = self.order.count_while(|_, score| match min {
+            Bound::Included(min) => score < min,
+            Bound::Excluded(min) => score <= min,
+            Bound::Unbounded => false,
+        });
+        let end = self.order.count_while(|_, score| match max {
+            Bound::Included(max) => score <= max,
+            Bound::Excluded(max) => score < max,
+            Bound::Unbounded => true,
+        });
+        start..end.max(start)
+    }
+
+    /// The positions of the members between `min` and `max`. As in Redis, this assumes that
+    /// all members share the same score.
+    pub fn ranks_by_lex(&self, min: &LexBound, max: &LexBound) -> Range<usize> {
+        let start = self.order.count_while(|member, _| match min {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Included(min) => member < min.as_slice(),
+            LexBound::Excluded(min) => member <= min.as_slice(),
+        });
+        let end = self.order.count_while(|member, _| match max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Included(max) => member <= max.as_slice(),
+            LexBound::Excluded(max) => member < max.as_slice(),
+        });
+        start..end.max(start)
+    }
+
+    /// Members whose score lies between `min` and `max`, in ascending order.
+    pub fn range_by_score(&self, min: Bound<f64>, max: Bound<f64>) -> skiplist::Iter<'_> {
+        self.range(self.ranks_by_score(min, max))
+    }
+
+    /// Removes the members whose positions lie in `ranks`, returning how many there were.
+    pub fn remove_range(&mut self, ranks: Range<usize>) -> usize {
+        let members: Vec<_> = self.range(ranks).map(|(m, _)| m.clone()).collect();
+        for member in members.iter() {
+            self.remove(member);
+        }
+        members.len()
+    }
+
+    pub fn pop_first(&mut self) -> Option<(RedisString, f64)> {
+        let member = self.order.first()?.0.clone();
+        let score = self.remove(&member)?;
+        Some((member, score))
+    }
+
+    pub fn pop_last(&mut self) -> Option<(RedisString, f64)> {
+        let member = self.order.last()?.0.clone();
+        let score = self.remove(&member)?;
+        Some((member, score))
+    }
+
+    /// One step of ZSCAN; see [`Dict::scan`].
+    pub fn scan<'a>(&'a self, cursor: u64, mut f: impl FnMut(&'a RedisString, f64)) -> u64 {
+        self.scores.scan(cursor, |member, score| f(member, *score))
+    }
 }
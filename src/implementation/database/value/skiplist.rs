@@ -0,0 +1,393 @@
+//! The order of a sorted set's members, kept as Redis's `zskiplist`.
+//!
+//! Members are ordered by score, then by member. Every link records how many nodes it skips,
+//! so that the rank of a member and the member at a rank are both found in O(log n), as are
+//! the bounds of a range of scores or members.
+
+use std::hash::{BuildHasher, Hasher};
+use std::ops::Range;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use super::RedisString;
+
+/// As in Redis, enough for 2^64 members.
+const MAX_LEVEL: usize = 32;
+
+/// The chance that a node on one level is also linked on the next.
+const LEVEL_UP: f64 = 0.25;
+
+/// The index of the head node, which holds no member.
+const HEAD: usize = 0;
+
+#[derive(Clone, Copy, Debug)]
+struct Link {
+    next: Option<usize>,
+    /// How many nodes following this link moves forward by.
+    span: usize,
+}
+
+const UNLINKED: Link = Link {
+    next: None,
+    span: 0,
+};
+
+#[derive(Clone, Debug)]
+struct Node {
+    member: RedisString,
+    score: f64,
+    prev: Option<usize>,
+    links: Vec<Link>,
+}
+
+impl Node {
+    /// Whether this node sorts before `(score, member)`.
+    fn before(&self, score: f64, member: &[u8]) -> bool {
+        self.score < score || (self.score == score && self.member.as_slice() < member)
+    }
+}
+
+/// The nodes live in an arena and refer to each other by index; removed ones are reused.
+#[derive(Clone, Debug)]
+pub struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    /// How many levels are in use.
+    level: usize,
+    len: usize,
+    tail: Option<usize>,
+    rng: SmallRng,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        // `RandomState` is seeded by the OS, so levels can't be predicted from the members
+        let seed = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        let head = Node {
+            member: RedisString::new(),
+            score: 0.0,
+            prev: None,
+            links: vec![UNLINKED; MAX_LEVEL],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: vec![],
+            level: 1,
+            len: 0,
+            tail: None,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl SkipList {
+    fn random_level(&mut self) -> usize {
+        let mut level = 1;
+        while level < MAX_LEVEL && self.rng.gen_bool(LEVEL_UP) {
+            level += 1;
+        }
+        level
+    }
+
+    /// For every level in use, the last node before `(score, member)` and its rank, counting
+    /// the head as 0.
+    fn path(&self, score: f64, member: &[u8]) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].links[i].next {
+                if !self.nodes[next].before(score, member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].links[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        (update, rank)
+    }
+
+    /// How many nodes `before` holds for, walking from the first one until it fails. This is
+    /// the number of leading nodes when `before` holds for a prefix of the list.
+    pub fn count_while(&self, before: impl Fn(&[u8], f64) -> bool) -> usize {
+        let mut x = HEAD;
+        let mut rank = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[i].next {
+                let node = &self.nodes[next];
+                if !before(&node.member, node.score) {
+                    break;
+                }
+                rank += self.nodes[x].links[i].span;
+                x = next;
+            }
+        }
+        rank
+    }
+
+    /// The 0-based rank of `member`, which has `score` if it is present.
+    pub fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let (update, rank) = self.path(score, member);
+        let next = self.nodes[update[0]].links[0].next?;
+        let node = &self.nodes[next];
+        (node.score == score && node.member == member).then_some(rank[0])
+    }
+
+    /// The node at the 0-based `rank`.
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[i].next {
+                if traversed + self.nodes[x].links[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].links[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    /// Adds `member`, which must not be present yet.
+    pub fn insert(&mut self, member: RedisString, score: f64) {
+        let (mut update, mut rank) = self.path(score, &member);
+        let level = self.random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].links[i].span = self.len;
+            }
+            self.level = level;
+        }
+        let node = Node {
+            member,
+            score,
+            prev: (update[0] != HEAD).then_some(update[0]),
+            links: vec![UNLINKED; level],
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for i in 0..level {
+            let before = self.nodes[update[i]].links[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[id].links[i] = Link {
+                next: before.next,
+                span: before.span - skipped,
+            };
+            self.nodes[update[i]].links[i] = Link {
+                next: Some(id),
+                span: skipped + 1,
+            };
+        }
+        for (i, &x) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[x].links[i].span += 1;
+        }
+        match self.nodes[id].links[0].next {
+            Some(next) => self.nodes[next].prev = Some(id),
+            None => self.tail = Some(id),
+        }
+        self.len += 1;
+    }
+
+    /// Removes `member`, which has `score` if it is present, returning whether it was.
+    pub fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let (update, _) = self.path(score, member);
+        let Some(id) = self.nodes[update[0]].links[0].next else {
+            return false;
+        };
+        if self.nodes[id].score != score || self.nodes[id].member != member {
+            return false;
+        }
+        for (i, &x) in update.iter().enumerate().take(self.level) {
+            let removed = self.nodes[id].links.get(i).copied();
+            let link = &mut self.nodes[x].links[i];
+            match removed {
+                Some(removed) if link.next == Some(id) => {
+                    // the last node on a level may have a span of 0
+                    link.span = link.span - 1 + removed.span;
+                    link.next = removed.next;
+                }
+                _ => link.span -= 1,
+            }
+        }
+        let prev = self.nodes[id].prev;
+        match self.nodes[id].links[0].next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => self.tail = prev,
+        }
+        while self.level > 1 && self.nodes[HEAD].links[self.level - 1].next.is_none() {
+            self.level -= 1;
+        }
+        let node = &mut self.nodes[id];
+        node.member = RedisString::new();
+        node.links = vec![];
+        self.free.push(id);
+        self.len -= 1;
+        true
+    }
+
+    pub fn first(&self) -> Option<(&RedisString, f64)> {
+        let node = &self.nodes[self.nodes[HEAD].links[0].next?];
+        Some((&node.member, node.score))
+    }
+
+    pub fn last(&self) -> Option<(&RedisString, f64)> {
+        let node = &self.nodes[self.tail?];
+        Some((&node.member, node.score))
+    }
+
+    /// The members whose 0-based ranks lie in `ranks`, in ascending order.
+    pub fn range(&self, ranks: Range<usize>) -> Iter<'_> {
+        let end = ranks.end.min(self.len);
+        if ranks.start >= end {
+            return Iter {
+                list: self,
+                front: None,
+                back: None,
+                len: 0,
+            };
+        }
+        Iter {
+            list: self,
+            front: self.node_at(ranks.start),
+            back: self.node_at(end - 1),
+            len: end - ranks.start,
+        }
+    }
+}
+
+pub struct Iter<'a> {
+    list: &'a SkipList,
+    front: Option<usize>,
+    back: Option<usize>,
+    len: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a RedisString, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let node = &self.list.nodes[self.front?];
+        self.front = node.links[0].next;
+        self.len -= 1;
+        Some((&node.member, node.score))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let node = &self.list.nodes[self.back?];
+        self.back = node.prev;
+        self.len -= 1;
+        Some((&node.member, node.score))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks every span and back link against a walk of the bottom level.
+    fn check(list: &SkipList, expected: &[(RedisString, f64)]) {
+        let members: Vec<_> = list
+            .range(0..usize::MAX)
+            .map(|(m, s)| (m.clone(), s))
+            .collect();
+        assert_eq!(members, expected);
+        let reversed: Vec<_> = list
+            .range(0..usize::MAX)
+            .rev()
+            .map(|(m, _)| m.clone())
+            .collect();
+        assert!(reversed.iter().eq(expected.iter().rev().map(|(m, _)| m)));
+        for (rank, (member, score)) in expected.iter().enumerate() {
+            assert_eq!(list.rank(*score, member), Some(rank));
+            assert_eq!(list.range(rank..rank + 1).next(), Some((member, *score)));
+        }
+        assert_eq!(list.len, expected.len());
+        assert_eq!(list.first(), expected.first().map(|(m, s)| (m, *s)));
+        assert_eq!(list.last(), expected.last().map(|(m, s)| (m, *s)));
+    }
+
+    #[test]
+    fn test_against_sorted_vec() {
+        let mut list = SkipList::default();
+        let mut expected: Vec<(RedisString, f64)> = vec![];
+        let mut rng = SmallRng::seed_from_u64(0);
+        for round in 0..2000 {
+            let member = format!("m{}", rng.gen_range(0..300)).into_bytes();
+            let score = rng.gen_range(0..20) as f64;
+            match expected.iter().position(|(m, _)| *m == member) {
+                Some(i) => {
+                    let (member, score) = expected.remove(i);
+                    assert!(!list.remove(score + 1.0, &member));
+                    assert!(list.remove(score, &member));
+                }
+                None => {
+                    assert_eq!(list.rank(score, &member), None);
+                    list.insert(member.clone(), score);
+                    expected.push((member, score));
+                    expected.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+                }
+            }
+            if round % 100 == 0 {
+                check(&list, &expected);
+            }
+        }
+        check(&list, &expected);
+        let below_ten = expected.iter().filter(|(_, s)| *s < 10.0).count();
+        assert_eq!(list.count_while(|_, s| s < 10.0), below_ten);
+        while let Some((member, score)) = expected.pop() {
+            assert!(list.remove(score, &member));
+        }
+        check(&list, &[]);
+        assert_eq!(list.level, 1);
+    }
+
+    #[test]
+    fn test_range() {
+        let mut list = SkipList::default();
+        for (i, member) in [b"a", b"b", b"c", b"d"].into_iter().enumerate() {
+            list.insert(member.to_vec(), i as f64);
+        }
+        fn members<'a>(iter: impl Iterator<Item = (&'a RedisString, f64)>) -> Vec<RedisString> {
+            iter.map(|(m, _)| m.clone()).collect()
+        }
+        assert_eq!(members(list.range(1..3)), [b"b", b"c"]);
+        assert_eq!(members(list.range(1..3).rev()), [b"c", b"b"]);
+        assert_eq!(list.range(2..100).len(), 2);
+        assert_eq!(list.range(3..3).len(), 0);
+        assert_eq!(list.range(4..5).next(), None);
+    }
+}
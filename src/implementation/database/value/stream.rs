@@ -0,0 +1,397 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+use crate::interface::database::map::{StreamId, StreamTrim, StreamTrimStrategy, XaddId};
+
+use super::RedisString;
+
+/// Redis keeps stream entries in radix tree nodes of up to `stream-node-max-entries`
+/// entries; approximate trimming only drops whole nodes, which we emulate by counting.
+pub const STREAM_NODE_MAX_ENTRIES: usize = 100;
+
+/// Turns reversed bounds, on which `BTreeMap::range` panics, into an empty range.
+fn ordered_bounds(
+    start: Bound<StreamId>,
+    end: Bound<StreamId>,
+) -> (Bound<StreamId>, Bound<StreamId>) {
+    use Bound::{Excluded, Included};
+    let reversed = match (&start, &end) {
+        (Included(s), Included(e)) => s > e,
+        (Included(s) | Excluded(s), Included(e) | Excluded(e)) => s >= e,
+        _ => false,
+    };
+    if reversed {
+        (Included(StreamId::MIN), Excluded(StreamId::MIN))
+    } else {
+        (start, end)
+    }
+}
+
+/// An append-only log of entries ordered by ID, each holding flattened field/value pairs.
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Vec<RedisString>>,
+    /// Never decreases, even when the newest entries are deleted.
+    last_id: StreamId,
+    max_deleted_id: StreamId,
+    entries_added: u64,
+    groups: BTreeMap<RedisString, ConsumerGroup>,
+}
+
+/// An entry delivered to a consumer and not acknowledged yet.
+#[derive(Clone, Debug)]
+pub struct PendingEntry {
+    pub consumer: RedisString,
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Consumer {
+    pub seen_time: u64,
+    /// When the consumer last read or claimed an entry, if ever.
+    pub active_time: Option<u64>,
+    pending: BTreeSet<StreamId>,
+}
+
+impl Consumer {
+    fn new(now: u64) -> Self {
+        Self {
+            seen_time: now,
+            active_time: None,
+            pending: BTreeSet::new(),
+        }
+    }
+
+    pub fn pending(&self) -> &BTreeSet<StreamId> {
+        &self.pending
+    }
+}
+
+/// A consumer group with its pending entries list, which is also indexed per consumer.
+#[derive(Clone, Debug)]
+pub struct ConsumerGroup {
+    last_delivered_id: StreamId,
+    /// How many entries the group has read, or `None` when that can no longer be told,
+    /// e.g. after a SETID to an arbitrary ID.
+    entries_read: Option<u64>,
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<RedisString, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered_id: StreamId, entries_read: Option<u64>) -> Self {
+        Self {
+            last_delivered_id,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    pub fn last_delivered_id(&self) -> StreamId {
+        self.last_delivered_id
+    }
+
+    pub fn entries_read(&self) -> Option<u64> {
+        self.entries_read
+    }
+
+    pub fn set_last_delivered_id(&mut self, id: StreamId, entries_read: Option<u64>) {
+        self.last_delivered_id = id;
+        self.entries_read = entries_read;
+    }
+
+    pub fn pending(&self) -> &BTreeMap<StreamId, PendingEntry> {
+        &self.pending
+    }
+
+    pub fn consumers(&self) -> &BTreeMap<RedisString, Consumer> {
+        &self.consumers
+    }
+
+    /// Returns `consumer`, creating it if needed, and marks it as seen at `now`.
+    pub fn touch_consumer(&mut self, consumer: &[u8], now: u64) -> &mut Consumer {
+        let consumer = self
+            .consumers
+            .entry(consumer.to_vec())
+            .or_insert_with(|| Consumer::new(now));
+        consumer.seen_time = now;
+        consumer
+    }
+
+    pub fn create_consumer(&mut self, consumer: &[u8], now: u64) -> bool {
+        if self.consumers.contains_key(consumer) {
+            return false;
+        }
+        self.consumers.insert(consumer.to_vec(), Consumer::new(now));
+        true
+    }
+
+    /// Returns how many entries the consumer had pending; those are dropped with it.
+    pub fn delete_consumer(&mut self, consumer: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(consumer)?;
+        for id in consumer.pending.iter() {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// Makes `id` pending for `consumer`, which must exist, taking it from its previous owner.
+    pub fn assign(
+        &mut self,
+        id: StreamId,
+        consumer: &[u8],
+        delivery_time: u64,
+        delivery_count: u64,
+    ) {
+        if let Some(previous) = self.pending.get(&id) {
+            if previous.consumer != consumer {
+                if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                    owner.pending.remove(&id);
+                }
+            }
+        }
+        self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.to_vec(),
+                delivery_time,
+                delivery_count,
+            },
+        );
+        self.consumers
+            .get_mut(consumer)
+            .expect("the consumer should have been touched first")
+            .pending
+            .insert(id);
+    }
+
+    pub fn pending_range(
+        &self,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &PendingEntry)> + '_ {
+        self.pending.range(ordered_bounds(start, end))
+    }
+
+    /// Records another delivery of a pending entry, as re-reading a consumer's history does.
+    pub fn redeliver(&mut self, id: &StreamId, now: u64) {
+        if let Some(entry) = self.pending.get_mut(id) {
+            entry.delivery_time = now;
+            entry.delivery_count += 1;
+        }
+    }
+
+    pub fn ack(&mut self, id: &StreamId) -> bool {
+        let Some(entry) = self.pending.remove(id) else {
+            return false;
+        };
+        if let Some(owner) = self.consumers.get_mut(&entry.consumer) {
+            owner.pending.remove(id);
+        }
+        true
+    }
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    /// The number of radix tree nodes Redis would use for the current entries.
+    pub fn node_count(&self) -> usize {
+        self.len().div_ceil(STREAM_NODE_MAX_ENTRIES)
+    }
+
+    pub fn first_entry(&self) -> Option<(&StreamId, &Vec<RedisString>)> {
+        self.entries.first_key_value()
+    }
+
+    pub fn last_entry(&self) -> Option<(&StreamId, &Vec<RedisString>)> {
+        self.entries.last_key_value()
+    }
+
+    /// Resolves the ID of a new entry added at `now`, or `None` if it would not be greater
+    /// than the last generated one.
+    pub fn next_id(&self, id: XaddId, now: u64) -> Option<StreamId> {
+        let last = self.last_id;
+        let after_last = |ms: u64| match last.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms, seq }),
+            None => ms.checked_add(1).map(|ms| StreamId { ms, seq: 0 }),
+        };
+        match id {
+            XaddId::Auto if now > last.ms => Some(StreamId { ms: now, seq: 0 }),
+            XaddId::Auto => after_last(last.ms),
+            XaddId::AutoSequence(ms) if ms > last.ms => Some(StreamId { ms, seq: 0 }),
+            XaddId::AutoSequence(ms) if ms == last.ms => {
+                last.seq.checked_add(1).map(|seq| StreamId { ms, seq })
+            }
+            XaddId::AutoSequence(_) => None,
+            XaddId::Explicit(id) => (id > last).then_some(id),
+        }
+    }
+
+    /// `id` must come from `next_id`.
+    pub fn insert(&mut self, id: StreamId, fields: Vec<RedisString>) {
+        debug_assert!(id > self.last_id);
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    pub fn remove(&mut self, id: &StreamId) -> bool {
+        if self.entries.remove(id).is_none() {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(*id);
+        true
+    }
+
+    pub fn get(&self, id: &StreamId) -> Option<&Vec<RedisString>> {
+        self.entries.get(id)
+    }
+
+    pub fn groups(&self) -> &BTreeMap<RedisString, ConsumerGroup> {
+        &self.groups
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    pub fn create_group(&mut self, name: &[u8], group: ConsumerGroup) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        self.groups.insert(name.to_vec(), group);
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Hands the entries following the group's last delivered one to `consumer`, as
+    /// XREADGROUP with `>` does, or returns `None` if there is no such group.
+    pub fn read_group(
+        &mut self,
+        name: &[u8],
+        consumer: &[u8],
+        count: usize,
+        no_ack: bool,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Vec<RedisString>)>> {
+        let group = self.groups.get(name)?;
+        let delivered: Vec<_> = self
+            .entries
+            .range((Bound::Excluded(group.last_delivered_id), Bound::Unbounded))
+            .take(count)
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect();
+        let mut entries_read = group.entries_read;
+        for (id, _) in delivered.iter() {
+            entries_read = match entries_read {
+                Some(n) if !self.has_tombstones_from(id) => Some(n + 1),
+                _ => self.estimate_entries_read(id),
+            };
+        }
+        let group = self.groups.get_mut(name).unwrap();
+        let consumer_state = group.touch_consumer(consumer, now);
+        if let Some((last, _)) = delivered.last() {
+            consumer_state.active_time = Some(now);
+            group.set_last_delivered_id(*last, entries_read);
+        }
+        if !no_ack {
+            for (id, _) in delivered.iter() {
+                group.assign(*id, consumer, now, 1);
+            }
+        }
+        Some(delivered)
+    }
+
+    /// How many entries the group has yet to read, if that can be told.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(n) if !self.has_tombstones_from(&group.last_delivered_id) => Some(n),
+            _ => self.estimate_entries_read(&group.last_delivered_id),
+        };
+        entries_read.map(|n| self.entries_added.saturating_sub(n))
+    }
+
+    /// Whether an entry at or after `id` was deleted, which makes read counters unreliable.
+    fn has_tombstones_from(&self, id: &StreamId) -> bool {
+        !self.entries.is_empty()
+            && self.max_deleted_id != StreamId::MIN
+            && *id <= self.max_deleted_id
+    }
+
+    /// Works out how many entries were added up to `id`, when no deletion gets in the way.
+    fn estimate_entries_read(&self, id: &StreamId) -> Option<u64> {
+        if self.entries_added == 0 || (self.entries.is_empty() && *id <= self.last_id) {
+            return Some(self.entries_added);
+        }
+        match id.cmp(&self.last_id) {
+            Ordering::Equal => return Some(self.entries_added),
+            Ordering::Greater => return None,
+            Ordering::Less => {}
+        }
+        let first_id = self.entries.keys().next().copied().unwrap_or_default();
+        if self.max_deleted_id != StreamId::MIN && self.max_deleted_id >= first_id {
+            return None;
+        }
+        let before_first = self.entries_added - self.len() as u64;
+        match id.cmp(&first_id) {
+            Ordering::Less => Some(before_first),
+            Ordering::Equal => Some(before_first + 1),
+            Ordering::Greater => None,
+        }
+    }
+
+    pub fn range(
+        &self,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &Vec<RedisString>)> + '_ {
+        self.entries.range(ordered_bounds(start, end))
+    }
+
+    /// Removes entries from the head as XTRIM does, returning how many were removed.
+    pub fn trim(&mut self, trim: &StreamTrim) -> usize {
+        let excess = match trim.strategy {
+            StreamTrimStrategy::MaxLen(max_len) => self.len().saturating_sub(max_len),
+            StreamTrimStrategy::MinId(min_id) => self.entries.range(..min_id).count(),
+        };
+        let count = if trim.approximate {
+            let limit = trim.limit.unwrap_or(100 * STREAM_NODE_MAX_ENTRIES);
+            let count = if limit == 0 {
+                excess
+            } else {
+                excess.min(limit)
+            };
+            count - count % STREAM_NODE_MAX_ENTRIES
+        } else {
+            excess
+        };
+        for _ in 0..count {
+            self.entries.pop_first();
+        }
+        count
+    }
+}
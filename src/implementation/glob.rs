@@ -12,8 +12,17 @@ enum Node {
 }
 
 impl Node {
-    const fn is_star(&self) -> bool {
-        matches!(self, Node::Star)
+    /// How many bytes at the start of `input` a node other than a star matches, if it does.
+    fn match_len(&self, input: &[u8]) -> Option<usize> {
+        let first = input.first();
+        let matched = match self {
+            Node::Star => unreachable!("stars are handled by the matcher"),
+            Node::Chars(pat) => return input.starts_with(pat).then_some(pat.len()),
+            Node::Question => first.is_some(),
+            Node::Pat(pat) => first.is_some_and(|ch| pat.iter().any(|el| el.contains(*ch))),
+            Node::NoPat(pat) => first.is_some_and(|ch| !pat.iter().any(|el| el.contains(*ch))),
+        };
+        matched.then_some(1)
     }
 }
 
@@ -23,6 +32,15 @@ enum PatElement {
     Range(RangeInclusive<u8>),
 }
 
+impl PatElement {
+    fn contains(&self, ch: u8) -> bool {
+        match self {
+            PatElement::Char(c) => *c == ch,
+            PatElement::Range(range) => range.contains(&ch),
+        }
+    }
+}
+
 #[allow(private_interfaces)]
 #[derive(Debug)]
 pub enum Finder {
@@ -54,25 +72,14 @@ impl Finder {
             }
         }
 
-        if nodes
-            .iter()
-            .all(|node| matches!(node, Node::Question | Node::Star))
-        {
-            let qs = nodes
-                .iter()
-                .filter(|node| matches!(node, Node::Question))
-                .count();
-            if qs == 0 {
-            } else {
-                return Finder::AllMatchWithLen(qs);
-            }
+        if nodes.iter().all(|node| matches!(node, Node::Question)) {
+            return Finder::AllMatchWithLen(nodes.len());
         }
 
         Finder::RequiresMatch(nodes)
     }
 
     pub fn it_matches(&self, input: &[u8]) -> bool {
-        debug_assert!(!input.is_empty());
         match self {
             Finder::NoMatch => false,
             Finder::AllMatch => true,
@@ -83,114 +90,40 @@ impl Finder {
         }
     }
 
+    /// Matches the nodes one after the other, and on a mismatch lets the last star seen take
+    /// one more byte and resumes after it. Earlier stars never need to take more, so this is
+    /// linear in the pattern for every byte of input, without recursion, where trying every
+    /// split at every star would be exponential.
     fn run_node(&self, input: &[u8], nodes: &[Node]) -> bool {
-        let mut node_it = nodes.iter();
-        let mut current_node = node_it.next();
-        let mut skip = false;
-
-        let mut pos = 0;
+        let (mut node, mut pos) = (0, 0);
+        // the node after the last star, and where in the input it is tried from
+        let mut last_star: Option<(usize, usize)> = None;
         loop {
-            let Some(node) = current_node else {
-                break;
-            };
-
-            if node.is_star() {
-                skip = true;
-                current_node = node_it.next();
-                continue;
-            }
-
-            'skipping: loop {
-                if pos >= input.len() {
-                    return false;
+            match nodes.get(node) {
+                Some(Node::Star) => {
+                    last_star = Some((node + 1, pos));
+                    node += 1;
+                    continue;
                 }
-
-                match node {
-                    Node::Star => unreachable!(),
-                    Node::Chars(pat) => {
-                        let Some(slice) = input.get(pos..pat.len()) else {
-                            return false;
-                        };
-                        if slice == pat {
-                            // OK
-                            pos += pat.len();
-                            current_node = node_it.next();
-                            break 'skipping;
-                        } else {
-                            if !skip {
-                                return false;
-                            }
-                            pos += 1;
-                        }
-                    }
-                    Node::Question => {
-                        // always OK
-                        pos += 1;
-                        current_node = node_it.next();
-                        break 'skipping;
-                    }
-                    Node::Pat(pat) => {
-                        let mut ok = false;
-                        for el in pat {
-                            match el {
-                                PatElement::Char(c) => {
-                                    if input[pos] == *c {
-                                        ok = true;
-                                        break;
-                                    }
-                                }
-                                PatElement::Range(range) => {
-                                    if range.contains(&input[pos]) {
-                                        ok = true;
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                        if ok {
-                            // OK
-                            pos += 1;
-                            current_node = node_it.next();
-                            break 'skipping;
-                        } else if skip {
-                            return false;
-                        }
-                    }
-
-                    Node::NoPat(pat) => {
-                        let mut ok = false;
-                        for el in pat {
-                            match el {
-                                PatElement::Char(c) => {
-                                    if input[pos] == *c {
-                                        ok = true;
-                                        break;
-                                    }
-                                }
-                                PatElement::Range(range) => {
-                                    if range.contains(&input[pos]) {
-                                        ok = true;
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                        if ok {
-                            // OK
-                            pos += 1;
-                            current_node = node_it.next();
-                            break 'skipping;
-                        } else if skip {
-                            return false;
-                        }
+                Some(n) => {
+                    if let Some(len) = n.match_len(&input[pos..]) {
+                        node += 1;
+                        pos += len;
+                        continue;
                     }
                 }
+                None if pos == input.len() => return true,
+                None => {}
+            }
+            match last_star {
+                Some((after_star, from)) if from < input.len() => {
+                    last_star = Some((after_star, from + 1));
+                    node = after_star;
+                    pos = from + 1;
+                }
+                _ => return false,
             }
-
-            skip = false;
         }
-
-        skip || current_node.is_none() && pos == input.len()
     }
 }
 
@@ -269,7 +202,7 @@ fn compile_pattern(pattern: &[u8]) -> Option<Vec<Node>> {
                 }
             }
             b'\\' => {
-                const SPECIALS: &[u8] = &[b'*', b'?', b'['];
+                const SPECIALS: &[u8] = b"*?[";
                 match it.next() {
                     Some(ch) if SPECIALS.contains(ch) => buffer.push(*ch),
                     Some(ch) => buffer.extend([b'\\', *ch]),
@@ -281,38 +214,17 @@ fn compile_pattern(pattern: &[u8]) -> Option<Vec<Node>> {
     }
 }
 
+/// Merges runs of stars, and of plain chars, into single nodes.
 fn optimise_nodes(nodes: Vec<Node>) -> Vec<Node> {
-    loop {
-        if nodes.len() <= 1 {
-            return nodes;
-        }
-        let mut changed = false;
-        let mut new_nodes = Vec::new();
-        let mut i = 0;
-        while i < nodes.len() - 1 {
-            match (&nodes[i], &nodes[i + 1]) {
-                (Node::Star, Node::Star) => {
-                    new_nodes.push(Node::Star);
-                    changed = true;
-                    i += 2;
-                }
-                (Node::Chars(v1), Node::Chars(v2)) => {
-                    let new_chars = v1.iter().chain(v2.iter()).cloned().collect();
-                    new_nodes.push(Node::Chars(new_chars));
-                    changed = true;
-                    i += 2;
-                }
-                _ => {
-                    new_nodes.push(nodes[i].clone());
-                    i += 1;
-                }
-            }
-        }
-
-        if !changed {
-            return nodes;
+    let mut optimised: Vec<Node> = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match (optimised.last_mut(), node) {
+            (Some(Node::Star), Node::Star) => {}
+            (Some(Node::Chars(v1)), Node::Chars(v2)) => v1.extend(v2),
+            (_, node) => optimised.push(node),
         }
     }
+    optimised
 }
 
 fn compile_bracket(inner: impl Iterator<Item = u8>) -> Option<Node> {
@@ -398,6 +310,41 @@ mod test {
         assert!(helper(b"*a*", b"ab"));
     }
 
+    #[test]
+    fn test_backtracking() {
+        assert!(helper(b"*a", b"aa"));
+        assert!(helper(b"a*b*c", b"abxbc"));
+        assert!(helper(b"**", b"anything"));
+        assert!(helper(b"h?llo*", b"hello world"));
+        assert!(!helper(b"??*", b"a"));
+        assert!(helper(b"??*", b"abc"));
+        assert!(helper(b"*", b""));
+        assert!(!helper(b"?", b""));
+    }
+
+    #[test]
+    fn test_brackets() {
+        assert!(helper(b"h[ae]llo", b"hallo"));
+        assert!(!helper(b"h[ae]llo", b"hillo"));
+        assert!(helper(b"h[^e]llo", b"hallo"));
+        assert!(!helper(b"h[^e]llo", b"hello"));
+        assert!(helper(b"h[a-c]llo", b"hbllo"));
+        assert!(!helper(b"[a-c]*", b"x"));
+        assert!(helper(b"*[0-9]", b"key:7"));
+    }
+
+    #[test]
+    fn test_pathological_patterns() {
+        // exponential with a matcher that tries every split at every star
+        let input = vec![b'a'; 10_000];
+        assert!(!helper(b"*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b", &input));
+        assert!(helper(b"*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a", &input));
+        assert!(!helper(&b"a*".repeat(5_000), &input[..4_999]));
+        assert!(helper(&b"a*".repeat(5_000), &input));
+        // deep enough to overflow the stack of a recursive matcher
+        assert!(helper(&b"?*".repeat(50_000), &vec![b'x'; 100_000]));
+    }
+
     #[test]
     fn test_escape() {
         assert!(helper(b"a\\*", b"a*"));
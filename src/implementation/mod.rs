@@ -1,11 +1,16 @@
 use std::cell::RefCell;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
 
 use acl::AclCategory;
+use blocking::{BlockedClient, BlockedClients, Blocking};
 use command::ControllerCommand;
 use smol::net::SocketAddr;
 
 mod acl;
+mod blocking;
 mod command;
+mod config;
 mod connection;
 pub mod database;
 mod glob;
@@ -19,19 +24,33 @@ use crate::interface::database::IDatabase;
 use crate::interface::database::IDatabaseWithInner;
 use crate::interface::types::InputValue;
 use crate::interface::types::OutputValue;
+use crate::interface::types::Reply;
 use crate::interface::IController;
 use crate::interface::UseController;
 use crate::interface::UseControllerWithDb;
 
 use command::{Command, CommandStore};
+use config::Config;
 use connection::ConnectionStore;
 use database::Database;
 
+/// What INFO reports about the active expire cycle, besides the number of expired keys.
+#[derive(Debug, Default)]
+struct ExpireStats {
+    /// A running estimate of the share of keys with a TTL that have expired, in percent.
+    stale_perc: f64,
+    /// How many cycles were cut short by their time budget.
+    time_cap_reached_count: u64,
+}
+
 #[derive(Debug)]
 pub struct Controller<I: 'static + Default> {
     db: RefCell<Database<I>>,
     cons: ConnectionStore,
     commands: CommandStore<I>,
+    blocked: BlockedClients,
+    config: Config,
+    expire_stats: ExpireStats,
 }
 
 pub enum Interrupt {
@@ -40,6 +59,9 @@ pub enum Interrupt {
     ClientId,
     CommandCount,
     CommandList(command::CommandListFilter),
+    ConfigGet(Vec<InputValue>),
+    ConfigSet(Vec<InputValue>),
+    Info(Vec<InputValue>),
     Select(usize),
     SwapDb(usize, usize),
     FlushAll,
@@ -75,6 +97,78 @@ impl<I: Default + MapAllCommands> Controller<I> {
         })
     }
 
+    /// Retries the commands of clients blocked on `keys` in `db_index`, and on those the
+    /// database signalled since, and replies to those that can now be served. Clients on a
+    /// key are retried in the order they blocked, and keys signalled by the retries (such as
+    /// BLMOVE's destination) are served in turn, as Redis's `handleClientsBlockedOnKeys`.
+    fn serve_blocked(&mut self, db_index: usize, mut keys: Vec<Vec<u8>>) {
+        loop {
+            keys.extend(self.db.borrow_mut().get_mut(db_index).take_ready_keys());
+            if keys.is_empty() || self.blocked.is_empty() {
+                return;
+            }
+            let mut seen = HashSet::new();
+            for key in std::mem::take(&mut keys) {
+                if !seen.insert(key.clone()) {
+                    continue;
+                }
+                for con_id in self.blocked.waiting_on(db_index, &key) {
+                    // may have been served through another key already
+                    let Some(client) = self.blocked.get(&con_id) else {
+                        continue;
+                    };
+                    let command = self
+                        .commands
+                        .blocking_commands
+                        .get(client.name.as_str())
+                        .expect("only blocking commands can block");
+                    let res = {
+                        let mut borrowed = self.db.borrow_mut();
+                        let db = borrowed.get_mut(db_index);
+                        command.execute(client.name.as_str(), db, &client.input)
+                    };
+                    if let Blocking::Ready(v) = res {
+                        let client = self.blocked.unblock(&con_id).unwrap();
+                        // the receiver is gone only if the client is going away
+                        let _ = client.sender.try_send(v.to_bytes_vec());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Renders the INFO `sections`. Only `stats` is supported, and only with the fields about
+    /// expiry. Any other section, including those Redis always includes such as `server` and
+    /// `keyspace`, is left out, as Redis does with sections it doesn't know.
+    fn info(&self, sections: &[InputValue]) -> OutputValue {
+        let wanted = |name: &str| {
+            sections.is_empty()
+                || sections
+                    .iter()
+                    .any(|s| match s.to_lower_string().as_deref() {
+                        Some("all" | "default" | "everything") => true,
+                        s => s == Some(name),
+                    })
+        };
+        let mut info = String::new();
+        if wanted("stats") {
+            info.push_str("# Stats\r\n");
+            info.push_str(&format!(
+                "expired_keys:{}\r\n",
+                self.db.borrow().expired_keys()
+            ));
+            info.push_str(&format!(
+                "expired_stale_perc:{:.2}\r\n",
+                self.expire_stats.stale_perc
+            ));
+            info.push_str(&format!(
+                "expired_time_cap_reached_count:{}\r\n",
+                self.expire_stats.time_cap_reached_count
+            ));
+        }
+        OutputValue::BulkString(info.into_bytes())
+    }
+
     fn get_db_id(&self, id: &ConnectionId) -> usize {
         let state = self.cons.get_state(id);
         state.db
@@ -92,9 +186,29 @@ impl<I: Default + MapAllCommands> Controller<I> {
                 Interrupt::ClientId => self.client_id(con_id),
                 Interrupt::CommandCount => self.commands.count(),
                 Interrupt::CommandList(filter) => self.commands.list(filter),
+                Interrupt::ConfigGet(patterns) => self.config.get(&patterns),
+                Interrupt::ConfigSet(pairs) => self.config.set(&pairs),
+                Interrupt::Info(sections) => self.info(&sections),
                 Interrupt::Select(db_index) => self.select(con_id, db_index),
-                Interrupt::SwapDb(db1, db2) => self.swap_db(db1, db2),
-                Interrupt::FlushAll => self.flushall(),
+                Interrupt::SwapDb(db1, db2) => {
+                    let res = self.swap_db(db1, db2);
+                    if res == OutputValue::Ok {
+                        // clients stay on their DB index, which may now hold their keys
+                        for db_index in [db1, db2] {
+                            self.serve_blocked(db_index, self.blocked.keys_in(db_index));
+                        }
+                    }
+                    res
+                }
+                Interrupt::FlushAll => {
+                    let res = self.flushall();
+                    // clients reading streams through a group fail now that they are gone
+                    let db_count = self.db.borrow().len();
+                    for db_index in 0..db_count {
+                        self.serve_blocked(db_index, Vec::new());
+                    }
+                    res
+                }
             },
         }
     }
@@ -106,6 +220,9 @@ impl<I: Default + MapAllCommands> IController for Controller<I> {
             db: RefCell::new(Database::new(db_count)),
             cons: ConnectionStore::default(),
             commands: CommandStore::default(),
+            blocked: BlockedClients::default(),
+            config: Config::default(),
+            expire_stats: ExpireStats::default(),
         }
     }
 
@@ -114,28 +231,72 @@ impl<I: Default + MapAllCommands> IController for Controller<I> {
     }
 
     fn disconnect(&mut self, con_id: ConnectionId) {
+        self.blocked.unblock(&con_id);
         self.cons.disconnect(&con_id);
     }
 
-    fn execute(&mut self, mut input: Vec<InputValue>, con_id: ConnectionId) -> Vec<u8> {
+    fn execute(&mut self, mut input: Vec<InputValue>, con_id: ConnectionId) -> Reply {
         debug_assert!(self.cons.has(&con_id));
 
         let name_bs = input[0].clone();
 
         // commands should be valid UTF-8
         let Ok(name) = std::str::from_utf8(&name_bs).map(str::to_ascii_lowercase) else {
-            return [b"ERR unknown command '", name_bs.as_slice(), b"'"]
-                .concat()
-                .to_redis_error();
+            return Reply::Ready(
+                [b"ERR unknown command '", name_bs.as_slice(), b"'"]
+                    .concat()
+                    .to_redis_error(),
+            );
         };
 
+        let db_index = self.get_db_id(&con_id);
+
         if let Some(v) = self.commands.simple_commands.get(name.as_str()).map(|cmd| {
             let mut borrowed = self.db.borrow_mut();
-            let db = borrowed.get_mut(self.get_db_id(&con_id));
+            let db = borrowed.get_mut(db_index);
             cmd.execute(name.as_str(), db, input.drain(1..).collect())
-                .to_bytes_vec()
         }) {
-            return v;
+            // reads can signal keys too, by finding that a stream has expired
+            self.serve_blocked(db_index, Vec::new());
+            return Reply::Ready(v.to_bytes_vec());
+        }
+
+        if let Some(v) = self
+            .commands
+            .blocking_commands
+            .get(name.as_str())
+            .map(|cmd| {
+                let mut borrowed = self.db.borrow_mut();
+                let db = borrowed.get_mut(db_index);
+                cmd.execute(name.as_str(), db, &input[1..])
+            })
+        {
+            return match v {
+                Blocking::Ready(v) => {
+                    self.serve_blocked(db_index, Vec::new());
+                    Reply::Ready(v.to_bytes_vec())
+                }
+                Blocking::Wait {
+                    keys,
+                    timeout,
+                    timeout_reply,
+                    retry_input,
+                } => {
+                    let (sender, receiver) = smol::channel::bounded(1);
+                    self.blocked.block(
+                        con_id,
+                        BlockedClient {
+                            db: db_index,
+                            keys,
+                            name,
+                            input: retry_input.unwrap_or_else(|| input.drain(1..).collect()),
+                            timeout_reply,
+                            sender,
+                        },
+                    );
+                    Reply::Blocked { receiver, timeout }
+                }
+            };
         }
 
         if let Some(v) = self
@@ -144,7 +305,7 @@ impl<I: Default + MapAllCommands> IController for Controller<I> {
             .get(name.as_str())
             .map(|command| command.execute(name.as_str(), input.drain(1..).collect()))
         {
-            return self.handle_interrupt(v, &con_id).to_bytes_vec();
+            return Reply::Ready(self.handle_interrupt(v, &con_id).to_bytes_vec());
         }
 
         if let Some(v) = self
@@ -153,12 +314,47 @@ impl<I: Default + MapAllCommands> IController for Controller<I> {
             .get(name.as_str())
             .map(|command| command.execute(name.as_str(), input.drain(1..).collect()))
         {
-            return self.handle_interrupt(v, &con_id).to_bytes_vec();
+            return Reply::Ready(self.handle_interrupt(v, &con_id).to_bytes_vec());
         }
 
-        return [b"ERR unknown command '", name_bs.as_slice(), b"'"]
-            .concat()
-            .to_redis_error();
+        Reply::Ready(
+            [b"ERR unknown command '", name_bs.as_slice(), b"'"]
+                .concat()
+                .to_redis_error(),
+        )
+    }
+
+    fn cron(&mut self) {
+        let deadline = Instant::now() + self.config.expire_cycle_budget();
+        let report = self
+            .db
+            .borrow_mut()
+            .active_expire_cycle(self.config.active_expire_effort, deadline);
+        let stale_perc = if report.sampled == 0 {
+            0.0
+        } else {
+            report.expired as f64 * 100.0 / report.sampled as f64
+        };
+        // smoothed over the last cycles, as a single sample is a rough estimate
+        self.expire_stats.stale_perc = stale_perc * 0.05 + self.expire_stats.stale_perc * 0.95;
+        if report.timed_out {
+            self.expire_stats.time_cap_reached_count += 1;
+        }
+        // as Redis's activerehashing, a millisecond for resizing keyspaces
+        let deadline = Instant::now() + Duration::from_millis(1);
+        self.db.borrow_mut().rehash_keyspaces(deadline);
+    }
+
+    fn hz(&self) -> u32 {
+        self.config.hz
+    }
+
+    fn unblock(&mut self, con_id: ConnectionId) -> Vec<u8> {
+        self.blocked
+            .unblock(&con_id)
+            .map(|client| client.timeout_reply)
+            .unwrap_or(OutputValue::NullArray)
+            .to_bytes_vec()
     }
 }
 
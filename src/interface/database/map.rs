@@ -1,24 +1,82 @@
+use std::fmt;
+use std::ops::Bound;
+use std::time::Instant;
+
 use crate::interface::types::OutputValue;
 
 pub trait Key: AsRef<[u8]> {}
 
+/// What a run of the active expire cycle did in one database.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExpireCycleReport {
+    pub sampled: usize,
+    pub expired: usize,
+    /// Whether the cycle was cut short by its deadline.
+    pub timed_out: bool,
+}
+
 pub trait IMap: Default {
     fn flushdb(&mut self) -> OutputValue;
+    /// Periodically called to reclaim expired data that nobody has accessed. Keys with a TTL
+    /// are sampled until few of them turn out stale or `deadline` passes; a higher `effort`,
+    /// from 1 to 10, samples more keys at a time.
+    fn active_expire_cycle(&mut self, effort: u32, deadline: Instant) -> ExpireCycleReport;
+    /// How many keys have been deleted because their TTL ran out.
+    fn expired_keys(&self) -> u64;
+    /// Periodically called to move the keyspace along in a resize, so that one that few
+    /// commands write to does not keep both tables for long.
+    fn rehash_keyspace(&mut self, deadline: Instant);
+    /// The keys written since the last call that clients blocked on them should retry:
+    /// lists, sorted sets and streams that were created or added to, and streams that were
+    /// deleted. A key may come up more than once.
+    fn take_ready_keys(&mut self) -> Vec<Vec<u8>>;
 }
 
-pub trait MapAllCommands: IMap + MapStringCommands + MapMiscCommands {}
+pub trait MapAllCommands:
+    IMap
+    + MapStringCommands
+    + MapBitmapCommands
+    + MapListCommands
+    + MapHashCommands
+    + MapSetCommands
+    + MapSortedSetCommands
+    + MapStreamCommands
+    + MapHyperLogLogCommands
+    + MapGeoCommands
+    + MapMiscCommands
+{
+}
 
 pub trait MapStringCommands {
-    fn get(&self, key: impl Key) -> OutputValue;
-    fn set(&mut self, key: impl Key, value: Vec<u8>) -> OutputValue;
-    fn mget(&self, key: Vec<impl Key>) -> OutputValue;
+    fn get(&mut self, key: impl Key) -> OutputValue;
+    /// SET with its NX/XX, GET and EX/PX/EXAT/PXAT/KEEPTTL options; `get` makes it reply
+    /// with the previous value.
+    fn set_with_options(
+        &mut self,
+        key: impl Key,
+        value: Vec<u8>,
+        condition: SetCondition,
+        get: bool,
+        ttl: TtlUpdate,
+    ) -> OutputValue;
+    fn getset(&mut self, key: impl Key, value: Vec<u8>) -> OutputValue;
+    fn setnx(&mut self, key: impl Key, value: Vec<u8>) -> OutputValue;
+    fn setex(&mut self, key: impl Key, ttl: ExpireTime, value: Vec<u8>) -> OutputValue;
+    fn psetex(&mut self, key: impl Key, ttl: ExpireTime, value: Vec<u8>) -> OutputValue;
+    fn getdel(&mut self, key: impl Key) -> OutputValue;
+    /// GETEX, where `TtlUpdate::Keep` leaves the TTL as it is.
+    fn getex(&mut self, key: impl Key, ttl: TtlUpdate) -> OutputValue;
+    fn mget(&mut self, key: Vec<impl Key>) -> OutputValue;
     fn mset(&mut self, key_values: Vec<Vec<u8>>) -> OutputValue;
     fn msetnx(&mut self, key_values: Vec<Vec<u8>>) -> OutputValue;
     fn append(&mut self, key: impl Key, value: Vec<u8>) -> OutputValue;
-    fn strlen(&self, key: impl Key) -> OutputValue;
+    fn strlen(&mut self, key: impl Key) -> OutputValue;
+    fn getrange(&mut self, key: impl Key, start: i64, end: i64) -> OutputValue;
+    fn setrange(&mut self, key: impl Key, offset: usize, value: Vec<u8>) -> OutputValue;
+    fn lcs(&mut self, key1: impl Key, key2: impl Key, output: LcsOutput) -> OutputValue;
     fn incrby(&mut self, key: impl Key, n: i64) -> OutputValue;
     fn decrby(&mut self, key: impl Key, n: i64) -> OutputValue;
-    fn incrbyfloat(&mut self, key: impl Key, n: f64) -> OutputValue;
+    fn incrbyfloat(&mut self, key: impl Key, n: LongDouble) -> OutputValue;
 
     fn incr(&mut self, key: impl Key) -> OutputValue {
         self.incrby(key, 1)
@@ -29,9 +87,757 @@ pub trait MapStringCommands {
     }
 }
 
+/// A float as INCRBYFLOAT and HINCRBYFLOAT add them: a C `long double` on x86-64, which has
+/// a 64-bit mantissa and a 15-bit exponent. A finite one is worth `mantissa × 2^exponent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LongDouble {
+    Finite {
+        negative: bool,
+        mantissa: u64,
+        exponent: i32,
+    },
+    Infinite {
+        negative: bool,
+    },
+}
+
+/// What LCS replies with: the subsequence itself, its length (LEN), or the matching ranges
+/// (IDX) that are at least `min_match_len` long.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LcsOutput {
+    String,
+    Len,
+    Idx {
+        min_match_len: usize,
+        with_match_len: bool,
+    },
+}
+
+/// Whether the indices of a bitmap range count bytes or bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitUnit {
+    Byte,
+    Bit,
+}
+
+/// `start [end] [BYTE|BIT]` of BITCOUNT and BITPOS, where negative indices count from the end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitRange {
+    pub start: i64,
+    pub end: Option<i64>,
+    pub unit: BitUnit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+    /// Bits of the first source that are in none of the others.
+    Diff,
+    /// Bits of the first source that are in at least one of the others.
+    AndOr,
+    /// Bits that are in exactly one source.
+    One,
+}
+
+/// The integer type of a BITFIELD field: signed up to 64 bits, unsigned up to 63.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitfieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitfieldOverflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitfieldOperation {
+    Get,
+    Set(i64),
+    IncrBy(i64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitfieldSubcommand {
+    pub operation: BitfieldOperation,
+    pub field: BitfieldType,
+    pub offset: u64,
+    /// The OVERFLOW behaviour in effect when the subcommand was given.
+    pub overflow: BitfieldOverflow,
+}
+
+pub trait MapBitmapCommands {
+    fn setbit(&mut self, key: impl Key, offset: u64, value: bool) -> OutputValue;
+    fn getbit(&self, key: impl Key, offset: u64) -> OutputValue;
+    fn bitcount(&self, key: impl Key, range: Option<BitRange>) -> OutputValue;
+    fn bitpos(&self, key: impl Key, bit: bool, range: Option<BitRange>) -> OutputValue;
+    fn bitop(
+        &mut self,
+        operation: BitOperation,
+        destination: impl Key,
+        sources: Vec<impl Key>,
+    ) -> OutputValue;
+    fn bitfield(&mut self, key: impl Key, subcommands: Vec<BitfieldSubcommand>) -> OutputValue;
+    fn bitfield_ro(&self, key: impl Key, subcommands: Vec<BitfieldSubcommand>) -> OutputValue;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+pub trait MapListCommands {
+    fn push(
+        &mut self,
+        key: impl Key,
+        values: Vec<Vec<u8>>,
+        end: ListEnd,
+        only_if_exists: bool,
+    ) -> OutputValue;
+    fn pop(&mut self, key: impl Key, end: ListEnd, count: Option<usize>) -> OutputValue;
+    fn lrange(&self, key: impl Key, start: i64, stop: i64) -> OutputValue;
+    fn llen(&self, key: impl Key) -> OutputValue;
+    fn lindex(&self, key: impl Key, index: i64) -> OutputValue;
+    fn lset(&mut self, key: impl Key, index: i64, value: Vec<u8>) -> OutputValue;
+    fn lrem(&mut self, key: impl Key, count: i64, value: Vec<u8>) -> OutputValue;
+    fn ltrim(&mut self, key: impl Key, start: i64, stop: i64) -> OutputValue;
+    fn linsert(
+        &mut self,
+        key: impl Key,
+        before: bool,
+        pivot: Vec<u8>,
+        value: Vec<u8>,
+    ) -> OutputValue;
+    fn lpos(
+        &self,
+        key: impl Key,
+        element: Vec<u8>,
+        rank: i64,
+        count: Option<usize>,
+        maxlen: usize,
+    ) -> OutputValue;
+    fn lmove(
+        &mut self,
+        source: impl Key,
+        destination: impl Key,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> OutputValue;
+
+    fn lpush(&mut self, key: impl Key, values: Vec<Vec<u8>>) -> OutputValue {
+        self.push(key, values, ListEnd::Left, false)
+    }
+
+    fn rpush(&mut self, key: impl Key, values: Vec<Vec<u8>>) -> OutputValue {
+        self.push(key, values, ListEnd::Right, false)
+    }
+
+    fn lpushx(&mut self, key: impl Key, values: Vec<Vec<u8>>) -> OutputValue {
+        self.push(key, values, ListEnd::Left, true)
+    }
+
+    fn rpushx(&mut self, key: impl Key, values: Vec<Vec<u8>>) -> OutputValue {
+        self.push(key, values, ListEnd::Right, true)
+    }
+
+    fn lpop(&mut self, key: impl Key, count: Option<usize>) -> OutputValue {
+        self.pop(key, ListEnd::Left, count)
+    }
+
+    fn rpop(&mut self, key: impl Key, count: Option<usize>) -> OutputValue {
+        self.pop(key, ListEnd::Right, count)
+    }
+
+    fn rpoplpush(&mut self, source: impl Key, destination: impl Key) -> OutputValue {
+        self.lmove(source, destination, ListEnd::Right, ListEnd::Left)
+    }
+}
+
+/// A deadline in milliseconds, either from now or since the Unix epoch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpireTime {
+    Relative(i64),
+    Absolute(i64),
+}
+
+/// The NX/XX/GT/LT flags of the EXPIRE family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpireCondition {
+    Always,
+    Nx,
+    Xx,
+    Gt,
+    Lt,
+}
+
+/// The NX/XX/GT/LT flags of EXPIRE, which unlike those of HEXPIRE may combine XX with GT or
+/// LT.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExpireFlags {
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+}
+
+/// How a remaining lifetime is reported: TTL, PTTL, EXPIRETIME or PEXPIRETIME.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TtlFormat {
+    Seconds,
+    Milliseconds,
+    UnixSeconds,
+    UnixMilliseconds,
+}
+
+/// What to do with the TTL of a value being written or read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TtlUpdate {
+    Keep,
+    Persist,
+    Expire(ExpireTime),
+}
+
+/// The NX/XX flags of SET-like commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetCondition {
+    Always,
+    Nx,
+    Xx,
+}
+
+/// The MATCH and COUNT options of the SCAN family.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Only what matches this glob-style pattern is returned.
+    pub pattern: Option<Vec<u8>>,
+    /// Roughly how many items a call looks at.
+    pub count: usize,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            count: 10,
+        }
+    }
+}
+
+pub trait MapHashCommands {
+    fn hset(&mut self, key: impl Key, field_values: Vec<Vec<u8>>) -> OutputValue;
+    fn hsetnx(&mut self, key: impl Key, field: Vec<u8>, value: Vec<u8>) -> OutputValue;
+    fn hget(&mut self, key: impl Key, field: Vec<u8>) -> OutputValue;
+    fn hmget(&mut self, key: impl Key, fields: Vec<Vec<u8>>) -> OutputValue;
+    fn hdel(&mut self, key: impl Key, fields: Vec<Vec<u8>>) -> OutputValue;
+    fn hlen(&mut self, key: impl Key) -> OutputValue;
+    fn hexists(&mut self, key: impl Key, field: Vec<u8>) -> OutputValue;
+    fn hkeys(&mut self, key: impl Key) -> OutputValue;
+    fn hvals(&mut self, key: impl Key) -> OutputValue;
+    fn hgetall(&mut self, key: impl Key) -> OutputValue;
+    fn hstrlen(&mut self, key: impl Key, field: Vec<u8>) -> OutputValue;
+    fn hincrby(&mut self, key: impl Key, field: Vec<u8>, n: i64) -> OutputValue;
+    fn hincrbyfloat(&mut self, key: impl Key, field: Vec<u8>, n: LongDouble) -> OutputValue;
+    /// A negative `count` allows the same field to be returned more than once.
+    fn hrandfield(&mut self, key: impl Key, count: Option<i64>, with_values: bool) -> OutputValue;
+    /// The HEXPIRE family; `command` is named in its errors.
+    fn hexpire(
+        &mut self,
+        key: impl Key,
+        at: ExpireTime,
+        condition: ExpireCondition,
+        fields: Vec<Vec<u8>>,
+        command: &str,
+    ) -> OutputValue;
+    fn httl(&mut self, key: impl Key, format: TtlFormat, fields: Vec<Vec<u8>>) -> OutputValue;
+    fn hpersist(&mut self, key: impl Key, fields: Vec<Vec<u8>>) -> OutputValue;
+    fn hgetdel(&mut self, key: impl Key, fields: Vec<Vec<u8>>) -> OutputValue;
+    fn hgetex(&mut self, key: impl Key, ttl: TtlUpdate, fields: Vec<Vec<u8>>) -> OutputValue;
+    fn hsetex(
+        &mut self,
+        key: impl Key,
+        condition: SetCondition,
+        ttl: TtlUpdate,
+        field_values: Vec<Vec<u8>>,
+    ) -> OutputValue;
+    fn hscan(
+        &mut self,
+        key: impl Key,
+        cursor: u64,
+        options: ScanOptions,
+        with_values: bool,
+    ) -> OutputValue;
+}
+
+/// The set algebra behind SINTER, SUNION and SDIFF.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetOperation {
+    Inter,
+    Union,
+    Diff,
+}
+
+pub trait MapSetCommands {
+    fn sadd(&mut self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue;
+    fn srem(&mut self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue;
+    fn sismember(&self, key: impl Key, member: Vec<u8>) -> OutputValue;
+    fn smismember(&self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue;
+    fn smembers(&self, key: impl Key) -> OutputValue;
+    fn scard(&self, key: impl Key) -> OutputValue;
+    fn smove(&mut self, source: impl Key, destination: impl Key, member: Vec<u8>) -> OutputValue;
+    /// A negative `count` allows the same member to be returned more than once.
+    fn srandmember(&self, key: impl Key, count: Option<i64>) -> OutputValue;
+    fn spop(&mut self, key: impl Key, count: Option<usize>) -> OutputValue;
+    fn set_operation(&self, operation: SetOperation, keys: Vec<Vec<u8>>) -> OutputValue;
+    /// Replaces `destination` with the result, deleting it if the result is empty.
+    fn set_operation_store(
+        &mut self,
+        operation: SetOperation,
+        destination: Vec<u8>,
+        keys: Vec<Vec<u8>>,
+    ) -> OutputValue;
+    /// A `limit` of 0 means no limit.
+    fn sintercard(&self, keys: Vec<Vec<u8>>, limit: usize) -> OutputValue;
+    fn sscan(&self, key: impl Key, cursor: u64, options: ScanOptions) -> OutputValue;
+}
+
+/// The GT/LT flags of ZADD.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreComparison {
+    Any,
+    Gt,
+    Lt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZaddOptions {
+    pub condition: SetCondition,
+    pub comparison: ScoreComparison,
+    /// Count updated members as well as added ones.
+    pub changed: bool,
+    /// Add the score to the current one and reply with the result, like ZINCRBY.
+    pub incr: bool,
+}
+
+impl Default for ZaddOptions {
+    fn default() -> Self {
+        Self {
+            condition: SetCondition::Always,
+            comparison: ScoreComparison::Any,
+            changed: false,
+            incr: false,
+        }
+    }
+}
+
+/// Which end of a sorted set ZPOPMIN/ZPOPMAX take from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortedSetEnd {
+    Min,
+    Max,
+}
+
+/// A bound of a BYLEX range, where `-` and `+` stand for the smallest and greatest strings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Included(Vec<u8>),
+    Excluded(Vec<u8>),
+}
+
+/// What the bounds of a ZRANGE-like command select, always given lowest first.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SortedSetRange {
+    Rank(i64, i64),
+    Score(Bound<f64>, Bound<f64>),
+    Lex(LexBound, LexBound),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZrangeOptions {
+    pub range: SortedSetRange,
+    /// Walk from the highest score down; ranks then count from the top.
+    pub reverse: bool,
+    /// `offset count` of LIMIT, a negative count meaning all.
+    pub limit: Option<(i64, i64)>,
+}
+
+/// How ZUNION and ZINTER combine the scores of a member found in several inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+pub trait MapSortedSetCommands {
+    fn zadd(
+        &mut self,
+        key: impl Key,
+        options: ZaddOptions,
+        members: Vec<(f64, Vec<u8>)>,
+    ) -> OutputValue;
+    fn zscore(&self, key: impl Key, member: Vec<u8>) -> OutputValue;
+    fn zmscore(&self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue;
+    fn zrank(&self, key: impl Key, member: Vec<u8>, reverse: bool, with_score: bool)
+        -> OutputValue;
+    fn zcard(&self, key: impl Key) -> OutputValue;
+    fn zcount(&self, key: impl Key, min: Bound<f64>, max: Bound<f64>) -> OutputValue;
+    fn zrem(&mut self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue;
+    fn zpop(&mut self, key: impl Key, end: SortedSetEnd, count: Option<usize>) -> OutputValue;
+    fn zrange(&self, key: impl Key, options: ZrangeOptions, with_scores: bool) -> OutputValue;
+    /// Replaces `destination` with the selected members, deleting it if there are none.
+    fn zrangestore(
+        &mut self,
+        destination: impl Key,
+        source: impl Key,
+        options: ZrangeOptions,
+    ) -> OutputValue;
+    fn zlexcount(&self, key: impl Key, min: LexBound, max: LexBound) -> OutputValue;
+    fn zremrange(&mut self, key: impl Key, range: SortedSetRange) -> OutputValue;
+    /// Plain sets are accepted as inputs, their members scoring 1. `weights` has one entry per key
+    /// and is ignored by the difference.
+    fn zset_operation(
+        &self,
+        operation: SetOperation,
+        keys: Vec<Vec<u8>>,
+        weights: Vec<f64>,
+        aggregate: Aggregate,
+        with_scores: bool,
+    ) -> OutputValue;
+    /// Replaces `destination` with the result, deleting it if the result is empty.
+    fn zset_operation_store(
+        &mut self,
+        operation: SetOperation,
+        destination: Vec<u8>,
+        keys: Vec<Vec<u8>>,
+        weights: Vec<f64>,
+        aggregate: Aggregate,
+    ) -> OutputValue;
+    /// A `limit` of 0 means no limit.
+    fn zintercard(&self, keys: Vec<Vec<u8>>, limit: usize) -> OutputValue;
+    fn zscan(
+        &self,
+        key: impl Key,
+        cursor: u64,
+        options: ScanOptions,
+        with_scores: bool,
+    ) -> OutputValue;
+
+    fn zincrby(&mut self, key: impl Key, increment: f64, member: Vec<u8>) -> OutputValue {
+        let options = ZaddOptions {
+            incr: true,
+            ..Default::default()
+        };
+        self.zadd(key, options, vec![(increment, member)])
+    }
+}
+
+/// The ID of a stream entry, rendered as `<ms>-<seq>`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The ID argument of XADD: `*`, `<ms>-*` or a full ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XaddId {
+    Auto,
+    AutoSequence(u64),
+    Explicit(StreamId),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamTrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// The MAXLEN/MINID options of XADD and XTRIM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamTrim {
+    pub strategy: StreamTrimStrategy,
+    /// `~`: only whole nodes are removed, so more entries than asked for may remain.
+    pub approximate: bool,
+    /// At most this many entries are removed; only valid with `~`, and 0 means no limit.
+    pub limit: Option<usize>,
+}
+
+/// Where XGROUP CREATE and SETID place a group: `$` or an explicit ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupStart {
+    LastEntry,
+    Id(StreamId),
+}
+
+/// The ID given to XREAD: entries after an ID, or `+` for the last entry. `$` is resolved
+/// into the stream's last ID beforehand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XreadId {
+    After(StreamId),
+    LastEntry,
+}
+
+/// The ID given to XREADGROUP: `>` for new entries, or an ID to re-read the consumer's
+/// pending entries after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadGroupId {
+    New,
+    History(StreamId),
+}
+
+/// The extended form of XPENDING.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XpendingRange {
+    pub min_idle: u64,
+    pub start: Bound<StreamId>,
+    pub end: Bound<StreamId>,
+    pub count: usize,
+    pub consumer: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XclaimOptions {
+    /// IDLE: the claimed entries are reported as delivered this long ago.
+    pub idle: Option<u64>,
+    /// TIME: the same as IDLE, as a Unix time in milliseconds.
+    pub time: Option<u64>,
+    pub retry_count: Option<u64>,
+    /// Claims entries that exist in the stream even if nobody has them pending.
+    pub force: bool,
+    /// Only the IDs are returned and the delivery counts are left alone.
+    pub just_id: bool,
+    pub last_id: Option<StreamId>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XautoclaimOptions {
+    pub count: usize,
+    pub just_id: bool,
+}
+
+pub trait MapStreamCommands {
+    /// `fields` holds field/value pairs flattened.
+    fn xadd(
+        &mut self,
+        key: impl Key,
+        id: XaddId,
+        no_mkstream: bool,
+        trim: Option<StreamTrim>,
+        fields: Vec<Vec<u8>>,
+    ) -> OutputValue;
+    /// Entries are returned from `end` down to `start` if `reverse` is set.
+    fn xrange(
+        &self,
+        key: impl Key,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+        count: Option<usize>,
+        reverse: bool,
+    ) -> OutputValue;
+    fn xlen(&self, key: impl Key) -> OutputValue;
+    fn xdel(&mut self, key: impl Key, ids: Vec<StreamId>) -> OutputValue;
+    fn xtrim(&mut self, key: impl Key, trim: StreamTrim) -> OutputValue;
+    fn xinfo_stream(&self, key: impl Key) -> OutputValue;
+    /// Replies with a null array if no stream has anything to return.
+    fn xread(&self, streams: Vec<(Vec<u8>, XreadId)>, count: Option<usize>) -> OutputValue;
+    /// The ID `$` stands for: the last ID generated in the stream, or 0-0 if there is none.
+    fn stream_last_id(&self, key: impl Key) -> Result<StreamId, OutputValue>;
+    /// `entries_read` is ENTRIESREAD, where `None` stands for -1.
+    fn xgroup_create(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        start: GroupStart,
+        mkstream: bool,
+        entries_read: Option<u64>,
+    ) -> OutputValue;
+    fn xgroup_setid(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        start: GroupStart,
+        entries_read: Option<u64>,
+    ) -> OutputValue;
+    fn xgroup_destroy(&mut self, key: impl Key, group: Vec<u8>) -> OutputValue;
+    fn xgroup_createconsumer(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+    ) -> OutputValue;
+    fn xgroup_delconsumer(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+    ) -> OutputValue;
+    fn xreadgroup(
+        &mut self,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+        streams: Vec<(Vec<u8>, ReadGroupId)>,
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> OutputValue;
+    fn xack(&mut self, key: impl Key, group: Vec<u8>, ids: Vec<StreamId>) -> OutputValue;
+    /// Without a `range`, the summary form is returned.
+    fn xpending(&self, key: impl Key, group: Vec<u8>, range: Option<XpendingRange>) -> OutputValue;
+    fn xclaim(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+        min_idle: u64,
+        ids: Vec<StreamId>,
+        options: XclaimOptions,
+    ) -> OutputValue;
+    fn xautoclaim(
+        &mut self,
+        key: impl Key,
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+        min_idle: u64,
+        start: StreamId,
+        options: XautoclaimOptions,
+    ) -> OutputValue;
+    fn xinfo_groups(&self, key: impl Key) -> OutputValue;
+    fn xinfo_consumers(&self, key: impl Key, group: Vec<u8>) -> OutputValue;
+}
+
+/// A unit of distance of the geo commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeoUnit {
+    Meters,
+    Kilometers,
+    Feet,
+    Miles,
+}
+
+impl GeoUnit {
+    pub fn in_meters(self) -> f64 {
+        match self {
+            GeoUnit::Meters => 1.0,
+            GeoUnit::Kilometers => 1000.0,
+            GeoUnit::Feet => 0.3048,
+            GeoUnit::Miles => 1609.34,
+        }
+    }
+}
+
+/// Where GEOSEARCH searches from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeoOrigin {
+    Member(Vec<u8>),
+    LonLat(f64, f64),
+}
+
+/// The area of GEOSEARCH, in its unit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeoSort {
+    Asc,
+    Desc,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeoSearch {
+    pub origin: GeoOrigin,
+    pub shape: GeoShape,
+    pub unit: GeoUnit,
+    pub sort: Option<GeoSort>,
+    pub count: Option<usize>,
+    /// Stop at the first `count` matches found rather than the `count` closest ones.
+    pub any: bool,
+    pub with_coord: bool,
+    pub with_dist: bool,
+    pub with_hash: bool,
+}
+
+pub trait MapGeoCommands {
+    fn geoadd(
+        &mut self,
+        key: impl Key,
+        options: ZaddOptions,
+        points: Vec<(f64, f64, Vec<u8>)>,
+    ) -> OutputValue;
+    fn geopos(&self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue;
+    fn geodist(
+        &self,
+        key: impl Key,
+        member1: Vec<u8>,
+        member2: Vec<u8>,
+        unit: GeoUnit,
+    ) -> OutputValue;
+    fn geohash(&self, key: impl Key, members: Vec<Vec<u8>>) -> OutputValue;
+    fn geosearch(&self, key: impl Key, search: GeoSearch) -> OutputValue;
+    fn geosearchstore(
+        &mut self,
+        destination: impl Key,
+        source: impl Key,
+        search: GeoSearch,
+        store_dist: bool,
+    ) -> OutputValue;
+}
+
+pub trait MapHyperLogLogCommands {
+    fn pfadd(&mut self, key: impl Key, elements: Vec<Vec<u8>>) -> OutputValue;
+    fn pfcount(&mut self, keys: Vec<impl Key>) -> OutputValue;
+    fn pfmerge(&mut self, destination: impl Key, sources: Vec<impl Key>) -> OutputValue;
+}
+
+/// Reads that come across a key whose TTL has run out delete it, hence `&mut self`.
 pub trait MapMiscCommands {
     fn del(&mut self, keys: Vec<impl Key>) -> OutputValue;
-    fn keys(&self, pattern: impl Key) -> OutputValue;
-    fn exists(&self, keys: Vec<impl Key>) -> OutputValue;
-    fn len(&self) -> usize;
+    fn keys(&mut self, pattern: impl Key) -> OutputValue;
+    fn exists(&mut self, keys: Vec<impl Key>) -> OutputValue;
+    /// The EXPIRE family; a deadline that has already passed deletes the key. `command` is
+    /// named in the error for a deadline that overflows.
+    fn expire(
+        &mut self,
+        key: impl Key,
+        at: ExpireTime,
+        flags: ExpireFlags,
+        command: &str,
+    ) -> OutputValue;
+    /// TTL, PTTL, EXPIRETIME or PEXPIRETIME: -2 for a missing key, -1 for one without a TTL.
+    fn ttl(&mut self, key: impl Key, format: TtlFormat) -> OutputValue;
+    fn persist(&mut self, key: impl Key) -> OutputValue;
+    /// OBJECT ENCODING: how the value at `key` is represented.
+    fn object_encoding(&mut self, key: impl Key) -> OutputValue;
+    /// SCAN: `value_type`, as TYPE names it, keeps only the keys holding that kind of value.
+    fn scan(
+        &mut self,
+        cursor: u64,
+        options: ScanOptions,
+        value_type: Option<Vec<u8>>,
+    ) -> OutputValue;
+    fn len(&mut self) -> usize;
 }
@@ -5,14 +5,20 @@ pub mod database;
 pub mod types;
 
 use connection::ConnectionId;
-use types::{InputValue, OutputValue};
+use types::{InputValue, OutputValue, Reply};
 
 // External interface
 pub trait IController {
     fn new(db_count: usize) -> Self;
     fn connect(&mut self, addr: SocketAddr) -> ConnectionId;
     fn disconnect(&mut self, con_id: ConnectionId);
-    fn execute(&mut self, input: Vec<InputValue>, con_id: ConnectionId) -> Vec<u8>;
+    fn execute(&mut self, input: Vec<InputValue>, con_id: ConnectionId) -> Reply;
+    /// Gives up waiting for a blocked client and returns its timeout reply.
+    fn unblock(&mut self, con_id: ConnectionId) -> Vec<u8>;
+    /// Background housekeeping, run periodically between commands.
+    fn cron(&mut self);
+    /// How many times per second `cron` should run.
+    fn hz(&self) -> u32;
 }
 
 // Internal interface
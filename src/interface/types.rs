@@ -1,7 +1,21 @@
+use std::time::Duration;
+
+use smol::channel::Receiver;
+
 use crate::bstr::BStr;
 
 pub type InputValue = Vec<u8>;
 
+pub enum Reply {
+    Ready(Vec<u8>),
+    /// The client is parked until another client's write serves it through `receiver`,
+    /// or until `timeout` elapses.
+    Blocked {
+        receiver: Receiver<Vec<u8>>,
+        timeout: Option<Duration>,
+    },
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum OutputValue {
     SimpleString(Vec<u8>),
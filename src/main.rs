@@ -13,6 +13,7 @@ use interface::types::InputValue;
 use parser::ParsedValue;
 use parser::Parser;
 use wrapper::ControllerWrapper;
+use wrapper::Handle;
 
 thread_local! {
     static INSTANCE: std::cell::OnceCell<ControllerWrapper> = const { std::cell::OnceCell::new() };
@@ -34,6 +35,36 @@ fn remove_non_command_values(value: ParsedValue) -> Result<Vec<InputValue>, &'st
     }
 }
 
+enum Event {
+    Reply(Vec<u8>),
+    Read(std::io::Result<usize>),
+}
+
+/// Executes a command, keeping the connection read while it is blocked so that
+/// pipelined input is buffered and a disconnection is noticed.
+/// Returns `None` if the client went away before the command finished.
+async fn execute_while_reading(
+    handle: &Handle,
+    input: Vec<InputValue>,
+    stream: &mut TcpStream,
+    buffer: &mut [u8],
+    parser: &mut Parser,
+) -> Option<Vec<u8>> {
+    let execution = handle.execute(input);
+    smol::pin!(execution);
+    loop {
+        let event = smol::future::or(async { Event::Reply(execution.as_mut().await) }, async {
+            Event::Read(stream.read(buffer).await)
+        })
+        .await;
+        match event {
+            Event::Reply(v) => return Some(v),
+            Event::Read(Ok(0)) | Event::Read(Err(_)) => return None,
+            Event::Read(Ok(n)) => parser.extend(&buffer[..n]),
+        }
+    }
+}
+
 async fn handle_stream(mut stream: TcpStream) {
     let handle = INSTANCE.with(|inner| {
         let addr = stream.peer_addr().unwrap();
@@ -44,20 +75,7 @@ async fn handle_stream(mut stream: TcpStream) {
     let mut parser = Parser::new();
     let mut stop = false;
 
-    while !stop {
-        match stream.read(&mut buffer).await {
-            Ok(0) => {
-                stop = true;
-            }
-            Ok(n) => {
-                parser.extend(&buffer[..n]);
-            }
-            Err(e) => {
-                println!("Error reading from client: {}", e);
-                break;
-            }
-        }
-
+    loop {
         // parse
         let mut values = Vec::new();
         let mut error = Vec::new();
@@ -75,16 +93,36 @@ async fn handle_stream(mut stream: TcpStream) {
             }
         }
 
-        let results: Vec<_> = values
-            .into_iter()
-            .map(|v| handle.execute(v))
-            .chain(error)
-            .collect();
-
-        for v in results.into_iter() {
+        // commands are run one by one, as a blocking command holds back the ones after it
+        for v in values {
+            let Some(result) =
+                execute_while_reading(&handle, v, &mut stream, &mut buffer, &mut parser).await
+            else {
+                return;
+            };
+            stream.write_all(result.as_slice()).await.unwrap();
+            stream.flush().await.unwrap();
+        }
+        for v in error {
             stream.write_all(v.as_slice()).await.unwrap();
             stream.flush().await.unwrap();
         }
+
+        if stop {
+            break;
+        }
+        match stream.read(&mut buffer).await {
+            Ok(0) => {
+                stop = true;
+            }
+            Ok(n) => {
+                parser.extend(&buffer[..n]);
+            }
+            Err(e) => {
+                println!("Error reading from client: {}", e);
+                break;
+            }
+        }
     }
 }
 
@@ -92,6 +130,16 @@ fn main() {
     INSTANCE.with(|inner| inner.set(ControllerWrapper::new(16)).unwrap());
     let executor = smol::LocalExecutor::new();
     smol::block_on(executor.run(async {
+        // background housekeeping, such as dropping expired keys, `hz` times per second
+        executor
+            .spawn(async {
+                loop {
+                    let hz = INSTANCE.with(|inner| inner.get().unwrap().hz());
+                    smol::Timer::after(std::time::Duration::from_secs(1) / hz).await;
+                    INSTANCE.with(|inner| inner.get().unwrap().cron());
+                }
+            })
+            .detach();
         let listener = TcpListener::bind("127.0.0.1:7379").await.unwrap();
         listener
             .incoming()
@@ -1,10 +1,14 @@
 use smol::net::SocketAddr;
+use smol::Timer;
 
 use std::ops::Deref;
 
 use crate::{
     implementation::{database::Map, Controller},
-    interface::{types::InputValue, IController},
+    interface::{
+        types::{InputValue, Reply},
+        IController,
+    },
 };
 
 #[derive(Debug)]
@@ -37,6 +41,14 @@ impl ControllerWrapper {
             con_id,
         }
     }
+
+    pub fn cron(&self) {
+        self.borrow_mut().cron();
+    }
+
+    pub fn hz(&self) -> u32 {
+        self.borrow().hz()
+    }
 }
 pub struct Handle {
     ex: ControllerWrapper,
@@ -44,8 +56,27 @@ pub struct Handle {
 }
 
 impl Handle {
-    pub fn execute(&self, input: Vec<InputValue>) -> Vec<u8> {
-        self.ex.borrow_mut().execute(input, self.con_id.clone())
+    /// Runs a command, waiting for other clients (or the timeout) if the command blocks.
+    pub async fn execute(&self, input: Vec<InputValue>) -> Vec<u8> {
+        let reply = self.ex.borrow_mut().execute(input, self.con_id.clone());
+        let (receiver, timeout) = match reply {
+            Reply::Ready(v) => return v,
+            Reply::Blocked { receiver, timeout } => (receiver, timeout),
+        };
+        let served = match timeout {
+            None => receiver.recv().await.ok(),
+            Some(timeout) => {
+                smol::future::or(async { receiver.recv().await.ok() }, async {
+                    Timer::after(timeout).await;
+                    None
+                })
+                .await
+            }
+        };
+        // the client may have been served right before the timer fired
+        served
+            .or_else(|| receiver.try_recv().ok())
+            .unwrap_or_else(|| self.ex.borrow_mut().unblock(self.con_id.clone()))
     }
 }
 